@@ -3,8 +3,11 @@ use lib_db as db;
 
 use super::common::*;
 use crate::iam::UserId;
+use channels::forge_release::ForgeRelease;
 use channels::github_release::GithubRelease;
+use channels::github_tag::GithubTag;
 use channels::twitter::Twitter;
+use channels::web_page::WebPage;
 use channels::*;
 use either::{Left, Right};
 use rocket::http::RawStr;
@@ -13,6 +16,7 @@ use rocket::Rocket;
 use rocket::State;
 use rocket_contrib::json::JsonValue;
 
+use std::collections::HashMap;
 use std::convert::{From, TryFrom, TryInto};
 
 pub fn mount(rocket: Rocket) -> Rocket {
@@ -32,7 +36,18 @@ pub struct TwitterTokens {
 enum ChannelType {
     RssFeed,
     GithubRelease,
+    GithubTag,
     Twitter,
+    Mastodon,
+    CratesIo,
+    Npm,
+    PyPi,
+    ForgeRelease,
+    OciImage,
+    LinkAggregator,
+    Reddit,
+    WebPage,
+    Newsletter,
     List,
 }
 
@@ -52,10 +67,19 @@ struct Channel {
     creator_id: Option<UserId>,
     link: Option<String>,
     verified: bool,
+    podcast: bool,
+    /// we stopped getting updates, because fetching the channel failed too often
+    dead: bool,
 }
 
 #[get("/<channel_type>/<id>")]
-fn show(db: DigesterDbConn, channel_type: ChannelType, id: i32) -> JsonResponse {
+fn show(
+    session: Option<Protected>,
+    db: DigesterDbConn,
+    channel_type: ChannelType,
+    id: i32,
+) -> JsonResponse {
+    let user_id = session.map(|s| s.0.user_id.into());
     let maybe_channel = match channel_type {
         ChannelType::List => match db::lists_find_by_id(&db, id) {
             Ok(v) => v.map(|(l, i)| Channel::from_list(l, i, Vec::new())),
@@ -64,15 +88,29 @@ fn show(db: DigesterDbConn, channel_type: ChannelType, id: i32) -> JsonResponse
                 return JsonResponse::InternalServerError;
             }
         },
-        ChannelType::GithubRelease | ChannelType::RssFeed | ChannelType::Twitter => {
-            match db::channels_find_by_id_opt(&db, id) {
-                Ok(v) => v.map(Channel::from_channel),
-                Err(err) => {
-                    eprintln!("Failed to fetch channel from db {:?}", err);
-                    return JsonResponse::InternalServerError;
-                }
+        ChannelType::GithubRelease
+        | ChannelType::GithubTag
+        | ChannelType::RssFeed
+        | ChannelType::Twitter
+        | ChannelType::Mastodon
+        | ChannelType::CratesIo
+        | ChannelType::Npm
+        | ChannelType::PyPi
+        | ChannelType::ForgeRelease
+        | ChannelType::OciImage
+        | ChannelType::LinkAggregator
+        | ChannelType::Reddit
+        | ChannelType::WebPage
+        | ChannelType::Newsletter => match db::channels_find_by_id_opt(&db, id) {
+            // nobody else must learn the address of a newsletter
+            Ok(v) => v
+                .filter(|c| !c.is_hidden_from(user_id))
+                .map(Channel::from_channel),
+            Err(err) => {
+                eprintln!("Failed to fetch channel from db {:?}", err);
+                return JsonResponse::InternalServerError;
             }
-        }
+        },
     };
 
     match maybe_channel {
@@ -83,7 +121,7 @@ fn show(db: DigesterDbConn, channel_type: ChannelType, id: i32) -> JsonResponse
 
 #[get("/search?<channel_type>&<query>")]
 fn search(
-    _session: Protected,
+    session: Protected,
     _r: RateLimited,
     db: DigesterDbConn,
     channel_type: ChannelType,
@@ -102,7 +140,18 @@ fn search(
     let channel_or_list = match channel_type {
         ChannelType::List => Left(()),
         ChannelType::GithubRelease => Right(db::ChannelType::GithubRelease),
+        ChannelType::GithubTag => Right(db::ChannelType::GithubTag),
         ChannelType::Twitter => Right(db::ChannelType::Twitter),
+        ChannelType::Mastodon => Right(db::ChannelType::Mastodon),
+        ChannelType::CratesIo => Right(db::ChannelType::CratesIo),
+        ChannelType::Npm => Right(db::ChannelType::Npm),
+        ChannelType::PyPi => Right(db::ChannelType::PyPi),
+        ChannelType::ForgeRelease => Right(db::ChannelType::ForgeRelease),
+        ChannelType::OciImage => Right(db::ChannelType::OciImage),
+        ChannelType::LinkAggregator => Right(db::ChannelType::LinkAggregator),
+        ChannelType::Reddit => Right(db::ChannelType::Reddit),
+        ChannelType::WebPage => Right(db::ChannelType::WebPage),
+        ChannelType::Newsletter => Right(db::ChannelType::Newsletter),
         ChannelType::RssFeed => Right(db::ChannelType::RssFeed),
     };
 
@@ -133,8 +182,19 @@ fn search(
         Right(db_channel_type) => {
             let channel_type = match db_channel_type {
                 db::ChannelType::GithubRelease => channels::ChannelType::GithubRelease,
+                db::ChannelType::GithubTag => channels::ChannelType::GithubTag,
                 db::ChannelType::RssFeed => channels::ChannelType::RssFeed,
                 db::ChannelType::Twitter => channels::ChannelType::Twitter,
+                db::ChannelType::Mastodon => channels::ChannelType::Mastodon,
+                db::ChannelType::CratesIo => channels::ChannelType::CratesIo,
+                db::ChannelType::Npm => channels::ChannelType::Npm,
+                db::ChannelType::PyPi => channels::ChannelType::PyPi,
+                db::ChannelType::ForgeRelease => channels::ChannelType::ForgeRelease,
+                db::ChannelType::OciImage => channels::ChannelType::OciImage,
+                db::ChannelType::LinkAggregator => channels::ChannelType::LinkAggregator,
+                db::ChannelType::Reddit => channels::ChannelType::Reddit,
+                db::ChannelType::WebPage => channels::ChannelType::WebPage,
+                db::ChannelType::Newsletter => channels::ChannelType::Newsletter,
             };
             match online_search(&gh_token, &twitter_tokens, &query, channel_type) {
                 Err(err) => err,
@@ -144,6 +204,11 @@ fn search(
                         return JsonResponse::Ok(json!(no_channels));
                     }
 
+                    // each search for a newsletter creates a new address for this user
+                    let owner_id = match db_channel_type {
+                        db::ChannelType::Newsletter => Some(session.0.user_id.into()),
+                        _ => None,
+                    };
                     let new_channels = channels
                         .iter()
                         .map(|f| db::NewChannel {
@@ -152,6 +217,8 @@ fn search(
                             ext_id: f.ext_id.clone(),
                             link: f.link.clone(),
                             verified: f.verified,
+                            podcast: f.podcast,
+                            owner_id,
                         })
                         .collect();
 
@@ -186,6 +253,14 @@ fn online_search(
         }
     };
 
+    let github_tag: GithubTag = match GithubTag::new(&gh_token.0) {
+        Ok(github_tag) => github_tag,
+        Err(err) => {
+            eprintln!("Failed to resolve github client: {:?}", err);
+            return Err(JsonResponse::InternalServerError);
+        }
+    };
+
     let twitter: Twitter = match Twitter::new(
         &twitter_tokens.api_key,
         &twitter_tokens.api_secret_key,
@@ -198,7 +273,18 @@ fn online_search(
             return Err(JsonResponse::InternalServerError);
         }
     };
-    let channel = channels::factory(&channel_type, &github, &twitter);
+    // only public repositories can be found through the api
+    let forge_release = ForgeRelease::new(HashMap::new());
+    let web_page = WebPage::without_snapshots();
+
+    let channel = channels::factory(
+        &channel_type,
+        &github,
+        &github_tag,
+        &forge_release,
+        &twitter,
+        &web_page,
+    );
 
     let online_query = match channel.sanitize(&query) {
         Err(err) => {
@@ -246,8 +332,19 @@ impl From<db::ChannelType> for ChannelType {
     fn from(ct: db::ChannelType) -> ChannelType {
         match ct {
             db::ChannelType::GithubRelease => ChannelType::GithubRelease,
+            db::ChannelType::GithubTag => ChannelType::GithubTag,
             db::ChannelType::RssFeed => ChannelType::RssFeed,
             db::ChannelType::Twitter => ChannelType::Twitter,
+            db::ChannelType::Mastodon => ChannelType::Mastodon,
+            db::ChannelType::CratesIo => ChannelType::CratesIo,
+            db::ChannelType::Npm => ChannelType::Npm,
+            db::ChannelType::PyPi => ChannelType::PyPi,
+            db::ChannelType::ForgeRelease => ChannelType::ForgeRelease,
+            db::ChannelType::OciImage => ChannelType::OciImage,
+            db::ChannelType::LinkAggregator => ChannelType::LinkAggregator,
+            db::ChannelType::Reddit => ChannelType::Reddit,
+            db::ChannelType::WebPage => ChannelType::WebPage,
+            db::ChannelType::Newsletter => ChannelType::Newsletter,
         }
     }
 }
@@ -258,8 +355,19 @@ impl TryFrom<&str> for ChannelType {
     fn try_from(ct: &str) -> Result<ChannelType, String> {
         match ct {
             "GithubRelease" => Ok(ChannelType::GithubRelease),
+            "GithubTag" => Ok(ChannelType::GithubTag),
             "RssFeed" => Ok(ChannelType::RssFeed),
             "Twitter" => Ok(ChannelType::Twitter),
+            "Mastodon" => Ok(ChannelType::Mastodon),
+            "CratesIo" => Ok(ChannelType::CratesIo),
+            "Npm" => Ok(ChannelType::Npm),
+            "PyPi" => Ok(ChannelType::PyPi),
+            "ForgeRelease" => Ok(ChannelType::ForgeRelease),
+            "OciImage" => Ok(ChannelType::OciImage),
+            "LinkAggregator" => Ok(ChannelType::LinkAggregator),
+            "Reddit" => Ok(ChannelType::Reddit),
+            "WebPage" => Ok(ChannelType::WebPage),
+            "Newsletter" => Ok(ChannelType::Newsletter),
             "List" => Ok(ChannelType::List),
             other => Err(format!("Invalid channel type: {}", other)),
         }
@@ -270,8 +378,19 @@ impl From<channels::ChannelType> for ChannelType {
     fn from(ct: channels::ChannelType) -> ChannelType {
         match ct {
             channels::ChannelType::GithubRelease => ChannelType::GithubRelease,
+            channels::ChannelType::GithubTag => ChannelType::GithubTag,
             channels::ChannelType::RssFeed => ChannelType::RssFeed,
             channels::ChannelType::Twitter => ChannelType::Twitter,
+            channels::ChannelType::Mastodon => ChannelType::Mastodon,
+            channels::ChannelType::CratesIo => ChannelType::CratesIo,
+            channels::ChannelType::Npm => ChannelType::Npm,
+            channels::ChannelType::PyPi => ChannelType::PyPi,
+            channels::ChannelType::ForgeRelease => ChannelType::ForgeRelease,
+            channels::ChannelType::OciImage => ChannelType::OciImage,
+            channels::ChannelType::LinkAggregator => ChannelType::LinkAggregator,
+            channels::ChannelType::Reddit => ChannelType::Reddit,
+            channels::ChannelType::WebPage => ChannelType::WebPage,
+            channels::ChannelType::Newsletter => ChannelType::Newsletter,
         }
     }
 }
@@ -318,6 +437,8 @@ impl Channel {
             creator_id: None,
             link: Some(c.link),
             verified: c.verified,
+            podcast: c.podcast,
+            dead: c.dead_since.is_some(),
         }
     }
 
@@ -332,6 +453,8 @@ impl Channel {
             creator_id: Some(UserId::from(i.user_id)),
             link: None,
             verified: false,
+            podcast: false,
+            dead: false,
         }
     }
 }
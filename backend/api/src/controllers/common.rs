@@ -29,6 +29,7 @@ pub enum JsonResponse {
     Unauthorized,
     TooManyRequests,
     UnprocessableEntity,
+    Gone,
 }
 impl<'r> Responder<'r> for JsonResponse {
     fn respond_to(self, req: &Request) -> response::Result<'r> {
@@ -41,6 +42,7 @@ impl<'r> Responder<'r> for JsonResponse {
             JsonResponse::Forbidden => (json!({}), HttpStatus::Forbidden),
             JsonResponse::TooManyRequests => (json!({}), HttpStatus::TooManyRequests),
             JsonResponse::UnprocessableEntity => (json!({}), HttpStatus::UnprocessableEntity),
+            JsonResponse::Gone => (json!({}), HttpStatus::Gone),
         };
         Custom(status, body).respond_to(req)
     }
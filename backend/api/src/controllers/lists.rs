@@ -3,9 +3,10 @@ use lib_db as db;
 use super::super::iam::UserId;
 use super::super::lists;
 use super::common::*;
+use super::websub::{self, WebsubCallbackUrl};
 use db::ChannelType;
 use rocket::http::RawStr;
-use rocket::Rocket;
+use rocket::{Rocket, State};
 use rocket_contrib::json::{Json, JsonValue};
 
 pub fn mount(rocket: Rocket) -> Rocket {
@@ -56,7 +57,11 @@ struct Channel {
     name: String,
     #[serde(rename = "type")]
     channel_type: ChannelType,
-    link: String,
+    link: Option<String>,
+    /// we stopped getting updates, because fetching the channel failed too often
+    dead: bool,
+    #[serde(rename = "lastErrorKind")]
+    last_error_kind: Option<db::FetchErrorKind>,
 }
 
 impl Into<JsonResponse> for List {
@@ -87,7 +92,12 @@ impl Into<JsonResponse> for Vec<List> {
 }
 
 impl List {
-    fn from_db(l: db::List, identity: db::Identity, channels: Vec<db::Channel>) -> List {
+    fn from_db(
+        l: db::List,
+        identity: db::Identity,
+        channels: Vec<db::Channel>,
+        user_id: Option<db::UserId>,
+    ) -> List {
         List {
             id: l.id,
             name: l.name,
@@ -95,19 +105,23 @@ impl List {
             creator_id: UserId::from(identity.user_id),
             channels: channels
                 .into_iter()
-                .map(Channel::from_db)
+                .map(|c| Channel::from_db(c, user_id))
                 .collect::<Vec<Channel>>(),
         }
     }
 }
 
 impl Channel {
-    fn from_db(c: db::Channel) -> Channel {
+    fn from_db(c: db::Channel, user_id: Option<db::UserId>) -> Channel {
+        // the owner may put a newsletter into a public list, but not its address
+        let hidden = c.is_hidden_from(user_id);
         Channel {
             id: c.id,
             name: c.name,
             channel_type: c.channel_type,
-            link: c.link,
+            link: if hidden { None } else { Some(c.link) },
+            dead: c.dead_since.is_some(),
+            last_error_kind: c.last_error_kind,
         }
     }
 }
@@ -115,7 +129,7 @@ impl Channel {
 #[get("/?<own>")]
 fn list(session: Option<Protected>, db: DigesterDbConn, own: Option<bool>) -> JsonResponse {
     let maybe_creator_id = match own {
-        Some(true) => match session {
+        Some(true) => match &session {
             Some(session) => Some(session.0.user_id),
             None => return JsonResponse::BadRequest("own parameter requires session".into()),
         },
@@ -129,11 +143,12 @@ fn list(session: Option<Protected>, db: DigesterDbConn, own: Option<bool>) -> Js
         }
     };
 
-    lists_to_resp(&db, lists).into()
+    let user_id = session.map(|s| s.0.user_id.into());
+    lists_to_resp(&db, lists, user_id).into()
 }
 
 #[get("/popular")]
-fn popular(db: DigesterDbConn) -> JsonResponse {
+fn popular(session: Option<Protected>, db: DigesterDbConn) -> JsonResponse {
     let lists = match db::lists_find_order_by_popularity(&db, 10) {
         Ok(lists) => lists,
         Err(err) => {
@@ -141,11 +156,12 @@ fn popular(db: DigesterDbConn) -> JsonResponse {
             return JsonResponse::InternalServerError;
         }
     };
-    lists_to_resp(&db, lists).into()
+    let user_id = session.map(|s| s.0.user_id.into());
+    lists_to_resp(&db, lists, user_id).into()
 }
 
 #[get("/<id>")]
-fn show(db: DigesterDbConn, id: i32) -> JsonResponse {
+fn show(session: Option<Protected>, db: DigesterDbConn, id: i32) -> JsonResponse {
     let list = match db::lists_find_by_id(&db, id) {
         Ok(None) => return JsonResponse::NotFound,
         Ok(Some(list)) => list,
@@ -157,11 +173,12 @@ fn show(db: DigesterDbConn, id: i32) -> JsonResponse {
 
     let mut lists = Vec::with_capacity(1);
     lists.push(list);
-    lists_to_resp(&db, lists)[0].clone().into()
+    let user_id = session.map(|s| s.0.user_id.into());
+    lists_to_resp(&db, lists, user_id)[0].clone().into()
 }
 
 #[get("/search?<query>")]
-fn search(db: DigesterDbConn, query: &RawStr) -> JsonResponse {
+fn search(session: Option<Protected>, db: DigesterDbConn, query: &RawStr) -> JsonResponse {
     let lists = match db::lists_search(&db, query) {
         Ok(lists) => lists,
         Err(err) => {
@@ -170,10 +187,15 @@ fn search(db: DigesterDbConn, query: &RawStr) -> JsonResponse {
         }
     };
 
-    lists_to_resp(&db, lists).into()
+    let user_id = session.map(|s| s.0.user_id.into());
+    lists_to_resp(&db, lists, user_id).into()
 }
 
-fn lists_to_resp(db: &DigesterDbConn, lists: Vec<(db::List, db::Identity)>) -> Vec<List> {
+fn lists_to_resp(
+    db: &DigesterDbConn,
+    lists: Vec<(db::List, db::Identity)>,
+    user_id: Option<db::UserId>,
+) -> Vec<List> {
     let lists_with_channels = match db::lists_identity_zip_with_channels(&db, lists) {
         Ok(lwc) => lwc,
         Err(err) => {
@@ -184,7 +206,7 @@ fn lists_to_resp(db: &DigesterDbConn, lists: Vec<(db::List, db::Identity)>) -> V
 
     lists_with_channels
         .into_iter()
-        .map(|(list, identity, channels)| List::from_db(list, identity, channels))
+        .map(|(list, identity, channels)| List::from_db(list, identity, channels, user_id))
         .collect::<Vec<List>>()
 }
 
@@ -220,7 +242,9 @@ fn add(session: Protected, db: DigesterDbConn, new_list: Json<NewList>) -> JsonR
         Ok((list, identity)) => {
             let mut lists = Vec::with_capacity(1);
             lists.push((list, identity));
-            lists_to_resp(&db, lists)[0].clone().into()
+            lists_to_resp(&db, lists, Some(session.0.user_id.into()))[0]
+                .clone()
+                .into()
         }
     }
 }
@@ -261,6 +285,7 @@ fn update(
 fn add_channel(
     session: Protected,
     db: DigesterDbConn,
+    websub_callback_url: State<WebsubCallbackUrl>,
     list_id: i32,
     add_channel: Json<AddChannel>,
 ) -> JsonResponse {
@@ -269,8 +294,22 @@ fn add_channel(
         Err(err) => return err,
     };
     let channel_id = add_channel.id;
+    let channel = match db::channels_find_by_id_opt(&db, channel_id) {
+        // only the owner may add a newsletter, anyone else doesn't even learn it exists
+        Ok(Some(channel)) if !channel.is_hidden_from(Some(session.0.user_id.into())) => channel,
+        Ok(_) => return JsonResponse::NotFound,
+        Err(err) => {
+            eprintln!("Failed to find channel {}: {}", channel_id, err);
+            return JsonResponse::InternalServerError;
+        }
+    };
     match db::lists_add_channel(&db, list, channel_id) {
-        Ok(()) => JsonResponse::Ok(json!("")),
+        Ok(()) => {
+            // channels of lists are not subscribed to one by one,
+            // so this is where we learn that the channel is used
+            websub::subscribe(&db, &websub_callback_url, &channel);
+            JsonResponse::Ok(json!(""))
+        }
         Err(err) => {
             eprintln!(
                 "Failed to add channel {} to list {}: {:?}",
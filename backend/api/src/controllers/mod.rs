@@ -2,6 +2,8 @@ pub mod auth;
 pub mod channels;
 pub mod common;
 pub mod lists;
+pub mod newsletters;
 pub mod settings;
 pub mod subscriptions;
 pub mod updates;
+pub mod websub;
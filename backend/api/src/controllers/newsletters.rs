@@ -0,0 +1,90 @@
+use super::common::*;
+use lib_db as db;
+use rocket::request::Request;
+use rocket::response::{self, content, Responder, Response};
+use rocket::Rocket;
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/newsletters", routes![show])
+}
+
+// the html of a newsletter comes from whoever sent the mail. the sandbox
+// prevents scripts and forms, while images and styles are still shown.
+static CONTENT_SECURITY_POLICY: &str =
+    "sandbox allow-popups; default-src 'none'; img-src * data:; style-src * 'unsafe-inline'";
+
+struct WebView(String);
+
+impl<'r> Responder<'r> for WebView {
+    fn respond_to(self, req: &Request) -> response::Result<'r> {
+        Response::build_from(content::Html(self.0).respond_to(req)?)
+            .raw_header("Content-Security-Policy", CONTENT_SECURITY_POLICY)
+            .raw_header("Referrer-Policy", "no-referrer")
+            .ok()
+    }
+}
+
+// not protected, because the links are sent in the digests. the
+// web_id is random, so the mails of others cannot be guessed
+#[get("/<web_id>")]
+fn show(db: DigesterDbConn, web_id: String) -> Result<WebView, JsonResponse> {
+    match db::newsletter_mails_find_by_web_id(&db, &web_id) {
+        Err(err) => {
+            eprintln!("Failed to fetch mail {}: {}", web_id, err);
+            Err(JsonResponse::InternalServerError)
+        }
+        Ok(None) => Err(JsonResponse::NotFound),
+        Ok(Some(mail)) => Ok(WebView(render(mail))),
+    }
+}
+
+fn render(mail: db::NewsletterMail) -> String {
+    match mail.html {
+        Some(html) => html,
+        None => format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{}</title></head>\
+             <body><pre style=\"white-space: pre-wrap\">{}</pre></body></html>",
+            escape(&mail.subject),
+            escape(&mail.text.unwrap_or_default())
+        ),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn mail(html: Option<&str>, text: Option<&str>) -> db::NewsletterMail {
+        db::NewsletterMail {
+            id: 1,
+            web_id: "abc".into(),
+            channel_id: 2,
+            sender: "news@example.com".into(),
+            subject: "Issue <1>".into(),
+            html: html.map(|h| h.into()),
+            text: text.map(|t| t.into()),
+            received: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn render_prefers_html() {
+        let rendered = render(mail(Some("<p>Hi</p>"), Some("Hi")));
+        assert_eq!("<p>Hi</p>", rendered);
+    }
+
+    #[test]
+    fn render_escapes_text() {
+        let rendered = render(mail(None, Some("a < b & <script>")));
+        assert!(rendered.contains("<title>Issue &lt;1&gt;</title>"));
+        assert!(rendered.contains(">a &lt; b &amp; &lt;script&gt;</pre>"));
+    }
+}
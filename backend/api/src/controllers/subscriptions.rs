@@ -3,13 +3,14 @@ use lib_messaging as messaging;
 
 use super::super::subscriptions;
 use super::common::*;
+use super::websub::{self, WebsubCallbackUrl};
 use chrono::naive::NaiveTime;
 use chrono::Utc;
 use chrono_tz::Tz;
 use db::{ChannelType, Day, Frequency, Timezone};
 use either::{Left, Right};
 use messaging::sendgrid::pending_subscriptions;
-use rocket::Rocket;
+use rocket::{Rocket, State};
 use rocket_contrib::json::{Json, JsonValue};
 use std::str::FromStr;
 use uuid::Uuid;
@@ -68,6 +69,9 @@ struct Subscription {
     frequency: Frequency,
     day: Option<Day>,
     time: NaiveTime,
+    /// names of the subscribed channels we stopped getting updates from
+    #[serde(rename = "deadChannels")]
+    dead_channels: Vec<String>,
 }
 
 impl Into<JsonResponse> for Subscription {
@@ -102,16 +106,26 @@ impl Into<JsonResponse> for Vec<Subscription> {
 
 impl Subscription {
     fn from_db_channel(sub: db::Subscription, chan: db::Channel) -> Subscription {
+        let dead_channels = if chan.dead_since.is_some() {
+            vec![chan.name.clone()]
+        } else {
+            Vec::new()
+        };
         Subscription {
             id: sub.id,
             name: chan.name.clone(),
             channel_type: SearchChannelType::from_db(chan.channel_type),
             channel_id: chan.id,
             summary: None,
-            channel_link: Some(chan.link),
+            channel_link: if chan.is_hidden_from(sub.user_id) {
+                None
+            } else {
+                Some(chan.link)
+            },
             frequency: sub.frequency,
             day: sub.day,
             time: sub.time,
+            dead_channels,
         }
     }
     fn from_db_list(
@@ -129,6 +143,11 @@ impl Subscription {
             frequency: sub.frequency,
             day: sub.day,
             time: sub.time,
+            dead_channels: channels
+                .into_iter()
+                .filter(|c| c.dead_since.is_some())
+                .map(|c| c.name)
+                .collect(),
         }
     }
 }
@@ -136,8 +155,19 @@ impl Subscription {
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
 enum SearchChannelType {
     GithubRelease,
+    GithubTag,
     RssFeed,
     Twitter,
+    Mastodon,
+    CratesIo,
+    Npm,
+    PyPi,
+    ForgeRelease,
+    OciImage,
+    LinkAggregator,
+    Reddit,
+    WebPage,
+    Newsletter,
     List,
 }
 
@@ -145,8 +175,19 @@ impl SearchChannelType {
     fn from_db(channel_type: db::ChannelType) -> SearchChannelType {
         match channel_type {
             ChannelType::GithubRelease => SearchChannelType::GithubRelease,
+            ChannelType::GithubTag => SearchChannelType::GithubTag,
             ChannelType::RssFeed => SearchChannelType::RssFeed,
             ChannelType::Twitter => SearchChannelType::Twitter,
+            ChannelType::Mastodon => SearchChannelType::Mastodon,
+            ChannelType::CratesIo => SearchChannelType::CratesIo,
+            ChannelType::Npm => SearchChannelType::Npm,
+            ChannelType::PyPi => SearchChannelType::PyPi,
+            ChannelType::ForgeRelease => SearchChannelType::ForgeRelease,
+            ChannelType::OciImage => SearchChannelType::OciImage,
+            ChannelType::LinkAggregator => SearchChannelType::LinkAggregator,
+            ChannelType::Reddit => SearchChannelType::Reddit,
+            ChannelType::WebPage => SearchChannelType::WebPage,
+            ChannelType::Newsletter => SearchChannelType::Newsletter,
         }
     }
 }
@@ -186,6 +227,7 @@ fn list(session: Protected, db: DigesterDbConn) -> JsonResponse {
 fn add(
     session: Protected,
     db: DigesterDbConn,
+    websub_callback_url: State<WebsubCallbackUrl>,
     new_subscription: Json<NewSubscription>,
 ) -> JsonResponse {
     use subscriptions::AddError::*;
@@ -195,8 +237,19 @@ fn add(
             subscriptions::SearchChannelType::List(new_subscription.channel_id)
         }
         SearchChannelType::GithubRelease
+        | SearchChannelType::GithubTag
         | SearchChannelType::RssFeed
-        | SearchChannelType::Twitter => {
+        | SearchChannelType::Twitter
+        | SearchChannelType::Mastodon
+        | SearchChannelType::CratesIo
+        | SearchChannelType::Npm
+        | SearchChannelType::PyPi
+        | SearchChannelType::ForgeRelease
+        | SearchChannelType::OciImage
+        | SearchChannelType::LinkAggregator
+        | SearchChannelType::Reddit
+        | SearchChannelType::WebPage
+        | SearchChannelType::Newsletter => {
             subscriptions::SearchChannelType::Channel(new_subscription.channel_id)
         }
     };
@@ -215,7 +268,10 @@ fn add(
         Err(NotFound(msg)) => JsonResponse::BadRequest(msg),
         Err(AlreadyExists) => JsonResponse::BadRequest("Subscription already exists".into()),
         Ok((sub, c_or_l)) => match c_or_l {
-            Left(channel) => Subscription::from_db_channel(sub, channel).into(),
+            Left(channel) => {
+                websub::subscribe(&db, &websub_callback_url, &channel);
+                Subscription::from_db_channel(sub, channel).into()
+            }
             Right((list, channels)) => Subscription::from_db_list(sub, list, channels).into(),
         },
     }
@@ -463,7 +519,24 @@ fn update(
             Err(_) => return JsonResponse::InternalServerError,
         };
 
-    match update_subscription(&db, updated_subscription.0, original) {
+    // switching the frequency may also switch the channel (eg. for reddit)
+    let channel_or_list = match channel_or_list {
+        Left(channel) => match subscriptions::channel_for_frequency(
+            &db,
+            channel,
+            &updated_subscription.frequency,
+        ) {
+            Ok(channel) => Left(channel),
+            Err(err) => {
+                eprintln!("Failed to find channel for subscription {}: {}", id, err);
+                return JsonResponse::InternalServerError;
+            }
+        },
+        Right(list) => Right(list),
+    };
+    let channel_id = channel_or_list.as_ref().left().map(|c| c.id);
+
+    match update_subscription(&db, updated_subscription.0, original, channel_id) {
         Ok(sub) => match channel_or_list {
             Left(channel) => Subscription::from_db_channel(sub, channel).into(),
             Right(list) => {
@@ -504,8 +577,10 @@ fn update_subscription(
     conn: &DigesterDbConn,
     updated: UpdatedSubscription,
     original: db::Subscription,
+    channel_id: Option<i32>,
 ) -> Result<db::Subscription, String> {
     let db_sub = db::Subscription {
+        channel_id: channel_id.or(original.channel_id),
         frequency: updated.frequency,
         day: updated.day,
         time: updated.time,
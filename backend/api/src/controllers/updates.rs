@@ -18,13 +18,34 @@ struct Update {
     #[serde(rename = "channelType")]
     channel_type: ChannelType,
     #[serde(rename = "channelLink")]
-    channel_link: String,
+    channel_link: Option<String>,
     title: String,
     url: String,
+    #[serde(rename = "externalUrl")]
+    external_url: Option<String>,
+    summary: Option<String>,
+    author: Option<String>,
+    categories: Vec<String>,
+    #[serde(rename = "imageUrl")]
+    image_url: Option<String>,
+    enclosures: Vec<Enclosure>,
+    #[serde(rename = "readingMinutes")]
+    reading_minutes: Option<i32>,
+    #[serde(rename = "durationSeconds")]
+    duration_seconds: Option<i32>,
+    episode: Option<i32>,
     // in the user's timezone
     published: DateTime<Tz>,
 }
 
+#[derive(Serialize, Clone, Debug, PartialEq)]
+struct Enclosure {
+    url: String,
+    #[serde(rename = "mimeType")]
+    mime_type: Option<String>,
+    length: Option<i64>,
+}
+
 impl Into<JsonResponse> for Update {
     fn into(self) -> JsonResponse {
         match serde_json::to_value(self.clone()) {
@@ -53,13 +74,40 @@ impl Into<JsonResponse> for Vec<Update> {
 }
 
 impl Update {
-    fn from_db(update: db::Update, chan: db::Channel, user_tz: Tz) -> Update {
+    fn from_db(
+        update: db::Update,
+        enclosures: Vec<db::UpdateEnclosure>,
+        chan: db::Channel,
+        user_id: db::UserId,
+        user_tz: Tz,
+    ) -> Update {
         Update {
             channel_name: chan.name.clone(),
             channel_type: chan.channel_type,
-            channel_link: chan.link,
+            // eg. the newsletter of someone else in a list
+            channel_link: if chan.is_hidden_from(Some(user_id)) {
+                None
+            } else {
+                Some(chan.link)
+            },
             title: update.title,
             url: update.url,
+            external_url: update.external_url,
+            summary: update.summary,
+            author: update.author,
+            categories: update.categories,
+            image_url: update.image_url,
+            enclosures: enclosures
+                .into_iter()
+                .map(|e| Enclosure {
+                    url: e.url,
+                    mime_type: e.mime_type,
+                    length: e.length,
+                })
+                .collect(),
+            reading_minutes: update.reading_minutes,
+            duration_seconds: update.duration_seconds,
+            episode: update.episode,
             published: utc_to_tz(update.published, user_tz),
         }
     }
@@ -91,11 +139,24 @@ fn list(session: Protected, db: DigesterDbConn, offset: u32, limit: u32) -> Json
             );
             JsonResponse::InternalServerError
         }
-        Ok(subs) => subs
-            .into_iter()
-            .map(|(update, chan)| Update::from_db(update, chan, timezone))
-            .collect::<Vec<Update>>()
-            .into(),
+        Ok(updates) => {
+            let update_ids: Vec<i64> = updates.iter().map(|(u, _)| u.id).collect();
+            let mut enclosures = match db::updates_find_enclosures(&db, &update_ids) {
+                Ok(enclosures) => enclosures,
+                Err(err) => {
+                    eprintln!("Failed to find enclosures: {}", err);
+                    return JsonResponse::InternalServerError;
+                }
+            };
+            updates
+                .into_iter()
+                .map(|(update, chan)| {
+                    let update_enclosures = enclosures.remove(&update.id).unwrap_or_default();
+                    Update::from_db(update, update_enclosures, chan, user.id, timezone)
+                })
+                .collect::<Vec<Update>>()
+                .into()
+        }
     }
 }
 
@@ -0,0 +1,150 @@
+use lib_db as db;
+use lib_fetcher::websub;
+
+use super::common::*;
+use rocket::http::ContentType;
+use rocket::request::{self, FromRequest, LenientForm, Request};
+use rocket::response::content;
+use rocket::{Data, Outcome, Rocket};
+use std::io::Read;
+
+pub fn mount(rocket: Rocket) -> Rocket {
+    rocket.mount("/websub", routes![verify, receive])
+}
+
+/// the url under which the hubs reach the routes below (eg.
+/// https://api.digester.app/websub). websub is disabled if this is not set.
+pub struct WebsubCallbackUrl(pub Option<String>);
+
+// feeds are small, anything bigger is most likely not a feed
+const MAX_CONTENT_LENGTH: u64 = 5 * 1024 * 1024;
+
+#[derive(FromForm)]
+struct Verification {
+    #[form(field = "hub.mode")]
+    mode: String,
+    #[form(field = "hub.topic")]
+    topic: String,
+    #[form(field = "hub.challenge")]
+    challenge: Option<String>,
+    #[form(field = "hub.lease_seconds")]
+    lease_seconds: Option<i64>,
+}
+
+// the signature is optional, because content without a signature
+// must be acknowledged like any other and is then ignored
+struct HubSignature(Option<String>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for HubSignature {
+    type Error = ();
+
+    fn from_request(req: &'a Request<'r>) -> request::Outcome<HubSignature, ()> {
+        let signature = req.headers().get_one("X-Hub-Signature");
+        Outcome::Success(HubSignature(signature.map(|s| s.to_owned())))
+    }
+}
+
+// the hub calls this to verify that we asked for the
+// subscription (or to tell us that it was denied)
+#[get("/<channel_id>?<verification..>")]
+fn verify(
+    db: DigesterDbConn,
+    channel_id: i32,
+    verification: LenientForm<Verification>,
+) -> Result<content::Plain<String>, JsonResponse> {
+    match (verification.mode.as_str(), &verification.challenge) {
+        ("subscribe", Some(challenge)) => {
+            match websub::verify_intent(
+                &db,
+                channel_id,
+                &verification.topic,
+                verification.lease_seconds,
+            ) {
+                Ok(true) => Ok(content::Plain(challenge.clone())),
+                Ok(false) => Err(JsonResponse::NotFound),
+                Err(err) => {
+                    eprintln!(
+                        "Failed to verify websub subscription of channel {}: {}",
+                        channel_id, err
+                    );
+                    Err(JsonResponse::InternalServerError)
+                }
+            }
+        }
+        ("denied", _) => match websub::denied(&db, channel_id, &verification.topic) {
+            Ok(true) => Ok(content::Plain(String::new())),
+            Ok(false) => Err(JsonResponse::NotFound),
+            Err(err) => {
+                eprintln!(
+                    "Failed to handle denied websub subscription of channel {}: {}",
+                    channel_id, err
+                );
+                Err(JsonResponse::InternalServerError)
+            }
+        },
+        // we never unsubscribe, but let leases expire instead
+        _ => Err(JsonResponse::NotFound),
+    }
+}
+
+#[post("/<channel_id>", data = "<body>")]
+fn receive(
+    db: DigesterDbConn,
+    channel_id: i32,
+    content_type: Option<&ContentType>,
+    signature: HubSignature,
+    body: Data,
+) -> Result<content::Plain<String>, JsonResponse> {
+    let mut bytes = Vec::new();
+    if let Err(err) = body.open().take(MAX_CONTENT_LENGTH).read_to_end(&mut bytes) {
+        eprintln!(
+            "Failed to read pushed content for channel {}: {:?}",
+            channel_id, err
+        );
+        return Err(JsonResponse::BadRequest("Failed to read body".into()));
+    }
+
+    let content_type = content_type.map(|ct| ct.to_string()).unwrap_or_default();
+    match websub::receive(
+        &db,
+        channel_id,
+        &content_type,
+        &bytes,
+        signature.0.as_deref(),
+    ) {
+        Ok(true) => Ok(content::Plain(String::new())),
+        // tells the hub to stop pushing, eg. after the channel was deleted
+        Ok(false) => Err(JsonResponse::Gone),
+        Err(err) => {
+            eprintln!(
+                "Failed to receive pushed content for channel {}: {}",
+                channel_id, err
+            );
+            Err(JsonResponse::InternalServerError)
+        }
+    }
+}
+
+/// subscribes to the hub of the channel, if it has one. failures are only logged,
+/// because the channel is still polled like any other
+pub fn subscribe(db: &DigesterDbConn, callback_url: &WebsubCallbackUrl, channel: &db::Channel) {
+    let callback_url = match &callback_url.0 {
+        Some(callback_url) => callback_url,
+        None => return,
+    };
+    match db::websub_subscriptions_find(db, channel.id) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            if let Err(err) = websub::subscribe(db, channel, callback_url) {
+                eprintln!(
+                    "Failed to subscribe to hub of channel {}: {}",
+                    channel.id, err
+                )
+            }
+        }
+        Err(err) => eprintln!(
+            "Failed to find websub subscription of channel {}: {}",
+            channel.id, err
+        ),
+    }
+}
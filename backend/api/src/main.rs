@@ -14,9 +14,11 @@ use api::controllers::auth;
 use api::controllers::channels;
 use api::controllers::common::*;
 use api::controllers::lists;
+use api::controllers::newsletters;
 use api::controllers::settings;
 use api::controllers::subscriptions;
 use api::controllers::updates;
+use api::controllers::websub;
 
 use lib_messaging::sendgrid;
 
@@ -93,6 +95,11 @@ fn main() -> Result<(), String> {
             access_token_secret: read_env("TWITTER_ACCESS_TOKEN_SECRET"),
         }))
     });
+
+    let websub_callback_url = AdHoc::on_attach("Websub Callback Url", |rocket| {
+        let callback_url = env::var("WEBSUB_CALLBACK_URL").ok();
+        Ok(rocket.manage(websub::WebsubCallbackUrl(callback_url)))
+    });
     let mut rocket = rocket::ignite();
 
     let cors_fairing = cors_fairing(rocket.config())?;
@@ -103,6 +110,8 @@ fn main() -> Result<(), String> {
     rocket = channels::mount(rocket);
     rocket = settings::mount(rocket);
     rocket = updates::mount(rocket);
+    rocket = newsletters::mount(rocket);
+    rocket = websub::mount(rocket);
 
     rocket
         .attach(DigesterDbConn::fairing())
@@ -113,6 +122,7 @@ fn main() -> Result<(), String> {
         .attach(github_api_token)
         .attach(twitter_tokens)
         .attach(sendgrid_api_key)
+        .attach(websub_callback_url)
         .register(catchers![
             internal_error,
             not_found,
@@ -3,6 +3,7 @@ use chrono::naive::NaiveTime;
 use db::{ChannelType, Day, Frequency};
 use diesel::pg::PgConnection;
 use either::{Either, Left, Right};
+use lib_channels as channels;
 use lib_db as db;
 
 pub fn delete(db: &PgConnection, id: i32) -> Result<(), String> {
@@ -77,7 +78,10 @@ pub fn add(
         }
         SearchChannelType::Channel(channel_id) => {
             let channel = db::channels_find_by_id(&db, channel_id)
-                .map_err(|_| NotFound("channel does not exist".into()))?;
+                .ok()
+                .filter(|c| !c.is_hidden_from(Some(identity.user_id)))
+                .ok_or_else(|| NotFound("channel does not exist".into()))?;
+            let channel = channel_for_frequency(&db, channel, &frequency).map_err(Unknown)?;
 
             let new_subscription = db::NewSubscription {
                 email: identity.email.clone(),
@@ -100,6 +104,43 @@ pub fn add(
     }
 }
 
+// a subreddit's top posts depend on the period, so if the user asked for
+// the top posts, we subscribe to the channel of the subreddit that matches
+// the frequency of the subscription. new posts don't depend on it.
+pub fn channel_for_frequency(
+    db: &PgConnection,
+    channel: db::Channel,
+    frequency: &Frequency,
+) -> Result<db::Channel, String> {
+    use channels::reddit::{channel_mode, channel_with_mode, Mode};
+
+    if channel.channel_type != ChannelType::Reddit {
+        return Ok(channel);
+    }
+
+    let mode = match (channel_mode(&channel.ext_id)?, frequency) {
+        (Mode::New, _) => return Ok(channel),
+        (_, Frequency::Daily) => Mode::TopOfDay,
+        (_, Frequency::Weekly) => Mode::TopOfWeek,
+    };
+    let info = channel_with_mode(&channel.ext_id, mode)?;
+    if info.ext_id == channel.ext_id {
+        return Ok(channel);
+    }
+    db::channels_insert_if_not_exists(
+        db,
+        db::NewChannel {
+            ext_id: info.ext_id,
+            channel_type: ChannelType::Reddit,
+            name: info.name,
+            link: info.link,
+            verified: info.verified,
+            podcast: info.podcast,
+            owner_id: None,
+        },
+    )
+}
+
 pub fn add_default_subscription(db: &PgConnection, user_id: UserId, email: &str) {
     let channel_name = "digesterapp";
     let channel = match db::channels_find_by_ext_id(&db, ChannelType::Twitter, channel_name) {
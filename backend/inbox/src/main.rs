@@ -0,0 +1,185 @@
+use channels::newsletter;
+use channels::summary;
+use lib_channels as channels;
+use lib_db as db;
+use std::io::{self, BufReader, Read};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use structopt::StructOpt;
+use uuid::Uuid;
+
+mod mime;
+mod smtp;
+
+use smtp::Mailbox;
+
+#[derive(Debug, StructOpt)]
+struct Opt {
+    /// address on which to accept smtp connections
+    #[structopt(long, default_value = "127.0.0.1:2525")]
+    listen: String,
+    #[structopt(long)]
+    database_uri: String,
+    /// base url of the web view in the api (eg. https://api.digester.app/newsletters)
+    #[structopt(long)]
+    web_view_url: String,
+}
+
+struct Inbox<'a> {
+    db: &'a db::Connection,
+    web_view_url: String,
+}
+
+fn main() -> Result<(), String> {
+    let opt = Opt::from_args();
+    let db_conn = db::connection_from_str(&opt.database_uri)?;
+    let mut inbox = Inbox {
+        db: &db_conn,
+        web_view_url: opt.web_view_url.trim_end_matches('/').to_owned(),
+    };
+
+    let listener = TcpListener::bind(&opt.listen)
+        .map_err(|err| format!("Failed to listen on {}: {:?}", opt.listen, err))?;
+    println!("Receiving newsletters on {}", opt.listen);
+
+    // newsletters are few and far between, so there is
+    // no need to handle more than one connection at a time
+    for stream in listener.incoming() {
+        match stream {
+            Err(err) => eprintln!("Failed to accept connection: {:?}", err),
+            Ok(stream) => {
+                if let Err(err) = handle(stream, &mut inbox) {
+                    eprintln!("Failed to handle smtp session: {}", err)
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// the read timeout only limits how long a client may be silent. one that sends
+// a byte every now and then would still keep every other sender waiting.
+const MAX_SESSION: Duration = Duration::from_secs(5 * 60);
+
+struct Deadline<R> {
+    inner: R,
+    until: Instant,
+}
+
+impl<R: Read> Read for Deadline<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if Instant::now() > self.until {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Session took too long",
+            ));
+        }
+        self.inner.read(buf)
+    }
+}
+
+fn handle(stream: TcpStream, inbox: &mut Inbox) -> Result<(), String> {
+    let timeout = Some(Duration::from_secs(60));
+    stream
+        .set_read_timeout(timeout)
+        .and_then(|_| stream.set_write_timeout(timeout))
+        .map_err(|err| format!("Failed to set timeouts: {:?}", err))?;
+    let reader = stream
+        .try_clone()
+        .map_err(|err| format!("Failed to clone stream: {:?}", err))?;
+    let reader = Deadline {
+        inner: reader,
+        until: Instant::now() + MAX_SESSION,
+    };
+    smtp::serve(
+        newsletter::INBOX_DOMAIN,
+        BufReader::new(reader),
+        stream,
+        inbox,
+    )
+    .map_err(|err| format!("{:?}", err))
+}
+
+impl Inbox<'_> {
+    fn find_channel(&self, recipient: &str) -> Result<Option<db::Channel>, String> {
+        match newsletter::recipient_token(recipient) {
+            None => Ok(None),
+            Some(token) => {
+                db::channels_find_by_ext_id_opt(&self.db.0, db::ChannelType::Newsletter, &token)
+            }
+        }
+    }
+}
+
+impl Mailbox for Inbox<'_> {
+    fn accepts(&self, recipient: &str) -> Result<bool, String> {
+        self.find_channel(recipient).map(|c| c.is_some())
+    }
+
+    fn deliver(&mut self, recipients: &[String], data: &[u8]) -> Result<(), String> {
+        let mail = mime::parse(data)?;
+        // the date header is set by the sender and can be anything,
+        // so we use the time we received it instead
+        let received = Utc::now();
+
+        for recipient in recipients {
+            let channel = match self.find_channel(recipient)? {
+                Some(channel) => channel,
+                None => continue,
+            };
+
+            let web_id = Uuid::new_v4().to_simple().to_string();
+            let title = if mail.subject.trim().is_empty() {
+                "(no subject)".to_owned()
+            } else {
+                mail.subject.trim().to_owned()
+            };
+            // a mail that the sender delivers again keeps its message id
+            let guid = mail.message_id.clone().unwrap_or_else(|| web_id.clone());
+            let update = db::NewUpdate {
+                channel_id: channel.id,
+                ext_id: mail.message_id.clone(),
+                title,
+                url: format!("{}/{}", self.web_view_url, web_id),
+                external_url: None,
+                published: received,
+                summary: mail
+                    .text
+                    .as_deref()
+                    .or_else(|| mail.html.as_deref())
+                    .and_then(summary::summarize),
+                author: mime::sender_name(&mail.from),
+                categories: Vec::new(),
+                image_url: None,
+                guid,
+                reading_minutes: None,
+                duration_seconds: None,
+                episode: None,
+            };
+            let new_mail = db::NewNewsletterMail {
+                web_id,
+                channel_id: channel.id,
+                sender: mail.from.clone(),
+                subject: mail.subject.clone(),
+                html: mail.html.clone(),
+                text: mail.text.clone(),
+                received,
+            };
+            match db::newsletter_mails_insert(self.db, &new_mail, &update) {
+                Ok(()) => println!("Received mail for channel {}", channel.id),
+                Err(db::InsertError::Duplicate) => {
+                    println!("Ignoring duplicate mail for channel {}", channel.id)
+                }
+                Err(db::InsertError::Unknown(err)) => {
+                    return Err(format!(
+                        "Failed to insert mail for channel {}: {:?}",
+                        channel.id, err
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+
+/// The parts of a mail that end up in the digest and the web view
+#[derive(Debug, PartialEq)]
+pub struct Mail {
+    pub from: String,
+    pub subject: String,
+    pub message_id: Option<String>,
+    pub html: Option<String>,
+    pub text: Option<String>,
+}
+
+struct ContentType {
+    mime: String,
+    params: HashMap<String, String>,
+}
+
+pub fn parse(raw: &[u8]) -> Result<Mail, String> {
+    let (headers, body) = split_part(raw);
+    if headers.is_empty() {
+        return Err("Mail has no headers".into());
+    }
+
+    let mut mail = Mail {
+        from: header(&headers, "from")
+            .map(|v| decode_words(&v))
+            .unwrap_or_default(),
+        subject: header(&headers, "subject")
+            .map(|v| decode_words(&v))
+            .unwrap_or_default(),
+        message_id: header(&headers, "message-id").map(|v| v.trim().to_owned()),
+        html: None,
+        text: None,
+    };
+    collect_bodies(&headers, body, 0, &mut mail);
+
+    if mail.html.is_none() && mail.text.is_none() {
+        Err("Mail has neither a text nor an html body".into())
+    } else {
+        Ok(mail)
+    }
+}
+
+/// The name of the sender (eg. 'News' of 'News <news@example.com>')
+/// or the address if the mail doesn't say
+pub fn sender_name(from: &str) -> Option<String> {
+    let name = match from.find('<') {
+        Some(idx) => &from[..idx],
+        None => from,
+    };
+    let name = name.trim().trim_matches('"').trim();
+    if !name.is_empty() {
+        return Some(name.to_owned());
+    }
+    let address = from
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .trim();
+    if address.is_empty() {
+        None
+    } else {
+        Some(address.to_owned())
+    }
+}
+
+// real mails don't nest their parts deeper than a few levels
+const MAX_DEPTH: usize = 10;
+
+// walks the tree of parts and takes the first text and html body.
+// attachments are ignored, since we can only show text in the web view
+fn collect_bodies(headers: &[(String, String)], body: &[u8], depth: usize, mail: &mut Mail) {
+    let content_type = header(headers, "content-type")
+        .map(|v| parse_content_type(&v))
+        .unwrap_or_else(|| ContentType {
+            mime: "text/plain".into(),
+            params: HashMap::new(),
+        });
+
+    let is_attachment = header(headers, "content-disposition")
+        .map(|v| v.trim().to_ascii_lowercase().starts_with("attachment"))
+        .unwrap_or(false);
+    if is_attachment {
+        return;
+    }
+
+    if content_type.mime.starts_with("multipart/") {
+        // deeper parts are skipped instead of overflowing the stack
+        if depth >= MAX_DEPTH {
+            return;
+        }
+        if let Some(boundary) = content_type.params.get("boundary") {
+            for part in split_multipart(body, boundary) {
+                let (part_headers, part_body) = split_part(part);
+                collect_bodies(&part_headers, part_body, depth + 1, mail);
+            }
+        }
+        return;
+    }
+
+    let target = match content_type.mime.as_str() {
+        "text/html" => &mut mail.html,
+        "text/plain" => &mut mail.text,
+        _ => return,
+    };
+    if target.is_none() {
+        let encoding = header(headers, "content-transfer-encoding")
+            .map(|v| v.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+        let bytes = decode_transfer(&encoding, body);
+        let charset = content_type
+            .params
+            .get("charset")
+            .map(|c| c.to_ascii_lowercase())
+            .unwrap_or_default();
+        *target = Some(decode_charset(&charset, &bytes));
+    }
+}
+
+// splits a message or part into its unfolded headers and the body
+fn split_part(raw: &[u8]) -> (Vec<(String, String)>, &[u8]) {
+    let (head, body) = match find(raw, b"\r\n\r\n", 0) {
+        Some(idx) => (&raw[..idx], &raw[idx + 4..]),
+        None => match find(raw, b"\n\n", 0) {
+            Some(idx) => (&raw[..idx], &raw[idx + 2..]),
+            None => (raw, &raw[raw.len()..]),
+        },
+    };
+
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in String::from_utf8_lossy(head).lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some(idx) = line.find(':') {
+            headers.push((
+                line[..idx].trim().to_ascii_lowercase(),
+                line[idx + 1..].trim().to_owned(),
+            ));
+        }
+    }
+    (headers, body)
+}
+
+fn header(headers: &[(String, String)], name: &str) -> Option<String> {
+    headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.clone())
+}
+
+fn parse_content_type(value: &str) -> ContentType {
+    let mut segments = value.split(';');
+    let mime = segments.next().unwrap_or("").trim().to_ascii_lowercase();
+    let params = segments
+        .filter_map(|segment| {
+            let idx = segment.find('=')?;
+            let key = segment[..idx].trim().to_ascii_lowercase();
+            let value = segment[idx + 1..].trim().trim_matches('"').to_owned();
+            Some((key, value))
+        })
+        .collect();
+    ContentType { mime, params }
+}
+
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+
+    // only delimiters at the beginning of a line count
+    let mut starts = Vec::new();
+    let mut from = 0;
+    while let Some(idx) = find(body, &delimiter, from) {
+        if idx == 0 || body[idx - 1] == b'\n' {
+            starts.push(idx);
+        }
+        from = idx + delimiter.len();
+    }
+
+    for window in starts.windows(2) {
+        let (start, end) = (window[0] + delimiter.len(), window[1]);
+        if body[start..].starts_with(b"--") {
+            break;
+        }
+        parts.push(trim_line_breaks(&body[start..end]));
+    }
+    parts
+}
+
+// removes the line break after the delimiter and the one before the next delimiter
+fn trim_line_breaks(part: &[u8]) -> &[u8] {
+    let mut part = part;
+    for prefix in &[&b"\r\n"[..], &b"\n"[..]] {
+        if part.starts_with(prefix) {
+            part = &part[prefix.len()..];
+            break;
+        }
+    }
+    for suffix in &[&b"\r\n"[..], &b"\n"[..]] {
+        if part.ends_with(suffix) {
+            part = &part[..part.len() - suffix.len()];
+            break;
+        }
+    }
+    part
+}
+
+fn decode_transfer(encoding: &str, body: &[u8]) -> Vec<u8> {
+    match encoding {
+        "base64" => {
+            let compact: Vec<u8> = body
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            base64::decode(&compact).unwrap_or_else(|_| body.to_vec())
+        }
+        "quoted-printable" => decode_quoted_printable(body, false),
+        _ => body.to_vec(),
+    }
+}
+
+// in encoded words (rfc2047), underscores represent spaces
+fn decode_quoted_printable(input: &[u8], underscore_is_space: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'=' if input[i + 1..].starts_with(b"\r\n") => i += 3,
+            b'=' if input[i + 1..].starts_with(b"\n") => i += 2,
+            b'=' if i + 2 < input.len() && hex_byte(&input[i + 1..i + 3]).is_some() => {
+                out.extend(hex_byte(&input[i + 1..i + 3]));
+                i += 3;
+            }
+            b'_' if underscore_is_space => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn hex_byte(hex: &[u8]) -> Option<u8> {
+    std::str::from_utf8(hex)
+        .ok()
+        .and_then(|h| u8::from_str_radix(h, 16).ok())
+}
+
+// we only know utf-8 and latin-1. the latter is what most
+// other western charsets are close enough to for a preview
+fn decode_charset(charset: &str, bytes: &[u8]) -> String {
+    match charset {
+        "iso-8859-1" | "iso-8859-15" | "latin1" | "windows-1252" => {
+            bytes.iter().map(|b| *b as char).collect()
+        }
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+// decodes encoded words (eg. =?utf-8?Q?caf=C3=A9?=) in headers. whitespace
+// between two adjacent encoded words is not part of the text
+fn decode_words(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut previous_was_word = false;
+    while let Some(start) = rest.find("=?") {
+        let (before, candidate) = rest.split_at(start);
+        match decode_word(candidate) {
+            Some((decoded, len)) => {
+                if !(previous_was_word && before.trim().is_empty()) {
+                    out.push_str(before);
+                }
+                out.push_str(&decoded);
+                rest = &candidate[len..];
+                previous_was_word = true;
+            }
+            None => {
+                out.push_str(before);
+                out.push_str("=?");
+                rest = &candidate[2..];
+                previous_was_word = false;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// returns the decoded text and the length of the encoded word
+fn decode_word(word: &str) -> Option<(String, usize)> {
+    let inner = &word[2..];
+    let mut fields = inner.splitn(3, '?');
+    let charset = fields.next()?.to_ascii_lowercase();
+    let encoding = fields.next()?.to_ascii_lowercase();
+    let rest = fields.next()?;
+    let end = rest.find("?=")?;
+    let text = &rest[..end];
+
+    let bytes = match encoding.as_str() {
+        "b" => base64::decode(text).ok()?,
+        "q" => decode_quoted_printable(text.as_bytes(), true),
+        _ => return None,
+    };
+    let len = 2 + charset.len() + 1 + encoding.len() + 1 + end + 2;
+    Some((decode_charset(&charset, &bytes), len))
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from > haystack.len() {
+        return None;
+    }
+    haystack[from..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|idx| idx + from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_plain_mail() {
+        let raw = b"From: News <news@example.com>\r\n\
+Subject: Issue #1\r\n\
+Message-ID: <abc@example.com>\r\n\
+\r\n\
+Hello\r\nWorld\r\n";
+        let mail = parse(raw).unwrap();
+        assert_eq!("News <news@example.com>", mail.from);
+        assert_eq!("Issue #1", mail.subject);
+        assert_eq!(Some("<abc@example.com>".into()), mail.message_id);
+        assert_eq!(Some("Hello\r\nWorld\r\n".into()), mail.text);
+        assert_eq!(None, mail.html);
+    }
+
+    #[test]
+    fn sender_name_prefers_display_name() {
+        assert_eq!(Some("News".into()), sender_name("News <news@example.com>"));
+        assert_eq!(
+            Some("The Weekly".into()),
+            sender_name("\"The Weekly\" <weekly@example.com>")
+        );
+        assert_eq!(
+            Some("news@example.com".into()),
+            sender_name("<news@example.com>")
+        );
+        assert_eq!(
+            Some("news@example.com".into()),
+            sender_name("news@example.com")
+        );
+        assert_eq!(None, sender_name("  "));
+    }
+
+    #[test]
+    fn parse_multipart_alternative() {
+        let raw = b"From: news@example.com\r\n\
+Subject: Weekly\r\n\
+Content-Type: multipart/alternative;\r\n boundary=\"XYZ\"\r\n\
+\r\n\
+preamble\r\n\
+--XYZ\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+Caf=C3=A9 is a very long line that was=\r\n wrapped\r\n\
+--XYZ\r\n\
+Content-Type: text/html; charset=utf-8\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+PHA+Q2Fmw6k8L3A+\r\n\
+--XYZ--\r\n\
+epilogue\r\n";
+        let mail = parse(raw).unwrap();
+        assert_eq!(
+            Some("Café is a very long line that was wrapped".into()),
+            mail.text
+        );
+        assert_eq!(Some("<p>Café</p>".into()), mail.html);
+    }
+
+    #[test]
+    fn parse_nested_multipart_ignores_attachments() {
+        let raw = b"Subject: Nested\n\
+Content-Type: multipart/mixed; boundary=outer\n\
+\n\
+--outer\n\
+Content-Type: multipart/alternative; boundary=inner\n\
+\n\
+--inner\n\
+Content-Type: text/html\n\
+\n\
+<b>hi</b>\n\
+--inner--\n\
+--outer\n\
+Content-Type: text/plain\n\
+Content-Disposition: attachment; filename=notes.txt\n\
+\n\
+not the body\n\
+--outer--\n";
+        let mail = parse(raw).unwrap();
+        assert_eq!(Some("<b>hi</b>".into()), mail.html);
+        assert_eq!(None, mail.text);
+    }
+
+    #[test]
+    fn parse_deeply_nested_multipart_fails() {
+        let nested = |levels: usize| {
+            let mut raw = String::from("Subject: Deep\n");
+            for level in 0..levels {
+                raw.push_str(&format!(
+                    "Content-Type: multipart/mixed; boundary=\"b{}.\"\n\n--b{}.\n",
+                    level, level
+                ));
+            }
+            raw.push_str("Content-Type: text/plain\n\ndeep\n");
+            for level in (0..levels).rev() {
+                raw.push_str(&format!("--b{}.--\n", level));
+            }
+            raw
+        };
+        assert_eq!(
+            Some("deep".into()),
+            parse(nested(MAX_DEPTH).as_bytes()).unwrap().text
+        );
+        assert!(parse(nested(MAX_DEPTH + 1).as_bytes()).is_err());
+    }
+
+    #[test]
+    fn parse_latin1_body() {
+        let raw = b"Subject: x\r\nContent-Type: text/plain; charset=ISO-8859-1\r\n\r\nCaf\xe9";
+        assert_eq!(Some("Café".into()), parse(raw).unwrap().text);
+    }
+
+    #[test]
+    fn parse_without_body_fails() {
+        let raw = b"Subject: x\r\nContent-Type: image/png\r\n\r\nabc";
+        assert!(parse(raw).is_err());
+        assert!(parse(b"").is_err());
+    }
+
+    #[test]
+    fn decode_encoded_words() {
+        assert_eq!("Café", decode_words("=?utf-8?Q?Caf=C3=A9?="));
+        assert_eq!("Café", decode_words("=?UTF-8?B?Q2Fmw6k=?="));
+        assert_eq!(
+            "Hello World",
+            decode_words("=?utf-8?q?Hello?= =?utf-8?q?_World?=")
+        );
+        assert_eq!(
+            "News: Café daily",
+            decode_words("News: =?iso-8859-1?q?Caf=E9?= daily")
+        );
+        assert_eq!("=?broken", decode_words("=?broken"));
+    }
+
+    #[test]
+    fn decode_quoted_printable_keeps_invalid_escapes() {
+        assert_eq!(b"a=zz".to_vec(), decode_quoted_printable(b"a=zz", false));
+        assert_eq!(b"a=".to_vec(), decode_quoted_printable(b"a=", false));
+    }
+}
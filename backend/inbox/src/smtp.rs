@@ -0,0 +1,336 @@
+use std::io::{self, BufRead, Write};
+
+const MAX_MESSAGE_SIZE: usize = 10 * 1024 * 1024;
+const MAX_RECIPIENTS: usize = 100;
+
+/// Where the received mails go
+pub trait Mailbox {
+    /// whether mails to this recipient are accepted. errors are
+    /// reported to the client as temporary failures.
+    fn accepts(&self, recipient: &str) -> Result<bool, String>;
+
+    /// stores a received mail for all (accepted) recipients
+    fn deliver(&mut self, recipients: &[String], data: &[u8]) -> Result<(), String>;
+}
+
+#[derive(Default)]
+struct Transaction {
+    from: Option<String>,
+    recipients: Vec<String>,
+}
+
+/// Runs an smtp session until the client quits or disconnects. Only the
+/// subset of smtp that is needed to receive mails from other servers is
+/// implemented (no auth, no relaying, no starttls).
+pub fn serve<R: BufRead, W: Write>(
+    hostname: &str,
+    mut reader: R,
+    mut writer: W,
+    mailbox: &mut dyn Mailbox,
+) -> io::Result<()> {
+    reply(
+        &mut writer,
+        &format!("220 {} ESMTP digester inbox", hostname),
+    )?;
+
+    let mut transaction = Transaction::default();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Ok(());
+        }
+        let command = String::from_utf8_lossy(&line);
+        let command = command.trim_end();
+        let verb = command
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_ascii_uppercase();
+
+        match verb.as_str() {
+            "HELO" => {
+                transaction = Transaction::default();
+                reply(&mut writer, &format!("250 {}", hostname))?
+            }
+            "EHLO" => {
+                transaction = Transaction::default();
+                reply(
+                    &mut writer,
+                    &format!(
+                        "250-{}\r\n250-8BITMIME\r\n250 SIZE {}",
+                        hostname, MAX_MESSAGE_SIZE
+                    ),
+                )?
+            }
+            "MAIL" => match path_argument(command, "MAIL FROM:") {
+                None => reply(&mut writer, "501 Syntax: MAIL FROM:<address>")?,
+                Some(_) if transaction.from.is_some() => {
+                    reply(&mut writer, "503 Nested MAIL command")?
+                }
+                Some(from) => {
+                    transaction.from = Some(from);
+                    reply(&mut writer, "250 OK")?
+                }
+            },
+            "RCPT" => match path_argument(command, "RCPT TO:") {
+                None => reply(&mut writer, "501 Syntax: RCPT TO:<address>")?,
+                Some(_) if transaction.from.is_none() => {
+                    reply(&mut writer, "503 Need MAIL command")?
+                }
+                Some(_) if transaction.recipients.len() >= MAX_RECIPIENTS => {
+                    reply(&mut writer, "452 Too many recipients")?
+                }
+                Some(recipient) => match mailbox.accepts(&recipient) {
+                    Ok(true) => {
+                        transaction.recipients.push(recipient);
+                        reply(&mut writer, "250 OK")?
+                    }
+                    Ok(false) => reply(&mut writer, "550 No such mailbox")?,
+                    Err(err) => {
+                        eprintln!("Failed to check recipient {}: {}", recipient, err);
+                        reply(&mut writer, "451 Temporary failure, try again later")?
+                    }
+                },
+            },
+            "DATA" => {
+                if transaction.recipients.is_empty() {
+                    reply(&mut writer, "503 Need RCPT command")?;
+                    continue;
+                }
+                reply(&mut writer, "354 End data with <CR><LF>.<CR><LF>")?;
+                match read_data(&mut reader)? {
+                    None => reply(&mut writer, "552 Message too large")?,
+                    Some(data) => match mailbox.deliver(&transaction.recipients, &data) {
+                        Ok(()) => reply(&mut writer, "250 OK")?,
+                        Err(err) => {
+                            eprintln!("Failed to deliver mail: {}", err);
+                            reply(&mut writer, "451 Local error in processing")?
+                        }
+                    },
+                }
+                transaction = Transaction::default();
+            }
+            "RSET" => {
+                transaction = Transaction::default();
+                reply(&mut writer, "250 OK")?
+            }
+            "NOOP" => reply(&mut writer, "250 OK")?,
+            "VRFY" => reply(&mut writer, "252 Cannot verify user")?,
+            "QUIT" => return reply(&mut writer, "221 Bye"),
+            _ => reply(&mut writer, "500 Command not recognized")?,
+        }
+    }
+}
+
+fn reply<W: Write>(writer: &mut W, line: &str) -> io::Result<()> {
+    writer.write_all(line.as_bytes())?;
+    writer.write_all(b"\r\n")?;
+    writer.flush()
+}
+
+// extracts the address from eg. 'MAIL FROM:<a@b.com> SIZE=123'
+fn path_argument(command: &str, prefix: &str) -> Option<String> {
+    // not sliced by index, because a character might end after it
+    let matches = command
+        .get(..prefix.len())
+        .map_or(false, |p| p.eq_ignore_ascii_case(prefix));
+    if !matches {
+        return None;
+    }
+    let argument = command[prefix.len()..].trim_start();
+    let path = if argument.starts_with('<') {
+        &argument[1..argument.find('>')?]
+    } else {
+        argument.split_whitespace().next()?
+    };
+    Some(path.to_owned())
+}
+
+// reads the message until the terminating dot and removes the dot-stuffing.
+// returns none if the message is too large, in which case the rest is discarded
+fn read_data<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    let mut too_large = false;
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Connection closed during DATA",
+            ));
+        }
+        if line == b".\r\n" || line == b".\n" {
+            break;
+        }
+        let content = if line.starts_with(b".") {
+            &line[1..]
+        } else {
+            &line[..]
+        };
+        if data.len() + content.len() > MAX_MESSAGE_SIZE {
+            too_large = true;
+        }
+        if !too_large {
+            data.extend_from_slice(content);
+        }
+    }
+    Ok(if too_large { None } else { Some(data) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    struct FakeMailbox {
+        known: Vec<String>,
+        delivered: Vec<(Vec<String>, Vec<u8>)>,
+    }
+
+    impl Mailbox for FakeMailbox {
+        fn accepts(&self, recipient: &str) -> Result<bool, String> {
+            if recipient == "broken@in.example.com" {
+                Err("db is down".into())
+            } else {
+                Ok(self.known.iter().any(|k| k == recipient))
+            }
+        }
+
+        fn deliver(&mut self, recipients: &[String], data: &[u8]) -> Result<(), String> {
+            self.delivered.push((recipients.to_vec(), data.to_vec()));
+            Ok(())
+        }
+    }
+
+    fn session(input: &str) -> (Vec<String>, FakeMailbox) {
+        let mut mailbox = FakeMailbox {
+            known: vec!["news@in.example.com".into()],
+            delivered: Vec::new(),
+        };
+        let mut output = Vec::new();
+        serve(
+            "in.example.com",
+            Cursor::new(input.as_bytes().to_vec()),
+            &mut output,
+            &mut mailbox,
+        )
+        .unwrap();
+        let replies = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|l| l.to_owned())
+            .collect();
+        (replies, mailbox)
+    }
+
+    #[test]
+    fn receive_mail() {
+        let (replies, mailbox) = session(
+            "EHLO client.example.com\r\n\
+MAIL FROM:<sender@example.com> SIZE=100\r\n\
+RCPT TO:<news@in.example.com>\r\n\
+DATA\r\n\
+Subject: Hi\r\n\
+\r\n\
+..leading dot\r\n\
+.\r\n\
+QUIT\r\n",
+        );
+        assert_eq!(
+            vec![
+                "220 in.example.com ESMTP digester inbox",
+                "250-in.example.com",
+                "250-8BITMIME",
+                "250 SIZE 10485760",
+                "250 OK",
+                "250 OK",
+                "354 End data with <CR><LF>.<CR><LF>",
+                "250 OK",
+                "221 Bye",
+            ],
+            replies
+        );
+        assert_eq!(1, mailbox.delivered.len());
+        let (recipients, data) = &mailbox.delivered[0];
+        assert_eq!(&vec!["news@in.example.com".to_owned()], recipients);
+        assert_eq!(
+            "Subject: Hi\r\n\r\n.leading dot\r\n",
+            String::from_utf8_lossy(data)
+        );
+    }
+
+    #[test]
+    fn reject_unknown_recipient() {
+        let (replies, mailbox) = session(
+            "HELO client\r\n\
+MAIL FROM:<sender@example.com>\r\n\
+RCPT TO:<other@in.example.com>\r\n\
+RCPT TO:<broken@in.example.com>\r\n\
+DATA\r\n\
+QUIT\r\n",
+        );
+        assert_eq!(
+            vec![
+                "220 in.example.com ESMTP digester inbox",
+                "250 in.example.com",
+                "250 OK",
+                "550 No such mailbox",
+                "451 Temporary failure, try again later",
+                "503 Need RCPT command",
+                "221 Bye",
+            ],
+            replies
+        );
+        assert!(mailbox.delivered.is_empty());
+    }
+
+    #[test]
+    fn enforce_command_order() {
+        let (replies, _) = session(
+            "RCPT TO:<news@in.example.com>\r\n\
+MAIL FROM:<a@example.com>\r\n\
+MAIL FROM:<b@example.com>\r\n\
+RSET\r\n\
+MAIL FROM:<b@example.com>\r\n\
+mail from:\r\n\
+STARTTLS\r\n",
+        );
+        assert_eq!(
+            vec![
+                "220 in.example.com ESMTP digester inbox",
+                "503 Need MAIL command",
+                "250 OK",
+                "503 Nested MAIL command",
+                "250 OK",
+                "250 OK",
+                "501 Syntax: MAIL FROM:<address>",
+                "500 Command not recognized",
+            ],
+            replies
+        );
+    }
+
+    #[test]
+    fn path_arguments() {
+        assert_eq!(
+            Some("a@b.com".into()),
+            path_argument("mail from: <a@b.com> BODY=8BITMIME", "MAIL FROM:")
+        );
+        assert_eq!(
+            Some("a@b.com".into()),
+            path_argument("RCPT TO:a@b.com", "RCPT TO:")
+        );
+        assert_eq!(Some("".into()), path_argument("MAIL FROM:<>", "MAIL FROM:"));
+        assert_eq!(None, path_argument("MAIL FROM:<a@b.com", "MAIL FROM:"));
+        assert_eq!(None, path_argument("MAIL", "MAIL FROM:"));
+        assert_eq!(None, path_argument("MAIL FROMé<a@b.com>", "MAIL FROM:"));
+    }
+
+    #[test]
+    fn data_without_terminator_is_an_error() {
+        let mut reader = Cursor::new(b"Subject: x\r\n".to_vec());
+        assert!(read_data(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,334 @@
+// Extracts the article from the page an update links to, for feeds that only
+// publish titles and links. This is a much simplified version of what browsers
+// do in their reader modes (see https://github.com/mozilla/readability).
+
+use super::channel::Metadata;
+use super::http;
+use super::summary;
+use kuchiki::traits::*;
+use kuchiki::NodeRef;
+use reqwest::header;
+use std::io::Read;
+use url::Url;
+
+/// anything beyond this is cut off. articles are much smaller than that,
+/// but the pages they are in often contain lots of inlined scripts.
+const MAX_PAGE_BYTES: u64 = 2 * 1024 * 1024;
+
+/// how fast an average adult reads
+const WORDS_PER_MINUTE: usize = 200;
+
+/// shorter paragraphs are rather captions, bylines or buttons
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+/// pages with less text than this are not articles (eg. a video)
+const MIN_ARTICLE_CHARS: usize = 250;
+
+// elements that are never part of the article
+const NOISE: &str = "script, style, noscript, iframe, svg, form, nav, header, footer, aside";
+
+// class names and ids of containers that rarely contain the article
+const UNLIKELY: [&str; 9] = [
+    "comment",
+    "sidebar",
+    "footer",
+    "related",
+    "share",
+    "social",
+    "promo",
+    "newsletter",
+    "nav",
+];
+
+/// The main content of a web page
+#[derive(Debug, PartialEq)]
+pub struct Article {
+    /// plain text, one paragraph per line
+    pub text: String,
+    /// the image that represents the article (eg. the one shown when sharing it)
+    pub image_url: Option<String>,
+    /// how long it takes to read the text, but at least a minute
+    pub reading_minutes: i32,
+}
+
+/// Fetches the page and extracts the article. Returns none if
+/// the page is not html or doesn't seem to contain an article.
+pub fn fetch(url: &str) -> Result<Option<Article>, String> {
+    let resp = http::get_response(url, "text/html")
+        .map_err(|err| format!("Failed to fetch article {}: {:?}", url, err))?;
+    let page_url = resp.url().clone();
+    let is_html = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.contains("html"))
+        .unwrap_or(false);
+    if !is_html {
+        return Ok(None);
+    }
+
+    let mut bytes = Vec::new();
+    resp.take(MAX_PAGE_BYTES)
+        .read_to_end(&mut bytes)
+        .map_err(|err| format!("Failed to read article {}: {:?}", url, err))?;
+    Ok(extract(&page_url, &String::from_utf8_lossy(&bytes)))
+}
+
+/// Extracts the article from the html of the page at the url
+pub fn extract(page_url: &Url, html: &str) -> Option<Article> {
+    let document = kuchiki::parse_html().one(html);
+    // the meta tags are in the head, so we look at them before removing anything
+    let lead_image = meta_image(&document, page_url);
+
+    if let Ok(noise) = document.select(NOISE) {
+        for node in noise.collect::<Vec<_>>() {
+            node.as_node().detach();
+        }
+    }
+
+    let content = find_content(&document)?;
+    let paragraphs = paragraphs(&content);
+    let text = paragraphs.join("\n");
+    if text.chars().count() < MIN_ARTICLE_CHARS {
+        return None;
+    }
+
+    let words = text.split_whitespace().count();
+    let reading_minutes = ((words + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE).max(1);
+    Some(Article {
+        text,
+        image_url: lead_image.or_else(|| first_image(&content, page_url)),
+        reading_minutes: reading_minutes as i32,
+    })
+}
+
+/// Adds what we found in the article to the metadata of an update. The summary
+/// is replaced, because that of excerpt-only feeds is rarely more than a teaser.
+pub fn enrich(metadata: &mut Metadata, article: Article) {
+    if let Some(excerpt) = summary::summarize(&escape(&article.text)) {
+        metadata.summary = Some(excerpt);
+    }
+    if metadata.image_url.is_none() {
+        metadata.image_url = article.image_url;
+    }
+    metadata.reading_minutes = Some(article.reading_minutes);
+}
+
+// the element with the most paragraphs of text in it. like readability, we
+// also count them for the grandparent, since articles are often split into
+// sections. this favors the element that contains the whole article.
+fn find_content(document: &NodeRef) -> Option<NodeRef> {
+    let mut scores: Vec<(NodeRef, f64)> = Vec::new();
+    let mut add_score = |node: NodeRef, score: f64| {
+        match scores.iter_mut().find(|(n, _)| *n == node) {
+            Some((_, existing)) => *existing += score,
+            None => scores.push((node, score)),
+        };
+    };
+
+    for paragraph in document.select("p, pre").ok()? {
+        let length = collapse(&paragraph.text_contents()).chars().count();
+        if length < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+        let parent = match paragraph.as_node().parent() {
+            Some(parent) => parent,
+            None => continue,
+        };
+        if let Some(grandparent) = parent.parent() {
+            add_score(grandparent, length as f64 / 2.0);
+        }
+        add_score(parent, length as f64);
+    }
+
+    scores
+        .into_iter()
+        .map(|(node, score)| {
+            let score = if is_unlikely(&node) {
+                score / 4.0
+            } else {
+                score
+            };
+            (node, score)
+        })
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|(node, _)| node)
+}
+
+fn is_unlikely(node: &NodeRef) -> bool {
+    let element = match node.as_element() {
+        Some(element) => element,
+        None => return false,
+    };
+    let attributes = element.attributes.borrow();
+    let names = format!(
+        "{} {}",
+        attributes.get("class").unwrap_or(""),
+        attributes.get("id").unwrap_or("")
+    )
+    .to_ascii_lowercase();
+    UNLIKELY.iter().any(|unlikely| names.contains(unlikely))
+}
+
+fn paragraphs(content: &NodeRef) -> Vec<String> {
+    match content.select("p, pre, li, blockquote, h2, h3") {
+        Err(_) => Vec::new(),
+        Ok(elements) => elements
+            .filter(|e| !is_nested(e.as_node(), content))
+            .map(|e| collapse(&e.text_contents()))
+            .filter(|text| !text.is_empty())
+            .collect(),
+    }
+}
+
+// a paragraph in a quote or list is already part of the text of that
+fn is_nested(node: &NodeRef, content: &NodeRef) -> bool {
+    for ancestor in node.ancestors() {
+        if ancestor == *content {
+            return false;
+        }
+        if let Some(element) = ancestor.as_element() {
+            if ["p", "pre", "li", "blockquote"].contains(&&*element.name.local) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+fn meta_image(document: &NodeRef, page_url: &Url) -> Option<String> {
+    let selector = "meta[property='og:image'], meta[name='twitter:image']";
+    let meta = document.select_first(selector).ok()?;
+    let attributes = meta.attributes.borrow();
+    absolute(page_url, attributes.get("content")?)
+}
+
+fn first_image(content: &NodeRef, page_url: &Url) -> Option<String> {
+    let img = content.select_first("img[src]").ok()?;
+    let attributes = img.attributes.borrow();
+    absolute(page_url, attributes.get("src")?)
+}
+
+fn absolute(page_url: &Url, link: &str) -> Option<String> {
+    page_url
+        .join(link.trim())
+        .ok()
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .map(|url| url.to_string())
+}
+
+fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// summarize expects html
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::mock;
+
+    const PAGE: &str = r#"<html>
+<head>
+  <title>A post</title>
+  <meta property="og:image" content="/images/lead.png">
+  <script>var tracking = "lots of text that is not part of the article at all";</script>
+</head>
+<body>
+  <nav><p>Home | About | A very long navigation that looks like a paragraph</p></nav>
+  <div id="main">
+    <article>
+      <h1>A post</h1>
+      <p>This is the first paragraph of the article, and it is long enough to count.</p>
+      <section>
+        <p>The second paragraph talks about <a href="/x">something else</a> &amp; more.</p>
+        <blockquote><p>Someone said something very wise that is worth quoting here.</p></blockquote>
+      </section>
+      <p>The third paragraph concludes the article with a few final words.</p>
+      <img src="/images/inline.png">
+    </article>
+    <div class="comments">
+      <p>First comment! This is a comment that is long enough to be a paragraph.</p>
+    </div>
+  </div>
+</body>
+</html>"#;
+
+    #[test]
+    fn extract_article() {
+        let url = Url::parse("https://example.com/blog/post").unwrap();
+        let article = extract(&url, PAGE).unwrap();
+        assert_eq!(
+            Article {
+                text: [
+                    "This is the first paragraph of the article, and it is long enough to count.",
+                    "The second paragraph talks about something else & more.",
+                    "Someone said something very wise that is worth quoting here.",
+                    "The third paragraph concludes the article with a few final words.",
+                ]
+                .join("\n"),
+                image_url: Some("https://example.com/images/lead.png".into()),
+                reading_minutes: 1,
+            },
+            article
+        );
+    }
+
+    #[test]
+    fn extract_reading_time_and_inline_image() {
+        let url = Url::parse("https://example.com/post").unwrap();
+        let paragraph = format!("<p>{}</p>", "word ".repeat(100));
+        let html = format!(
+            "<body><div><img src=\"a.png\">{}</div></body>",
+            paragraph.repeat(5)
+        );
+        let article = extract(&url, &html).unwrap();
+        assert_eq!(3, article.reading_minutes);
+        assert_eq!(Some("https://example.com/a.png".into()), article.image_url);
+    }
+
+    #[test]
+    fn no_article_in_page_without_text() {
+        let url = Url::parse("https://example.com/video").unwrap();
+        let html =
+            "<body><video src=\"a.mp4\"></video><p>Watch the video above, it's great!</p></body>";
+        assert_eq!(None, extract(&url, html));
+    }
+
+    #[test]
+    fn enrich_metadata() {
+        let mut metadata = Metadata {
+            summary: Some("Continue reading...".into()),
+            image_url: Some("https://example.com/feed.png".into()),
+            ..Metadata::default()
+        };
+        let article = Article {
+            text: "Fish & Chips\nand <more>".into(),
+            image_url: Some("https://example.com/article.png".into()),
+            reading_minutes: 4,
+        };
+        enrich(&mut metadata, article);
+        assert_eq!(
+            Metadata {
+                summary: Some("Fish & Chips and <more>".into()),
+                image_url: Some("https://example.com/feed.png".into()),
+                reading_minutes: Some(4),
+                ..Metadata::default()
+            },
+            metadata
+        );
+    }
+
+    #[test]
+    fn fetch_only_html() {
+        let _m = mock("GET", "/article/image.png")
+            .with_header("content-type", "image/png")
+            .with_body("not an article")
+            .create();
+        let url = format!("{}/article/image.png", mockito::server_url());
+        assert_eq!(Ok(None), fetch(&url));
+    }
+}
@@ -1,10 +1,23 @@
 #![deny(missing_docs)]
 
 use chrono::{DateTime, Utc};
+use core::time::Duration;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use url::Url;
 
+use super::forge_release::ForgeRelease;
 use super::github_release::GithubRelease;
+use super::github_tag::GithubTag;
+use super::link_aggregator::LinkAggregator;
+use super::mastodon::Mastodon;
+use super::newsletter::Newsletter;
+use super::oci_image::OciImage;
+use super::package_release::{CratesIo, Npm, PyPi};
+use super::reddit::Reddit;
 use super::rss::Rss;
 use super::twitter::Twitter;
+use super::web_page::WebPage;
 
 /// A channel type is a certain source that we can pull updates
 /// from
@@ -12,10 +25,32 @@ use super::twitter::Twitter;
 pub enum ChannelType {
     /// Fetch releases from a specific github repository
     GithubRelease,
+    /// Fetch tags from a specific github repository
+    GithubTag,
     /// Fetch new items from an rss feed
     RssFeed,
     /// Fetch tweets
     Twitter,
+    /// Fetch public statuses of a mastodon account
+    Mastodon,
+    /// Fetch releases of a crate from crates.io
+    CratesIo,
+    /// Fetch releases of a package from npm
+    Npm,
+    /// Fetch releases of a package from pypi
+    PyPi,
+    /// Fetch releases from a repository on gitlab or gitea
+    ForgeRelease,
+    /// Fetch tags of a container image from an oci registry
+    OciImage,
+    /// Fetch stories from hacker news or lobsters
+    LinkAggregator,
+    /// Fetch posts of a subreddit
+    Reddit,
+    /// Fetch changes of a web page without a feed
+    WebPage,
+    /// Receive newsletters by email
+    Newsletter,
 }
 
 /// An update is a new thing from a channel. In RSS terminology, this
@@ -30,10 +65,48 @@ pub struct Update {
     /// The url points to some place where the user can read more about
     /// this. For a blog post, this would be a link to the post.
     pub url: String,
+    /// Where the update itself points to, if the url is only a place
+    /// to discuss it (eg. the article a reddit post links to).
+    pub external_url: Option<String>,
+    /// Details that make the update more than a bare title in the digest.
+    /// Most channels only have some of them, if any.
+    pub metadata: Metadata,
     /// The datetime when the update was published in the channel.
     pub published: DateTime<Utc>,
 }
 
+/// What we know about an update besides its title and links
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+    /// A short plain text summary (see `summary::summarize`)
+    pub summary: Option<String>,
+    /// Name of whoever wrote the update
+    pub author: Option<String>,
+    /// Tags or categories (eg. hashtags of a tweet)
+    pub categories: Vec<String>,
+    /// An image that illustrates the update
+    pub image_url: Option<String>,
+    /// Media files attached to the update (eg. the audio of a podcast episode)
+    pub enclosures: Vec<Enclosure>,
+    /// How many minutes it takes to read the article (see `article::fetch`)
+    pub reading_minutes: Option<i32>,
+    /// How long the episode of a podcast is
+    pub duration_seconds: Option<i32>,
+    /// Number of the episode of a podcast
+    pub episode: Option<i32>,
+}
+
+/// A media file attached to an update
+#[derive(Clone, Debug, PartialEq)]
+pub struct Enclosure {
+    /// where the file can be downloaded
+    pub url: String,
+    /// eg. audio/mpeg
+    pub mime_type: Option<String>,
+    /// size in bytes
+    pub length: Option<i64>,
+}
+
 impl Update {
     /// Returns true if this update was published before we last fetched
     /// this channel. Passing `None` for last_fetched means we never fetched
@@ -41,6 +114,81 @@ impl Update {
     pub fn is_old(&self, last_fetched: Option<DateTime<Utc>>) -> bool {
         last_fetched.map(|lf| lf > self.published).unwrap_or(false)
     }
+
+    /// Identifies the update within its channel, so we recognize it again
+    /// when the channel returns it with an edited title or date. This is the
+    /// ext_id (eg. the guid of an rss item) or, if the channel doesn't have
+    /// one, a fingerprint of the canonicalized url and the title.
+    pub fn guid(&self) -> String {
+        match &self.ext_id {
+            Some(ext_id) => ext_id.clone(),
+            None => fingerprint(&self.url, &self.title),
+        }
+    }
+}
+
+fn fingerprint(url: &str, title: &str) -> String {
+    let title = title.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let input = format!("{}\n{}", canonicalize_url(url), title);
+    let hash: String = Sha256::digest(input.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256:{}", hash)
+}
+
+// the same article is often linked in slightly different ways (eg. http vs
+// https or with tracking parameters), which shouldn't make it a new update
+fn canonicalize_url(url: &str) -> String {
+    let parsed = match Url::parse(url.trim()) {
+        Ok(parsed) => parsed,
+        Err(_) => return url.trim().to_owned(),
+    };
+    let host = parsed.host_str().unwrap_or("");
+    let host = host.trim_start_matches("www.");
+    let port = parsed.port().map(|p| format!(":{}", p)).unwrap_or_default();
+    let path = parsed.path().trim_end_matches('/');
+    let query: Vec<String> = parsed
+        .query_pairs()
+        .filter(|(key, _)| !is_tracking_parameter(key))
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    if query.is_empty() {
+        format!("{}{}{}", host, port, path)
+    } else {
+        format!("{}{}{}?{}", host, port, path, query.join("&"))
+    }
+}
+
+fn is_tracking_parameter(key: &str) -> bool {
+    key.starts_with("utm_") || key == "fbclid" || key == "gclid"
+}
+
+/// Validators of the last response of a channel. They allow the server
+/// to tell us that nothing changed since (http conditional get).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CacheValidators {
+    /// the etag header, sent back as If-None-Match
+    pub etag: Option<String>,
+    /// the last-modified header, sent back as If-Modified-Since
+    pub last_modified: Option<String>,
+    /// how long the channel says it doesn't change (eg. Cache-Control max-age
+    /// or the ttl of an rss feed). this is not sent back, but only a hint for
+    /// when to fetch the channel again.
+    pub max_age: Option<Duration>,
+}
+
+/// The result of fetching a channel with validators
+#[derive(Debug, PartialEq)]
+pub enum ConditionalUpdates {
+    /// Nothing changed since the response the validators belong to
+    NotModified,
+    /// The updates along with the validators of this response
+    Modified(Vec<Update>, CacheValidators),
+    /// The channel permanently moved to the given location (eg. a feed
+    /// that redirects with 301). The updates and validators are the same
+    /// as with Modified, so nothing is lost if the new location is broken.
+    Moved(String, Vec<Update>, CacheValidators),
 }
 
 /// The failure cases when validating a channel
@@ -54,6 +202,45 @@ pub enum SearchError {
     TechnicalError(String),
 }
 
+/// The failure cases when fetching the updates of a channel. The fetcher
+/// decides by them whether the channel is broken or only temporarily down.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FetchError {
+    /// The server answered with a status we can't handle (eg. 404 or 503)
+    Status(u16, String),
+    /// The host doesn't exist (anymore)
+    NotFound(String),
+    /// The operation took too long
+    Timeout(String),
+    /// We may not fetch it (eg. the robots.txt disallows it) or not yet
+    Refused(String),
+    /// We got something else than updates (eg. it's not a feed anymore)
+    Invalid(String),
+    /// Something else went wrong
+    Other(String),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use FetchError::*;
+        match self {
+            Status(_, msg)
+            | NotFound(msg)
+            | Timeout(msg)
+            | Refused(msg)
+            | Invalid(msg)
+            | Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+// most channels only have a message
+impl From<String> for FetchError {
+    fn from(msg: String) -> FetchError {
+        FetchError::Other(msg)
+    }
+}
+
 /// a specific type for channel names that have passed sanitization
 /// TODO: make this a generic associated type (problem was to
 ///       type the factory function in fetcher.rs)
@@ -74,6 +261,9 @@ pub struct ChannelInfo {
     /// if true, this channel is a verified account. currently only used
     /// for twitter
     pub verified: bool,
+    /// if true, this channel is a podcast (ie. its updates are episodes
+    /// with audio files). currently only used for rss
+    pub podcast: bool,
 }
 
 /// A channel is a thing where we can pull updates from.
@@ -114,18 +304,47 @@ pub trait Channel {
     /// This is to ensure that if digester dives for a couple of days, we'd still
     /// get all updates for the weekly digests.
     fn fetch_updates(&self, ext_id: &str) -> Result<Vec<Update>, String>;
+
+    /// Like fetch_updates, but the channel may skip downloading the updates
+    /// if they didn't change since the response with the given validators.
+    ///
+    /// The default implementation always fetches all updates, because most
+    /// apis we use don't support conditional requests.
+    fn fetch_updates_if_modified(
+        &self,
+        ext_id: &str,
+        _validators: &CacheValidators,
+    ) -> Result<ConditionalUpdates, FetchError> {
+        self.fetch_updates(ext_id)
+            .map(|updates| ConditionalUpdates::Modified(updates, CacheValidators::default()))
+            .map_err(FetchError::from)
+    }
 }
 
 /// Factory function to create the channel based on the channel type.
 pub fn factory<'a>(
     channel_type: &ChannelType,
     github_release: &'a GithubRelease,
+    github_tag: &'a GithubTag,
+    forge_release: &'a ForgeRelease,
     twitter: &'a Twitter,
+    web_page: &'a WebPage<'a>,
 ) -> &'a dyn Channel {
     match channel_type {
         ChannelType::GithubRelease => github_release,
+        ChannelType::GithubTag => github_tag,
         ChannelType::RssFeed => &Rss {},
         ChannelType::Twitter => twitter,
+        ChannelType::Mastodon => &Mastodon {},
+        ChannelType::CratesIo => &CratesIo {},
+        ChannelType::Npm => &Npm {},
+        ChannelType::PyPi => &PyPi {},
+        ChannelType::ForgeRelease => forge_release,
+        ChannelType::OciImage => &OciImage {},
+        ChannelType::LinkAggregator => &LinkAggregator {},
+        ChannelType::Reddit => &Reddit {},
+        ChannelType::WebPage => web_page,
+        ChannelType::Newsletter => &Newsletter {},
     }
 }
 
@@ -140,6 +359,8 @@ mod tests {
             ext_id: None,
             title: "T".into(),
             url: "U".into(),
+            external_url: None,
+            metadata: Metadata::default(),
             published: Utc::now(),
         };
         assert_eq!(false, u.is_old(None))
@@ -151,6 +372,8 @@ mod tests {
             ext_id: None,
             title: "T".into(),
             url: "U".into(),
+            external_url: None,
+            metadata: Metadata::default(),
             published: Utc::now(),
         };
         assert_eq!(
@@ -165,8 +388,47 @@ mod tests {
             ext_id: None,
             title: "T".into(),
             url: "U".into(),
+            external_url: None,
+            metadata: Metadata::default(),
             published: Utc.ymd(1990, 10, 10).and_hms(1, 1, 1),
         };
         assert_eq!(true, u.is_old(Some(Utc::now())))
     }
+
+    #[test]
+    fn guid_is_ext_id() {
+        let u = Update {
+            ext_id: Some("urn:uuid:1225c695".into()),
+            title: "T".into(),
+            url: "https://example.com/a".into(),
+            external_url: None,
+            metadata: Metadata::default(),
+            published: Utc::now(),
+        };
+        assert_eq!("urn:uuid:1225c695", u.guid())
+    }
+
+    #[test]
+    fn guid_falls_back_to_fingerprint() {
+        let fp = |url: &str, title: &str| {
+            Update {
+                ext_id: None,
+                title: title.into(),
+                url: url.into(),
+                external_url: None,
+                metadata: Metadata::default(),
+                published: Utc::now(),
+            }
+            .guid()
+        };
+        let guid = fp("https://example.com/post/", "A Post");
+        assert!(guid.starts_with("sha256:"));
+        assert_eq!(guid, fp("http://www.example.com/post#comments", "A  Post "));
+        assert_eq!(
+            guid,
+            fp("https://example.com/post?utm_source=rss", "A Post")
+        );
+        assert_ne!(guid, fp("https://example.com/post?id=2", "A Post"));
+        assert_ne!(guid, fp("https://example.com/post", "Another Post"));
+    }
 }
@@ -0,0 +1,539 @@
+use super::channel::*;
+use super::http::{self, HttpError};
+use super::summary::summarize_markdown;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::IpAddr;
+use url::Url;
+
+/// Follows the releases of a repository on a self-hostable forge
+/// like gitlab or gitea (and its fork forgejo).
+#[derive(Clone)]
+pub struct ForgeRelease {
+    // api tokens by host (eg. gitlab.com). public
+    // repositories can be accessed without a token
+    tokens: HashMap<String, String>,
+}
+
+impl ForgeRelease {
+    pub fn new(tokens: HashMap<String, String>) -> ForgeRelease {
+        ForgeRelease { tokens }
+    }
+
+    fn token(&self, repo: &ForgeRepository) -> Option<&str> {
+        self.tokens.get(&repo.host).map(|t| t.as_str())
+    }
+}
+
+/// The apis we know how to talk to
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Flavor {
+    GitLab,
+    Gitea,
+}
+
+impl Flavor {
+    fn parse(flavor: &str) -> Result<Self, String> {
+        match flavor {
+            "gitlab" => Ok(Flavor::GitLab),
+            "gitea" => Ok(Flavor::Gitea),
+            other => Err(format!("Unknown forge: {}", other)),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Flavor::GitLab => "gitlab",
+            Flavor::Gitea => "gitea",
+        }
+    }
+}
+
+/// A repository on some host. The path is owner/repository, but
+/// gitlab allows nesting groups (eg. group/subgroup/project)
+#[derive(PartialEq, Debug)]
+pub struct ForgeRepository {
+    host: String,
+    path: String,
+}
+
+impl ForgeRepository {
+    fn unsafe_parse(name: &str) -> Self {
+        Self::parse(name).unwrap()
+    }
+
+    fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim().trim_end_matches('/');
+        let with_scheme = if name.starts_with("https://") || name.starts_with("http://") {
+            name.to_owned()
+        } else {
+            format!("https://{}", name)
+        };
+        let url =
+            Url::parse(&with_scheme).map_err(|err| format!("Invalid url '{}': {}", name, err))?;
+
+        let host = match url.host_str() {
+            None => return Err("missing host".into()),
+            Some(host) if host.parse::<IpAddr>().is_ok() => return Err("cannot be ip".into()),
+            Some(host) if !host.contains('.') => return Err("missing tld".into()),
+            Some(host) => host.to_owned(),
+        };
+        if url.port().is_some() {
+            return Err("cannot have port".into());
+        }
+
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|s| s.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_else(Vec::new);
+        let valid_segment = |s: &&str| {
+            s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        };
+        if segments.len() < 2 {
+            Err("Repository must have format host/owner/repository".into())
+        } else if !segments.iter().all(valid_segment) {
+            Err(format!("Invalid repository: {}", segments.join("/")))
+        } else {
+            Ok(ForgeRepository {
+                host,
+                path: segments.join("/"),
+            })
+        }
+    }
+
+    fn to_url(&self) -> String {
+        format!("https://{}/{}", self.host, self.path)
+    }
+
+    fn api_base(&self) -> String {
+        format!("https://{}", self.host)
+    }
+}
+
+impl Display for ForgeRepository {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.host, self.path)
+    }
+}
+
+impl From<SanitizedName> for ForgeRepository {
+    fn from(name: SanitizedName) -> Self {
+        ForgeRepository::unsafe_parse(&name.0)
+    }
+}
+
+impl Into<SanitizedName> for ForgeRepository {
+    fn into(self) -> SanitizedName {
+        SanitizedName(self.to_string())
+    }
+}
+
+// the channel's ext_id, which remembers which api the host speaks (eg.
+// gitlab:gitlab.com/group/project), so we don't have to guess every time
+fn format_ext_id(flavor: Flavor, repo: &ForgeRepository) -> String {
+    format!("{}:{}", flavor.as_str(), repo)
+}
+
+fn parse_ext_id(ext_id: &str) -> Result<(Flavor, ForgeRepository), String> {
+    let idx = ext_id
+        .find(':')
+        .ok_or_else(|| format!("Missing forge in ext_id: {}", ext_id))?;
+    let flavor = Flavor::parse(&ext_id[..idx])?;
+    let repo = ForgeRepository::parse(&ext_id[idx + 1..])?;
+    Ok((flavor, repo))
+}
+
+fn auth_header(flavor: Flavor, token: Option<&str>) -> Option<(&'static str, String)> {
+    token.map(|token| match flavor {
+        Flavor::GitLab => ("PRIVATE-TOKEN", token.to_owned()),
+        Flavor::Gitea => ("Authorization", format!("token {}", token)),
+    })
+}
+
+fn get_json<T: serde::de::DeserializeOwned>(
+    url: &str,
+    flavor: Flavor,
+    token: Option<&str>,
+) -> Result<T, HttpError> {
+    match auth_header(flavor, token) {
+        Some((name, value)) => http::get_json_with_headers(url, &[(name, &value)]),
+        None => http::get_json(url),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct GitLabProjectResponse {
+    path_with_namespace: String,
+    web_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct GitLabReleaseResponse {
+    name: Option<String>,
+    tag_name: String,
+    created_at: String,
+    released_at: Option<String>,
+    #[serde(default)]
+    upcoming_release: bool,
+    description: Option<String>,
+    author: Option<GitLabAuthorResponse>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GitLabAuthorResponse {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct GiteaRepoResponse {
+    full_name: String,
+    html_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct GiteaReleaseResponse {
+    name: Option<String>,
+    tag_name: String,
+    html_url: String,
+    draft: bool,
+    published_at: Option<String>,
+    created_at: String,
+    body: Option<String>,
+    author: Option<GiteaAuthorResponse>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GiteaAuthorResponse {
+    login: String,
+}
+
+fn gitlab_project_url(base_url: &str, repo: &ForgeRepository) -> String {
+    // gitlab accepts the url encoded path instead of the numeric id
+    let mut url = Url::parse(base_url).expect("base url must be valid");
+    url.path_segments_mut()
+        .expect("base url must not be cannot-be-a-base")
+        .pop_if_empty()
+        .extend(&["api", "v4", "projects", &repo.path]);
+    url.to_string()
+}
+
+fn gitea_repo_url(base_url: &str, repo: &ForgeRepository) -> String {
+    format!("{}/api/v1/repos/{}", base_url, repo.path)
+}
+
+fn gitlab_search(
+    base_url: &str,
+    repo: &ForgeRepository,
+    token: Option<&str>,
+) -> Result<ChannelInfo, HttpError> {
+    let url = gitlab_project_url(base_url, repo);
+    let project: GitLabProjectResponse = get_json(&url, Flavor::GitLab, token)?;
+    let found = ForgeRepository {
+        host: repo.host.clone(),
+        path: project.path_with_namespace,
+    };
+    Ok(ChannelInfo {
+        name: found.path.clone(),
+        link: project.web_url,
+        ext_id: format_ext_id(Flavor::GitLab, &found),
+        verified: false,
+        podcast: false,
+    })
+}
+
+fn gitea_search(
+    base_url: &str,
+    repo: &ForgeRepository,
+    token: Option<&str>,
+) -> Result<ChannelInfo, HttpError> {
+    let url = gitea_repo_url(base_url, repo);
+    let gitea_repo: GiteaRepoResponse = get_json(&url, Flavor::Gitea, token)?;
+    let found = ForgeRepository {
+        host: repo.host.clone(),
+        path: gitea_repo.full_name,
+    };
+    Ok(ChannelInfo {
+        name: found.path.clone(),
+        link: gitea_repo.html_url,
+        ext_id: format_ext_id(Flavor::Gitea, &found),
+        verified: false,
+        podcast: false,
+    })
+}
+
+// we don't know which software runs on the host, so we just try both. gitea
+// only has owner/repository, while gitlab also allows nested groups
+fn search_forges(
+    base_url: &str,
+    repo: &ForgeRepository,
+    token: Option<&str>,
+) -> Result<Vec<ChannelInfo>, HttpError> {
+    if repo.path.matches('/').count() == 1 {
+        match gitea_search(base_url, repo, token) {
+            Ok(channel) => return Ok(vec![channel]),
+            Err(HttpError::NotFound(_)) => (),
+            Err(err) => println!("Failed to search gitea for {}: {:?}", repo, err),
+        }
+    }
+    gitlab_search(base_url, repo, token).map(|channel| vec![channel])
+}
+
+fn gitlab_releases(
+    base_url: &str,
+    repo: &ForgeRepository,
+    token: Option<&str>,
+) -> Result<Vec<Update>, String> {
+    let url = format!("{}/releases", gitlab_project_url(base_url, repo));
+    let releases: Vec<GitLabReleaseResponse> = get_json(&url, Flavor::GitLab, token)
+        .map_err(|err| format!("Failed to fetch releases of {}: {:?}", repo, err))?;
+
+    let mut updates = Vec::with_capacity(releases.len());
+    for release in releases {
+        if release.upcoming_release {
+            continue;
+        }
+        let published = release.released_at.as_ref().unwrap_or(&release.created_at);
+        updates.push(Update {
+            ext_id: Some(release.tag_name.clone()),
+            url: format!("{}/-/releases/{}", repo.to_url(), release.tag_name),
+            external_url: None,
+            metadata: Metadata {
+                summary: release.description.as_deref().and_then(summarize_markdown),
+                author: release.author.map(|a| a.name),
+                ..Metadata::default()
+            },
+            published: parse_rfc3339(published)?,
+            title: release_title(release.name, release.tag_name),
+        });
+    }
+    Ok(updates)
+}
+
+fn gitea_releases(
+    base_url: &str,
+    repo: &ForgeRepository,
+    token: Option<&str>,
+) -> Result<Vec<Update>, String> {
+    let url = format!("{}/releases", gitea_repo_url(base_url, repo));
+    let releases: Vec<GiteaReleaseResponse> = get_json(&url, Flavor::Gitea, token)
+        .map_err(|err| format!("Failed to fetch releases of {}: {:?}", repo, err))?;
+
+    let mut updates = Vec::with_capacity(releases.len());
+    for release in releases {
+        // only visible with a token, but we don't want them either way
+        if release.draft {
+            continue;
+        }
+        let published = release.published_at.as_ref().unwrap_or(&release.created_at);
+        updates.push(Update {
+            ext_id: Some(release.tag_name.clone()),
+            url: release.html_url,
+            external_url: None,
+            metadata: Metadata {
+                summary: release.body.as_deref().and_then(summarize_markdown),
+                author: release.author.map(|a| a.login),
+                ..Metadata::default()
+            },
+            published: parse_rfc3339(published)?,
+            title: release_title(release.name, release.tag_name),
+        });
+    }
+    Ok(updates)
+}
+
+// same as for github: the name is optional, but the tag is not
+fn release_title(name: Option<String>, tag_name: String) -> String {
+    name.map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty())
+        .unwrap_or(tag_name)
+}
+
+fn parse_rfc3339(date: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(date)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|err| format!("Failed to parse {} as rfc3339: {:?}", date, err))
+}
+
+impl Channel for ForgeRelease {
+    fn sanitize(&self, name: &str) -> Result<SanitizedName, String> {
+        ForgeRepository::parse(name).map(|r| r.into())
+    }
+
+    fn search(&self, name: SanitizedName) -> Result<Vec<ChannelInfo>, SearchError> {
+        let repo = ForgeRepository::from(name);
+        search_forges(&repo.api_base(), &repo, self.token(&repo)).map_err(|err| err.into())
+    }
+
+    fn fetch_updates(&self, ext_id: &str) -> Result<Vec<Update>, String> {
+        let (flavor, repo) = parse_ext_id(ext_id)?;
+        let token = self.token(&repo);
+        match flavor {
+            Flavor::GitLab => gitlab_releases(&repo.api_base(), &repo, token),
+            Flavor::Gitea => gitea_releases(&repo.api_base(), &repo, token),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use mockito::{mock, Matcher};
+
+    fn repo(host: &str, path: &str) -> ForgeRepository {
+        ForgeRepository {
+            host: host.into(),
+            path: path.into(),
+        }
+    }
+
+    #[test]
+    fn parse_repository() {
+        assert_eq!(
+            Ok(repo("gitlab.com", "group/project")),
+            ForgeRepository::parse("gitlab.com/group/project")
+        );
+        assert_eq!(
+            Ok(repo("gitlab.com", "group/subgroup/project")),
+            ForgeRepository::parse("https://gitlab.com/group/subgroup/project/")
+        );
+        assert_eq!(
+            Ok(repo("codeberg.org", "forgejo/forgejo")),
+            ForgeRepository::parse("codeberg.org/forgejo/forgejo")
+        );
+    }
+
+    #[test]
+    fn parse_invalid_repository() {
+        assert_eq!(true, ForgeRepository::parse("gitlab.com/project").is_err());
+        assert_eq!(true, ForgeRepository::parse("localhost/a/b").is_err());
+        assert_eq!(true, ForgeRepository::parse("127.0.0.1/a/b").is_err());
+        assert_eq!(true, ForgeRepository::parse("git.ch:8080/a/b").is_err());
+        assert_eq!(true, ForgeRepository::parse("git.ch/a/b'c").is_err());
+    }
+
+    #[test]
+    fn ext_id_roundtrip() {
+        let ext_id = format_ext_id(Flavor::GitLab, &repo("gitlab.com", "a/b/c"));
+        assert_eq!("gitlab:gitlab.com/a/b/c", ext_id);
+        assert_eq!(
+            Ok((Flavor::GitLab, repo("gitlab.com", "a/b/c"))),
+            parse_ext_id(&ext_id)
+        );
+        assert_eq!(true, parse_ext_id("gitlab.com/a/b").is_err());
+    }
+
+    #[test]
+    fn search_falls_back_to_gitlab() {
+        let _gitea = mock("GET", "/api/v1/repos/inkscape/inkscape")
+            .with_status(404)
+            .create();
+        let _gitlab = mock("GET", "/api/v4/projects/inkscape%2Finkscape")
+            .match_header("PRIVATE-TOKEN", "secret")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id":3472737,"path_with_namespace":"inkscape/inkscape","web_url":"https://gitlab.com/inkscape/inkscape"}"#)
+            .create();
+
+        let channels = search_forges(
+            &mockito::server_url(),
+            &repo("gitlab.com", "inkscape/inkscape"),
+            Some("secret"),
+        )
+        .expect("Failed to search");
+        assert_eq!(
+            vec![ChannelInfo {
+                name: "inkscape/inkscape".into(),
+                link: "https://gitlab.com/inkscape/inkscape".into(),
+                ext_id: "gitlab:gitlab.com/inkscape/inkscape".into(),
+                verified: false,
+                podcast: false,
+            }],
+            channels
+        );
+    }
+
+    #[test]
+    fn search_gitea() {
+        let _gitea = mock("GET", "/api/v1/repos/forgejo/forgejo")
+            .match_header("authorization", Matcher::Missing)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"id":1,"full_name":"forgejo/forgejo","html_url":"https://codeberg.org/forgejo/forgejo"}"#)
+            .create();
+
+        let channels = search_forges(
+            &mockito::server_url(),
+            &repo("codeberg.org", "forgejo/forgejo"),
+            None,
+        )
+        .expect("Failed to search");
+        assert_eq!(1, channels.len());
+        assert_eq!("gitea:codeberg.org/forgejo/forgejo", channels[0].ext_id);
+    }
+
+    #[test]
+    fn fetch_gitlab_releases() {
+        let _m = mock("GET", "/api/v4/projects/group%2Fproject/releases")
+            .with_header("content-type", "application/json")
+            .with_body(r#"[
+                {"name":"","tag_name":"v1.1","description":"**Fixes**\n\n- Crash on start","author":{"name":"Jane Doe"},"created_at":"2020-07-01T10:00:00.000Z","released_at":"2020-07-02T10:00:00.000Z","upcoming_release":false},
+                {"name":"Next","tag_name":"v2.0","created_at":"2020-07-01T10:00:00.000Z","released_at":"2099-01-01T00:00:00.000Z","upcoming_release":true}
+            ]"#)
+            .create();
+
+        let updates = gitlab_releases(
+            &mockito::server_url(),
+            &repo("gitlab.com", "group/project"),
+            None,
+        )
+        .expect("Failed to fetch");
+        assert_eq!(
+            vec![Update {
+                ext_id: Some("v1.1".into()),
+                title: "v1.1".into(),
+                url: "https://gitlab.com/group/project/-/releases/v1.1".into(),
+                external_url: None,
+                metadata: Metadata {
+                    summary: Some("Fixes Crash on start".into()),
+                    author: Some("Jane Doe".into()),
+                    ..Metadata::default()
+                },
+                published: Utc.ymd(2020, 7, 2).and_hms(10, 0, 0),
+            }],
+            updates
+        );
+    }
+
+    #[test]
+    fn fetch_gitea_releases() {
+        let _m = mock("GET", "/api/v1/repos/owner/repo/releases")
+            .match_header("authorization", "token secret")
+            .with_header("content-type", "application/json")
+            .with_body(r#"[
+                {"name":"Version 1.2","tag_name":"v1.2","html_url":"https://git.example.org/owner/repo/releases/tag/v1.2","draft":false,"prerelease":false,"created_at":"2020-07-01T10:00:00+02:00","published_at":"2020-07-01T12:00:00+02:00"},
+                {"name":"WIP","tag_name":"v1.3","html_url":"https://git.example.org/owner/repo/releases/tag/v1.3","draft":true,"prerelease":false,"created_at":"2020-07-05T10:00:00+02:00","published_at":null}
+            ]"#)
+            .create();
+
+        let updates = gitea_releases(
+            &mockito::server_url(),
+            &repo("git.example.org", "owner/repo"),
+            Some("secret"),
+        )
+        .expect("Failed to fetch");
+        assert_eq!(
+            vec![Update {
+                ext_id: Some("v1.2".into()),
+                title: "Version 1.2".into(),
+                url: "https://git.example.org/owner/repo/releases/tag/v1.2".into(),
+                external_url: None,
+                metadata: Metadata::default(),
+                published: Utc.ymd(2020, 7, 1).and_hms(10, 0, 0),
+            }],
+            updates
+        );
+    }
+}
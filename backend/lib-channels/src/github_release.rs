@@ -1,4 +1,5 @@
 use super::channel::*;
+use super::summary::summarize_markdown;
 
 use chrono::{DateTime, Utc};
 use github_rs::client::{Executor, Github};
@@ -47,6 +48,14 @@ struct ReleaseResponse {
     name: Option<String>,
     tag_name: String,
     published_at: String,
+    // the release notes in markdown
+    body: Option<String>,
+    author: Option<AuthorResponse>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AuthorResponse {
+    login: String,
 }
 
 impl TryInto<Update> for ReleaseResponse {
@@ -76,6 +85,12 @@ impl TryInto<Update> for ReleaseResponse {
             ext_id: None,
             title,
             url: self.html_url,
+            external_url: None,
+            metadata: Metadata {
+                summary: self.body.as_deref().and_then(summarize_markdown),
+                author: self.author.map(|a| a.login),
+                ..Metadata::default()
+            },
             published,
         })
     }
@@ -83,12 +98,12 @@ impl TryInto<Update> for ReleaseResponse {
 
 #[derive(PartialEq, Debug)]
 pub struct GithubRepository {
-    owner: String,
-    repository: String,
+    pub(crate) owner: String,
+    pub(crate) repository: String,
 }
 
 impl GithubRepository {
-    fn to_url(&self) -> String {
+    pub(crate) fn to_url(&self) -> String {
         format!("https://github.com/{}", self.to_string())
     }
 }
@@ -110,7 +125,7 @@ impl GithubRepository {
         Self::parse(name).unwrap()
     }
 
-    fn parse(name: &str) -> Result<Self, String> {
+    pub(crate) fn parse(name: &str) -> Result<Self, String> {
         let parts: Vec<&str> = name.split('/').collect();
         if parts.len() != 2 {
             return Err("Repository must have format owner/repository".into());
@@ -136,50 +151,7 @@ impl Channel for GithubRelease {
     }
 
     fn search(&self, name: SanitizedName) -> Result<Vec<ChannelInfo>, SearchError> {
-        let repo: GithubRepository = GithubRepository::from(name);
-        let query = self
-            .client
-            .get()
-            .repos()
-            .owner(&repo.owner)
-            .repo(&repo.repository);
-
-        // todo handle rate limiting
-        match query.execute::<Value>() {
-            Ok((_, status, Some(json))) if status == StatusCode::OK => {
-                serde_json::from_value::<RepoResponse>(json.clone())
-                    .map_err(|err| {
-                        SearchError::TechnicalError(format!(
-                            "Failed to parse RepoResponse from json {:?}: {:?}",
-                            json, err,
-                        ))
-                    })
-                    .and_then(|repo| {
-                        self.sanitize(&repo.full_name).map_err(|err| {
-                            SearchError::TechnicalError(format!(
-                                "Failed to sanitize repository '{}' from github: {:?}",
-                                repo.full_name, err
-                            ))
-                        })
-                    })
-                    .map(|sanitized| {
-                        let repo = GithubRepository::from(sanitized);
-                        vec![ChannelInfo {
-                            name: repo.to_string(),
-                            link: repo.to_url(),
-                            ext_id: repo.to_string(),
-                            verified: false,
-                        }]
-                    })
-            }
-            Ok((_, status, _)) if status == StatusCode::NOT_FOUND => {
-                Err(SearchError::ChannelNotFound("github returned 404".into()))
-            }
-            other => Err(SearchError::TechnicalError(format!(
-                "Failed to query github whether repo {} is valid: {:?}",
-                repo, other
-            ))),
-        }
+        search_repository(&self.client, name)
     }
 
     fn fetch_updates(&self, repo_name: &str) -> Result<Vec<Update>, String> {
@@ -200,6 +172,57 @@ impl Channel for GithubRelease {
     }
 }
 
+// looks up the repository on github. this is shared by all channels
+// that follow a github repository (eg. releases and tags)
+pub(crate) fn search_repository(
+    client: &Github,
+    name: SanitizedName,
+) -> Result<Vec<ChannelInfo>, SearchError> {
+    let repo: GithubRepository = GithubRepository::from(name);
+    let query = client
+        .get()
+        .repos()
+        .owner(&repo.owner)
+        .repo(&repo.repository);
+
+    // todo handle rate limiting
+    match query.execute::<Value>() {
+        Ok((_, status, Some(json))) if status == StatusCode::OK => {
+            serde_json::from_value::<RepoResponse>(json.clone())
+                .map_err(|err| {
+                    SearchError::TechnicalError(format!(
+                        "Failed to parse RepoResponse from json {:?}: {:?}",
+                        json, err,
+                    ))
+                })
+                .and_then(|repo| {
+                    GithubRepository::parse(&repo.full_name).map_err(|err| {
+                        SearchError::TechnicalError(format!(
+                            "Failed to sanitize repository '{}' from github: {:?}",
+                            repo.full_name, err
+                        ))
+                    })
+                })
+                .map(|repo| {
+                    vec![ChannelInfo {
+                        name: repo.to_string(),
+                        link: repo.to_url(),
+                        ext_id: repo.to_string(),
+                        verified: false,
+                        podcast: false,
+                    }]
+                })
+        }
+        Ok((_, status, _)) if status == StatusCode::NOT_FOUND => {
+            Err(SearchError::ChannelNotFound("github returned 404".into()))
+        }
+        other => Err(SearchError::TechnicalError(format!(
+            "Failed to query github whether repo {} is valid: {:?}",
+            repo, other
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,200 @@
+use super::channel::*;
+use super::github_release::{search_repository, GithubRepository};
+
+use chrono::{DateTime, Utc};
+use github_rs::client::{Executor, Github};
+use github_rs::StatusCode;
+use serde::Deserialize;
+use serde_json::Value;
+
+// every tag requires an additional request for the commit date,
+// so we only look at the most recent ones
+const MAX_TAGS: usize = 20;
+
+/// Follows the tags of a github repository. Many repositories
+/// only push tags and never create a release on github.
+pub struct GithubTag {
+    client: Github,
+}
+
+impl GithubTag {
+    pub fn new(api_token: &str) -> Result<GithubTag, String> {
+        let github = Github::new(api_token)
+            .map_err(|err| format!("Failed to initialize github client: {:?}", err))?;
+        Ok(GithubTag { client: github })
+    }
+
+    fn fetch_tags(&self, repo: &GithubRepository) -> Result<Vec<TagResponse>, String> {
+        let endpoint = format!(
+            "repos/{}/{}/tags?per_page={}",
+            repo.owner, repo.repository, MAX_TAGS
+        );
+        let query = self.client.get().custom_endpoint(&endpoint);
+        match query.execute::<Value>() {
+            Ok((_, status, Some(json))) if status == StatusCode::OK => {
+                serde_json::from_value::<Vec<TagResponse>>(json.clone())
+                    .map_err(|err| format!("Failed to parse tags: {:?}, json: {}", err, json))
+            }
+            other => Err(format!("Failed to fetch tags: {:?}", other)),
+        }
+    }
+
+    fn fetch_commit_date(
+        &self,
+        repo: &GithubRepository,
+        sha: &str,
+    ) -> Result<DateTime<Utc>, String> {
+        let endpoint = format!("repos/{}/{}/commits/{}", repo.owner, repo.repository, sha);
+        let query = self.client.get().custom_endpoint(&endpoint);
+        match query.execute::<Value>() {
+            Ok((_, status, Some(json))) if status == StatusCode::OK => {
+                let commit = serde_json::from_value::<CommitResponse>(json.clone())
+                    .map_err(|err| format!("Failed to parse commit: {:?}, json: {}", err, json))?;
+                parse_commit_date(&commit)
+            }
+            other => Err(format!("Failed to fetch commit {}: {:?}", sha, other)),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct TagResponse {
+    name: String,
+    commit: TagCommitResponse,
+}
+
+#[derive(Deserialize, Debug)]
+struct TagCommitResponse {
+    sha: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct CommitResponse {
+    commit: CommitDetailResponse,
+}
+
+#[derive(Deserialize, Debug)]
+struct CommitDetailResponse {
+    committer: CommitSignatureResponse,
+}
+
+#[derive(Deserialize, Debug)]
+struct CommitSignatureResponse {
+    date: String,
+}
+
+fn parse_commit_date(commit: &CommitResponse) -> Result<DateTime<Utc>, String> {
+    let date = &commit.commit.committer.date;
+    DateTime::parse_from_rfc3339(date)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|err| format!("Failed to parse {} as rfc3339: {:?}", date, err))
+}
+
+// creates one update per tag. the url points to the changes since the
+// previous tag or to the tree if there is no previous tag we know of.
+fn tags_to_updates(repo: &GithubRepository, mut tags: Vec<(String, DateTime<Utc>)>) -> Vec<Update> {
+    tags.sort_by_key(|(_, published)| *published);
+
+    let mut updates = Vec::with_capacity(tags.len());
+    let mut previous: Option<&str> = None;
+    for (tag, published) in &tags {
+        let url = match previous {
+            Some(prev) => format!("{}/compare/{}...{}", repo.to_url(), prev, tag),
+            None => format!("{}/tree/{}", repo.to_url(), tag),
+        };
+        updates.push(Update {
+            ext_id: Some(tag.clone()),
+            title: tag.clone(),
+            url,
+            external_url: None,
+            metadata: Metadata::default(),
+            published: *published,
+        });
+        previous = Some(tag);
+    }
+    updates
+}
+
+impl Channel for GithubTag {
+    fn sanitize(&self, name: &str) -> Result<SanitizedName, String> {
+        GithubRepository::parse(name).map(|r| r.into())
+    }
+
+    fn search(&self, name: SanitizedName) -> Result<Vec<ChannelInfo>, SearchError> {
+        search_repository(&self.client, name)
+    }
+
+    fn fetch_updates(&self, repo_name: &str) -> Result<Vec<Update>, String> {
+        let repo = GithubRepository::parse(repo_name)?;
+        let tags = self.fetch_tags(&repo)?;
+        let mut dated_tags = Vec::with_capacity(tags.len());
+        for tag in tags {
+            let published = self.fetch_commit_date(&repo, &tag.commit.sha)?;
+            dated_tags.push((tag.name, published));
+        }
+        Ok(tags_to_updates(&repo, dated_tags))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    const TAGS_RESPONSE: &str = r#"[{"name":"v0.2.0","zipball_url":"https://api.github.com/repos/rethab/digester/zipball/v0.2.0","tarball_url":"https://api.github.com/repos/rethab/digester/tarball/v0.2.0","commit":{"sha":"c5b97d5ae6c19d5c5df71a34c7fbeeda2479ccbc","url":"https://api.github.com/repos/rethab/digester/commits/c5b97d5ae6c19d5c5df71a34c7fbeeda2479ccbc"},"node_id":"MDM6UmVmMTIzOnYwLjIuMA=="}]"#;
+
+    const COMMIT_RESPONSE: &str = r#"{"sha":"c5b97d5ae6c19d5c5df71a34c7fbeeda2479ccbc","commit":{"author":{"name":"Reto","email":"reto@example.com","date":"2020-05-01T10:00:00Z"},"committer":{"name":"GitHub","email":"noreply@github.com","date":"2020-05-02T12:30:00Z"},"message":"Release 0.2.0"}}"#;
+
+    #[test]
+    fn parse_tags() {
+        let tags: Vec<TagResponse> =
+            serde_json::from_str(TAGS_RESPONSE).expect("Failed to parse json");
+        assert_eq!(1, tags.len());
+        assert_eq!("v0.2.0", tags[0].name);
+        assert_eq!(
+            "c5b97d5ae6c19d5c5df71a34c7fbeeda2479ccbc",
+            tags[0].commit.sha
+        );
+    }
+
+    #[test]
+    fn parse_committer_date() {
+        let commit: CommitResponse =
+            serde_json::from_str(COMMIT_RESPONSE).expect("Failed to parse json");
+        assert_eq!(
+            Ok(Utc.ymd(2020, 5, 2).and_hms(12, 30, 0)),
+            parse_commit_date(&commit)
+        );
+    }
+
+    #[test]
+    fn compare_with_previous_tag() {
+        let repo = GithubRepository::parse("owner/repo").unwrap();
+        let tags = vec![
+            ("v1.1".to_owned(), Utc.ymd(2020, 5, 2).and_hms(0, 0, 0)),
+            ("v1.0".to_owned(), Utc.ymd(2020, 5, 1).and_hms(0, 0, 0)),
+        ];
+        let updates = tags_to_updates(&repo, tags);
+        assert_eq!(
+            vec![
+                Update {
+                    ext_id: Some("v1.0".into()),
+                    title: "v1.0".into(),
+                    url: "https://github.com/owner/repo/tree/v1.0".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 5, 1).and_hms(0, 0, 0),
+                },
+                Update {
+                    ext_id: Some("v1.1".into()),
+                    title: "v1.1".into(),
+                    url: "https://github.com/owner/repo/compare/v1.0...v1.1".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 5, 2).and_hms(0, 0, 0),
+                },
+            ],
+            updates
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use super::channel::SearchError;
+use super::politeness::{self, Access};
+
+use core::time::Duration;
+use reqwest::blocking::{Client, Response};
+use reqwest::header;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+
+#[derive(Debug)]
+pub enum HttpError {
+    NotFound(String),
+    Timeout(String),
+    TechnicalError(String),
+}
+
+impl Into<SearchError> for HttpError {
+    fn into(self) -> SearchError {
+        use HttpError::*;
+        match self {
+            NotFound(msg) => SearchError::ChannelNotFound(msg),
+            Timeout(msg) => SearchError::Timeout(msg),
+            TechnicalError(msg) => SearchError::TechnicalError(msg),
+        }
+    }
+}
+
+// fetches the url and deserializes the response body as json. this is
+// meant for the apis of channels that don't come with their own client.
+pub fn get_json<T: DeserializeOwned>(url: &str) -> Result<T, HttpError> {
+    get_json_with_headers(url, &[])
+}
+
+// same as get_json, but sends additional headers (eg. for authentication)
+pub fn get_json_with_headers<T: DeserializeOwned>(
+    url: &str,
+    headers: &[(&str, &str)],
+) -> Result<T, HttpError> {
+    get(url, "application/json", headers, Access::Api)?
+        .json::<T>()
+        .map_err(|err| {
+            HttpError::TechnicalError(format!("Failed to parse json from {}: {:?}", url, err))
+        })
+}
+
+// fetches the url and returns the response body as text (eg. html)
+pub fn get_text(url: &str) -> Result<String, HttpError> {
+    get(url, "text/html,*/*", &[], Access::Crawl)?
+        .text()
+        .map_err(|err| {
+            HttpError::TechnicalError(format!("Failed to read body from {}: {:?}", url, err))
+        })
+}
+
+// fetches the url and returns the whole response, for when the headers matter
+pub fn get_response(url: &str, accept: &str) -> Result<Response, HttpError> {
+    get(url, accept, &[], Access::Crawl)
+}
+
+// posts the parameters as form and accepts any successful status
+pub fn post_form(url: &str, params: &[(&str, &str)]) -> Result<Response, HttpError> {
+    use HttpError::*;
+
+    let _permit = politeness::acquire(url, Access::Api).map_err(TechnicalError)?;
+    let timeout = Duration::from_secs(3);
+    let result = Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|err| TechnicalError(format!("Failed to build client: {:?}", err)))?
+        .post(url)
+        .header(header::USER_AGENT, politeness::user_agent())
+        .form(params)
+        .send();
+    if let Ok(resp) = &result {
+        politeness::record_response(url, resp.status(), resp.headers());
+    }
+
+    match result {
+        Ok(resp) if resp.status().is_success() => Ok(resp),
+        Ok(resp) => Err(TechnicalError(format!(
+            "Server returned code {} for post to {}",
+            resp.status(),
+            url
+        ))),
+        Err(err) if err.is_timeout() => Err(Timeout(format!(
+            "Failed to post to {} within {:?}: {:?}",
+            url, timeout, err
+        ))),
+        Err(err) => Err(TechnicalError(format!(
+            "Failed to post to {}: {:?}",
+            url, err
+        ))),
+    }
+}
+
+fn get(
+    url: &str,
+    accept: &str,
+    headers: &[(&str, &str)],
+    access: Access,
+) -> Result<Response, HttpError> {
+    use HttpError::*;
+
+    let _permit = politeness::acquire(url, access).map_err(TechnicalError)?;
+    let timeout = Duration::from_secs(3);
+    let mut builder = Client::builder()
+        .timeout(timeout)
+        .build()
+        .map_err(|err| TechnicalError(format!("Failed to build client: {:?}", err)))?
+        .get(url)
+        .header(header::USER_AGENT, politeness::user_agent())
+        .header(header::ACCEPT, accept);
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    let result = builder.send();
+    if let Ok(resp) = &result {
+        politeness::record_response(url, resp.status(), resp.headers());
+    }
+
+    match result {
+        Ok(resp) if resp.status() == StatusCode::OK => Ok(resp),
+        Ok(resp) if resp.status() == StatusCode::NOT_FOUND || resp.status() == StatusCode::GONE => {
+            Err(NotFound(format!(
+                "Server returned code {} for url {}",
+                resp.status(),
+                url
+            )))
+        }
+        Ok(resp) => Err(TechnicalError(format!(
+            "Server returned code {} for url {}",
+            resp.status(),
+            url
+        ))),
+        Err(err) if err.is_timeout() => Err(Timeout(format!(
+            "Failed to fetch {} within {:?}: {:?}",
+            url, timeout, err
+        ))),
+        Err(err) => Err(TechnicalError(format!(
+            "Failed to fetch {}: {:?}",
+            url, err
+        ))),
+    }
+}
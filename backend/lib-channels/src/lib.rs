@@ -6,6 +6,21 @@ extern crate chrono;
 /// from that can be sent as updates to people.
 pub mod channel;
 pub use channel::*;
+pub mod article;
+pub mod forge_release;
 pub mod github_release;
+pub mod github_tag;
+mod http;
+pub mod link_aggregator;
+pub mod mastodon;
+pub mod newsletter;
+pub mod oci_image;
+pub mod package_release;
+pub mod politeness;
+pub mod reddit;
+mod robots;
 pub mod rss;
+pub mod summary;
 pub mod twitter;
+pub mod web_page;
+pub mod websub;
@@ -0,0 +1,523 @@
+use super::channel::*;
+use super::http::{self, HttpError};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde::Deserialize;
+use std::fmt::Display;
+use url::Url;
+
+const HN_API_URL: &str = "https://hn.algolia.com/api/v1";
+const LOBSTERS_URL: &str = "https://lobste.rs";
+
+// the algolia tags that make sense for stories
+const HN_TAGS: [&str; 3] = ["show_hn", "ask_hn", "front_page"];
+
+// lobsters only returns 25 stories per page
+const MAX_LOBSTERS_PAGES: usize = 4;
+
+/// Follows the stories on a link aggregator (hacker news or lobsters)
+/// that match a query, eg. hn:score>=300 or lobsters:tag=rust
+pub struct LinkAggregator {}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Site {
+    HackerNews,
+    Lobsters,
+}
+
+/// What stories we are interested in. Format of the ext_id is
+/// site[:filter,filter..] where the filters are tag=x, domain=x
+/// and score>=n (eg. lobsters:tag=rust,score>=20)
+#[derive(PartialEq, Debug)]
+pub struct StoryQuery {
+    site: Site,
+    tag: Option<String>,
+    domain: Option<String>,
+    min_score: Option<i64>,
+}
+
+impl StoryQuery {
+    fn parse(query: &str) -> Result<Self, String> {
+        let query = query.trim().to_ascii_lowercase();
+        let (site, filters) = match query.find(':') {
+            Some(idx) => (&query[..idx], &query[idx + 1..]),
+            None => (query.as_str(), ""),
+        };
+        let site = match site.trim() {
+            "hn" | "hackernews" => Site::HackerNews,
+            "lobsters" | "lobste.rs" => Site::Lobsters,
+            other => return Err(format!("Unknown site '{}', must be hn or lobsters", other)),
+        };
+
+        let mut parsed = StoryQuery {
+            site,
+            tag: None,
+            domain: None,
+            min_score: None,
+        };
+        let valid_word = |s: &str, extra: &str| {
+            !s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || extra.contains(c))
+        };
+        for filter in filters
+            .split(',')
+            .map(|f| f.trim())
+            .filter(|f| !f.is_empty())
+        {
+            if let Some(score) = filter.strip_prefix("score>=") {
+                let score = score
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| format!("Score must be a number: {}", score))?;
+                parsed.min_score = Some(score);
+            } else if let Some(tag) = filter.strip_prefix("tag=") {
+                let tag = tag.trim();
+                if !valid_word(tag, "_-") {
+                    return Err(format!("Invalid tag: {}", tag));
+                }
+                if site == Site::HackerNews && !HN_TAGS.contains(&tag) {
+                    return Err(format!(
+                        "Hacker News tag must be one of {}",
+                        HN_TAGS.join(", ")
+                    ));
+                }
+                parsed.tag = Some(tag.to_owned());
+            } else if let Some(domain) = filter.strip_prefix("domain=") {
+                let domain = domain.trim().trim_start_matches("www.");
+                if !valid_word(domain, "-.") || !domain.contains('.') {
+                    return Err(format!("Invalid domain: {}", domain));
+                }
+                parsed.domain = Some(domain.to_owned());
+            } else {
+                return Err(format!(
+                    "Unknown filter '{}', must be one of tag=, domain= or score>=",
+                    filter
+                ));
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn site_name(&self) -> &'static str {
+        match self.site {
+            Site::HackerNews => "Hacker News",
+            Site::Lobsters => "Lobsters",
+        }
+    }
+
+    fn to_url(&self) -> String {
+        match (self.site, &self.tag, &self.domain) {
+            (Site::HackerNews, Some(tag), _) if tag == "show_hn" => {
+                "https://news.ycombinator.com/show".into()
+            }
+            (Site::HackerNews, Some(tag), _) if tag == "ask_hn" => {
+                "https://news.ycombinator.com/ask".into()
+            }
+            (Site::HackerNews, _, Some(domain)) => {
+                format!("https://news.ycombinator.com/from?site={}", domain)
+            }
+            (Site::HackerNews, _, _) => "https://news.ycombinator.com".into(),
+            (Site::Lobsters, Some(tag), _) => format!("{}/t/{}", LOBSTERS_URL, tag),
+            (Site::Lobsters, _, Some(domain)) => format!("{}/domains/{}", LOBSTERS_URL, domain),
+            (Site::Lobsters, _, _) => LOBSTERS_URL.into(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        let filters = self.filters();
+        if filters.is_empty() {
+            self.site_name().into()
+        } else {
+            format!("{} ({})", self.site_name(), filters.join(", "))
+        }
+    }
+
+    fn filters(&self) -> Vec<String> {
+        let mut filters = Vec::new();
+        if let Some(tag) = &self.tag {
+            filters.push(format!("tag={}", tag));
+        }
+        if let Some(domain) = &self.domain {
+            filters.push(format!("domain={}", domain));
+        }
+        if let Some(score) = self.min_score {
+            filters.push(format!("score>={}", score));
+        }
+        filters
+    }
+
+    fn matches(&self, story: &Story) -> bool {
+        let domain_matches = match (&self.domain, story.host()) {
+            (None, _) => true,
+            (Some(domain), Some(host)) => {
+                host == *domain || host.ends_with(&format!(".{}", domain))
+            }
+            (Some(_), None) => false,
+        };
+        let tag_matches = match &self.tag {
+            // hacker news filters by tag in the api
+            Some(tag) if self.site == Site::Lobsters => story.tags.contains(tag),
+            _ => true,
+        };
+        let score_matches = self.min_score.map(|s| story.score >= s).unwrap_or(true);
+        domain_matches && tag_matches && score_matches
+    }
+}
+
+impl Display for StoryQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let site = match self.site {
+            Site::HackerNews => "hn",
+            Site::Lobsters => "lobsters",
+        };
+        let filters = self.filters();
+        if filters.is_empty() {
+            write!(f, "{}", site)
+        } else {
+            write!(f, "{}:{}", site, filters.join(","))
+        }
+    }
+}
+
+struct Story {
+    id: String,
+    title: String,
+    url: Option<String>,
+    discussion_url: String,
+    score: i64,
+    tags: Vec<String>,
+    created: DateTime<Utc>,
+}
+
+impl Story {
+    fn host(&self) -> Option<String> {
+        self.url
+            .as_ref()
+            .and_then(|u| Url::parse(u).ok())
+            .and_then(|u| {
+                u.host_str()
+                    .map(|h| h.trim_start_matches("www.").to_owned())
+            })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct HnSearchResponse {
+    hits: Vec<HnHit>,
+}
+
+#[derive(Deserialize, Debug)]
+struct HnHit {
+    #[serde(rename = "objectID")]
+    object_id: String,
+    title: Option<String>,
+    url: Option<String>,
+    points: Option<i64>,
+    created_at_i: i64,
+}
+
+#[derive(Deserialize, Debug)]
+struct LobstersStory {
+    short_id: String,
+    title: String,
+    url: String,
+    score: i64,
+    comments_url: String,
+    created_at: String,
+    tags: Vec<String>,
+}
+
+fn hn_stories(
+    base_url: &str,
+    query: &StoryQuery,
+    since: DateTime<Utc>,
+) -> Result<Vec<Story>, HttpError> {
+    let mut url = Url::parse(&format!("{}/search_by_date", base_url))
+        .map_err(|err| HttpError::TechnicalError(format!("Invalid url: {:?}", err)))?;
+    {
+        let mut params = url.query_pairs_mut();
+        let tags = match &query.tag {
+            Some(tag) => format!("story,{}", tag),
+            None => "story".into(),
+        };
+        params.append_pair("tags", &tags);
+        let mut numeric_filters = format!("created_at_i>{}", since.timestamp());
+        if let Some(score) = query.min_score {
+            numeric_filters.push_str(&format!(",points>={}", score));
+        }
+        params.append_pair("numericFilters", &numeric_filters);
+        if let Some(domain) = &query.domain {
+            // narrows down the results, the exact match is done in StoryQuery::matches
+            params.append_pair("query", domain);
+            params.append_pair("restrictSearchableAttributes", "url");
+        }
+        params.append_pair("hitsPerPage", "500");
+    }
+
+    let response: HnSearchResponse = http::get_json(url.as_str())?;
+    Ok(response
+        .hits
+        .into_iter()
+        .filter_map(|hit| {
+            let title = hit.title?;
+            Some(Story {
+                discussion_url: format!("https://news.ycombinator.com/item?id={}", hit.object_id),
+                id: hit.object_id,
+                title,
+                url: hit.url.filter(|u| !u.is_empty()),
+                score: hit.points.unwrap_or(0),
+                tags: Vec::new(),
+                created: Utc.timestamp(hit.created_at_i, 0),
+            })
+        })
+        .collect())
+}
+
+fn lobsters_stories(
+    base_url: &str,
+    query: &StoryQuery,
+    since: DateTime<Utc>,
+) -> Result<Vec<Story>, HttpError> {
+    let path = match (&query.tag, &query.domain) {
+        (Some(tag), _) => format!("t/{}", tag),
+        (_, Some(domain)) => format!("domains/{}", domain),
+        (None, None) => "newest".into(),
+    };
+
+    let mut stories = Vec::new();
+    for page in 1..=MAX_LOBSTERS_PAGES {
+        let url = if page == 1 {
+            format!("{}/{}.json", base_url, path)
+        } else {
+            format!("{}/{}/page/{}.json", base_url, path, page)
+        };
+        let response: Vec<LobstersStory> = http::get_json(&url)?;
+        let n_stories = response.len();
+        let mut reached_since = n_stories == 0;
+        for story in response {
+            let created = DateTime::parse_from_rfc3339(&story.created_at)
+                .map(|dt| dt.with_timezone(&Utc))
+                .map_err(|err| {
+                    HttpError::TechnicalError(format!(
+                        "Failed to parse {} as rfc3339: {:?}",
+                        story.created_at, err
+                    ))
+                })?;
+            if created < since {
+                reached_since = true;
+                continue;
+            }
+            stories.push(Story {
+                id: story.short_id,
+                title: story.title,
+                url: Some(story.url).filter(|u| !u.is_empty()),
+                discussion_url: story.comments_url,
+                score: story.score,
+                tags: story.tags,
+                created,
+            });
+        }
+        if reached_since {
+            break;
+        }
+    }
+    Ok(stories)
+}
+
+// stories keep collecting points after they were posted, so we can
+// only tell whether they crossed the threshold once they settled. such
+// stories are published when they settled, because the fetcher ignores
+// updates published before the last fetch.
+fn settle_time(query: &StoryQuery) -> Duration {
+    match query.min_score {
+        Some(_) => Duration::days(1),
+        None => Duration::zero(),
+    }
+}
+
+fn stories_to_updates(query: &StoryQuery, stories: Vec<Story>, now: DateTime<Utc>) -> Vec<Update> {
+    let settle_time = settle_time(query);
+    stories
+        .into_iter()
+        .filter(|s| s.created + settle_time <= now)
+        .filter(|s| query.matches(s))
+        .map(|s| Update {
+            ext_id: Some(s.id),
+            title: s.title,
+            url: s.url.unwrap_or(s.discussion_url),
+            external_url: None,
+            metadata: Metadata::default(),
+            published: s.created + settle_time,
+        })
+        .collect()
+}
+
+fn fetch_stories(
+    base_url: &str,
+    query: &StoryQuery,
+    now: DateTime<Utc>,
+) -> Result<Vec<Update>, String> {
+    let since = now - Duration::weeks(1) - settle_time(query);
+    let stories = match query.site {
+        Site::HackerNews => hn_stories(base_url, query, since),
+        Site::Lobsters => lobsters_stories(base_url, query, since),
+    }
+    .map_err(|err| format!("Failed to fetch stories for {}: {:?}", query, err))?;
+    Ok(stories_to_updates(query, stories, now))
+}
+
+impl Channel for LinkAggregator {
+    fn sanitize(&self, query: &str) -> Result<SanitizedName, String> {
+        StoryQuery::parse(query).map(|q| SanitizedName(q.to_string()))
+    }
+
+    fn search(&self, name: SanitizedName) -> Result<Vec<ChannelInfo>, SearchError> {
+        let query = StoryQuery::parse(&name.0).map_err(SearchError::TechnicalError)?;
+        // lobsters returns 404 for unknown tags
+        if let (Site::Lobsters, Some(tag)) = (query.site, &query.tag) {
+            http::get_json::<Vec<LobstersStory>>(&format!("{}/t/{}.json", LOBSTERS_URL, tag))
+                .map_err(|err| err.into())?;
+        }
+        Ok(vec![ChannelInfo {
+            name: query.describe(),
+            link: query.to_url(),
+            ext_id: query.to_string(),
+            verified: false,
+            podcast: false,
+        }])
+    }
+
+    fn fetch_updates(&self, ext_id: &str) -> Result<Vec<Update>, String> {
+        let query = StoryQuery::parse(ext_id)?;
+        let base_url = match query.site {
+            Site::HackerNews => HN_API_URL,
+            Site::Lobsters => LOBSTERS_URL,
+        };
+        fetch_stories(base_url, &query, Utc::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, Matcher};
+
+    #[test]
+    fn parse_queries() {
+        assert_eq!(
+            Ok(StoryQuery {
+                site: Site::HackerNews,
+                tag: None,
+                domain: None,
+                min_score: Some(300),
+            }),
+            StoryQuery::parse("hn:score>=300")
+        );
+        assert_eq!(
+            Ok(StoryQuery {
+                site: Site::Lobsters,
+                tag: Some("rust".into()),
+                domain: Some("github.com".into()),
+                min_score: None,
+            }),
+            StoryQuery::parse(" Lobsters: domain=www.github.com, tag=rust ")
+        );
+        assert_eq!(true, StoryQuery::parse("reddit:tag=rust").is_err());
+        assert_eq!(true, StoryQuery::parse("hn:score>=many").is_err());
+        assert_eq!(true, StoryQuery::parse("hn:author=pg").is_err());
+        assert_eq!(true, StoryQuery::parse("hn:tag=rust").is_err());
+    }
+
+    #[test]
+    fn canonical_ext_id() {
+        let sanitized = LinkAggregator {}
+            .sanitize("lobsters:score>=10,tag=rust")
+            .map(|n| n.0);
+        assert_eq!(Ok("lobsters:tag=rust,score>=10".to_owned()), sanitized);
+        assert_eq!(
+            Ok("hn".to_owned()),
+            LinkAggregator {}.sanitize("hn").map(|n| n.0)
+        );
+    }
+
+    #[test]
+    fn hn_stories_above_score() {
+        let now = Utc.ymd(2020, 7, 10).and_hms(12, 0, 0);
+        let query = StoryQuery::parse("hn:score>=300,domain=github.com").unwrap();
+        let _m = mock("GET", "/search_by_date")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("tags".into(), "story".into()),
+                Matcher::UrlEncoded(
+                    "numericFilters".into(),
+                    format!(
+                        "created_at_i>{},points>=300",
+                        (now - Duration::days(8)).timestamp()
+                    ),
+                ),
+                Matcher::UrlEncoded("query".into(), "github.com".into()),
+            ]))
+            .with_body(format!(
+                r#"{{"hits":[
+                    {{"objectID":"1","title":"Settled","url":"https://github.com/a/b","points":512,"created_at_i":{}}},
+                    {{"objectID":"2","title":"Too fresh","url":"https://github.com/c/d","points":400,"created_at_i":{}}},
+                    {{"objectID":"3","title":"Other domain","url":"https://notgithub.com/e","points":350,"created_at_i":{}}}
+                ]}}"#,
+                (now - Duration::days(2)).timestamp(),
+                (now - Duration::hours(2)).timestamp(),
+                (now - Duration::days(2)).timestamp(),
+            ))
+            .create();
+
+        let updates = fetch_stories(&mockito::server_url(), &query, now).expect("Failed to fetch");
+        assert_eq!(
+            vec![Update {
+                ext_id: Some("1".into()),
+                title: "Settled".into(),
+                url: "https://github.com/a/b".into(),
+                external_url: None,
+                metadata: Metadata::default(),
+                published: now - Duration::days(1),
+            }],
+            updates
+        );
+    }
+
+    #[test]
+    fn lobsters_stories_by_tag() {
+        let now = Utc.ymd(2020, 7, 10).and_hms(12, 0, 0);
+        let query = StoryQuery::parse("lobsters:tag=rust").unwrap();
+        let _m = mock("GET", "/t/rust.json")
+            .with_body(
+                r#"[
+                {"short_id":"abc","title":"Async rust","url":"https://example.com/async","score":12,"comments_url":"https://lobste.rs/s/abc/async_rust","created_at":"2020-07-09T10:00:00.000-05:00","tags":["rust"]},
+                {"short_id":"def","title":"Ask: rust books?","url":"","score":3,"comments_url":"https://lobste.rs/s/def/ask","created_at":"2020-07-08T10:00:00.000-05:00","tags":["rust","ask"]},
+                {"short_id":"old","title":"Old","url":"https://example.com/old","score":50,"comments_url":"https://lobste.rs/s/old/old","created_at":"2020-06-01T10:00:00.000-05:00","tags":["rust"]}
+            ]"#,
+            )
+            .create();
+
+        let updates = fetch_stories(&mockito::server_url(), &query, now).expect("Failed to fetch");
+        assert_eq!(
+            vec![
+                Update {
+                    ext_id: Some("abc".into()),
+                    title: "Async rust".into(),
+                    url: "https://example.com/async".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 7, 9).and_hms(15, 0, 0),
+                },
+                Update {
+                    ext_id: Some("def".into()),
+                    title: "Ask: rust books?".into(),
+                    url: "https://lobste.rs/s/def/ask".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 7, 8).and_hms(15, 0, 0),
+                },
+            ],
+            updates
+        );
+    }
+}
@@ -0,0 +1,369 @@
+use super::channel::*;
+use super::http::{self, HttpError};
+use super::summary::html_to_text;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::fmt::Display;
+use url::Url;
+
+/// Follows public statuses of an account on a mastodon
+/// (or any other fediverse server that speaks the mastodon api)
+pub struct Mastodon {}
+
+#[derive(PartialEq, Debug)]
+pub struct MastodonAccount {
+    username: String,
+    instance: String,
+}
+
+impl MastodonAccount {
+    fn unsafe_parse(name: &str) -> Self {
+        Self::parse(name).unwrap()
+    }
+
+    // accepts the handle with or without the leading @ (eg. @user@instance or
+    // user@instance) as well as the url of the profile (eg. https://instance/@user)
+    fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        let (username, instance) = if name.contains("://") {
+            let url = Url::parse(name).map_err(|err| format!("Invalid url '{}': {}", name, err))?;
+            let instance = url
+                .host_str()
+                .ok_or_else(|| format!("Url without host: {}", name))?
+                .to_owned();
+            let username = url
+                .path_segments()
+                .and_then(|mut segments| segments.next())
+                .filter(|segment| segment.starts_with('@'))
+                .map(|segment| segment.trim_start_matches('@').to_owned())
+                .ok_or("Profile url must have format https://instance/@user")?;
+            (username, instance)
+        } else {
+            let parts: Vec<&str> = name.trim_start_matches('@').split('@').collect();
+            if parts.len() != 2 {
+                return Err("Account must have format @user@instance".into());
+            }
+            (parts[0].to_owned(), parts[1].to_ascii_lowercase())
+        };
+
+        let valid_username =
+            |u: &str| !u.is_empty() && u.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        let valid_instance = |i: &str| {
+            i.contains('.')
+                && !i.starts_with('.')
+                && !i.ends_with('.')
+                && i.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+        };
+
+        if !valid_username(&username) {
+            Err(format!("Invalid username: {}", username))
+        } else if !valid_instance(&instance) {
+            Err(format!("Invalid instance: {}", instance))
+        } else {
+            Ok(MastodonAccount { username, instance })
+        }
+    }
+
+    fn api_url(&self, path: &str, params: &[(&str, &str)]) -> String {
+        let mut url = Url::parse(&format!("https://{}{}", self.instance, path))
+            .expect("instance is validated");
+        if !params.is_empty() {
+            url.query_pairs_mut().extend_pairs(params);
+        }
+        url.to_string()
+    }
+}
+
+impl Display for MastodonAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}@{}", self.username, self.instance)
+    }
+}
+
+impl From<SanitizedName> for MastodonAccount {
+    fn from(name: SanitizedName) -> Self {
+        MastodonAccount::unsafe_parse(&name.0)
+    }
+}
+
+impl Into<SanitizedName> for MastodonAccount {
+    fn into(self) -> SanitizedName {
+        SanitizedName(self.to_string())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct WebfingerResponse {
+    subject: String,
+    #[serde(default)]
+    links: Vec<WebfingerLink>,
+}
+
+#[derive(Deserialize, Debug)]
+struct WebfingerLink {
+    rel: String,
+    #[serde(rename = "type")]
+    link_type: Option<String>,
+    href: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AccountResponse {
+    id: String,
+    username: String,
+    display_name: String,
+    url: String,
+    locked: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct StatusResponse {
+    id: String,
+    created_at: String,
+    url: Option<String>,
+    uri: String,
+    content: String,
+    spoiler_text: String,
+}
+
+impl Channel for Mastodon {
+    fn sanitize(&self, name: &str) -> Result<SanitizedName, String> {
+        MastodonAccount::parse(name).map(|a| a.into())
+    }
+
+    fn search(&self, name: SanitizedName) -> Result<Vec<ChannelInfo>, SearchError> {
+        let handle = MastodonAccount::from(name);
+
+        // the handle's domain is not necessarily the server that hosts the
+        // account (eg. user@example.com could live on social.example.com).
+        // webfinger tells us where to actually find it.
+        let webfinger: WebfingerResponse = http::get_json(&handle.api_url(
+            "/.well-known/webfinger",
+            &[("resource", format!("acct:{}", handle).as_str())],
+        ))
+        .map_err(|err| err.into())?;
+        let account = resolve_webfinger(&webfinger).map_err(SearchError::ChannelNotFound)?;
+
+        let response: AccountResponse = http::get_json(&account.api_url(
+            "/api/v1/accounts/lookup",
+            &[("acct", account.username.as_str())],
+        ))
+        .map_err(|err| err.into())?;
+
+        if response.locked {
+            // same as protected accounts on twitter. we don't want to
+            // send their statuses around in e-mails
+            return Ok(Vec::new());
+        }
+
+        let account = MastodonAccount {
+            username: response.username,
+            instance: account.instance,
+        };
+        let name = if response.display_name.trim().is_empty() {
+            format!("@{}", account)
+        } else {
+            response.display_name.trim().to_owned()
+        };
+        Ok(vec![ChannelInfo {
+            name,
+            link: response.url,
+            ext_id: account.to_string(),
+            verified: false,
+            podcast: false,
+        }])
+    }
+
+    fn fetch_updates(&self, ext_id: &str) -> Result<Vec<Update>, String> {
+        let account = MastodonAccount::parse(ext_id)?;
+        let show_err =
+            |err: HttpError| format!("Failed to fetch statuses for {}: {:?}", account, err);
+
+        let response: AccountResponse = http::get_json(&account.api_url(
+            "/api/v1/accounts/lookup",
+            &[("acct", account.username.as_str())],
+        ))
+        .map_err(show_err)?;
+
+        let statuses: Vec<StatusResponse> = http::get_json(&account.api_url(
+            &format!("/api/v1/accounts/{}/statuses", response.id),
+            &[
+                ("exclude_replies", "true"),
+                ("exclude_reblogs", "true"),
+                ("limit", "40"),
+            ],
+        ))
+        .map_err(show_err)?;
+
+        statuses_to_updates(statuses)
+    }
+}
+
+// finds the account on the server that actually hosts it
+fn resolve_webfinger(webfinger: &WebfingerResponse) -> Result<MastodonAccount, String> {
+    let is_activity_json = |link: &&WebfingerLink| {
+        link.rel == "self"
+            && link
+                .link_type
+                .as_ref()
+                .map(|t| t.contains("activity+json") || t.contains("ld+json"))
+                .unwrap_or(false)
+    };
+
+    let subject = MastodonAccount::parse(webfinger.subject.trim_start_matches("acct:"))?;
+    let instance = webfinger
+        .links
+        .iter()
+        .find(is_activity_json)
+        .and_then(|link| link.href.as_ref())
+        .and_then(|href| Url::parse(href).ok())
+        .and_then(|url| url.host_str().map(|h| h.to_owned()))
+        .ok_or_else(|| format!("No activitypub actor for {}", webfinger.subject))?;
+
+    Ok(MastodonAccount {
+        username: subject.username,
+        instance,
+    })
+}
+
+fn statuses_to_updates(statuses: Vec<StatusResponse>) -> Result<Vec<Update>, String> {
+    let mut updates = Vec::with_capacity(statuses.len());
+    for status in statuses {
+        let published = DateTime::parse_from_rfc3339(&status.created_at)
+            .map(|dt| dt.with_timezone(&Utc))
+            .map_err(|err| {
+                format!(
+                    "Failed to parse {} as rfc3339: {:?}",
+                    status.created_at, err
+                )
+            })?;
+        let url = status.url.unwrap_or(status.uri);
+        let text = html_to_text(&status.content);
+        let title = if !text.is_empty() {
+            text
+        } else if !status.spoiler_text.trim().is_empty() {
+            status.spoiler_text.trim().to_owned()
+        } else {
+            // eg. a status with only an image
+            url.clone()
+        };
+        updates.push(Update {
+            ext_id: Some(status.id),
+            title,
+            url,
+            external_url: None,
+            metadata: Metadata::default(),
+            published,
+        });
+    }
+    Ok(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const STATUSES_RESPONSE: &str = r#"[{"id":"104546339582914356","created_at":"2020-07-20T09:01:13.000Z","in_reply_to_id":null,"sensitive":false,"spoiler_text":"","visibility":"public","language":"en","uri":"https://mastodon.social/users/Gargron/statuses/104546339582914356","url":"https://mastodon.social/@Gargron/104546339582914356","content":"<p>Mastodon 3.2 is out!</p><p>Check out the <a href=\"https://blog.joinmastodon.org/\" rel=\"nofollow noopener noreferrer\" target=\"_blank\"><span class=\"invisible\">https://</span><span class=\"\">blog.joinmastodon.org/</span></a></p>","reblog":null,"media_attachments":[]},{"id":"104546339582914357","created_at":"2020-07-19T10:00:00.000Z","spoiler_text":"cw: food","uri":"https://mastodon.social/users/Gargron/statuses/104546339582914357","url":null,"content":"","media_attachments":[]}]"#;
+
+    const WEBFINGER_RESPONSE: &str = r#"{"subject":"acct:Gargron@example.com","aliases":["https://social.example.com/@Gargron"],"links":[{"rel":"http://webfinger.net/rel/profile-page","type":"text/html","href":"https://social.example.com/@Gargron"},{"rel":"self","type":"application/activity+json","href":"https://social.example.com/users/Gargron"}]}"#;
+
+    #[test]
+    fn parse_handle_with_leading_at() {
+        assert_eq!(
+            Ok(MastodonAccount {
+                username: "Gargron".into(),
+                instance: "mastodon.social".into()
+            }),
+            MastodonAccount::parse("@Gargron@mastodon.social")
+        );
+    }
+
+    #[test]
+    fn parse_handle_without_leading_at() {
+        assert_eq!(
+            Ok(MastodonAccount {
+                username: "Gargron".into(),
+                instance: "mastodon.social".into()
+            }),
+            MastodonAccount::parse("Gargron@Mastodon.Social")
+        );
+    }
+
+    #[test]
+    fn parse_profile_url() {
+        assert_eq!(
+            Ok(MastodonAccount {
+                username: "Gargron".into(),
+                instance: "mastodon.social".into()
+            }),
+            MastodonAccount::parse("https://mastodon.social/@Gargron")
+        );
+    }
+
+    #[test]
+    fn parse_invalid_handles() {
+        assert_eq!(true, MastodonAccount::parse("Gargron").is_err());
+        assert_eq!(true, MastodonAccount::parse("@Gargron@localhost").is_err());
+        assert_eq!(
+            true,
+            MastodonAccount::parse("@Gar'gron@mastodon.social").is_err()
+        );
+        assert_eq!(true, MastodonAccount::parse("a@b@mastodon.social").is_err());
+        assert_eq!(
+            true,
+            MastodonAccount::parse("https://mastodon.social/about").is_err()
+        );
+    }
+
+    #[test]
+    fn sanitization_roundtrip() {
+        let account: SanitizedName =
+            MastodonAccount::from(SanitizedName("@Gargron@mastodon.social".into())).into();
+        assert_eq!("Gargron@mastodon.social", account.0)
+    }
+
+    #[test]
+    fn resolve_account_on_other_host() {
+        let webfinger: WebfingerResponse =
+            serde_json::from_str(WEBFINGER_RESPONSE).expect("Failed to parse json");
+        assert_eq!(
+            Ok(MastodonAccount {
+                username: "Gargron".into(),
+                instance: "social.example.com".into()
+            }),
+            resolve_webfinger(&webfinger)
+        );
+    }
+
+    #[test]
+    fn parse_statuses() {
+        let statuses: Vec<StatusResponse> =
+            serde_json::from_str(STATUSES_RESPONSE).expect("Failed to parse json");
+        let updates = statuses_to_updates(statuses).expect("Failed to parse into updates");
+        assert_eq!(
+            vec![
+                Update {
+                    ext_id: Some("104546339582914356".into()),
+                    title: "Mastodon 3.2 is out! Check out the https://blog.joinmastodon.org/"
+                        .into(),
+                    url: "https://mastodon.social/@Gargron/104546339582914356".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 7, 20).and_hms(9, 1, 13),
+                },
+                Update {
+                    ext_id: Some("104546339582914357".into()),
+                    title: "cw: food".into(),
+                    url: "https://mastodon.social/users/Gargron/statuses/104546339582914357".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 7, 19).and_hms(10, 0, 0),
+                }
+            ],
+            updates
+        );
+    }
+}
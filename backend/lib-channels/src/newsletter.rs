@@ -0,0 +1,122 @@
+use super::channel::*;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+/// mails sent to <token>@INBOX_DOMAIN are folded into the channel with that token
+pub const INBOX_DOMAIN: &str = "in.digester.local";
+
+const TOKEN_LENGTH: usize = 20;
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Newsletters that are only delivered by email. The updates are not fetched,
+/// but pushed into the database by the inbox when a mail is received.
+pub struct Newsletter {}
+
+impl Channel for Newsletter {
+    fn sanitize(&self, name: &str) -> Result<SanitizedName, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            Err("Name must not be empty".into())
+        } else if name.chars().count() > MAX_NAME_LENGTH {
+            Err(format!("Name must be at most {} chars", MAX_NAME_LENGTH))
+        } else {
+            Ok(SanitizedName(name.into()))
+        }
+    }
+
+    // every search creates a new address, because each user
+    // should get their own and the token must not be guessable
+    fn search(&self, query: SanitizedName) -> Result<Vec<ChannelInfo>, SearchError> {
+        let token = generate_token();
+        Ok(vec![ChannelInfo {
+            name: query.0,
+            link: format!("mailto:{}", address(&token)),
+            ext_id: token,
+            verified: false,
+            podcast: false,
+        }])
+    }
+
+    fn fetch_updates(&self, _ext_id: &str) -> Result<Vec<Update>, String> {
+        Ok(Vec::new())
+    }
+}
+
+/// the address to which the newsletter of the channel with this token should be sent
+pub fn address(token: &str) -> String {
+    format!("{}@{}", token, INBOX_DOMAIN)
+}
+
+/// extracts the token from a recipient (eg. '<abc@in.digester.local>'),
+/// if the address belongs to the inbox
+pub fn recipient_token(recipient: &str) -> Option<String> {
+    let recipient = recipient
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>');
+    let at = recipient.rfind('@')?;
+    let (token, domain) = (&recipient[..at], &recipient[at + 1..]);
+    let valid_token =
+        token.len() == TOKEN_LENGTH && token.chars().all(|c| c.is_ascii_alphanumeric());
+    if valid_token && domain.eq_ignore_ascii_case(INBOX_DOMAIN) {
+        Some(token.to_ascii_lowercase())
+    } else {
+        None
+    }
+}
+
+// lowercase, because some mail clients change the case of the local part
+fn generate_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(TOKEN_LENGTH)
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_name() {
+        let n = Newsletter {};
+        assert_eq!("Weekly News", n.sanitize("  Weekly News ").unwrap().0);
+        assert!(n.sanitize("   ").is_err());
+        assert!(n.sanitize(&"x".repeat(101)).is_err());
+    }
+
+    #[test]
+    fn search_generates_unique_address() {
+        let n = Newsletter {};
+        let query = || SanitizedName("Weekly News".into());
+        let first = n.search(query()).unwrap().remove(0);
+        let second = n.search(query()).unwrap().remove(0);
+        assert_eq!("Weekly News", first.name);
+        assert_eq!(TOKEN_LENGTH, first.ext_id.len());
+        assert_eq!(format!("mailto:{}", address(&first.ext_id)), first.link);
+        assert_ne!(first.ext_id, second.ext_id);
+    }
+
+    #[test]
+    fn recipient_token_roundtrip() {
+        let token = generate_token();
+        assert_eq!(Some(token.clone()), recipient_token(&address(&token)));
+        assert_eq!(
+            Some(token.clone()),
+            recipient_token(&format!("<{}>", address(&token).to_uppercase()))
+        );
+    }
+
+    #[test]
+    fn recipient_token_rejects_foreign_addresses() {
+        let token = generate_token();
+        assert_eq!(None, recipient_token(&format!("{}@example.com", token)));
+        assert_eq!(None, recipient_token("short@in.digester.local"));
+        assert_eq!(None, recipient_token("no-at-sign"));
+        assert_eq!(
+            None,
+            recipient_token(&format!("{}@in.digester.local", "a-b".repeat(7)))
+        );
+    }
+}
@@ -0,0 +1,692 @@
+use super::channel::*;
+use super::politeness;
+
+use chrono::{DateTime, Utc};
+use core::time::Duration;
+use reqwest::blocking::{Client, Response};
+use reqwest::header;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::IpAddr;
+use url::Url;
+
+const DOCKER_HUB: &str = "docker.io";
+const DOCKER_HUB_API: &str = "https://registry-1.docker.io";
+
+// every tag costs a couple of requests (and docker hub rate
+// limits manifest requests), so we only look at the newest ones
+const MAX_TAGS: usize = 5;
+
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+                              application/vnd.docker.distribution.manifest.list.v2+json, \
+                              application/vnd.oci.image.manifest.v1+json, \
+                              application/vnd.docker.distribution.manifest.v2+json";
+
+/// Follows the tags of a container image in docker hub
+/// or any other registry that speaks the distribution api
+pub struct OciImage {}
+
+/// An image in a registry (eg. docker.io/library/postgres) with an
+/// optional pattern the tags must match (eg. *.*-alpine)
+#[derive(PartialEq, Debug)]
+pub struct ImageRepository {
+    registry: String,
+    name: String,
+    pattern: Option<String>,
+}
+
+impl ImageRepository {
+    fn unsafe_parse(name: &str) -> Self {
+        Self::parse(name).unwrap()
+    }
+
+    // accepts the same names as docker pull (eg. postgres, bitnami/redis
+    // or ghcr.io/org/img) with an optional tag pattern (eg. postgres:*.*)
+    fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim().to_ascii_lowercase();
+        let (image, pattern) = match name.rfind(':') {
+            Some(idx) if !name[idx..].contains('/') => {
+                (&name[..idx], Some(name[idx + 1..].to_owned()))
+            }
+            _ => (name.as_str(), None),
+        };
+
+        let mut segments: Vec<&str> = image.split('/').collect();
+        let registry = if segments.len() > 1 && segments[0].contains('.') {
+            segments.remove(0).to_owned()
+        } else {
+            DOCKER_HUB.to_owned()
+        };
+        if registry.parse::<IpAddr>().is_ok() {
+            return Err("cannot be ip".into());
+        }
+        if registry == DOCKER_HUB && segments.len() == 1 {
+            // official images
+            segments.insert(0, "library");
+        }
+
+        let valid_segment = |s: &&str| {
+            !s.is_empty()
+                && s.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+        };
+        let valid_pattern = |p: &String| {
+            !p.is_empty()
+                && p.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.*".contains(c))
+        };
+        if !registry
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-.".contains(c))
+        {
+            Err(format!("Invalid registry: {}", registry))
+        } else if segments.is_empty() || !segments.iter().all(valid_segment) {
+            Err(format!("Invalid image: {}", image))
+        } else if !pattern.as_ref().map(valid_pattern).unwrap_or(true) {
+            Err("Tag pattern may only contain letters, digits, '-', '_', '.' and '*'".into())
+        } else {
+            Ok(ImageRepository {
+                registry,
+                name: segments.join("/"),
+                pattern,
+            })
+        }
+    }
+
+    fn api_base(&self) -> String {
+        if self.registry == DOCKER_HUB {
+            DOCKER_HUB_API.into()
+        } else {
+            format!("https://{}", self.registry)
+        }
+    }
+
+    fn to_url(&self) -> String {
+        if self.registry != DOCKER_HUB {
+            format!("https://{}/{}", self.registry, self.name)
+        } else if self.name.starts_with("library/") {
+            format!(
+                "https://hub.docker.com/_/{}",
+                &self.name["library/".len()..]
+            )
+        } else {
+            format!("https://hub.docker.com/r/{}", self.name)
+        }
+    }
+
+    // the image without registry and pattern, as people know it
+    fn short_name(&self) -> String {
+        if self.registry != DOCKER_HUB {
+            format!("{}/{}", self.registry, self.name)
+        } else if self.name.starts_with("library/") {
+            self.name["library/".len()..].to_owned()
+        } else {
+            self.name.clone()
+        }
+    }
+
+    fn matches(&self, tag: &str) -> bool {
+        match &self.pattern {
+            None => true,
+            Some(pattern) => matches_pattern(pattern.as_bytes(), tag.as_bytes()),
+        }
+    }
+}
+
+impl Display for ImageRepository {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.pattern {
+            Some(pattern) => write!(f, "{}/{}:{}", self.registry, self.name, pattern),
+            None => write!(f, "{}/{}", self.registry, self.name),
+        }
+    }
+}
+
+impl From<SanitizedName> for ImageRepository {
+    fn from(name: SanitizedName) -> Self {
+        ImageRepository::unsafe_parse(&name.0)
+    }
+}
+
+impl Into<SanitizedName> for ImageRepository {
+    fn into(self) -> SanitizedName {
+        SanitizedName(self.to_string())
+    }
+}
+
+// a '*' in the pattern matches one or more digits, everything else must
+// match literally. eg. *.*-alpine matches 12.3-alpine but not 12-alpine.
+fn matches_pattern(pattern: &[u8], tag: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => tag.is_empty(),
+        Some((b'*', rest)) => {
+            let digits = tag.iter().take_while(|c| c.is_ascii_digit()).count();
+            (1..=digits).any(|n| matches_pattern(rest, &tag[n..]))
+        }
+        Some((c, rest)) => tag.first() == Some(c) && matches_pattern(rest, &tag[1..]),
+    }
+}
+
+// compares tags so that numbers are compared by value
+// (eg. 12.10 is newer than 12.9) and everything else as text
+fn compare_tags(a: &str, b: &str) -> Ordering {
+    fn chunks(tag: &str) -> Vec<(u64, String)> {
+        let mut chunks = Vec::new();
+        let mut rest = tag;
+        while !rest.is_empty() {
+            let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+            if digits > 0 {
+                chunks.push((rest[..digits].parse().unwrap_or(u64::MAX), String::new()));
+                rest = &rest[digits..];
+            } else {
+                let text = rest.chars().take_while(|c| !c.is_ascii_digit()).count();
+                chunks.push((0, rest[..text].to_owned()));
+                rest = &rest[text..];
+            }
+        }
+        chunks
+    }
+    chunks(a).cmp(&chunks(b))
+}
+
+#[derive(Deserialize, Debug)]
+struct TagsResponse {
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ManifestResponse {
+    // set for multi platform images (index/manifest list)
+    manifests: Option<Vec<PlatformManifest>>,
+    // set for single platform images
+    config: Option<Descriptor>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PlatformManifest {
+    digest: String,
+    platform: Option<Platform>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Platform {
+    architecture: String,
+    os: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Descriptor {
+    digest: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ConfigResponse {
+    created: Option<String>,
+}
+
+struct Tag {
+    name: String,
+    digest: String,
+    created: DateTime<Utc>,
+}
+
+// talks to the distribution api of one image. anonymous access to public
+// images still requires a token (eg. docker hub or ghcr.io), which we
+// get from wherever the registry sends us on the first 401.
+struct RegistryClient<'a> {
+    base_url: String,
+    image: &'a ImageRepository,
+    client: Client,
+    token: Option<String>,
+}
+
+impl<'a> RegistryClient<'a> {
+    fn new(base_url: String, image: &'a ImageRepository) -> Result<Self, String> {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .map_err(|err| format!("Failed to build client: {:?}", err))?;
+        Ok(RegistryClient {
+            base_url,
+            image,
+            client,
+            token: None,
+        })
+    }
+
+    fn get(&mut self, path: &str, accept: &str) -> Result<Response, SearchError> {
+        let url = format!("{}/v2/{}/{}", self.base_url, self.image.name, path);
+        let resp = self.send(&url, accept)?;
+        if resp.status() == StatusCode::UNAUTHORIZED && self.token.is_none() {
+            let challenge = resp
+                .headers()
+                .get(header::WWW_AUTHENTICATE)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| {
+                    SearchError::TechnicalError(format!("401 without challenge from {}", url))
+                })?
+                .to_owned();
+            self.token = Some(self.fetch_token(&challenge)?);
+            self.check_status(self.send(&url, accept)?)
+        } else {
+            self.check_status(resp)
+        }
+    }
+
+    fn get_json<T: DeserializeOwned>(
+        &mut self,
+        path: &str,
+        accept: &str,
+    ) -> Result<T, SearchError> {
+        self.get(path, accept)?.json::<T>().map_err(|err| {
+            SearchError::TechnicalError(format!("Failed to parse json from {}: {:?}", path, err))
+        })
+    }
+
+    fn send(&self, url: &str, accept: &str) -> Result<Response, SearchError> {
+        let mut builder = self
+            .client
+            .get(url)
+            .header(header::USER_AGENT, politeness::user_agent())
+            .header(header::ACCEPT, accept);
+        if let Some(token) = &self.token {
+            builder = builder.bearer_auth(token);
+        }
+        builder.send().map_err(|err| {
+            if err.is_timeout() {
+                SearchError::Timeout(format!("Failed to fetch {}: {:?}", url, err))
+            } else {
+                SearchError::TechnicalError(format!("Failed to fetch {}: {:?}", url, err))
+            }
+        })
+    }
+
+    fn check_status(&self, resp: Response) -> Result<Response, SearchError> {
+        match resp.status() {
+            StatusCode::OK => Ok(resp),
+            // registries also return 401 for images that don't exist
+            StatusCode::NOT_FOUND | StatusCode::UNAUTHORIZED => Err(SearchError::ChannelNotFound(
+                format!("Registry returned {} for {}", resp.status(), resp.url()),
+            )),
+            status => Err(SearchError::TechnicalError(format!(
+                "Registry returned {} for {}",
+                status,
+                resp.url()
+            ))),
+        }
+    }
+
+    fn fetch_token(&self, challenge: &str) -> Result<String, SearchError> {
+        let params = parse_challenge(challenge).ok_or_else(|| {
+            SearchError::TechnicalError(format!("Unsupported challenge: {}", challenge))
+        })?;
+        let realm = params.get("realm").ok_or_else(|| {
+            SearchError::TechnicalError(format!("Challenge without realm: {}", challenge))
+        })?;
+        let mut url = Url::parse(realm)
+            .map_err(|err| SearchError::TechnicalError(format!("Invalid realm: {:?}", err)))?;
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(service) = params.get("service") {
+                query.append_pair("service", service);
+            }
+            let scope = params
+                .get("scope")
+                .cloned()
+                .unwrap_or_else(|| format!("repository:{}:pull", self.image.name));
+            query.append_pair("scope", &scope);
+        }
+
+        let resp = self.check_status(self.send(url.as_str(), "application/json")?)?;
+        let token: TokenResponse = resp.json().map_err(|err| {
+            SearchError::TechnicalError(format!("Failed to parse token: {:?}", err))
+        })?;
+        token
+            .token
+            .or(token.access_token)
+            .ok_or_else(|| SearchError::TechnicalError(format!("No token from {}", realm)))
+    }
+
+    fn tags(&mut self) -> Result<Vec<String>, SearchError> {
+        let response: TagsResponse = self.get_json("tags/list", "application/json")?;
+        Ok(response.tags.unwrap_or_default())
+    }
+
+    fn tag(&mut self, name: &str) -> Result<Tag, SearchError> {
+        let resp = self.get(&format!("manifests/{}", name), MANIFEST_TYPES)?;
+        let digest = resp
+            .headers()
+            .get("Docker-Content-Digest")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_owned())
+            .ok_or_else(|| {
+                SearchError::TechnicalError(format!("No digest for {}:{}", self.image, name))
+            })?;
+        let mut manifest: ManifestResponse = resp.json().map_err(|err| {
+            SearchError::TechnicalError(format!("Failed to parse manifest: {:?}", err))
+        })?;
+
+        // multi platform images don't have a config, so we take the
+        // one of the most common platform instead
+        if let Some(manifests) = manifest.manifests {
+            let platform = manifests
+                .iter()
+                .find(|m| {
+                    m.platform
+                        .as_ref()
+                        .map(|p| p.os == "linux" && p.architecture == "amd64")
+                        .unwrap_or(false)
+                })
+                .or_else(|| manifests.first())
+                .ok_or_else(|| {
+                    SearchError::TechnicalError(format!("Empty index for {}:{}", self.image, name))
+                })?;
+            manifest = self.get_json(&format!("manifests/{}", platform.digest), MANIFEST_TYPES)?;
+        }
+
+        let config = manifest.config.ok_or_else(|| {
+            SearchError::TechnicalError(format!("No config for {}:{}", self.image, name))
+        })?;
+        let config: ConfigResponse =
+            self.get_json(&format!("blobs/{}", config.digest), "application/json")?;
+        let created = config
+            .created
+            .ok_or_else(|| {
+                SearchError::TechnicalError(format!("No creation date for {}:{}", self.image, name))
+            })
+            .and_then(|created| {
+                DateTime::parse_from_rfc3339(&created)
+                    .map(|dt| dt.with_timezone(&Utc))
+                    .map_err(|err| {
+                        SearchError::TechnicalError(format!(
+                            "Failed to parse {} as rfc3339: {:?}",
+                            created, err
+                        ))
+                    })
+            })?;
+
+        Ok(Tag {
+            name: name.to_owned(),
+            digest,
+            created,
+        })
+    }
+}
+
+// parses a header like: Bearer realm="https://auth.docker.io/token",service="registry.docker.io"
+fn parse_challenge(challenge: &str) -> Option<HashMap<String, String>> {
+    let params = challenge.trim().strip_prefix("Bearer ")?;
+    let mut parsed = HashMap::new();
+    let mut rest = params.trim();
+    while !rest.is_empty() {
+        let eq = rest.find('=')?;
+        let key = rest[..eq].trim().to_ascii_lowercase();
+        rest = &rest[eq + 1..];
+        let value = if rest.starts_with('"') {
+            let end = rest[1..].find('"')? + 1;
+            let value = &rest[1..end];
+            rest = &rest[end + 1..];
+            value
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            let value = &rest[..end];
+            rest = &rest[end..];
+            value
+        };
+        parsed.insert(key, value.to_owned());
+        rest = rest.trim_start_matches(&[',', ' '][..]);
+    }
+    Some(parsed)
+}
+
+fn search_image(
+    base_url: String,
+    image: &ImageRepository,
+) -> Result<Vec<ChannelInfo>, SearchError> {
+    let mut client = RegistryClient::new(base_url, image).map_err(SearchError::TechnicalError)?;
+    let tags = client.tags()?;
+    if image.pattern.is_some() && !tags.iter().any(|t| image.matches(t)) {
+        return Err(SearchError::ChannelNotFound(format!(
+            "No tag of {} matches the pattern",
+            image
+        )));
+    }
+    let name = match &image.pattern {
+        Some(pattern) => format!("{}:{}", image.short_name(), pattern),
+        None => image.short_name(),
+    };
+    Ok(vec![ChannelInfo {
+        name,
+        link: image.to_url(),
+        ext_id: image.to_string(),
+        verified: false,
+        podcast: false,
+    }])
+}
+
+fn fetch_tags(base_url: String, image: &ImageRepository) -> Result<Vec<Update>, String> {
+    let show_err = |err: SearchError| format!("Failed to fetch tags of {}: {:?}", image, err);
+    let mut client = RegistryClient::new(base_url, image)?;
+
+    let mut names: Vec<String> = client
+        .tags()
+        .map_err(show_err)?
+        .into_iter()
+        .filter(|t| image.matches(t))
+        .collect();
+    names.sort_by(|a, b| compare_tags(b, a));
+    names.truncate(MAX_TAGS);
+
+    let mut updates = Vec::with_capacity(names.len());
+    for name in names {
+        let tag = client.tag(&name).map_err(show_err)?;
+        // several tags often point to the same image, so
+        // the digest alone doesn't identify the update
+        updates.push(Update {
+            ext_id: Some(format!("{}@{}", tag.name, tag.digest)),
+            title: format!("{}:{}", image.short_name(), tag.name),
+            url: image.to_url(),
+            external_url: None,
+            metadata: Metadata::default(),
+            published: tag.created,
+        });
+    }
+    Ok(updates)
+}
+
+impl Channel for OciImage {
+    fn sanitize(&self, name: &str) -> Result<SanitizedName, String> {
+        ImageRepository::parse(name).map(|i| i.into())
+    }
+
+    fn search(&self, name: SanitizedName) -> Result<Vec<ChannelInfo>, SearchError> {
+        let image = ImageRepository::from(name);
+        search_image(image.api_base(), &image)
+    }
+
+    fn fetch_updates(&self, ext_id: &str) -> Result<Vec<Update>, String> {
+        let image = ImageRepository::parse(ext_id)?;
+        fetch_tags(image.api_base(), &image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use mockito::{mock, Matcher};
+
+    fn image(registry: &str, name: &str, pattern: Option<&str>) -> ImageRepository {
+        ImageRepository {
+            registry: registry.into(),
+            name: name.into(),
+            pattern: pattern.map(|p| p.into()),
+        }
+    }
+
+    #[test]
+    fn parse_image_names() {
+        assert_eq!(
+            Ok(image("docker.io", "library/postgres", None)),
+            ImageRepository::parse("postgres")
+        );
+        assert_eq!(
+            Ok(image("docker.io", "bitnami/redis", Some("*.*.*"))),
+            ImageRepository::parse("bitnami/redis:*.*.*")
+        );
+        assert_eq!(
+            Ok(image("ghcr.io", "org/img", None)),
+            ImageRepository::parse("ghcr.io/org/img")
+        );
+        assert_eq!(true, ImageRepository::parse("127.0.0.1/img").is_err());
+        assert_eq!(true, ImageRepository::parse("postgres:1?").is_err());
+        assert_eq!(true, ImageRepository::parse("org//img").is_err());
+    }
+
+    #[test]
+    fn sanitization_roundtrip() {
+        let name: SanitizedName =
+            ImageRepository::from(SanitizedName("postgres:*-alpine".into())).into();
+        assert_eq!("docker.io/library/postgres:*-alpine", name.0);
+        assert_eq!(
+            Ok(image("docker.io", "library/postgres", Some("*-alpine"))),
+            ImageRepository::parse(&name.0)
+        );
+    }
+
+    #[test]
+    fn match_tag_patterns() {
+        let m = |p: &str, t: &str| matches_pattern(p.as_bytes(), t.as_bytes());
+        assert_eq!(true, m("*.*", "12.3"));
+        assert_eq!(true, m("*.*-alpine", "12.13-alpine"));
+        assert_eq!(false, m("*.*", "12.3-alpine"));
+        assert_eq!(false, m("*.*", "12"));
+        assert_eq!(false, m("*", "latest"));
+        assert_eq!(true, m("v*", "v12"));
+    }
+
+    #[test]
+    fn sort_tags_by_version() {
+        let mut tags = vec!["12.9", "latest", "12.10", "9.6", "12.10-alpine"];
+        tags.sort_by(|a, b| compare_tags(b, a));
+        assert_eq!(vec!["12.10-alpine", "12.10", "12.9", "9.6", "latest"], tags);
+    }
+
+    #[test]
+    fn parse_www_authenticate() {
+        let params = parse_challenge(
+            r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/postgres:pull""#,
+        )
+        .expect("Failed to parse challenge");
+        assert_eq!(
+            Some(&"https://auth.docker.io/token".to_owned()),
+            params.get("realm")
+        );
+        assert_eq!(
+            Some(&"registry.docker.io".to_owned()),
+            params.get("service")
+        );
+        assert_eq!(
+            Some(&"repository:library/postgres:pull".to_owned()),
+            params.get("scope")
+        );
+        assert_eq!(None, parse_challenge(r#"Basic realm="registry""#));
+    }
+
+    #[test]
+    fn fetch_tags_with_token() {
+        let server = mockito::server_url();
+        let challenge = format!(
+            r#"Bearer realm="{}/token",service="registry.test",scope="repository:org/img:pull""#,
+            server
+        );
+        let _unauthorized = mock("GET", "/v2/org/img/tags/list")
+            .match_header("authorization", Matcher::Missing)
+            .with_status(401)
+            .with_header("www-authenticate", &challenge)
+            .create();
+        let _token = mock("GET", "/token")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("service".into(), "registry.test".into()),
+                Matcher::UrlEncoded("scope".into(), "repository:org/img:pull".into()),
+            ]))
+            .with_body(r#"{"token":"secret"}"#)
+            .create();
+        let _tags = mock("GET", "/v2/org/img/tags/list")
+            .match_header("authorization", "Bearer secret")
+            .with_body(r#"{"name":"org/img","tags":["1.0","1.1","1.1-rc1","latest"]}"#)
+            .create();
+        let _index = mock("GET", "/v2/org/img/manifests/1.1")
+            .match_header("authorization", "Bearer secret")
+            .with_header("Docker-Content-Digest", "sha256:index11")
+            .with_body(
+                r#"{"schemaVersion":2,"manifests":[
+                {"digest":"sha256:arm","platform":{"architecture":"arm64","os":"linux"}},
+                {"digest":"sha256:amd","platform":{"architecture":"amd64","os":"linux"}}
+            ]}"#,
+            )
+            .create();
+        let _platform = mock("GET", "/v2/org/img/manifests/sha256:amd")
+            .with_body(r#"{"schemaVersion":2,"config":{"digest":"sha256:config11"}}"#)
+            .create();
+        let _manifest = mock("GET", "/v2/org/img/manifests/1.0")
+            .with_header("Docker-Content-Digest", "sha256:manifest10")
+            .with_body(r#"{"schemaVersion":2,"config":{"digest":"sha256:config10"}}"#)
+            .create();
+        let _config11 = mock("GET", "/v2/org/img/blobs/sha256:config11")
+            .with_body(r#"{"architecture":"amd64","created":"2020-07-02T10:00:00.123456789Z"}"#)
+            .create();
+        let _config10 = mock("GET", "/v2/org/img/blobs/sha256:config10")
+            .with_body(r#"{"architecture":"amd64","created":"2020-06-01T10:00:00Z"}"#)
+            .create();
+
+        let updates =
+            fetch_tags(server, &image("ghcr.io", "org/img", Some("*.*"))).expect("Failed to fetch");
+        assert_eq!(
+            vec![
+                Update {
+                    ext_id: Some("1.1@sha256:index11".into()),
+                    title: "ghcr.io/org/img:1.1".into(),
+                    url: "https://ghcr.io/org/img".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 7, 2).and_hms_nano(10, 0, 0, 123_456_789),
+                },
+                Update {
+                    ext_id: Some("1.0@sha256:manifest10".into()),
+                    title: "ghcr.io/org/img:1.0".into(),
+                    url: "https://ghcr.io/org/img".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 6, 1).and_hms(10, 0, 0),
+                },
+            ],
+            updates
+        );
+    }
+
+    #[test]
+    fn search_unknown_image() {
+        let _m = mock("GET", "/v2/library/nope/tags/list")
+            .with_status(404)
+            .create();
+
+        match search_image(
+            mockito::server_url(),
+            &image("docker.io", "library/nope", None),
+        ) {
+            Err(SearchError::ChannelNotFound(_)) => (),
+            other => panic!("Expected not found, got {:?}", other),
+        }
+    }
+}
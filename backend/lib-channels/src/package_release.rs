@@ -0,0 +1,586 @@
+use super::channel::*;
+use super::http::{self, HttpError};
+
+use chrono::naive::NaiveDateTime;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+use url::Url;
+
+const CRATES_IO_URL: &str = "https://crates.io";
+const NPM_URL: &str = "https://registry.npmjs.org";
+const PYPI_URL: &str = "https://pypi.org";
+
+// how many packages we return when searching a registry
+const MAX_SEARCH_RESULTS: usize = 10;
+
+/// Follows the releases of a rust crate on crates.io
+pub struct CratesIo {}
+
+/// Follows the releases of a package on npm
+pub struct Npm {}
+
+/// Follows the releases of a python package on pypi
+pub struct PyPi {}
+
+impl Channel for CratesIo {
+    fn sanitize(&self, name: &str) -> Result<SanitizedName, String> {
+        sanitize_package_name(name, |c| c == '-' || c == '_')
+    }
+
+    fn search(&self, name: SanitizedName) -> Result<Vec<ChannelInfo>, SearchError> {
+        crates_io_search(CRATES_IO_URL, &name.0).map_err(|err| err.into())
+    }
+
+    fn fetch_updates(&self, name: &str) -> Result<Vec<Update>, String> {
+        crates_io_versions(CRATES_IO_URL, name)
+    }
+}
+
+impl Channel for Npm {
+    fn sanitize(&self, name: &str) -> Result<SanitizedName, String> {
+        // scoped packages look like @scope/name
+        let (scope, package) = match name.trim().strip_prefix('@') {
+            Some(scoped) => match scoped.find('/') {
+                Some(idx) => (Some(&scoped[..idx]), &scoped[idx + 1..]),
+                None => return Err("Scoped package must have format @scope/name".into()),
+            },
+            None => (None, name),
+        };
+        let valid = |c: char| c == '-' || c == '_' || c == '.';
+        let SanitizedName(package) = sanitize_package_name(package, valid)?;
+        match scope {
+            Some(scope) => {
+                let SanitizedName(scope) = sanitize_package_name(scope, valid)?;
+                Ok(SanitizedName(format!("@{}/{}", scope, package)))
+            }
+            None => Ok(SanitizedName(package)),
+        }
+    }
+
+    fn search(&self, name: SanitizedName) -> Result<Vec<ChannelInfo>, SearchError> {
+        npm_search(NPM_URL, &name.0).map_err(|err| err.into())
+    }
+
+    fn fetch_updates(&self, name: &str) -> Result<Vec<Update>, String> {
+        npm_versions(NPM_URL, name)
+    }
+}
+
+impl Channel for PyPi {
+    fn sanitize(&self, name: &str) -> Result<SanitizedName, String> {
+        sanitize_package_name(name, |c| c == '-' || c == '_' || c == '.')
+    }
+
+    fn search(&self, name: SanitizedName) -> Result<Vec<ChannelInfo>, SearchError> {
+        pypi_search(PYPI_URL, &name.0).map_err(|err| err.into())
+    }
+
+    fn fetch_updates(&self, name: &str) -> Result<Vec<Update>, String> {
+        pypi_versions(PYPI_URL, name)
+    }
+}
+
+fn sanitize_package_name<F>(name: &str, is_valid_special: F) -> Result<SanitizedName, String>
+where
+    F: Fn(char) -> bool,
+{
+    let name = name.trim();
+    if name.is_empty() {
+        Err("Package name must not be empty".into())
+    } else if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !is_valid_special(*c))
+    {
+        Err(format!("Invalid character in package name: {}", c))
+    } else {
+        Ok(SanitizedName(name.to_owned()))
+    }
+}
+
+// builds the url from escaped path segments, because package
+// names may contain slashes (eg. scoped packages in npm)
+fn api_url(base_url: &str, segments: &[&str], params: &[(&str, &str)]) -> String {
+    let mut url = Url::parse(base_url).expect("base url must be valid");
+    url.path_segments_mut()
+        .expect("base url must not be cannot-be-a-base")
+        .pop_if_empty()
+        .extend(segments);
+    if !params.is_empty() {
+        url.query_pairs_mut().extend_pairs(params);
+    }
+    url.to_string()
+}
+
+fn parse_rfc3339(date: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(date)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|err| format!("Failed to parse {} as rfc3339: {:?}", date, err))
+}
+
+// crates.io and npm both use semver, where everything
+// with a dash (eg. 1.0.0-beta.1) is a pre-release
+fn is_semver_prerelease(version: &str) -> bool {
+    version.split('+').next().unwrap_or(version).contains('-')
+}
+
+// pep 440 pre-releases and development releases contain letters
+// (eg. 1.0a1, 2.0rc1 or 1.0.dev3), post releases are fine though
+fn is_pep440_prerelease(version: &str) -> bool {
+    version
+        .split('+')
+        .next()
+        .unwrap_or(version)
+        .to_ascii_lowercase()
+        .replace("post", "")
+        .chars()
+        .any(|c| c.is_ascii_alphabetic())
+}
+
+#[derive(Deserialize, Debug)]
+struct CratesSearchResponse {
+    crates: Vec<CrateResponse>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CrateResponse {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct CrateVersionsResponse {
+    versions: Vec<CrateVersionResponse>,
+}
+
+#[derive(Deserialize, Debug)]
+struct CrateVersionResponse {
+    num: String,
+    yanked: bool,
+    created_at: String,
+}
+
+fn crates_io_search(base_url: &str, query: &str) -> Result<Vec<ChannelInfo>, HttpError> {
+    let per_page = MAX_SEARCH_RESULTS.to_string();
+    let response: CratesSearchResponse = http::get_json(&api_url(
+        base_url,
+        &["api", "v1", "crates"],
+        &[("q", query), ("per_page", &per_page)],
+    ))?;
+    Ok(response
+        .crates
+        .into_iter()
+        .map(|krate| ChannelInfo {
+            link: format!("https://crates.io/crates/{}", krate.name),
+            ext_id: krate.name.clone(),
+            name: krate.name,
+            verified: false,
+            podcast: false,
+        })
+        .collect())
+}
+
+fn crates_io_versions(base_url: &str, name: &str) -> Result<Vec<Update>, String> {
+    let response: CrateVersionsResponse = http::get_json(&api_url(
+        base_url,
+        &["api", "v1", "crates", name, "versions"],
+        &[],
+    ))
+    .map_err(|err| format!("Failed to fetch versions of crate {}: {:?}", name, err))?;
+
+    let mut updates = Vec::with_capacity(response.versions.len());
+    for version in response.versions {
+        if version.yanked || is_semver_prerelease(&version.num) {
+            continue;
+        }
+        updates.push(Update {
+            title: format!("{} {}", name, version.num),
+            url: format!("https://crates.io/crates/{}/{}", name, version.num),
+            external_url: None,
+            metadata: Metadata::default(),
+            published: parse_rfc3339(&version.created_at)?,
+            ext_id: Some(version.num),
+        });
+    }
+    Ok(updates)
+}
+
+#[derive(Deserialize, Debug)]
+struct NpmSearchResponse {
+    objects: Vec<NpmSearchObject>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NpmSearchObject {
+    package: NpmSearchPackage,
+}
+
+#[derive(Deserialize, Debug)]
+struct NpmSearchPackage {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct NpmPackageResponse {
+    #[serde(default)]
+    versions: HashMap<String, NpmVersionResponse>,
+    // maps each version to its publish date. unpublished
+    // packages don't have versions, only this time map
+    #[serde(default)]
+    time: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NpmVersionResponse {
+    deprecated: Option<serde_json::Value>,
+}
+
+fn npm_link(name: &str) -> String {
+    format!("https://www.npmjs.com/package/{}", name)
+}
+
+fn npm_search(base_url: &str, query: &str) -> Result<Vec<ChannelInfo>, HttpError> {
+    let size = MAX_SEARCH_RESULTS.to_string();
+    let response: NpmSearchResponse = http::get_json(&api_url(
+        base_url,
+        &["-", "v1", "search"],
+        &[("text", query), ("size", &size)],
+    ))?;
+    Ok(response
+        .objects
+        .into_iter()
+        .map(|object| ChannelInfo {
+            link: npm_link(&object.package.name),
+            ext_id: object.package.name.clone(),
+            name: object.package.name,
+            verified: false,
+            podcast: false,
+        })
+        .collect())
+}
+
+fn npm_versions(base_url: &str, name: &str) -> Result<Vec<Update>, String> {
+    let response: NpmPackageResponse = http::get_json(&api_url(base_url, &[name], &[]))
+        .map_err(|err| format!("Failed to fetch package {}: {:?}", name, err))?;
+
+    let mut updates = Vec::with_capacity(response.versions.len());
+    for (version, details) in response.versions {
+        // npm doesn't allow removing versions, so deprecating
+        // is the closest thing to yanking them
+        if details.deprecated.is_some() || is_semver_prerelease(&version) {
+            continue;
+        }
+        let published = match response.time.get(&version).and_then(|t| t.as_str()) {
+            Some(time) => parse_rfc3339(time)?,
+            None => {
+                println!("No publish time for version {} of {}", version, name);
+                continue;
+            }
+        };
+        updates.push(Update {
+            title: format!("{} {}", name, version),
+            url: format!("{}/v/{}", npm_link(name), version),
+            external_url: None,
+            metadata: Metadata::default(),
+            published,
+            ext_id: Some(version),
+        });
+    }
+    updates.sort_by_key(|u| u.published);
+    Ok(updates)
+}
+
+#[derive(Deserialize, Debug)]
+struct PyPiPackageResponse {
+    info: PyPiInfoResponse,
+    #[serde(default)]
+    releases: HashMap<String, Vec<PyPiFileResponse>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PyPiInfoResponse {
+    name: String,
+    package_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct PyPiFileResponse {
+    upload_time_iso_8601: Option<String>,
+    upload_time: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+impl PyPiFileResponse {
+    fn uploaded(&self) -> Result<DateTime<Utc>, String> {
+        match &self.upload_time_iso_8601 {
+            Some(time) => parse_rfc3339(time),
+            // older responses only have the naive time, which is in utc
+            None => NaiveDateTime::parse_from_str(&self.upload_time, "%Y-%m-%dT%H:%M:%S")
+                .map(|naive| DateTime::from_utc(naive, Utc))
+                .map_err(|err| format!("Failed to parse {}: {:?}", self.upload_time, err)),
+        }
+    }
+}
+
+// pypi disabled its search api, so the best we can do
+// is look up the package by its exact name
+fn pypi_search(base_url: &str, query: &str) -> Result<Vec<ChannelInfo>, HttpError> {
+    let response: PyPiPackageResponse =
+        http::get_json(&api_url(base_url, &["pypi", query, "json"], &[]))?;
+    Ok(vec![ChannelInfo {
+        name: response.info.name.clone(),
+        link: response.info.package_url,
+        ext_id: response.info.name,
+        verified: false,
+        podcast: false,
+    }])
+}
+
+fn pypi_versions(base_url: &str, name: &str) -> Result<Vec<Update>, String> {
+    let response: PyPiPackageResponse =
+        http::get_json(&api_url(base_url, &["pypi", name, "json"], &[]))
+            .map_err(|err| format!("Failed to fetch package {}: {:?}", name, err))?;
+
+    let mut updates = Vec::with_capacity(response.releases.len());
+    for (version, files) in response.releases {
+        // a release is yanked as a whole, but the flag is on each file
+        if files.is_empty() || files.iter().all(|f| f.yanked) || is_pep440_prerelease(&version) {
+            continue;
+        }
+        let mut published = files[0].uploaded()?;
+        for file in &files[1..] {
+            published = published.min(file.uploaded()?);
+        }
+        updates.push(Update {
+            title: format!("{} {}", response.info.name, version),
+            url: format!("{}{}/", response.info.package_url, version),
+            external_url: None,
+            metadata: Metadata::default(),
+            published,
+            ext_id: Some(version),
+        });
+    }
+    updates.sort_by_key(|u| u.published);
+    Ok(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use mockito::{mock, Matcher};
+
+    #[test]
+    fn sanitize_package_names() {
+        assert_eq!(
+            Ok("serde_json".to_owned()),
+            CratesIo {}.sanitize(" serde_json ").map(|n| n.0)
+        );
+        assert_eq!(true, CratesIo {}.sanitize("serde json").is_err());
+        assert_eq!(
+            Ok("@angular/core".to_owned()),
+            Npm {}.sanitize("@angular/core").map(|n| n.0)
+        );
+        assert_eq!(true, Npm {}.sanitize("@angular").is_err());
+        assert_eq!(
+            Ok("zope.interface".to_owned()),
+            PyPi {}.sanitize("zope.interface").map(|n| n.0)
+        );
+        assert_eq!(true, PyPi {}.sanitize("").is_err());
+    }
+
+    #[test]
+    fn detect_prereleases() {
+        assert_eq!(true, is_semver_prerelease("1.0.0-beta.1"));
+        assert_eq!(false, is_semver_prerelease("1.0.0"));
+        assert_eq!(false, is_semver_prerelease("1.0.0+build-5"));
+        assert_eq!(true, is_pep440_prerelease("2.0rc1"));
+        assert_eq!(true, is_pep440_prerelease("1.0.dev3"));
+        assert_eq!(false, is_pep440_prerelease("1.0.post1"));
+        assert_eq!(false, is_pep440_prerelease("1.0+cpu"));
+    }
+
+    #[test]
+    fn search_crates_io() {
+        let _m = mock("GET", "/api/v1/crates")
+            .match_query(Matcher::UrlEncoded("q".into(), "serde".into()))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"crates":[{"id":"serde","name":"serde","max_version":"1.0.114"},{"id":"serde_json","name":"serde_json","max_version":"1.0.56"}],"meta":{"total":2}}"#)
+            .create();
+
+        let channels = crates_io_search(&mockito::server_url(), "serde").expect("Failed to search");
+        assert_eq!(
+            vec!["serde", "serde_json"],
+            channels
+                .iter()
+                .map(|c| c.ext_id.as_str())
+                .collect::<Vec<&str>>()
+        );
+        assert_eq!("https://crates.io/crates/serde", channels[0].link);
+    }
+
+    #[test]
+    fn fetch_crates_io_versions() {
+        let _m = mock("GET", "/api/v1/crates/serde/versions")
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"versions":[
+                {"num":"1.0.114","yanked":false,"created_at":"2020-07-10T18:41:12.456789+00:00"},
+                {"num":"1.0.113","yanked":true,"created_at":"2020-07-01T10:00:00.000000+00:00"},
+                {"num":"1.0.0-rc.1","yanked":false,"created_at":"2017-04-01T10:00:00.000000+00:00"}
+            ]}"#,
+            )
+            .create();
+
+        let updates = crates_io_versions(&mockito::server_url(), "serde").expect("Failed to fetch");
+        assert_eq!(1, updates.len());
+        assert_eq!(Some("1.0.114".into()), updates[0].ext_id);
+        assert_eq!("serde 1.0.114", updates[0].title);
+        assert_eq!("https://crates.io/crates/serde/1.0.114", updates[0].url);
+    }
+
+    #[test]
+    fn fetch_missing_crate() {
+        let _m = mock("GET", "/api/v1/crates/nope/versions")
+            .with_status(404)
+            .create();
+
+        assert_eq!(
+            true,
+            crates_io_versions(&mockito::server_url(), "nope").is_err()
+        );
+    }
+
+    #[test]
+    fn search_npm() {
+        let _m = mock("GET", "/-/v1/search")
+            .match_query(Matcher::UrlEncoded("text".into(), "left-pad".into()))
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"objects":[{"package":{"name":"left-pad","version":"1.3.0"},"score":{"final":0.9}}],"total":1}"#)
+            .create();
+
+        let channels = npm_search(&mockito::server_url(), "left-pad").expect("Failed to search");
+        assert_eq!(1, channels.len());
+        assert_eq!("left-pad", channels[0].ext_id);
+        assert_eq!("https://www.npmjs.com/package/left-pad", channels[0].link);
+    }
+
+    #[test]
+    fn fetch_npm_versions_of_scoped_package() {
+        let _m = mock("GET", "/@angular%2Fcore")
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{"name":"@angular/core","versions":{
+                "10.0.2":{"version":"10.0.2"},
+                "10.0.0-rc.1":{"version":"10.0.0-rc.1"},
+                "9.0.0":{"version":"9.0.0","deprecated":"security issue"},
+                "10.0.1":{"version":"10.0.1"}
+            },"time":{
+                "created":"2016-05-01T00:00:00.000Z",
+                "10.0.2":"2020-07-01T18:00:00.000Z",
+                "10.0.0-rc.1":"2020-06-01T18:00:00.000Z",
+                "9.0.0":"2020-02-06T18:00:00.000Z",
+                "10.0.1":"2020-06-26T18:00:00.000Z"
+            }}"#,
+            )
+            .create();
+
+        let updates =
+            npm_versions(&mockito::server_url(), "@angular/core").expect("Failed to fetch");
+        assert_eq!(
+            vec![
+                Update {
+                    ext_id: Some("10.0.1".into()),
+                    title: "@angular/core 10.0.1".into(),
+                    url: "https://www.npmjs.com/package/@angular/core/v/10.0.1".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 6, 26).and_hms(18, 0, 0),
+                },
+                Update {
+                    ext_id: Some("10.0.2".into()),
+                    title: "@angular/core 10.0.2".into(),
+                    url: "https://www.npmjs.com/package/@angular/core/v/10.0.2".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 7, 1).and_hms(18, 0, 0),
+                },
+            ],
+            updates
+        );
+    }
+
+    #[test]
+    fn fetch_unpublished_npm_package() {
+        let _m = mock("GET", "/gone")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"name":"gone","time":{"created":"2016-05-01T00:00:00.000Z","unpublished":{"time":"2016-06-01T00:00:00.000Z"}}}"#)
+            .create();
+
+        let updates = npm_versions(&mockito::server_url(), "gone").expect("Failed to fetch");
+        assert_eq!(true, updates.is_empty());
+    }
+
+    #[test]
+    fn search_pypi() {
+        let _m = mock("GET", "/pypi/Django/json")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"info":{"name":"Django","package_url":"https://pypi.org/project/Django/"},"releases":{}}"#)
+            .create();
+
+        let channels = pypi_search(&mockito::server_url(), "Django").expect("Failed to search");
+        assert_eq!(1, channels.len());
+        assert_eq!("Django", channels[0].ext_id);
+        assert_eq!("https://pypi.org/project/Django/", channels[0].link);
+    }
+
+    #[test]
+    fn search_pypi_not_found() {
+        let _m = mock("GET", "/pypi/nope/json").with_status(404).create();
+
+        match pypi_search(&mockito::server_url(), "nope") {
+            Err(HttpError::NotFound(_)) => (),
+            other => panic!("Expected not found, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fetch_pypi_versions() {
+        let _m = mock("GET", "/pypi/Django/json")
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"info":{"name":"Django","package_url":"https://pypi.org/project/Django/"},"releases":{
+                "3.0.8":[
+                    {"upload_time":"2020-07-01T07:00:00","upload_time_iso_8601":"2020-07-01T07:00:00.123456Z","yanked":false},
+                    {"upload_time":"2020-07-01T06:00:00","upload_time_iso_8601":"2020-07-01T06:00:00.123456Z","yanked":false}
+                ],
+                "3.0.7":[{"upload_time":"2020-06-03T09:36:00","upload_time_iso_8601":"2020-06-03T09:36:00.000000Z","yanked":true}],
+                "3.1rc1":[{"upload_time":"2020-07-20T09:36:00","upload_time_iso_8601":"2020-07-20T09:36:00.000000Z","yanked":false}],
+                "1.0":[{"upload_time":"2008-09-03T09:36:00"}],
+                "0.9":[]
+            }}"#)
+            .create();
+
+        let updates = pypi_versions(&mockito::server_url(), "Django").expect("Failed to fetch");
+        assert_eq!(
+            vec![
+                Update {
+                    ext_id: Some("1.0".into()),
+                    title: "Django 1.0".into(),
+                    url: "https://pypi.org/project/Django/1.0/".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2008, 9, 3).and_hms(9, 36, 0),
+                },
+                Update {
+                    ext_id: Some("3.0.8".into()),
+                    title: "Django 3.0.8".into(),
+                    url: "https://pypi.org/project/Django/3.0.8/".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 7, 1).and_hms_micro(6, 0, 0, 123_456),
+                },
+            ],
+            updates
+        );
+    }
+}
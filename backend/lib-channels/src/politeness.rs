@@ -0,0 +1,446 @@
+use super::robots::Robots;
+
+use chrono::{DateTime, Utc};
+use core::time::Duration;
+use lazy_static::lazy_static;
+use reqwest::blocking::Client;
+use reqwest::header::{self, HeaderMap};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Instant;
+use url::Url;
+
+/// Who we are to the sites we fetch from. The contact url should tell
+/// site owners what we do and how to reach us if we cause them trouble.
+pub const DEFAULT_CONTACT_URL: &str = "https://digester.app";
+
+// how long we keep the robots.txt of a host
+const ROBOTS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+// how long we keep that a host has no (readable) robots.txt
+const ROBOTS_ERROR_TTL: Duration = Duration::from_secs(60 * 60);
+// no matter what a site asks for, we won't wait longer between requests..
+const MAX_CRAWL_DELAY: Duration = Duration::from_secs(60);
+// ..or keep away for longer than a day
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+// if we are rate limited without being told for how long
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(10 * 60);
+
+/// How we behave towards the hosts we fetch from. The default has no
+/// limits, because it is also used when users search for channels.
+#[derive(Clone, Debug)]
+pub struct Politeness {
+    /// sent with every request
+    pub user_agent: String,
+    /// minimum time between two requests to the same host
+    pub host_delay: Duration,
+    /// how many requests may be in flight to the same host at a time
+    pub host_concurrency: usize,
+    /// if true, we don't crawl feeds and pages the robots.txt disallows
+    pub respect_robots: bool,
+}
+
+impl Default for Politeness {
+    fn default() -> Self {
+        Politeness {
+            user_agent: user_agent_with_contact(DEFAULT_CONTACT_URL),
+            host_delay: Duration::from_secs(0),
+            host_concurrency: usize::MAX,
+            respect_robots: false,
+        }
+    }
+}
+
+/// eg. 'Digester/0.1.0 (+https://digester.app)'
+pub fn user_agent_with_contact(contact_url: &str) -> String {
+    format!("Digester/{} (+{})", env!("CARGO_PKG_VERSION"), contact_url)
+}
+
+lazy_static! {
+    static ref POLITENESS: Mutex<Politeness> = Mutex::new(Politeness::default());
+    static ref HOSTS: Hosts = Hosts::default();
+}
+
+/// Sets how all requests of this process behave. Meant to be
+/// called once at startup (eg. by the worker).
+pub fn configure(politeness: Politeness) {
+    *lock(&POLITENESS) = politeness;
+}
+
+/// The user agent we send with every request
+pub fn user_agent() -> String {
+    lock(&POLITENESS).user_agent.clone()
+}
+
+fn politeness() -> Politeness {
+    lock(&POLITENESS).clone()
+}
+
+/// What a request needs to do before it is sent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    /// eg. an api, which are not meant to be crawled
+    Api,
+    /// eg. a feed or a web page, for which we check the robots.txt
+    Crawl,
+}
+
+/// While this is held, the request counts towards the concurrency
+/// limit of its host. Drop it once the response arrived.
+pub struct HostPermit<'a> {
+    hosts: &'a Hosts,
+    host: String,
+}
+
+impl Drop for HostPermit<'_> {
+    fn drop(&mut self) {
+        let mut states = lock(&self.hosts.states);
+        if let Some(state) = states.get_mut(&self.host) {
+            state.active = state.active.saturating_sub(1);
+        }
+        self.hosts.released.notify_all();
+    }
+}
+
+/// Waits until we may send a request to the host of the url. Fails if
+/// the robots.txt disallows it or the host asked us to come back later.
+pub fn acquire(url: &str, access: Access) -> Result<HostPermit<'static>, String> {
+    HOSTS.acquire(&politeness(), url, access)
+}
+
+/// Remembers if the host asks us to slow down (429 or 503 with Retry-After)
+pub fn record_response(url: &str, status: StatusCode, headers: &HeaderMap) {
+    HOSTS.record_response(url, status, headers, Utc::now())
+}
+
+#[derive(Default)]
+struct Hosts {
+    states: Mutex<HashMap<String, HostState>>,
+    released: Condvar,
+    robots: Mutex<HashMap<String, (Option<Robots>, Instant)>>,
+}
+
+#[derive(Default)]
+struct HostState {
+    active: usize,
+    next_request: Option<Instant>,
+    blocked_until: Option<DateTime<Utc>>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Hosts {
+    fn acquire(
+        &self,
+        politeness: &Politeness,
+        url: &str,
+        access: Access,
+    ) -> Result<HostPermit<'_>, String> {
+        let url = Url::parse(url).map_err(|err| format!("Invalid url {}: {:?}", url, err))?;
+        let host = host(&url);
+
+        if access == Access::Crawl && politeness.respect_robots {
+            let allowed = self.with_robots(politeness, &url, |robots| {
+                let path = match url.query() {
+                    Some(query) => format!("{}?{}", url.path(), query),
+                    None => url.path().to_owned(),
+                };
+                robots.is_allowed(&path)
+            })?;
+            if !allowed {
+                return Err(format!("The robots.txt of {} disallows {}", host, url));
+            }
+        }
+
+        let wait = {
+            let mut states = lock(&self.states);
+            loop {
+                let state = states.entry(host.clone()).or_default();
+                if let Some(until) = state.blocked_until.filter(|until| *until > Utc::now()) {
+                    return Err(format!("{} asked us to come back after {}", host, until));
+                }
+                if state.active < politeness.host_concurrency.max(1) {
+                    break;
+                }
+                states = self
+                    .released
+                    .wait(states)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+            }
+            let state = states.get_mut(&host).expect("Host state vanished");
+            state.active += 1;
+
+            let now = Instant::now();
+            let delay = state
+                .crawl_delay
+                .map(|d| d.min(MAX_CRAWL_DELAY))
+                .unwrap_or_default()
+                .max(politeness.host_delay);
+            let at = state.next_request.filter(|at| *at > now).unwrap_or(now);
+            state.next_request = Some(at + delay);
+            at - now
+        };
+
+        let permit = HostPermit {
+            hosts: self,
+            host: host.clone(),
+        };
+        if wait > Duration::from_secs(0) {
+            thread::sleep(wait);
+        }
+        Ok(permit)
+    }
+
+    // the robots.txt of the host, which we fetch if we don't know it yet
+    fn with_robots<T, F: FnOnce(&Robots) -> T>(
+        &self,
+        politeness: &Politeness,
+        url: &Url,
+        f: F,
+    ) -> Result<T, String> {
+        let host = host(url);
+        let cached = lock(&self.robots)
+            .get(&host)
+            .map(|(_, expires)| *expires > Instant::now())
+            .unwrap_or(false);
+        if !cached {
+            let robots_url = url
+                .join("/robots.txt")
+                .map_err(|err| format!("Invalid robots.txt url for {}: {:?}", url, err))?;
+            let robots = {
+                let _permit = self.acquire(politeness, robots_url.as_str(), Access::Api)?;
+                fetch_robots(&robots_url, &politeness.user_agent)
+            };
+            let ttl = if robots.is_some() {
+                ROBOTS_TTL
+            } else {
+                ROBOTS_ERROR_TTL
+            };
+            let crawl_delay = robots.as_ref().and_then(|r| r.crawl_delay());
+            lock(&self.states)
+                .entry(host.clone())
+                .or_default()
+                .crawl_delay = crawl_delay;
+            lock(&self.robots).insert(host.clone(), (robots, Instant::now() + ttl));
+        }
+
+        let robots = lock(&self.robots);
+        match robots.get(&host) {
+            Some((Some(robots), _)) => Ok(f(robots)),
+            _ => Ok(f(&Robots::default())),
+        }
+    }
+
+    fn record_response(
+        &self,
+        url: &str,
+        status: StatusCode,
+        headers: &HeaderMap,
+        now: DateTime<Utc>,
+    ) {
+        if status != StatusCode::TOO_MANY_REQUESTS && status != StatusCode::SERVICE_UNAVAILABLE {
+            return;
+        }
+        let retry_after = headers
+            .get(header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| parse_retry_after(v, now));
+        // a 503 without retry-after is most likely just an error
+        let retry_after = match retry_after {
+            Some(retry_after) => retry_after,
+            None if status == StatusCode::TOO_MANY_REQUESTS => DEFAULT_RETRY_AFTER,
+            None => return,
+        };
+        if let Ok(url) = Url::parse(url) {
+            let until = now
+                + chrono::Duration::from_std(retry_after.min(MAX_RETRY_AFTER))
+                    .unwrap_or_else(|_| chrono::Duration::zero());
+            println!("{} asked us to come back after {}", host(&url), until);
+            lock(&self.states)
+                .entry(host(&url))
+                .or_default()
+                .blocked_until = Some(until);
+        }
+    }
+}
+
+// only fails if we couldn't read it, a missing robots.txt allows everything
+fn fetch_robots(url: &Url, user_agent: &str) -> Option<Robots> {
+    let response = Client::builder()
+        .timeout(Duration::from_secs(3))
+        .build()
+        .and_then(|client| {
+            client
+                .get(url.as_str())
+                .header(header::USER_AGENT, user_agent)
+                .send()
+        });
+    match response {
+        Ok(resp) if resp.status().is_success() => match resp.text() {
+            Ok(text) => Some(Robots::parse(&text, user_agent)),
+            Err(err) => {
+                eprintln!("Failed to read {}: {:?}", url, err);
+                None
+            }
+        },
+        Ok(resp) if resp.status().is_client_error() => Some(Robots::default()),
+        Ok(resp) => {
+            eprintln!("Server returned code {} for {}", resp.status(), url);
+            None
+        }
+        Err(err) => {
+            eprintln!("Failed to fetch {}: {:?}", url, err);
+            None
+        }
+    }
+}
+
+// either seconds or a date (eg. 'Wed, 21 Oct 2015 07:28:00 GMT')
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => DateTime::parse_from_rfc2822(value)
+            .ok()
+            .and_then(|date| (date.with_timezone(&Utc) - now).to_std().ok()),
+    }
+}
+
+fn host(url: &Url) -> String {
+    match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or(""), port),
+        None => url.host_str().unwrap_or("").to_owned(),
+    }
+}
+
+// a panic while holding the lock doesn't make the state invalid
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use reqwest::header::HeaderValue;
+
+    fn politeness(host_delay: Duration, host_concurrency: usize) -> Politeness {
+        Politeness {
+            host_delay,
+            host_concurrency,
+            ..Politeness::default()
+        }
+    }
+
+    #[test]
+    fn wait_between_requests_to_same_host() {
+        let hosts = Hosts::default();
+        let politeness = politeness(Duration::from_millis(200), 10);
+        let start = Instant::now();
+        hosts
+            .acquire(&politeness, "https://a.test/1", Access::Api)
+            .unwrap();
+        hosts
+            .acquire(&politeness, "https://b.test/1", Access::Api)
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_millis(200));
+        hosts
+            .acquire(&politeness, "https://a.test/2", Access::Api)
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn limit_concurrent_requests_to_same_host() {
+        let hosts: &'static Hosts = Box::leak(Box::new(Hosts::default()));
+        let politeness = politeness(Duration::from_secs(0), 1);
+        let first = hosts
+            .acquire(&politeness, "https://a.test/1", Access::Api)
+            .unwrap();
+
+        let other = politeness.clone();
+        let second = thread::spawn(move || {
+            let start = Instant::now();
+            hosts
+                .acquire(&other, "https://a.test/2", Access::Api)
+                .unwrap();
+            start.elapsed()
+        });
+        thread::sleep(Duration::from_millis(100));
+        drop(first);
+        assert!(second.join().unwrap() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn come_back_after_retry_after() {
+        let hosts = Hosts::default();
+        let politeness = Politeness::default();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::RETRY_AFTER, HeaderValue::from_static("120"));
+
+        // 200 and a 503 without retry-after don't matter
+        hosts.record_response("https://a.test/", StatusCode::OK, &headers, Utc::now());
+        hosts.record_response(
+            "https://a.test/",
+            StatusCode::SERVICE_UNAVAILABLE,
+            &HeaderMap::new(),
+            Utc::now(),
+        );
+        assert!(hosts
+            .acquire(&politeness, "https://a.test/", Access::Api)
+            .is_ok());
+
+        hosts.record_response(
+            "https://a.test/",
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers,
+            Utc::now(),
+        );
+        assert!(hosts
+            .acquire(&politeness, "https://a.test/feed", Access::Api)
+            .is_err());
+        assert!(hosts
+            .acquire(&politeness, "https://b.test/", Access::Api)
+            .is_ok());
+
+        // long ago
+        let past = Utc.ymd(2020, 1, 1).and_hms(0, 0, 0);
+        hosts.record_response(
+            "https://c.test/",
+            StatusCode::TOO_MANY_REQUESTS,
+            &headers,
+            past,
+        );
+        assert!(hosts
+            .acquire(&politeness, "https://c.test/", Access::Api)
+            .is_ok());
+    }
+
+    #[test]
+    fn parse_retry_after_values() {
+        let now = Utc.ymd(2015, 10, 21).and_hms(7, 0, 0);
+        assert_eq!(
+            Some(Duration::from_secs(120)),
+            parse_retry_after(" 120 ", now)
+        );
+        assert_eq!(
+            Some(Duration::from_secs(28 * 60)),
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT", now)
+        );
+        // in the past
+        assert_eq!(
+            None,
+            parse_retry_after("Wed, 21 Oct 2015 06:28:00 GMT", now)
+        );
+        assert_eq!(None, parse_retry_after("soon", now));
+    }
+
+    #[test]
+    fn honest_user_agent() {
+        assert!(user_agent_with_contact("https://example.org/bot").starts_with("Digester/"));
+        assert!(user_agent_with_contact("https://example.org/bot")
+            .ends_with(" (+https://example.org/bot)"));
+    }
+}
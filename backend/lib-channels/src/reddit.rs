@@ -0,0 +1,435 @@
+use super::channel::*;
+use super::http::{self, HttpError};
+
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+use serde::Deserialize;
+use std::fmt::Display;
+
+const REDDIT_URL: &str = "https://www.reddit.com";
+
+const REDDIT_HOSTS: [&str; 5] = [
+    "reddit.com",
+    "www.reddit.com",
+    "old.reddit.com",
+    "new.reddit.com",
+    "np.reddit.com",
+];
+
+// reddit doesn't return more than 100 posts per listing
+const MAX_NEW_POSTS: usize = 100;
+const MAX_TOP_POSTS: usize = 10;
+
+/// Follows the posts of a subreddit
+pub struct Reddit {}
+
+/// Which posts of a subreddit end up in the digest. The top modes
+/// match the frequencies of subscriptions, so that a weekly digest
+/// gets the best posts of the week rather than the newest ones.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Mode {
+    /// all posts as they come in
+    New,
+    /// the top posts of the last day
+    TopOfDay,
+    /// the top posts of the last week
+    TopOfWeek,
+}
+
+#[derive(PartialEq, Debug)]
+struct Subreddit {
+    name: String,
+    mode: Mode,
+}
+
+impl Subreddit {
+    fn unsafe_parse(name: &str) -> Self {
+        Self::parse(name).unwrap()
+    }
+
+    // accepts r/rust, /r/rust, rust and urls like
+    // https://www.reddit.com/r/rust/top/?t=week
+    fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        let name = name
+            .strip_prefix("https://")
+            .or_else(|| name.strip_prefix("http://"))
+            .unwrap_or(name);
+        let (path, query) = match name.find('?') {
+            Some(idx) => (&name[..idx], Some(&name[idx + 1..])),
+            None => (name, None),
+        };
+
+        let mut segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        if let Some(host) = segments.first() {
+            if host.contains('.') {
+                if !REDDIT_HOSTS.contains(&host.to_ascii_lowercase().as_str()) {
+                    return Err(format!("Not a reddit url: {}", host));
+                }
+                segments.remove(0);
+            }
+        }
+        if segments.first().map(|s| s.eq_ignore_ascii_case("r")) == Some(true) {
+            segments.remove(0);
+        }
+
+        let subreddit = match segments.first() {
+            Some(subreddit) => subreddit.to_ascii_lowercase(),
+            None => return Err("Missing name of subreddit".into()),
+        };
+        if subreddit.len() < 2
+            || subreddit.len() > 21
+            || !subreddit
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(format!("Invalid subreddit: {}", subreddit));
+        }
+
+        let period = query
+            .into_iter()
+            .flat_map(|q| q.split('&'))
+            .find_map(|param| param.strip_prefix("t="));
+        let mode = match (&segments[1..], period) {
+            ([], _) | (["new"], _) => Mode::New,
+            (["top"], None) | (["top"], Some("day")) | (["top", "day"], _) => Mode::TopOfDay,
+            (["top"], Some("week")) | (["top", "week"], _) => Mode::TopOfWeek,
+            _ => {
+                return Err(format!(
+                    "Unsupported listing of {}, must be new, top of day or top of week",
+                    subreddit
+                ))
+            }
+        };
+
+        Ok(Subreddit {
+            name: subreddit,
+            mode,
+        })
+    }
+
+    fn with_mode(self, mode: Mode) -> Self {
+        Subreddit { mode, ..self }
+    }
+
+    fn to_url(&self) -> String {
+        match self.mode {
+            Mode::New => format!("{}/r/{}/new/", REDDIT_URL, self.name),
+            Mode::TopOfDay => format!("{}/r/{}/top/?t=day", REDDIT_URL, self.name),
+            Mode::TopOfWeek => format!("{}/r/{}/top/?t=week", REDDIT_URL, self.name),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.mode {
+            Mode::New => format!("r/{}", self.name),
+            Mode::TopOfDay => format!("r/{} (top of the day)", self.name),
+            Mode::TopOfWeek => format!("r/{} (top of the week)", self.name),
+        }
+    }
+
+    fn to_channel_info(&self) -> ChannelInfo {
+        ChannelInfo {
+            name: self.describe(),
+            link: self.to_url(),
+            ext_id: self.to_string(),
+            verified: false,
+            podcast: false,
+        }
+    }
+}
+
+impl Display for Subreddit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.mode {
+            Mode::New => write!(f, "r/{}", self.name),
+            Mode::TopOfDay => write!(f, "r/{}/top/day", self.name),
+            Mode::TopOfWeek => write!(f, "r/{}/top/week", self.name),
+        }
+    }
+}
+
+impl From<SanitizedName> for Subreddit {
+    fn from(name: SanitizedName) -> Self {
+        Subreddit::unsafe_parse(&name.0)
+    }
+}
+
+impl Into<SanitizedName> for Subreddit {
+    fn into(self) -> SanitizedName {
+        SanitizedName(self.to_string())
+    }
+}
+
+/// Returns the mode of a channel by its ext_id
+pub fn channel_mode(ext_id: &str) -> Result<Mode, String> {
+    Subreddit::parse(ext_id).map(|s| s.mode)
+}
+
+/// Returns the channel of the same subreddit with a different mode
+/// (eg. to match the frequency of a subscription)
+pub fn channel_with_mode(ext_id: &str, mode: Mode) -> Result<ChannelInfo, String> {
+    Subreddit::parse(ext_id).map(|s| s.with_mode(mode).to_channel_info())
+}
+
+#[derive(Deserialize, Debug)]
+struct AboutResponse {
+    kind: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListingResponse {
+    data: ListingData,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListingData {
+    children: Vec<ListingChild>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListingChild {
+    data: Post,
+}
+
+#[derive(Deserialize, Debug)]
+struct Post {
+    id: String,
+    title: String,
+    permalink: String,
+    url: Option<String>,
+    created_utc: f64,
+    is_self: bool,
+    stickied: bool,
+}
+
+fn search_subreddit(base_url: &str, subreddit: &Subreddit) -> Result<ChannelInfo, HttpError> {
+    // unknown subreddits redirect to the search, which isn't a t5 (subreddit)
+    let about: AboutResponse =
+        http::get_json(&format!("{}/r/{}/about.json", base_url, subreddit.name))?;
+    if about.kind == "t5" {
+        Ok(subreddit.to_channel_info())
+    } else {
+        Err(HttpError::NotFound(format!(
+            "Subreddit {} does not exist",
+            subreddit.name
+        )))
+    }
+}
+
+// the top posts of a period keep changing until the period is over. we
+// therefore take the top posts once per period and publish them at the
+// beginning of the period. later fetches in the same period are then
+// ignored by the fetcher, since they are older than the last update.
+fn period_start(mode: Mode, now: DateTime<Utc>) -> DateTime<Utc> {
+    let today = Utc.ymd(now.year(), now.month(), now.day()).and_hms(0, 0, 0);
+    match mode {
+        Mode::New | Mode::TopOfDay => today,
+        Mode::TopOfWeek => today - Duration::days(now.weekday().num_days_from_monday().into()),
+    }
+}
+
+fn fetch_posts(
+    base_url: &str,
+    subreddit: &Subreddit,
+    now: DateTime<Utc>,
+) -> Result<Vec<Update>, String> {
+    let url = match subreddit.mode {
+        Mode::New => format!(
+            "{}/r/{}/new.json?limit={}",
+            base_url, subreddit.name, MAX_NEW_POSTS
+        ),
+        Mode::TopOfDay => format!(
+            "{}/r/{}/top.json?t=day&limit={}",
+            base_url, subreddit.name, MAX_TOP_POSTS
+        ),
+        Mode::TopOfWeek => format!(
+            "{}/r/{}/top.json?t=week&limit={}",
+            base_url, subreddit.name, MAX_TOP_POSTS
+        ),
+    };
+    let listing: ListingResponse = http::get_json(&url)
+        .map_err(|err| format!("Failed to fetch posts of {}: {:?}", subreddit, err))?;
+
+    Ok(listing
+        .data
+        .children
+        .into_iter()
+        .map(|child| child.data)
+        .filter(|post| !post.stickied)
+        .map(|post| Update {
+            ext_id: Some(post.id),
+            title: post.title,
+            url: format!("{}{}", REDDIT_URL, post.permalink),
+            external_url: if post.is_self { None } else { post.url },
+            metadata: Metadata::default(),
+            published: match subreddit.mode {
+                Mode::New => Utc.timestamp(post.created_utc as i64, 0),
+                mode => period_start(mode, now),
+            },
+        })
+        .collect())
+}
+
+impl Channel for Reddit {
+    fn sanitize(&self, name: &str) -> Result<SanitizedName, String> {
+        Subreddit::parse(name).map(|s| s.into())
+    }
+
+    fn search(&self, name: SanitizedName) -> Result<Vec<ChannelInfo>, SearchError> {
+        let subreddit = Subreddit::from(name);
+        search_subreddit(REDDIT_URL, &subreddit)
+            .map(|info| vec![info])
+            .map_err(|err| err.into())
+    }
+
+    fn fetch_updates(&self, ext_id: &str) -> Result<Vec<Update>, String> {
+        let subreddit = Subreddit::parse(ext_id)?;
+        fetch_posts(REDDIT_URL, &subreddit, Utc::now())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, Matcher};
+
+    fn subreddit(name: &str, mode: Mode) -> Subreddit {
+        Subreddit {
+            name: name.into(),
+            mode,
+        }
+    }
+
+    #[test]
+    fn parse_subreddit_names() {
+        assert_eq!(Ok(subreddit("rust", Mode::New)), Subreddit::parse("r/rust"));
+        assert_eq!(
+            Ok(subreddit("rust", Mode::New)),
+            Subreddit::parse("/r/Rust/")
+        );
+        assert_eq!(Ok(subreddit("rust", Mode::New)), Subreddit::parse("rust"));
+        assert_eq!(
+            Ok(subreddit("rust", Mode::TopOfWeek)),
+            Subreddit::parse("https://www.reddit.com/r/rust/top/?t=week")
+        );
+        assert_eq!(
+            Ok(subreddit("rust", Mode::TopOfDay)),
+            Subreddit::parse("old.reddit.com/r/rust/top")
+        );
+        assert_eq!(
+            Ok(subreddit("rust", Mode::TopOfDay)),
+            Subreddit::parse("r/rust/top/day")
+        );
+        assert_eq!(
+            true,
+            Subreddit::parse("https://example.com/r/rust").is_err()
+        );
+        assert_eq!(true, Subreddit::parse("r/rust/top/?t=all").is_err());
+        assert_eq!(true, Subreddit::parse("r/").is_err());
+    }
+
+    #[test]
+    fn ext_id_roundtrip() {
+        for mode in &[Mode::New, Mode::TopOfDay, Mode::TopOfWeek] {
+            let sub = subreddit("rust", *mode);
+            assert_eq!(
+                Ok(subreddit("rust", *mode)),
+                Subreddit::parse(&sub.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn switch_mode() {
+        assert_eq!(
+            Ok(ChannelInfo {
+                name: "r/rust (top of the week)".into(),
+                link: "https://www.reddit.com/r/rust/top/?t=week".into(),
+                ext_id: "r/rust/top/week".into(),
+                verified: false,
+                podcast: false,
+            }),
+            channel_with_mode("r/rust", Mode::TopOfWeek)
+        );
+        assert_eq!(Ok(Mode::New), channel_mode("r/rust"));
+        assert_eq!(Ok(Mode::TopOfDay), channel_mode("r/rust/top/day"));
+    }
+
+    #[test]
+    fn top_posts_are_published_at_period_start() {
+        // a wednesday
+        let now = Utc.ymd(2020, 7, 15).and_hms(13, 0, 0);
+        assert_eq!(
+            Utc.ymd(2020, 7, 15).and_hms(0, 0, 0),
+            period_start(Mode::TopOfDay, now)
+        );
+        assert_eq!(
+            Utc.ymd(2020, 7, 13).and_hms(0, 0, 0),
+            period_start(Mode::TopOfWeek, now)
+        );
+    }
+
+    #[test]
+    fn fetch_top_posts() {
+        let now = Utc.ymd(2020, 7, 15).and_hms(13, 0, 0);
+        let _m = mock("GET", "/r/rust/top.json")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("t".into(), "week".into()),
+                Matcher::UrlEncoded("limit".into(), "10".into()),
+            ]))
+            .with_body(
+                r#"{"kind":"Listing","data":{"children":[
+                {"kind":"t3","data":{"id":"hu7mb3","title":"Announcing Rust 1.45.0","permalink":"/r/rust/comments/hu7mb3/announcing_rust_1450/","url":"https://blog.rust-lang.org/2020/07/16/Rust-1.45.0.html","created_utc":1594911603.0,"is_self":false,"stickied":false}},
+                {"kind":"t3","data":{"id":"hsw1ta","title":"What's everyone working on this week?","permalink":"/r/rust/comments/hsw1ta/whats_everyone_working_on/","url":"https://www.reddit.com/r/rust/comments/hsw1ta/whats_everyone_working_on/","created_utc":1594600000.0,"is_self":true,"stickied":true}},
+                {"kind":"t3","data":{"id":"htzq1a","title":"My rust journey","permalink":"/r/rust/comments/htzq1a/my_rust_journey/","url":"https://www.reddit.com/r/rust/comments/htzq1a/my_rust_journey/","created_utc":1594880000.0,"is_self":true,"stickied":false}}
+            ]}}"#,
+            )
+            .create();
+
+        let updates = fetch_posts(
+            &mockito::server_url(),
+            &subreddit("rust", Mode::TopOfWeek),
+            now,
+        )
+        .expect("Failed to fetch");
+        assert_eq!(
+            vec![
+                Update {
+                    ext_id: Some("hu7mb3".into()),
+                    title: "Announcing Rust 1.45.0".into(),
+                    url: "https://www.reddit.com/r/rust/comments/hu7mb3/announcing_rust_1450/"
+                        .into(),
+                    external_url: Some(
+                        "https://blog.rust-lang.org/2020/07/16/Rust-1.45.0.html".into()
+                    ),
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 7, 13).and_hms(0, 0, 0),
+                },
+                Update {
+                    ext_id: Some("htzq1a".into()),
+                    title: "My rust journey".into(),
+                    url: "https://www.reddit.com/r/rust/comments/htzq1a/my_rust_journey/".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 7, 13).and_hms(0, 0, 0),
+                },
+            ],
+            updates
+        );
+    }
+
+    #[test]
+    fn search_unknown_subreddit() {
+        let _m = mock("GET", "/r/doesnotexist/about.json")
+            .with_body(r#"{"kind":"Listing","data":{"children":[]}}"#)
+            .create();
+
+        match search_subreddit(
+            &mockito::server_url(),
+            &subreddit("doesnotexist", Mode::New),
+        ) {
+            Err(HttpError::NotFound(_)) => (),
+            other => panic!("Expected not found, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use core::time::Duration;
+
+/// The rules of a robots.txt that apply to us (see https://www.rfc-editor.org/rfc/rfc9309)
+#[derive(Debug, Default, PartialEq)]
+pub struct Robots {
+    // (allow, path pattern)
+    rules: Vec<(bool, String)>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    /// Parses the robots.txt and keeps the rules of the group for `agent`
+    /// (eg. 'digester') or, if there is none, those of the group for '*'.
+    pub fn parse(robots_txt: &str, agent: &str) -> Robots {
+        let agent = agent.to_ascii_lowercase();
+        let mut ours = Robots::default();
+        let mut anyone = Robots::default();
+        let mut found_ours = false;
+
+        // a group starts with one or more user-agent lines
+        let mut group_agents: Vec<String> = Vec::new();
+        let mut in_rules = false;
+        for line in robots_txt.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut parts = line.splitn(2, ':');
+            let (key, value) = match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) => (key.trim().to_ascii_lowercase(), value.trim()),
+                _ => continue,
+            };
+
+            if key == "user-agent" {
+                if in_rules {
+                    group_agents.clear();
+                    in_rules = false;
+                }
+                group_agents.push(value.to_ascii_lowercase());
+                continue;
+            }
+            if key == "sitemap" {
+                // not part of any group
+                continue;
+            }
+            in_rules = true;
+
+            let is_ours = group_agents
+                .iter()
+                .any(|a| !a.is_empty() && agent.contains(a.as_str()));
+            let is_anyones = group_agents.iter().any(|a| a == "*");
+            let mut targets: Vec<&mut Robots> = Vec::new();
+            if is_ours {
+                found_ours = true;
+                targets.push(&mut ours);
+            } else if is_anyones {
+                targets.push(&mut anyone);
+            }
+            for robots in targets {
+                match key.as_str() {
+                    "allow" if !value.is_empty() => robots.rules.push((true, value.to_owned())),
+                    // an empty disallow allows everything
+                    "disallow" if !value.is_empty() => robots.rules.push((false, value.to_owned())),
+                    "crawl-delay" => {
+                        robots.crawl_delay = value
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|d| *d >= 0.0)
+                            .map(|d| Duration::from_millis((d * 1000.0) as u64))
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if found_ours {
+            ours
+        } else {
+            anyone
+        }
+    }
+
+    /// Whether we may fetch the path (including the query). The most
+    /// specific (longest) rule wins and allow wins over an equal disallow.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        let mut best: Option<(usize, bool)> = None;
+        for (allow, pattern) in &self.rules {
+            if matches(pattern, path) {
+                let better = match best {
+                    None => true,
+                    Some((length, allowed)) => {
+                        pattern.len() > length || (pattern.len() == length && *allow && !allowed)
+                    }
+                };
+                if better {
+                    best = Some((pattern.len(), *allow));
+                }
+            }
+        }
+        best.map(|(_, allow)| allow).unwrap_or(true)
+    }
+
+    /// How long the site wants us to wait between two requests
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+}
+
+// patterns match the beginning of the path, '*' matches any
+// characters and a '$' at the end matches the end of the path
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    if !path.starts_with(first) {
+        return false;
+    }
+    let mut rest = &path[first.len()..];
+    let parts: Vec<&str> = parts.collect();
+    for (index, part) in parts.iter().enumerate() {
+        let is_last = index == parts.len() - 1;
+        if is_last && anchored {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(start) => rest = &rest[start + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS_TXT: &str = "
+# comments are ignored
+User-agent: *
+Disallow: /private/
+Allow: /private/feed.xml
+Disallow: /*.pdf$
+Crawl-delay: 5
+
+User-agent: BadBot
+User-agent: OtherBot
+Disallow: /
+";
+
+    #[test]
+    fn rules_for_anyone() {
+        let robots = Robots::parse(ROBOTS_TXT, "Digester");
+        assert!(robots.is_allowed("/blog/feed.xml"));
+        assert!(!robots.is_allowed("/private/drafts"));
+        assert!(robots.is_allowed("/private/feed.xml"));
+        assert!(!robots.is_allowed("/papers/a.pdf"));
+        assert!(robots.is_allowed("/papers/a.pdf?download=1"));
+        assert_eq!(Some(Duration::from_secs(5)), robots.crawl_delay());
+    }
+
+    #[test]
+    fn rules_for_us() {
+        let robots = Robots::parse(ROBOTS_TXT, "Mozilla/5.0 (compatible; OtherBot/1.0)");
+        assert!(!robots.is_allowed("/blog/feed.xml"));
+        assert_eq!(None, robots.crawl_delay());
+
+        let robots_txt = "User-agent: *\nDisallow: /\n\nUser-agent: digester\nDisallow:\n";
+        let robots = Robots::parse(robots_txt, "Digester/1.0 (+https://digester.app/bot)");
+        assert!(robots.is_allowed("/feed.xml"));
+    }
+
+    #[test]
+    fn no_rules() {
+        assert!(Robots::parse("", "digester").is_allowed("/anything"));
+        assert!(Robots::parse("<html>Not found</html>", "digester").is_allowed("/"));
+        assert!(Robots::parse("User-agent: *\nDisallow:\n", "digester").is_allowed("/"));
+    }
+
+    #[test]
+    fn match_patterns() {
+        assert!(matches("/", "/anything"));
+        assert!(matches("/feed", "/feed.xml"));
+        assert!(!matches("/feed$", "/feed.xml"));
+        assert!(matches("/*/feed", "/blog/feed/"));
+        assert!(matches("/*.xml$", "/blog/feed.xml"));
+        assert!(!matches("/*.xml$", "/blog/feed.xml?page=2"));
+        assert!(!matches("/blog", "/"));
+    }
+}
@@ -1,20 +1,29 @@
 use super::channel::*;
+use super::politeness::{self, Access};
+use super::summary::summarize;
 
 use atom_syndication::Error as AtomError;
 use atom_syndication::Feed;
 use chrono::naive::{NaiveDate, NaiveDateTime};
 use chrono::{DateTime, Utc};
 use core::time::Duration;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
 use kuchiki::iter::{Descendants, Elements, Select};
 use kuchiki::traits::*;
 use reqwest::blocking::{Client, Response};
 use reqwest::header;
 use reqwest::header::ToStrError;
+use reqwest::redirect::Policy;
 use reqwest::StatusCode;
+use rss::extension::syndication::UpdatePeriod;
 use rss::Error as RssError;
 use rss::{Channel as RssChannel, Item as RssItem};
+use serde::Deserialize;
+use std::borrow::Cow;
 use std::fmt::Display;
 use std::io::{BufRead, BufReader};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use url::Url;
 
 /// While it is called RSS, this
@@ -125,46 +134,173 @@ impl Channel for Rss {
     }
 
     fn fetch_updates(&self, url: &str) -> Result<Vec<Update>, String> {
-        let resp = fetch_resource(url)
-            .map_err(|err| format!("Failed to fetch url '{}': {:?}", url, err))?;
-
-        parse_feed(resp)
-            .map_err(|err| format!("Failed to parse '{}': {:?}", url, err))
-            .and_then(|feed| match feed {
-                ParsedFeed::Rss(rss) => rss_to_updates(&rss),
-                ParsedFeed::Atom(atom) => atom_to_updates(&atom),
+        self.fetch_updates_if_modified(url, &CacheValidators::default())
+            .map(|fetched| match fetched {
+                ConditionalUpdates::NotModified => Vec::new(),
+                ConditionalUpdates::Modified(updates, _) => updates,
+                ConditionalUpdates::Moved(_, updates, _) => updates,
             })
+            .map_err(|err| err.to_string())
     }
+
+    fn fetch_updates_if_modified(
+        &self,
+        url: &str,
+        validators: &CacheValidators,
+    ) -> Result<ConditionalUpdates, FetchError> {
+        let (resp, redirected_to) = match fetch_resource_if_modified(url, validators)
+            .map_err(|err| err.into_fetch_error(url))?
+        {
+            None => return Ok(ConditionalUpdates::NotModified),
+            Some(fetched) => fetched,
+        };
+        let mut new_validators = response_validators(&resp);
+        let final_url = resp.url().clone();
+
+        let feed = parse_feed(resp)
+            .map_err(|err| FetchError::Invalid(format!("Failed to parse '{}': {:?}", url, err)))?;
+        new_validators.max_age = new_validators.max_age.max(update_period(&feed));
+        let moved_to = redirected_to.map(|u| u.to_string()).or_else(|| {
+            declared_location(&feed, &final_url).filter(|location| !is_same_location(url, location))
+        });
+        let updates = feed_to_updates(feed)?;
+        Ok(match moved_to {
+            Some(location) => ConditionalUpdates::Moved(location, updates, new_validators),
+            None => ConditionalUpdates::Modified(updates, new_validators),
+        })
+    }
+}
+
+/// parses updates from a feed we didn't fetch ourselves (eg. pushed via websub)
+pub fn updates_from_bytes(content_type: &str, bytes: &[u8]) -> Result<Vec<Update>, String> {
+    parse_feed_bytes(content_type, bytes)
+        .map_err(|err| format!("Failed to parse feed: {:?}", err))
+        .and_then(feed_to_updates)
+}
+
+fn feed_to_updates(feed: ParsedFeed) -> Result<Vec<Update>, String> {
+    let updates = match feed {
+        ParsedFeed::Rss(rss) => rss_to_updates(&rss),
+        ParsedFeed::Atom(atom) => atom_to_updates(&atom),
+        ParsedFeed::Json(json) => json_to_updates(&json),
+    }?;
+    // eg. microblogs without titles, where we take the title from the text
+    Ok(updates
+        .into_iter()
+        .map(|mut update| {
+            if update.metadata.summary.as_ref() == Some(&update.title) {
+                update.metadata.summary = None;
+            }
+            update.title = decode_entities(&update.title);
+            update
+        })
+        .collect())
+}
+
+// many feeds escape the titles twice (eg. '&amp;amp;' or '&amp;#8217;'), so
+// that we still get an entity after parsing the xml. titles are text, so we
+// decode them. only the common named ones, because html knows over 2000.
+// '&lt;' and '&gt;' are left alone: a title about html may well contain
+// them literally and they're the ones that would turn text into markup.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest
+            .find(';')
+            .filter(|end| *end <= 10)
+            .and_then(|end| decode_entity(&rest[1..end]).map(|c| (c, end)))
+            .filter(|(c, _)| *c != '<' && *c != '>');
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(|c| c == 'x' || c == 'X') {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => number.parse().ok(),
+        };
+        return code.and_then(std::char::from_u32);
+    }
+    let c = match name {
+        "amp" => '&',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        "laquo" => '«',
+        "raquo" => '»',
+        "ndash" => '–',
+        "mdash" => '—',
+        "hellip" => '…',
+        "euro" => '€',
+        "auml" => 'ä',
+        "ouml" => 'ö',
+        "uuml" => 'ü',
+        "Auml" => 'Ä',
+        "Ouml" => 'Ö',
+        "Uuml" => 'Ü',
+        "szlig" => 'ß',
+        "eacute" => 'é',
+        "egrave" => 'è',
+        "agrave" => 'à',
+        "ccedil" => 'ç',
+        _ => return None,
+    };
+    Some(c)
 }
 
 fn rss_to_updates(channel: &RssChannel) -> Result<Vec<Update>, String> {
+    let first_seen = Utc::now();
+    // podcasts often only have artwork for the whole show
+    let channel_image = channel
+        .itunes_ext()
+        .and_then(|i| i.image())
+        .and_then(non_empty);
     let mut updates = Vec::with_capacity(channel.items().len());
     for item in channel.items() {
-        let maybe_date = item.pub_date().or_else(|| rss_dc_date(&item));
-        match maybe_date {
-            None => println!(
-                "Neither pub_date nor dc:date for item {} in channel {} ({})",
-                item.title().unwrap_or("???"),
-                channel.title(),
-                channel.link()
-            ),
-            Some(date) => {
-                let update = Update {
-                    ext_id: None,
-                    title: item
-                        .title()
-                        .ok_or_else(|| format!("No title for {:?}", item))?
-                        .to_owned(),
-                    url: item
-                        .link()
-                        .map(|l| make_absolute(channel.link(), l))
-                        .ok_or_else(|| format!("No url for {:?}", item))?
-                        .to_owned(),
-                    published: parse_pub_date(date)?,
-                };
-                updates.push(update);
-            }
-        }
+        let published = match item.pub_date().or_else(|| rss_dc_date(&item)) {
+            Some(date) => parse_pub_date(date)?,
+            // the fetcher recognizes the item by its guid, so
+            // it keeps the date when we first saw it
+            None => first_seen,
+        };
+        let update = Update {
+            ext_id: item
+                .guid()
+                .map(|guid| guid.value().trim().to_owned())
+                .filter(|guid| !guid.is_empty()),
+            title: item
+                .title()
+                .ok_or_else(|| format!("No title for {:?}", item))?
+                .to_owned(),
+            url: item
+                .link()
+                .map(|l| make_absolute(channel.link(), l))
+                .ok_or_else(|| format!("No url for {:?}", item))?
+                .to_owned(),
+            external_url: None,
+            metadata: rss_item_metadata(item, &channel_image),
+            published,
+        };
+        updates.push(update);
     }
     Ok(updates)
 }
@@ -173,10 +309,12 @@ fn atom_to_updates(feed: &Feed) -> Result<Vec<Update>, String> {
     let mut updates = Vec::with_capacity(feed.entries().len());
     for entry in feed.entries() {
         let update = Update {
-            ext_id: None,
+            ext_id: Some(entry.id().trim().to_owned()).filter(|id| !id.is_empty()),
             title: entry.title().into(),
             url: atom_article_link(feed.links(), entry.links())
                 .unwrap_or_else(|| format!("No links for {:?}", entry)),
+            external_url: None,
+            metadata: atom_entry_metadata(entry),
             published: entry
                 .published()
                 .cloned()
@@ -188,6 +326,309 @@ fn atom_to_updates(feed: &Feed) -> Result<Vec<Update>, String> {
     Ok(updates)
 }
 
+fn json_to_updates(feed: &JsonFeed) -> Result<Vec<Update>, String> {
+    let feed_link = feed.home_page_url.as_deref().unwrap_or("");
+    let first_seen = Utc::now();
+    let mut updates = Vec::with_capacity(feed.items.len());
+    for item in &feed.items {
+        // date_published is optional in json feed. same as with rss,
+        // items without any date get the date when we first saw them.
+        let published = match item
+            .date_published
+            .as_ref()
+            .or_else(|| item.date_modified.as_ref())
+        {
+            Some(date) => parse_pub_date(date)?,
+            None => first_seen,
+        };
+        let update = Update {
+            ext_id: json_item_id(item),
+            title: json_item_title(item).ok_or_else(|| format!("No title for {:?}", item))?,
+            url: item
+                .url
+                .as_ref()
+                .or_else(|| item.external_url.as_ref())
+                .map(|l| make_absolute(feed_link, l))
+                .ok_or_else(|| format!("No url for {:?}", item))?,
+            external_url: None,
+            metadata: json_item_metadata(item),
+            published,
+        };
+        updates.push(update);
+    }
+    Ok(updates)
+}
+
+// the id must be a string, but some feeds use numbers
+fn json_item_id(item: &JsonFeedItem) -> Option<String> {
+    match &item.id {
+        Some(serde_json::Value::String(id)) => Some(id.trim().to_owned()),
+        Some(serde_json::Value::Number(id)) => Some(id.to_string()),
+        _ => None,
+    }
+    .filter(|id| !id.is_empty())
+}
+
+// title is optional in json feed (eg. for microblogs), so we fall back
+// to the summary or the beginning of the text
+fn json_item_title(item: &JsonFeedItem) -> Option<String> {
+    let max_chars = 100;
+    let non_empty = |s: &Option<String>| {
+        s.as_ref()
+            .map(|s| s.split_whitespace().collect::<Vec<&str>>().join(" "))
+            .filter(|s| !s.is_empty())
+    };
+
+    non_empty(&item.title)
+        .or_else(|| non_empty(&item.summary))
+        .or_else(|| {
+            non_empty(&item.content_text).map(|text| {
+                if text.chars().count() > max_chars {
+                    format!("{}...", text.chars().take(max_chars).collect::<String>())
+                } else {
+                    text
+                }
+            })
+        })
+}
+
+fn rss_item_metadata(item: &RssItem, channel_image: &Option<String>) -> Metadata {
+    let enclosures: Vec<Enclosure> = item
+        .enclosure()
+        .map(|e| Enclosure {
+            url: e.url().to_owned(),
+            mime_type: non_empty(e.mime_type()),
+            length: parse_length(e.length()),
+        })
+        .into_iter()
+        .collect();
+    Metadata {
+        summary: item
+            .description()
+            .or_else(|| item.content())
+            .and_then(summarize),
+        author: item.author().and_then(non_empty).or_else(|| {
+            item.dublin_core_ext()
+                .and_then(|dc| dc.creators().iter().find_map(|c| non_empty(c)))
+        }),
+        categories: categories(item.categories().iter().map(|c| c.name())),
+        image_url: media_image_url(&media_elements(item.extensions().get("media")))
+            .or_else(|| {
+                item.itunes_ext()
+                    .and_then(|i| i.image())
+                    .and_then(non_empty)
+            })
+            .or_else(|| image_enclosure(&enclosures))
+            .or_else(|| channel_image.clone()),
+        enclosures,
+        reading_minutes: None,
+        duration_seconds: item
+            .itunes_ext()
+            .and_then(|i| i.duration())
+            .and_then(parse_duration),
+        episode: item
+            .itunes_ext()
+            .and_then(|i| i.episode())
+            .and_then(|e| e.trim().parse().ok()),
+    }
+}
+
+fn atom_entry_metadata(entry: &atom_syndication::Entry) -> Metadata {
+    let enclosures: Vec<Enclosure> = entry
+        .links()
+        .iter()
+        .filter(|l| l.rel() == "enclosure")
+        .map(|l| Enclosure {
+            url: l.href().to_owned(),
+            mime_type: l.mime_type().and_then(non_empty),
+            length: l.length().and_then(parse_length),
+        })
+        .collect();
+    Metadata {
+        summary: entry
+            .summary()
+            .or_else(|| entry.content().and_then(|c| c.value()))
+            .and_then(summarize),
+        author: entry.authors().iter().find_map(|a| non_empty(a.name())),
+        categories: categories(
+            entry
+                .categories()
+                .iter()
+                .map(|c| c.label().unwrap_or_else(|| c.term())),
+        ),
+        image_url: media_image_url(&media_elements(entry.extensions().get("media")))
+            .or_else(|| image_enclosure(&enclosures)),
+        enclosures,
+        reading_minutes: None,
+        duration_seconds: None,
+        episode: None,
+    }
+}
+
+fn json_item_metadata(item: &JsonFeedItem) -> Metadata {
+    let enclosures: Vec<Enclosure> = item
+        .attachments
+        .iter()
+        .map(|a| Enclosure {
+            url: a.url.clone(),
+            mime_type: non_empty(&a.mime_type),
+            length: a.size_in_bytes,
+        })
+        .collect();
+    Metadata {
+        summary: item
+            .summary
+            .as_ref()
+            .or_else(|| item.content_html.as_ref())
+            .or_else(|| item.content_text.as_ref())
+            .and_then(|s| summarize(s)),
+        author: item
+            .authors
+            .iter()
+            .chain(item.author.iter())
+            .find_map(|a| a.name.as_ref().and_then(|n| non_empty(n))),
+        categories: categories(item.tags.iter().map(|t| t.as_str())),
+        image_url: item
+            .image
+            .as_ref()
+            .or_else(|| item.banner_image.as_ref())
+            .and_then(|i| non_empty(i))
+            .or_else(|| image_enclosure(&enclosures)),
+        enclosures,
+        reading_minutes: None,
+        duration_seconds: item
+            .attachments
+            .iter()
+            .find_map(|a| a.duration_in_seconds)
+            .filter(|d| *d > 0.0)
+            .map(|d| d.round() as i32),
+        episode: None,
+    }
+}
+
+// itunes allows seconds or [hh:]mm:ss, but some feeds
+// also have fractions of seconds (eg. '01:02:03.5')
+fn parse_duration(duration: &str) -> Option<i32> {
+    let parts: Vec<&str> = duration.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    let mut seconds: f64 = 0.0;
+    for part in parts {
+        seconds = seconds * 60.0 + part.trim().parse::<f64>().ok().filter(|p| *p >= 0.0)?;
+    }
+    Some(seconds.round() as i32).filter(|s| *s > 0)
+}
+
+// audio or video files, as opposed to eg. images
+fn is_media(mime_type: &str) -> bool {
+    mime_type.starts_with("audio/") || mime_type.starts_with("video/")
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    let trimmed = s.trim();
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_owned())
+    }
+}
+
+// some feeds repeat categories or have empty ones
+fn categories<'a, I: Iterator<Item = &'a str>>(names: I) -> Vec<String> {
+    let mut categories: Vec<String> = Vec::new();
+    for name in names.filter_map(non_empty) {
+        if !categories.contains(&name) {
+            categories.push(name);
+        }
+    }
+    categories
+}
+
+// feeds often set the length to 0 if they don't know it
+fn parse_length(length: &str) -> Option<i64> {
+    length.trim().parse().ok().filter(|l| *l > 0)
+}
+
+fn image_enclosure(enclosures: &[Enclosure]) -> Option<String> {
+    enclosures
+        .iter()
+        .find(|e| e.mime_type.as_deref().unwrap_or("").starts_with("image/"))
+        .map(|e| e.url.clone())
+}
+
+// media rss (eg. youtube, flickr) looks the same in rss and atom,
+// but each crate has its own type for extensions
+trait MediaElement {
+    fn attribute(&self, name: &str) -> Option<&str>;
+    fn child_elements(&self) -> Vec<(&str, &Self)>;
+}
+
+impl MediaElement for rss::extension::Extension {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attrs().get(name).map(|v| v.as_str())
+    }
+
+    fn child_elements(&self) -> Vec<(&str, &Self)> {
+        self.children()
+            .iter()
+            .flat_map(|(name, es)| es.iter().map(move |e| (name.as_str(), e)))
+            .collect()
+    }
+}
+
+impl MediaElement for atom_syndication::extension::Extension {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attrs().get(name).map(|v| v.as_str())
+    }
+
+    fn child_elements(&self) -> Vec<(&str, &Self)> {
+        self.children()
+            .iter()
+            .flat_map(|(name, es)| es.iter().map(move |e| (name.as_str(), e)))
+            .collect()
+    }
+}
+
+fn media_elements<'a, E, M>(media: Option<&'a M>) -> Vec<(&'a str, &'a E)>
+where
+    &'a M: IntoIterator<Item = (&'a String, &'a Vec<E>)>,
+{
+    media
+        .into_iter()
+        .flat_map(|m| m.into_iter())
+        .flat_map(|(name, es)| es.iter().map(move |e| (name.as_str(), e)))
+        .collect()
+}
+
+// thumbnails are preferred, because media:content is often the video itself
+fn media_image_url<E: MediaElement>(elements: &[(&str, &E)]) -> Option<String> {
+    fn find<E: MediaElement>(
+        elements: &[(&str, &E)],
+        wanted: &dyn Fn(&str, &E) -> bool,
+    ) -> Option<String> {
+        elements.iter().find_map(|(name, element)| {
+            if *name == "group" {
+                find(&element.child_elements(), wanted)
+            } else if wanted(name, element) {
+                element.attribute("url").and_then(non_empty)
+            } else {
+                None
+            }
+        })
+    }
+    find(elements, &|name, _| name == "thumbnail").or_else(|| {
+        find(elements, &|name, element| {
+            name == "content"
+                && (element.attribute("medium") == Some("image")
+                    || element
+                        .attribute("type")
+                        .unwrap_or("")
+                        .starts_with("image/"))
+        })
+    })
+}
+
 fn make_absolute(feed_link: &str, url: &str) -> String {
     if url.starts_with('/') {
         format!("{}{}", feed_link, url)
@@ -199,6 +640,8 @@ fn make_absolute(feed_link: &str, url: &str) -> String {
 #[derive(Debug)]
 pub enum FeedError {
     NotFound(String),
+    Status(StatusCode, String),
+    Refused(String),
     TechnicalError(String),
     UnknownError(String),
     Timeout(String),
@@ -209,13 +652,29 @@ impl Into<SearchError> for FeedError {
         use FeedError::*;
         match self {
             NotFound(msg) => SearchError::ChannelNotFound(msg),
+            Status(_, msg) => SearchError::ChannelNotFound(msg),
             Timeout(msg) => SearchError::Timeout(msg),
+            Refused(msg) => SearchError::TechnicalError(msg),
             TechnicalError(msg) => SearchError::TechnicalError(msg),
             UnknownError(msg) => SearchError::TechnicalError(msg),
         }
     }
 }
 
+impl FeedError {
+    fn into_fetch_error(self, url: &str) -> FetchError {
+        use FeedError::*;
+        let msg = |msg: String| format!("Failed to fetch url '{}': {}", url, msg);
+        match self {
+            NotFound(m) => FetchError::NotFound(msg(m)),
+            Status(status, m) => FetchError::Status(status.as_u16(), msg(m)),
+            Refused(m) => FetchError::Refused(msg(m)),
+            Timeout(m) => FetchError::Timeout(msg(m)),
+            TechnicalError(m) | UnknownError(m) => FetchError::Other(msg(m)),
+        }
+    }
+}
+
 impl From<ToStrError> for FeedError {
     fn from(err: ToStrError) -> FeedError {
         FeedError::TechnicalError(format!(
@@ -246,18 +705,37 @@ impl From<reqwest::Error> for FeedError {
     }
 }
 
+// how deep we follow links from html pages to other html pages
+const MAX_RECURSION: u8 = 2;
+
+// where feeds usually are if a page doesn't advertise them
+const WELL_KNOWN_PATHS: [&str; 5] = ["feed", "rss.xml", "atom.xml", "index.xml", "feed.json"];
+
+// every probe waits for the politeness delay of the host and most of them
+// end in a 404, so the user would wait for the search for a long time
+const MAX_PROBING: Duration = Duration::from_secs(10);
+
 // recursed determines how many times we already recursively
 // called this method. we want to prevent infinite recursion
 // because an html page could point to itself and then we'd
 // never get out of here
 fn fetch_channel_info(full_url: &Url, recursed: u8) -> Result<Vec<ChannelInfo>, FeedError> {
+    // some platforms have their feeds at known places, so we don't
+    // need to look at the page (which often doesn't advertise them)
+    if recursed == 0 {
+        let feeds = fetch_candidates(&platform_feeds(full_url));
+        if !feeds.is_empty() {
+            return Ok(feeds);
+        }
+    }
+
     let sane_url = full_url.to_string();
     let response = fetch_resource(&sane_url)?;
 
     if is_html(&response) {
         // if we didn't prevent this, we might recurse forever if a page
         // points to itself (maliciously or not..)
-        if recursed > 2 {
+        if recursed > MAX_RECURSION {
             println!(
                 "Url {} points to html, but we already recursed {}",
                 sane_url, recursed
@@ -274,33 +752,265 @@ fn fetch_channel_info(full_url: &Url, recursed: u8) -> Result<Vec<ChannelInfo>,
                 add_channel_info(&mut feeds, channel_info)
             }
         }
+
+        if recursed == 0 {
+            // the page may not advertise all of them (eg. wordpress comment feeds
+            // are, but not the posts) or none at all, so we look for more
+            let candidates = html_platform_feeds(full_url, &body);
+            for channel_info in fetch_candidates(&candidates) {
+                add_channel_info(&mut feeds, channel_info)
+            }
+            if feeds.is_empty() && candidates.is_empty() {
+                feeds.extend(probe_well_known_feeds(full_url));
+            }
+        }
         Ok(feeds)
     } else {
-        match parse_feed(response) {
-            Ok(ParsedFeed::Rss(channel)) => Ok(vec![ChannelInfo {
-                name: channel.title().into(),
-                ext_id: sane_url,
-                link: channel.link().into(),
-                verified: false,
-            }]),
-            Ok(ParsedFeed::Atom(feed)) => Ok(vec![ChannelInfo {
-                name: feed.title().into(),
-                ext_id: sane_url.clone(),
-                link: atom_link(feed.links()).unwrap_or(sane_url),
-                verified: false,
-            }]),
-            Err(err) => Err(FeedError::UnknownError(format!(
-                "Neither atom nor rss: {:?}",
-                err
-            ))),
+        feed_info(sane_url, response).map(|feed| vec![feed])
+    }
+}
+
+fn feed_info(sane_url: String, response: Response) -> Result<ChannelInfo, FeedError> {
+    let feed = parse_feed(response).map_err(|err| {
+        FeedError::UnknownError(format!("Neither atom, rss nor json feed: {:?}", err))
+    })?;
+    let podcast = is_podcast(&feed);
+    Ok(match feed {
+        ParsedFeed::Rss(channel) => ChannelInfo {
+            name: decode_entities(channel.title()),
+            ext_id: sane_url,
+            link: channel.link().into(),
+            verified: false,
+            podcast,
+        },
+        ParsedFeed::Atom(feed) => ChannelInfo {
+            name: decode_entities(feed.title()),
+            ext_id: sane_url.clone(),
+            link: atom_link(feed.links()).unwrap_or(sane_url),
+            verified: false,
+            podcast,
+        },
+        ParsedFeed::Json(feed) => ChannelInfo {
+            name: decode_entities(&feed.title),
+            ext_id: sane_url.clone(),
+            link: feed.home_page_url.clone().unwrap_or(sane_url),
+            verified: false,
+            podcast,
+        },
+    })
+}
+
+// podcasts declare themselves with the itunes namespace, but feeds of other
+// platforms (eg. youtube) or in other formats may only have media files
+// attached to their items. a blog with the odd audio file is not a podcast.
+fn is_podcast(feed: &ParsedFeed) -> bool {
+    let media_types: Vec<Option<&str>> = match feed {
+        ParsedFeed::Rss(channel) => {
+            if channel.itunes_ext().is_some() {
+                return true;
+            }
+            channel
+                .items()
+                .iter()
+                .map(|i| i.enclosure().map(|e| e.mime_type()))
+                .collect()
+        }
+        ParsedFeed::Atom(feed) => feed
+            .entries()
+            .iter()
+            .map(|e| {
+                e.links()
+                    .iter()
+                    .filter(|l| l.rel() == "enclosure")
+                    .find_map(|l| l.mime_type().filter(|m| is_media(m)))
+            })
+            .collect(),
+        ParsedFeed::Json(feed) => feed
+            .items
+            .iter()
+            .map(|i| {
+                i.attachments
+                    .iter()
+                    .map(|a| a.mime_type.as_str())
+                    .find(|m| is_media(m))
+            })
+            .collect(),
+    };
+    let with_media = media_types
+        .iter()
+        .filter(|m| m.map(is_media).unwrap_or(false))
+        .count();
+    with_media > 0 && with_media * 2 >= media_types.len()
+}
+
+// the feeds among urls where we only guess that there might be one.
+// anything else (eg. a 404 or a html page) is ignored.
+fn fetch_candidates(urls: &[Url]) -> Vec<ChannelInfo> {
+    let mut feeds = Vec::new();
+    for url in urls {
+        if let Some(feed) = fetch_candidate(url) {
+            add_channel_info(&mut feeds, feed)
         }
     }
+    feeds
+}
+
+fn fetch_candidate(url: &Url) -> Option<ChannelInfo> {
+    let sane_url = url.to_string();
+    let feed = fetch_resource(&sane_url).and_then(|response| {
+        if is_html(&response) {
+            Err(FeedError::UnknownError("html".into()))
+        } else {
+            feed_info(sane_url.clone(), response)
+        }
+    });
+    match feed {
+        Ok(feed) => Some(feed),
+        Err(err) => {
+            println!("No feed at {}: {:?}", sane_url, err);
+            None
+        }
+    }
+}
+
+// one feed is enough to subscribe to the site, so we stop at the first one
+fn probe_well_known_feeds(url: &Url) -> Option<ChannelInfo> {
+    let started = Instant::now();
+    for candidate in well_known_feeds(url) {
+        if started.elapsed() > MAX_PROBING {
+            println!(
+                "Stopped probing for feeds of {} after {:?}",
+                url, MAX_PROBING
+            );
+            return None;
+        }
+        if let Some(feed) = fetch_candidate(&candidate) {
+            return Some(feed);
+        }
+    }
+    None
+}
+
+// feeds of platforms that we know by the url of a page
+fn platform_feeds(url: &Url) -> Vec<Url> {
+    let host = url.host_str().unwrap_or("").trim_start_matches("www.");
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    let feed = |feed_url: String| Url::parse(&feed_url).ok();
+
+    let feeds = match (host, segments.as_slice()) {
+        ("github.com", [owner, repo, ..]) => vec![feed(format!(
+            "https://github.com/{}/{}/releases.atom",
+            owner, repo
+        ))],
+        ("youtube.com", ["channel", id, ..]) => vec![feed(format!(
+            "https://www.youtube.com/feeds/videos.xml?channel_id={}",
+            id
+        ))],
+        ("youtube.com", ["user", name, ..]) => vec![feed(format!(
+            "https://www.youtube.com/feeds/videos.xml?user={}",
+            name
+        ))],
+        ("youtube.com", ["playlist"]) => url
+            .query_pairs()
+            .find(|(key, _)| key == "list")
+            .map(|(_, id)| {
+                feed(format!(
+                    "https://www.youtube.com/feeds/videos.xml?playlist_id={}",
+                    id
+                ))
+            })
+            .into_iter()
+            .collect(),
+        // medium.com/@user or a publication (medium.com/some-publication)
+        ("medium.com", [name, ..]) if *name != "feed" => {
+            vec![feed(format!("https://medium.com/feed/{}", name))]
+        }
+        (host, _) if host.ends_with(".medium.com") || host.ends_with(".substack.com") => {
+            vec![feed(format!("https://{}/feed", host))]
+        }
+        (host, _) if host.ends_with(".wordpress.com") => {
+            vec![feed(format!("https://{}/feed/", host))]
+        }
+        _ => Vec::new(),
+    };
+    feeds.into_iter().flatten().collect()
+}
+
+// feeds of platforms that we only recognize by the html of a page
+fn html_platform_feeds(url: &Url, html: &str) -> Vec<Url> {
+    let document = kuchiki::parse_html().one(html);
+
+    // eg. youtube.com/@handle is a channel/<id> in disguise
+    let canonical = document
+        .select_first("link[rel='canonical']")
+        .ok()
+        .and_then(|link| link.attributes.borrow().get("href").map(|h| h.to_owned()))
+        .and_then(|href| url.join(&href).ok());
+    if let Some(canonical) = canonical {
+        if canonical != *url {
+            let feeds = platform_feeds(&canonical);
+            if !feeds.is_empty() {
+                return feeds;
+            }
+        }
+    }
+
+    let generator = document
+        .select_first("meta[name='generator']")
+        .ok()
+        .and_then(|meta| {
+            meta.attributes
+                .borrow()
+                .get("content")
+                .map(|c| c.to_owned())
+        })
+        .unwrap_or_default();
+    let root = |path: &str| url.join(path).ok().into_iter().collect();
+    if generator.starts_with("WordPress") || html.contains("/wp-content/") {
+        root("/feed/")
+    } else if html.contains("substackcdn.com") {
+        // substack with a custom domain
+        root("/feed")
+    } else {
+        Vec::new()
+    }
+}
+
+// we try both next to the page and at the root of the site,
+// because blogs are often in a directory (eg. example.com/blog/)
+fn well_known_feeds(url: &Url) -> Vec<Url> {
+    let mut directory = url.clone();
+    directory.set_query(None);
+    directory.set_fragment(None);
+    if !directory.path().ends_with('/') {
+        let path = format!("{}/", directory.path());
+        directory.set_path(&path);
+    }
+
+    let mut feeds: Vec<Url> = Vec::new();
+    for base in &[directory.as_str(), "/"] {
+        for path in WELL_KNOWN_PATHS.iter() {
+            let feed = directory
+                .join(base)
+                .and_then(|base| base.join(path))
+                .ok()
+                .filter(|feed| !feeds.contains(feed));
+            if let Some(feed) = feed {
+                feeds.push(feed);
+            }
+        }
+    }
+    feeds
 }
 
 // adds the new feed to the existing feed if it is new or replaces
 // an existing if it is the same feed in a newer format. For example,
 // if we have an rss feed for site x and `new_feed` is an atom feed
-// for the same page, the rss feed will be replaced
+// for the same page, the rss feed will be replaced. the feeds are
+// kept in the order of their score, so the best one comes first.
 fn add_channel_info(feeds: &mut Vec<ChannelInfo>, new_feed: ChannelInfo) {
     let mut maybe_duplicate: Option<usize> = None;
     for (index, feed) in feeds.iter().enumerate() {
@@ -316,7 +1026,7 @@ fn add_channel_info(feeds: &mut Vec<ChannelInfo>, new_feed: ChannelInfo) {
     }
 
     if let Some(index) = maybe_duplicate {
-        if is_better(&new_feed, &feeds[index]) {
+        if score(&new_feed) > score(&feeds[index]) {
             feeds[index] = new_feed;
         } else {
             println!("Ignoring duplicate feed: {:?}", new_feed);
@@ -324,40 +1034,94 @@ fn add_channel_info(feeds: &mut Vec<ChannelInfo>, new_feed: ChannelInfo) {
     } else {
         feeds.push(new_feed);
     }
+    // stable, so feeds with the same score stay in the order we found them
+    feeds.sort_by_key(|feed| -score(feed));
 }
 
-// returns true if the new feed is better and should replace the old one
-fn is_better(new_feed: &ChannelInfo, existing: &ChannelInfo) -> bool {
-    let guess_atom = |feed: &ChannelInfo| {
-        feed.name.to_ascii_lowercase().contains("atom")
-            || feed.ext_id.to_ascii_lowercase().contains("atom")
-    };
-
-    guess_atom(new_feed) && !guess_atom(existing)
+// how likely it is that the feed is the one the user is looking for. we
+// don't know the format, so we guess it from the url and name (atom has
+// better defined dates and ids than rss). but more importantly, the feed
+// of the whole site should come before that of comments or a category.
+fn score(feed: &ChannelInfo) -> i32 {
+    let name = feed.name.to_ascii_lowercase();
+    let url = feed.ext_id.to_ascii_lowercase();
+    let hint = |word: &str| name.contains(word) || url.contains(word);
+
+    let mut score = 0;
+    if hint("atom") {
+        score += 2;
+    } else if url.contains("json") {
+        score += 1;
+    }
+    if hint("comment") {
+        score -= 10;
+    }
+    if ["/category/", "/tag/", "/tags/", "/author/"]
+        .iter()
+        .any(|part| url.contains(part))
+    {
+        score -= 5;
+    }
+    // the deeper the feed, the more specific it probably is
+    let depth = Url::parse(&feed.ext_id)
+        .ok()
+        .and_then(|url| {
+            url.path_segments()
+                .map(|s| s.filter(|s| !s.is_empty()).count())
+        })
+        .unwrap_or(0);
+    score - depth as i32
 }
 
 fn fetch_resource(url: &str) -> Result<Response, FeedError> {
+    fetch_resource_if_modified(url, &CacheValidators::default())?
+        .map(|(resp, _)| resp)
+        .ok_or_else(|| {
+            FeedError::UnknownError(format!("Unconditional request to {} returned 304", url))
+        })
+}
+
+// returns none if the server says the resource didn't change since the response
+// the validators are from (304 not modified). otherwise, the response comes with
+// the url the resource permanently moved to, if it was only reached via 301 or 308.
+fn fetch_resource_if_modified(
+    url: &str,
+    validators: &CacheValidators,
+) -> Result<Option<(Response, Option<Url>)>, FeedError> {
     use FeedError::*;
 
+    let _permit = politeness::acquire(url, Access::Crawl).map_err(Refused)?;
     let timeout = Duration::from_secs(3);
+    let moved_to = Arc::new(Mutex::new(None));
     let mut builder = Client::builder()
         .gzip(true)
         .timeout(timeout)
+        .redirect(record_permanent_redirects(moved_to.clone()))
         .build()?
         .get(url);
-    builder = builder.header(
-        header::USER_AGENT,
-        "Mozilla/5.0 (X11; Ubuntu; Linux x86_64; rv:72.0) Gecko/20100101 Firefox/72.0",
-    );
+    builder = builder.header(header::USER_AGENT, politeness::user_agent());
     builder = builder.header(header::ACCEPT_ENCODING, "gzip");
+    if let Some(etag) = &validators.etag {
+        builder = builder.header(header::IF_NONE_MATCH, etag.as_str());
+    }
+    if let Some(last_modified) = &validators.last_modified {
+        builder = builder.header(header::IF_MODIFIED_SINCE, last_modified.as_str());
+    }
 
-    match builder.send() {
-        Ok(resp) if resp.status() == StatusCode::OK => Ok(resp),
-        Ok(resp) => Err(NotFound(format!(
-            "Server returned code {} for url {}",
+    let result = builder.send();
+    if let Ok(resp) = &result {
+        politeness::record_response(url, resp.status(), resp.headers());
+    }
+    match result {
+        Ok(resp) if resp.status() == StatusCode::OK => {
+            let moved_to = moved_to.lock().ok().and_then(|mut m| m.take());
+            Ok(Some((resp, moved_to)))
+        }
+        Ok(resp) if resp.status() == StatusCode::NOT_MODIFIED => Ok(None),
+        Ok(resp) => Err(Status(
             resp.status(),
-            url
-        ))),
+            format!("Server returned code {} for url {}", resp.status(), url),
+        )),
         Err(err) if format!("{:?}", err).contains("Name or service not known") => {
             // todo I guess the above could be improved ;-)
             Err(NotFound(format!("DNS lookup failed: {:?}", err)))
@@ -370,6 +1134,134 @@ fn fetch_resource(url: &str) -> Result<Response, FeedError> {
     }
 }
 
+// follows redirects like reqwest does by default, but remembers where they lead to
+// if all of them are permanent. a temporary one means the old url is still valid.
+fn record_permanent_redirects(moved_to: Arc<Mutex<Option<Url>>>) -> Policy {
+    Policy::custom(move |attempt| {
+        if attempt.previous().len() > 10 {
+            return attempt.error("too many redirects");
+        }
+        let permanent = attempt.status() == StatusCode::MOVED_PERMANENTLY
+            || attempt.status() == StatusCode::PERMANENT_REDIRECT;
+        if let Ok(mut moved_to) = moved_to.lock() {
+            let first = attempt.previous().len() == 1;
+            *moved_to = if permanent && (first || moved_to.is_some()) {
+                Some(attempt.url().clone())
+            } else {
+                None
+            };
+        }
+        attempt.follow()
+    })
+}
+
+// where the feed says it lives (eg. after the author moved it to another host)
+fn declared_location(feed: &ParsedFeed, feed_url: &Url) -> Option<String> {
+    let location = match feed {
+        ParsedFeed::Rss(rss) => rss
+            .itunes_ext()
+            .and_then(|itunes| itunes.new_feed_url())
+            .map(|url| url.to_owned())
+            .or_else(|| {
+                rss.extensions()
+                    .get("atom")
+                    .and_then(|atom| atom.get("link"))
+                    .and_then(|links| {
+                        links.iter().find(|link| {
+                            link.attrs().get("rel").map(|r| r.as_str()) == Some("self")
+                        })
+                    })
+                    .and_then(|link| link.attrs().get("href").cloned())
+            }),
+        ParsedFeed::Atom(atom) => atom
+            .links()
+            .iter()
+            .find(|link| link.rel() == "self")
+            .map(|link| link.href().to_owned()),
+        ParsedFeed::Json(json) => json.feed_url.clone(),
+    }?;
+    feed_url
+        .join(location.trim())
+        .ok()
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .map(|url| url.to_string())
+}
+
+// many feeds declare themselves with a slightly different url (eg. http
+// instead of https), which is no reason to move the channel
+fn is_same_location(a: &str, b: &str) -> bool {
+    let without_scheme = |url: &str| {
+        Url::parse(url).ok().map(|url| {
+            let query = url.query().map(|q| format!("?{}", q)).unwrap_or_default();
+            format!(
+                "{}{}{}",
+                url.host_str().unwrap_or("").trim_start_matches("www."),
+                url.path().trim_end_matches('/'),
+                query
+            )
+        })
+    };
+    without_scheme(a) == without_scheme(b)
+}
+
+fn response_validators(resp: &Response) -> CacheValidators {
+    let header_value = |name| {
+        resp.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_owned())
+    };
+    CacheValidators {
+        etag: header_value(header::ETAG),
+        last_modified: header_value(header::LAST_MODIFIED),
+        max_age: header_value(header::CACHE_CONTROL).and_then(|v| cache_max_age(&v)),
+    }
+}
+
+// how long the response may be cached according to its Cache-Control header
+fn cache_max_age(cache_control: &str) -> Option<Duration> {
+    let directives: Vec<String> = cache_control
+        .split(',')
+        .map(|directive| directive.trim().to_ascii_lowercase())
+        .collect();
+    if directives
+        .iter()
+        .any(|directive| directive == "no-cache" || directive == "no-store")
+    {
+        return None;
+    }
+    directives
+        .iter()
+        .filter_map(|directive| directive.strip_prefix("max-age="))
+        .filter_map(|seconds| seconds.trim_matches('"').parse::<u64>().ok())
+        .next()
+        .map(Duration::from_secs)
+}
+
+// how often an rss feed says it changes, either with <ttl> (in minutes)
+// or with sy:updatePeriod and sy:updateFrequency (eg. twice daily)
+fn update_period(feed: &ParsedFeed) -> Option<Duration> {
+    let rss = match feed {
+        ParsedFeed::Rss(rss) => rss,
+        ParsedFeed::Atom(_) | ParsedFeed::Json(_) => return None,
+    };
+    let ttl = rss
+        .ttl()
+        .and_then(|minutes| minutes.trim().parse::<u64>().ok())
+        .map(|minutes| Duration::from_secs(minutes * 60));
+    let syndication = rss.syndication_ext().map(|sy| {
+        let period = match sy.period() {
+            UpdatePeriod::Hourly => 60 * 60,
+            UpdatePeriod::Daily => 24 * 60 * 60,
+            UpdatePeriod::Weekly => 7 * 24 * 60 * 60,
+            UpdatePeriod::Monthly => 30 * 24 * 60 * 60,
+            UpdatePeriod::Yearly => 365 * 24 * 60 * 60,
+        };
+        Duration::from_secs(period / u64::from(sy.frequency().max(1)))
+    });
+    ttl.max(syndication)
+}
+
 fn extract_feeds_from_html(url: &Url, html: &str) -> Result<Vec<Url>, FeedError> {
     // create absolute urls from relative urls
     let mk_urls = |links: Vec<String>| {
@@ -475,6 +1367,7 @@ fn extract_feeds_from_html_link(link_tags: Select<Elements<Descendants>>) -> Vec
             .map(|t| match t.as_str() {
                 "application/rss+xml" => true,
                 "application/atom+xml" => true,
+                "application/feed+json" => true,
                 _ => false,
             })
             .unwrap_or(false)
@@ -530,6 +1423,54 @@ fn is_html(resp: &Response) -> bool {
 enum ParsedFeed {
     Atom(Box<Feed>),
     Rss(Box<RssChannel>),
+    Json(Box<JsonFeed>),
+}
+
+// https://www.jsonfeed.org/version/1.1/ (only the parts we need)
+#[derive(Deserialize, Debug)]
+struct JsonFeed {
+    version: String,
+    title: String,
+    home_page_url: Option<String>,
+    feed_url: Option<String>,
+    #[serde(default)]
+    items: Vec<JsonFeedItem>,
+}
+
+#[derive(Deserialize, Debug)]
+struct JsonFeedItem {
+    id: Option<serde_json::Value>,
+    url: Option<String>,
+    external_url: Option<String>,
+    title: Option<String>,
+    summary: Option<String>,
+    content_html: Option<String>,
+    content_text: Option<String>,
+    image: Option<String>,
+    banner_image: Option<String>,
+    date_published: Option<String>,
+    date_modified: Option<String>,
+    // author was replaced by authors in json feed 1.1
+    author: Option<JsonFeedAuthor>,
+    #[serde(default)]
+    authors: Vec<JsonFeedAuthor>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    attachments: Vec<JsonFeedAttachment>,
+}
+
+#[derive(Deserialize, Debug)]
+struct JsonFeedAuthor {
+    name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct JsonFeedAttachment {
+    url: String,
+    mime_type: String,
+    size_in_bytes: Option<i64>,
+    duration_in_seconds: Option<f64>,
 }
 
 impl ParsedFeed {
@@ -544,29 +1485,46 @@ impl ParsedFeed {
             .map(|feed| ParsedFeed::Atom(Box::new(feed)))
             .map_err(|err| format!("Failed to parse as atom: {:?}", err))
     }
+
+    fn parse_json<R: BufRead>(buffer: R) -> Result<ParsedFeed, String> {
+        let feed: JsonFeed = serde_json::from_reader(buffer)
+            .map_err(|err| format!("Failed to parse as json feed: {:?}", err))?;
+        // application/json could be anything, so we make sure it's actually a feed
+        if feed.version.starts_with("https://jsonfeed.org/version/") {
+            Ok(ParsedFeed::Json(Box::new(feed)))
+        } else {
+            Err(format!("Unknown json feed version: {}", feed.version))
+        }
+    }
 }
 
 fn parse_feed(mut resp: Response) -> Result<ParsedFeed, String> {
-    if is_rss(&resp) {
-        let buffer = BufReader::new(resp);
+    // we copy the entire response into a byte vector, because `reqwest.Response`
+    // doesn't allow us to seek back to the beginning after having looked at the
+    // first few bytes in the response (see below).
+    let content_type = c_type(&resp);
+    let mut bytes = Vec::with_capacity(resp.content_length().unwrap_or(512) as usize);
+    resp.copy_to(&mut bytes)
+        .map_err(|err| format!("Failed to copy buffer: {:?}", err))?;
+    parse_feed_bytes(&content_type, &bytes)
+}
+
+fn parse_feed_bytes(content_type: &str, bytes: &[u8]) -> Result<ParsedFeed, String> {
+    // before transcoding, because that would mangle the gzip header
+    let gzipped = bytes.starts_with(&[31, 139, 8]);
+    let bytes: &[u8] = &to_utf8(content_type, bytes);
+    let buffer = BufReader::with_capacity(bytes.len(), bytes);
+    if is_rss(content_type) {
         ParsedFeed::parse_rss(buffer)
-    } else if is_atom(&resp) {
-        let buffer = BufReader::new(resp);
+    } else if is_atom(content_type) {
         ParsedFeed::parse_atom(buffer)
-    } else if is_xml(&resp) {
+    } else if is_json(content_type) {
+        ParsedFeed::parse_json(buffer)
+    } else if is_xml(content_type) {
         // if we don't know the content type, we take a look at the beginning
         // of the body and look for xml tags for rss or atom.
-        // this is a bit cumbersome/inefficient, because `reqwest.Response`
-        // doesn't allow us to seek back to the beginning after having looked
-        // at the first few bytes in the response. therefore, we copy the entire
-        // response into a byte vector, which then allows us to peek at the
-        // first few bytes.
-        let mut bytes = Vec::with_capacity(resp.content_length().unwrap_or(512) as usize);
-        resp.copy_to(&mut bytes)
-            .map_err(|err| format!("Failed to copy buffer: {:?}", err))?;
-        let contents = peek_buffer(&bytes);
-        let buffer = BufReader::with_capacity(bytes.len(), &bytes[..]);
-        if bytes.starts_with(&[31, 139, 8]) {
+        let contents = peek_buffer(bytes);
+        if gzipped {
             // happens if a website returns a gzip'd response w/o
             // setting the header. eg. https://onlineitguru.com/blog/feed
             Err(format!(
@@ -583,26 +1541,150 @@ fn parse_feed(mut resp: Response) -> Result<ParsedFeed, String> {
                 bytes
             ))
         }
+    } else if is_unspecific(content_type) && looks_like_json_feed(bytes) {
+        // some servers don't know json feeds and serve them as plain text or
+        // plain bytes, eg. text/plain or application/octet-stream
+        ParsedFeed::parse_json(buffer)
+    } else {
+        Err(format!("Unhandled content type: {}", content_type))
+    }
+}
+
+// the parsers only understand utf-8, so we transcode everything else. the
+// encoding comes from (in that order) the byte order mark, the content type
+// or the xml declaration. many servers don't know what they serve though, so
+// we go with the first of those that can actually decode the body and fall
+// back to windows-1252 (a superset of iso-8859-1), which decodes anything.
+// text in a legacy encoding is practically never valid utf-8 though, so if
+// the body is, the labels are most likely wrong. whatever we hand to the
+// parser is utf-8, so it must not declare anything else.
+fn to_utf8<'a>(content_type: &str, bytes: &'a [u8]) -> Cow<'a, [u8]> {
+    let declared = declared_charset(bytes);
+    let labels: Vec<&'static Encoding> = header_charset(content_type)
+        .into_iter()
+        .chain(declared)
+        .collect();
+    let utf8_first = labels.iter().all(|encoding| encoding.is_ascii_compatible());
+    let candidates = Encoding::for_bom(bytes)
+        .map(|(encoding, _)| encoding)
+        .into_iter()
+        .chain(Some(UTF_8).filter(|_| utf8_first))
+        .chain(labels)
+        .chain(vec![UTF_8, WINDOWS_1252]);
+
+    for encoding in candidates {
+        match encoding.decode_with_bom_removal(bytes) {
+            (_, true) => continue,
+            // valid utf-8 or only ascii, so we don't need to transcode
+            (Cow::Borrowed(text), false) if declared.map_or(true, |d| d == UTF_8) => {
+                return Cow::Borrowed(text.as_bytes())
+            }
+            (Cow::Borrowed(text), false) => return Cow::Owned(declare_utf8(text).into_bytes()),
+            (Cow::Owned(text), false) => {
+                println!("Transcoding feed from {}", encoding.name());
+                // otherwise the parser would decode it again
+                return Cow::Owned(declare_utf8(&text).into_bytes());
+            }
+        }
+    }
+    Cow::Borrowed(bytes)
+}
+
+// eg. 'application/rss+xml; charset=ISO-8859-1'
+fn header_charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|param| {
+            let mut parts = param.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("charset") => {
+                    Some(value.trim().trim_matches('"'))
+                }
+                _ => None,
+            }
+        })
+        .find_map(|label| Encoding::for_label(label.as_bytes()))
+}
+
+// eg. '<?xml version="1.0" encoding="ISO-8859-1"?>'
+fn declared_charset(bytes: &[u8]) -> Option<&'static Encoding> {
+    let declaration = xml_declaration(&peek_buffer(bytes))?;
+    let start = declaration.find("encoding=")? + "encoding=".len();
+    let value = &declaration[start..];
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let label = value[1..].split(quote).next()?;
+    Encoding::for_label(label.as_bytes())
+}
+
+fn xml_declaration(text: &str) -> Option<String> {
+    let text = text.trim_start_matches('\u{feff}');
+    if text.starts_with("<?xml") {
+        text.find("?>").map(|end| text[..end].to_owned())
     } else {
-        Err(format!("Unhandled content type: {}", c_type(&resp)))
+        None
+    }
+}
+
+fn declare_utf8(text: &str) -> String {
+    let text = text.trim_start_matches('\u{feff}');
+    match xml_declaration(text) {
+        Some(declaration) if declaration.contains("encoding=") => {
+            let utf8 = declaration
+                .split_whitespace()
+                .map(|attr| {
+                    if attr.starts_with("encoding=") {
+                        "encoding=\"UTF-8\""
+                    } else {
+                        attr
+                    }
+                })
+                .collect::<Vec<&str>>()
+                .join(" ");
+            format!("{}{}", utf8, &text[declaration.len()..])
+        }
+        _ => text.to_owned(),
     }
 }
 
 fn peek_buffer(bytes: &[u8]) -> String {
-    String::from_utf8_lossy(&bytes[0..256]).into()
+    String::from_utf8_lossy(&bytes[..bytes.len().min(256)]).into()
+}
+
+fn is_rss(c_type: &str) -> bool {
+    c_type.contains("application/") && (c_type.contains("rss") || c_type.contains("rdf"))
+}
+
+fn is_atom(c_type: &str) -> bool {
+    c_type.contains("application/atom+xml")
 }
 
-fn is_rss(resp: &Response) -> bool {
-    c_type(resp).contains("application/")
-        && (c_type(resp).contains("rss") || c_type(resp).contains("rdf"))
+fn is_json(c_type: &str) -> bool {
+    c_type.contains("application/feed+json") || c_type.contains("application/json")
 }
 
-fn is_atom(resp: &Response) -> bool {
-    c_type(resp).contains("application/atom+xml")
+fn is_xml(c_type: &str) -> bool {
+    c_type.contains("application/xml") || c_type.contains("text/xml")
 }
 
-fn is_xml(resp: &Response) -> bool {
-    c_type(resp).contains("application/xml") || c_type(resp).contains("text/xml")
+fn is_unspecific(c_type: &str) -> bool {
+    c_type.is_empty()
+        || c_type.contains("text/plain")
+        || c_type.contains("application/octet-stream")
+}
+
+// a json object with a json feed version, no matter where in the object
+fn looks_like_json_feed(bytes: &[u8]) -> bool {
+    #[derive(Deserialize)]
+    struct Version {
+        version: String,
+    }
+
+    let first = bytes.iter().find(|b| !b.is_ascii_whitespace());
+    first == Some(&b'{')
+        && serde_json::from_slice::<Version>(bytes)
+            .map(|v| v.version.starts_with("https://jsonfeed.org/version/"))
+            .unwrap_or(false)
 }
 
 fn c_type(resp: &Response) -> String {
@@ -686,6 +1768,7 @@ mod tests {
                 ext_id: "https://theverge.com/rss/index.xml".into(),
                 link: "https://www.theverge.com/".into(),
                 verified: false,
+                podcast: false,
             },
             *all_posts
         );
@@ -699,6 +1782,7 @@ mod tests {
                 ext_id: "https://www.theverge.com/rss/front-page/index.xml".into(),
                 link: "https://www.theverge.com/".into(),
                 verified: false,
+                podcast: false,
             },
             *front_pages
         );
@@ -726,6 +1810,7 @@ mod tests {
                 ext_id: "https://theverge.com/rss/index.xml".into(),
                 link: "https://www.theverge.com/".into(),
                 verified: false,
+                podcast: false,
             },
             *all_posts
         );
@@ -751,6 +1836,7 @@ mod tests {
                 ext_id: "https://softwareengineeringdaily.com/category/podcast/feed/".into(),
                 link: "https://softwareengineeringdaily.com".into(),
                 verified: false,
+                podcast: false,
             },
             *all_posts
         );
@@ -772,6 +1858,7 @@ mod tests {
                 ext_id: "https://blog.acolyer.org/feed/".into(),
                 link: "https://blog.acolyer.org".into(),
                 verified: false,
+                podcast: false,
             },
             *feed
         );
@@ -785,6 +1872,7 @@ mod tests {
                 ext_id: "https://blog.acolyer.org/comments/feed/".into(),
                 link: "https://blog.acolyer.org".into(),
                 verified: false,
+                podcast: false,
             },
             *comments
         );
@@ -802,6 +1890,7 @@ mod tests {
                 ext_id: "https://rss.nytimes.com/services/xml/rss/nyt/HomePage.xml".into(),
                 link: "https://www.nytimes.com".into(),
                 verified: false,
+                podcast: false,
             },
             feed
         );
@@ -822,6 +1911,7 @@ mod tests {
                 ext_id: "https://medium.com/feed/@nikitonsky".into(),
                 link: "https://medium.com/@nikitonsky?source=rss-5247cb846abe------2".into(),
                 verified: false,
+                podcast: false,
             },
             feed
         );
@@ -844,6 +1934,7 @@ mod tests {
                         .into(),
                 link: "https://www.youtube.com/channel/UCxec_VgCE-5DUZ8MocKbEdg".into(),
                 verified: false,
+                podcast: false,
             },
             feed
         );
@@ -861,6 +1952,7 @@ mod tests {
                 ext_id: "https://200ok.ch/atom.xml".into(),
                 link: "https://200ok.ch/".into(),
                 verified: false,
+                podcast: false,
             },
             feed
         );
@@ -901,6 +1993,7 @@ mod tests {
                 ext_id: "http://rss.slashdot.org/Slashdot/slashdotMain".into(),
                 link: "https://slashdot.org/".into(),
                 verified: false,
+                podcast: false,
             },
             feed
         );
@@ -949,6 +2042,678 @@ mod tests {
         assert_eq!(200, get(post_url).expect("Failed to fetch").status())
     }
 
+    #[test]
+    fn parse_json_feed_updates() {
+        let json = r#"{
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": "My Example Feed",
+            "home_page_url": "https://example.org",
+            "feed_url": "https://example.org/feed.json",
+            "items": [
+                {
+                    "id": "2",
+                    "content_text": "This is a second item.",
+                    "url": "https://example.org/second-item",
+                    "date_published": "2020-05-12T11:09:25+02:00"
+                },
+                {
+                    "id": "1",
+                    "title": "First item",
+                    "content_html": "<p>Hello, world!</p>",
+                    "url": "/initial-post",
+                    "date_modified": "2020-05-10T08:00:00Z"
+                },
+                {
+                    "id": "0",
+                    "title": "Undated item",
+                    "url": "https://example.org/undated"
+                }
+            ]
+        }"#;
+        let feed = match ParsedFeed::parse_json(json.as_bytes()) {
+            Ok(ParsedFeed::Json(feed)) => feed,
+            _ => panic!("Failed to parse json feed"),
+        };
+        let before = Utc::now();
+        let updates = feed_to_updates(ParsedFeed::Json(feed)).expect("Failed to convert updates");
+        assert_eq!(
+            vec![
+                Update {
+                    ext_id: Some("2".into()),
+                    title: "This is a second item.".into(),
+                    url: "https://example.org/second-item".into(),
+                    external_url: None,
+                    metadata: Metadata::default(),
+                    published: Utc.ymd(2020, 5, 12).and_hms(9, 9, 25),
+                },
+                Update {
+                    ext_id: Some("1".into()),
+                    title: "First item".into(),
+                    url: "https://example.org/initial-post".into(),
+                    external_url: None,
+                    metadata: Metadata {
+                        summary: Some("Hello, world!".into()),
+                        ..Metadata::default()
+                    },
+                    published: Utc.ymd(2020, 5, 10).and_hms(8, 0, 0),
+                },
+            ],
+            updates[..2]
+        );
+        // the undated item is published when we first see it
+        assert_eq!(Some("0".into()), updates[2].ext_id);
+        assert!(updates[2].published >= before && updates[2].published <= Utc::now());
+    }
+
+    #[test]
+    fn fetch_updates_returns_validators() {
+        let _m = mockito::mock("GET", "/validators/feed.json")
+            .with_header("content-type", "application/feed+json")
+            .with_header("etag", "\"v1\"")
+            .with_header("last-modified", "Tue, 12 May 2020 09:00:00 GMT")
+            .with_header("cache-control", "public, max-age=600")
+            .with_body(
+                r#"{"version": "https://jsonfeed.org/version/1.1", "title": "T", "items": [
+                    {"title": "A", "url": "https://example.org/a", "date_published": "2020-05-12T09:00:00Z"}
+                ]}"#,
+            )
+            .create();
+        let url = format!("{}/validators/feed.json", mockito::server_url());
+        let fetched = Rss {}
+            .fetch_updates_if_modified(&url, &CacheValidators::default())
+            .expect("Failed to fetch");
+        match fetched {
+            ConditionalUpdates::Modified(updates, validators) => {
+                assert_eq!(1, updates.len());
+                assert_eq!(
+                    CacheValidators {
+                        etag: Some("\"v1\"".into()),
+                        last_modified: Some("Tue, 12 May 2020 09:00:00 GMT".into()),
+                        max_age: Some(Duration::from_secs(600)),
+                    },
+                    validators
+                );
+            }
+            other => panic!("Expected updates, but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fetch_updates_not_modified() {
+        let _m = mockito::mock("GET", "/not-modified/feed.json")
+            .match_header("if-none-match", "\"v1\"")
+            .match_header("if-modified-since", "Tue, 12 May 2020 09:00:00 GMT")
+            .with_status(304)
+            .create();
+        let url = format!("{}/not-modified/feed.json", mockito::server_url());
+        let validators = CacheValidators {
+            etag: Some("\"v1\"".into()),
+            last_modified: Some("Tue, 12 May 2020 09:00:00 GMT".into()),
+            max_age: None,
+        };
+        assert_eq!(
+            Ok(ConditionalUpdates::NotModified),
+            Rss {}.fetch_updates_if_modified(&url, &validators)
+        );
+    }
+
+    #[test]
+    fn fetch_updates_keeps_the_status_of_failures() {
+        let _gone = mockito::mock("GET", "/status/gone.xml")
+            .with_status(410)
+            .create();
+        let _down = mockito::mock("GET", "/status/down.xml")
+            .with_status(503)
+            .create();
+        let status = |path: &str| {
+            let url = format!("{}{}", mockito::server_url(), path);
+            let fetched = Rss {}.fetch_updates_if_modified(&url, &CacheValidators::default());
+            match fetched {
+                Err(FetchError::Status(status, _)) => status,
+                other => panic!("Expected a status, but got {:?}", other),
+            }
+        };
+        assert_eq!(410, status("/status/gone.xml"));
+        assert_eq!(503, status("/status/down.xml"));
+    }
+
+    #[test]
+    fn fetch_updates_of_permanently_redirected_feed() {
+        let _old = mockito::mock("GET", "/redirect/old.json")
+            .with_status(301)
+            .with_header("location", "/redirect/temporary.json")
+            .create();
+        let _temporary = mockito::mock("GET", "/redirect/temporary.json")
+            .with_status(308)
+            .with_header("location", "/redirect/new.json")
+            .create();
+        let _new = mockito::mock("GET", "/redirect/new.json")
+            .with_header("content-type", "application/feed+json")
+            .with_body(
+                r#"{"version": "https://jsonfeed.org/version/1.1", "title": "T", "items": [
+                    {"title": "A", "url": "https://example.org/a", "date_published": "2020-05-12T09:00:00Z"}
+                ]}"#,
+            )
+            .create();
+        let url = format!("{}/redirect/old.json", mockito::server_url());
+        let fetched = Rss {}.fetch_updates_if_modified(&url, &CacheValidators::default());
+        match fetched {
+            Ok(ConditionalUpdates::Moved(location, updates, _)) => {
+                assert_eq!(
+                    format!("{}/redirect/new.json", mockito::server_url()),
+                    location
+                );
+                assert_eq!(1, updates.len());
+            }
+            other => panic!("Expected a move, but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fetch_updates_of_temporarily_redirected_feed() {
+        let _old = mockito::mock("GET", "/temporary/old.json")
+            .with_status(301)
+            .with_header("location", "/temporary/maintenance.json")
+            .create();
+        let _maintenance = mockito::mock("GET", "/temporary/maintenance.json")
+            .with_status(302)
+            .with_header("location", "/temporary/new.json")
+            .create();
+        let _new = mockito::mock("GET", "/temporary/new.json")
+            .with_header("content-type", "application/feed+json")
+            .with_body(r#"{"version": "https://jsonfeed.org/version/1.1", "title": "T"}"#)
+            .create();
+        let url = format!("{}/temporary/old.json", mockito::server_url());
+        assert_eq!(
+            Ok(ConditionalUpdates::Modified(
+                Vec::new(),
+                CacheValidators::default()
+            )),
+            Rss {}.fetch_updates_if_modified(&url, &CacheValidators::default())
+        );
+    }
+
+    #[test]
+    fn fetch_updates_of_feed_with_new_location() {
+        let _m = mockito::mock("GET", "/declared/feed.xml")
+            .with_header("content-type", "application/rss+xml")
+            .with_body(
+                r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+  <channel>
+    <title>Podcast</title>
+    <link>https://example.org</link>
+    <description>Episodes</description>
+    <itunes:new-feed-url>https://podcasts.example.org/feed.xml</itunes:new-feed-url>
+  </channel>
+</rss>"#,
+            )
+            .create();
+        let url = format!("{}/declared/feed.xml", mockito::server_url());
+        assert_eq!(
+            Ok(ConditionalUpdates::Moved(
+                "https://podcasts.example.org/feed.xml".into(),
+                Vec::new(),
+                CacheValidators::default()
+            )),
+            Rss {}.fetch_updates_if_modified(&url, &CacheValidators::default())
+        );
+    }
+
+    #[test]
+    fn parse_cache_control() {
+        assert_eq!(
+            Some(Duration::from_secs(3600)),
+            cache_max_age("public, max-age=3600")
+        );
+        assert_eq!(
+            Some(Duration::from_secs(60)),
+            cache_max_age("Max-Age=\"60\", must-revalidate")
+        );
+        assert_eq!(None, cache_max_age("no-cache, max-age=3600"));
+        assert_eq!(None, cache_max_age("private"));
+    }
+
+    #[test]
+    fn update_period_of_feeds() {
+        let feed = |channel_elements: &str| {
+            let rss = format!(
+                r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:sy="http://purl.org/rss/1.0/modules/syndication/">
+  <channel>
+    <title>Blog</title>
+    <link>https://example.org</link>
+    <description>Posts</description>
+    {}
+  </channel>
+</rss>"#,
+                channel_elements
+            );
+            parse_feed_bytes("application/rss+xml", rss.as_bytes()).expect("Failed to parse")
+        };
+        assert_eq!(
+            Some(Duration::from_secs(90 * 60)),
+            update_period(&feed("<ttl>90</ttl>"))
+        );
+        assert_eq!(
+            Some(Duration::from_secs(12 * 60 * 60)),
+            update_period(&feed(
+                "<sy:updatePeriod>daily</sy:updatePeriod><sy:updateFrequency>2</sy:updateFrequency>"
+            ))
+        );
+        assert_eq!(
+            Some(Duration::from_secs(7 * 24 * 60 * 60)),
+            update_period(&feed(
+                "<ttl>60</ttl><sy:updatePeriod>weekly</sy:updatePeriod>"
+            ))
+        );
+        assert_eq!(None, update_period(&feed("")));
+    }
+
+    #[test]
+    fn declared_location_of_feeds() {
+        let feed_url = Url::parse("https://example.org/blog/feed.xml").unwrap();
+        let rss = r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+  <channel>
+    <title>Blog</title>
+    <link>https://example.org</link>
+    <description>Posts</description>
+    <atom:link rel="self" type="application/rss+xml" href="/rss.xml"/>
+  </channel>
+</rss>"#;
+        let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Blog</title>
+  <id>urn:blog</id>
+  <updated>2020-05-12T09:00:00Z</updated>
+  <link href="https://example.org/"/>
+  <link rel="self" href="https://feeds.example.org/atom"/>
+</feed>"#;
+        let json = r#"{"version": "https://jsonfeed.org/version/1.1", "title": "T", "feed_url": "https://example.org/feed.json"}"#;
+        let declared = |content_type: &str, body: &str| {
+            let feed = parse_feed_bytes(content_type, body.as_bytes()).unwrap();
+            declared_location(&feed, &feed_url)
+        };
+        assert_eq!(
+            Some("https://example.org/rss.xml".into()),
+            declared("application/rss+xml", rss)
+        );
+        assert_eq!(
+            Some("https://feeds.example.org/atom".into()),
+            declared("application/atom+xml", atom)
+        );
+        assert_eq!(
+            Some("https://example.org/feed.json".into()),
+            declared("application/feed+json", json)
+        );
+    }
+
+    #[test]
+    fn same_location_ignores_scheme_and_trailing_slash() {
+        assert!(is_same_location(
+            "https://blog.example.org/feed/",
+            "http://www.blog.example.org/feed"
+        ));
+        assert!(!is_same_location(
+            "https://example.org/feed?tag=a",
+            "https://example.org/feed?tag=b"
+        ));
+        assert!(!is_same_location(
+            "https://example.org/feed",
+            "https://example.com/feed"
+        ));
+    }
+
+    #[test]
+    fn parse_pushed_atom_updates() {
+        let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Blog</title>
+  <link href="https://example.org/"/>
+  <updated>2020-05-12T09:00:00Z</updated>
+  <id>urn:blog</id>
+  <entry>
+    <title>Pushed post</title>
+    <link href="https://example.org/pushed"/>
+    <id>urn:pushed</id>
+    <updated>2020-05-12T09:00:00Z</updated>
+  </entry>
+</feed>"#;
+        let expected = vec![Update {
+            ext_id: Some("urn:pushed".into()),
+            title: "Pushed post".into(),
+            url: "https://example.org/pushed".into(),
+            external_url: None,
+            metadata: Metadata::default(),
+            published: Utc.ymd(2020, 5, 12).and_hms(9, 0, 0),
+        }];
+        assert_eq!(
+            Ok(expected.clone()),
+            updates_from_bytes("application/atom+xml", atom.as_bytes())
+        );
+        assert_eq!(
+            Ok(expected),
+            updates_from_bytes("text/xml; charset=utf-8", atom.as_bytes())
+        );
+        assert!(updates_from_bytes("text/plain", atom.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn rss_items_with_guids_and_without_dates() {
+        let rss = r#"<?xml version="1.0"?>
+<rss version="2.0">
+  <channel>
+    <title>Blog</title>
+    <link>https://example.org</link>
+    <description>Posts</description>
+    <item>
+      <title>Dated</title>
+      <link>https://example.org/dated</link>
+      <guid isPermaLink="false">post-2</guid>
+      <pubDate>Tue, 12 May 2020 09:00:00 GMT</pubDate>
+    </item>
+    <item>
+      <title>Undated</title>
+      <link>https://example.org/undated</link>
+      <guid> </guid>
+    </item>
+  </channel>
+</rss>"#;
+        let before = Utc::now();
+        let updates = updates_from_bytes("application/rss+xml", rss.as_bytes()).unwrap();
+        assert_eq!(2, updates.len());
+        assert_eq!(Some("post-2".into()), updates[0].ext_id);
+        assert_eq!(Utc.ymd(2020, 5, 12).and_hms(9, 0, 0), updates[0].published);
+        assert_eq!(None, updates[1].ext_id);
+        assert!(updates[1].published >= before && updates[1].published <= Utc::now());
+    }
+
+    #[test]
+    fn rss_item_with_metadata() {
+        let rss = r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:media="http://search.yahoo.com/mrss/">
+  <channel>
+    <title>Podcast</title>
+    <link>https://example.org</link>
+    <description>Episodes</description>
+    <item>
+      <title>Episode 1</title>
+      <link>https://example.org/1</link>
+      <description><![CDATA[<p>We talk about <b>Rust</b>.</p><p>And more</p>]]></description>
+      <dc:creator>Jane Doe</dc:creator>
+      <category>rust</category>
+      <category> </category>
+      <category>rust</category>
+      <category>programming</category>
+      <media:group><media:thumbnail url="https://example.org/1.jpg"/></media:group>
+      <enclosure url="https://example.org/1.mp3" type="audio/mpeg" length="1234"/>
+      <pubDate>Tue, 12 May 2020 09:00:00 GMT</pubDate>
+    </item>
+    <item>
+      <title>Episode 0</title>
+      <link>https://example.org/0</link>
+      <pubDate>Mon, 11 May 2020 09:00:00 GMT</pubDate>
+    </item>
+  </channel>
+</rss>"#;
+        let updates = updates_from_bytes("application/rss+xml", rss.as_bytes()).unwrap();
+        assert_eq!(
+            Metadata {
+                summary: Some("We talk about Rust. And more".into()),
+                author: Some("Jane Doe".into()),
+                categories: vec!["rust".into(), "programming".into()],
+                image_url: Some("https://example.org/1.jpg".into()),
+                enclosures: vec![Enclosure {
+                    url: "https://example.org/1.mp3".into(),
+                    mime_type: Some("audio/mpeg".into()),
+                    length: Some(1234),
+                }],
+                reading_minutes: None,
+                duration_seconds: None,
+                episode: None,
+            },
+            updates[0].metadata
+        );
+        assert_eq!(Metadata::default(), updates[1].metadata);
+    }
+
+    #[test]
+    fn podcast_item_with_itunes_metadata() {
+        let rss = r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+  <channel>
+    <title>Podcast</title>
+    <link>https://example.org</link>
+    <description>Episodes</description>
+    <itunes:image href="https://example.org/show.jpg"/>
+    <item>
+      <title>Episode 12</title>
+      <link>https://example.org/12</link>
+      <enclosure url="https://example.org/12.mp3" type="audio/mpeg" length="1234"/>
+      <itunes:duration>1:02:03</itunes:duration>
+      <itunes:episode>12</itunes:episode>
+      <itunes:image href="https://example.org/12.jpg"/>
+      <pubDate>Tue, 12 May 2020 09:00:00 GMT</pubDate>
+    </item>
+    <item>
+      <title>Episode 11</title>
+      <link>https://example.org/11</link>
+      <enclosure url="https://example.org/11.mp3" type="audio/mpeg" length="1234"/>
+      <itunes:duration>3600</itunes:duration>
+      <pubDate>Mon, 11 May 2020 09:00:00 GMT</pubDate>
+    </item>
+  </channel>
+</rss>"#;
+        let updates = updates_from_bytes("application/rss+xml", rss.as_bytes()).unwrap();
+        let enclosure = |url: &str| Enclosure {
+            url: url.into(),
+            mime_type: Some("audio/mpeg".into()),
+            length: Some(1234),
+        };
+        assert_eq!(
+            Metadata {
+                image_url: Some("https://example.org/12.jpg".into()),
+                enclosures: vec![enclosure("https://example.org/12.mp3")],
+                duration_seconds: Some(3723),
+                episode: Some(12),
+                ..Metadata::default()
+            },
+            updates[0].metadata
+        );
+        assert_eq!(
+            Metadata {
+                image_url: Some("https://example.org/show.jpg".into()),
+                enclosures: vec![enclosure("https://example.org/11.mp3")],
+                duration_seconds: Some(3600),
+                ..Metadata::default()
+            },
+            updates[1].metadata
+        );
+    }
+
+    #[test]
+    fn parse_itunes_durations() {
+        assert_eq!(Some(3723), parse_duration("1:02:03"));
+        assert_eq!(Some(3723), parse_duration(" 62:03 "));
+        assert_eq!(Some(3723), parse_duration("3723"));
+        assert_eq!(Some(3724), parse_duration("01:02:03.5"));
+        assert_eq!(None, parse_duration("0"));
+        assert_eq!(None, parse_duration(""));
+        assert_eq!(None, parse_duration("1 hour"));
+        assert_eq!(None, parse_duration("1:-2:03"));
+        assert_eq!(None, parse_duration("1:1:02:03"));
+    }
+
+    #[test]
+    fn detect_podcasts() {
+        let podcast = |content_type: &str, body: &str| {
+            is_podcast(&parse_feed_bytes(content_type, body.as_bytes()).unwrap())
+        };
+        let rss = |namespace: &str, items: &str| {
+            format!(
+                r#"<?xml version="1.0"?>
+<rss version="2.0" {}>
+  <channel>
+    <title>T</title>
+    <link>https://example.org</link>
+    <description>D</description>
+    {}
+  </channel>
+</rss>"#,
+                namespace, items
+            )
+        };
+        let item = |enclosure: &str| {
+            format!(
+                "<item><title>I</title><link>https://example.org/i</link>{}</item>",
+                enclosure
+            )
+        };
+        let audio =
+            item(r#"<enclosure url="https://example.org/1.mp3" type="audio/mpeg" length="1"/>"#);
+        let image =
+            item(r#"<enclosure url="https://example.org/1.png" type="image/png" length="1"/>"#);
+        let itunes = r#"xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd""#;
+
+        let show = format!("<itunes:author>Someone</itunes:author>{}", item(""));
+        assert!(podcast("application/rss+xml", &rss(itunes, &show)));
+        assert!(podcast(
+            "application/rss+xml",
+            &rss("", &[audio.clone(), audio.clone(), item("")].concat())
+        ));
+        // a blog with the odd audio file
+        assert!(!podcast(
+            "application/rss+xml",
+            &rss("", &[audio, item(""), item("")].concat())
+        ));
+        assert!(!podcast("application/rss+xml", &rss("", &image)));
+        assert!(!podcast("application/rss+xml", &rss("", "")));
+
+        let json = r#"{"version": "https://jsonfeed.org/version/1.1", "title": "T", "items": [
+            {"id": "1", "title": "A", "attachments": [{"url": "https://example.org/1.m4a", "mime_type": "audio/x-m4a"}]}
+        ]}"#;
+        assert!(podcast("application/feed+json", json));
+
+        let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Videos</title>
+  <id>urn:videos</id>
+  <updated>2020-05-12T09:00:00Z</updated>
+  <entry>
+    <title>Video</title>
+    <id>urn:video</id>
+    <link rel="enclosure" type="video/mp4" href="https://example.org/1.mp4"/>
+    <updated>2020-05-12T09:00:00Z</updated>
+  </entry>
+</feed>"#;
+        assert!(podcast("application/atom+xml", atom));
+    }
+
+    #[test]
+    fn atom_entry_with_metadata() {
+        let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xmlns:media="http://search.yahoo.com/mrss/">
+  <title>Blog</title>
+  <link href="https://example.org/"/>
+  <updated>2020-05-12T09:00:00Z</updated>
+  <id>urn:blog</id>
+  <entry>
+    <title>Post</title>
+    <link href="https://example.org/post"/>
+    <link rel="enclosure" type="image/png" href="https://example.org/post.png"/>
+    <id>urn:post</id>
+    <author><name>John Doe</name></author>
+    <category term="rust" label="Rust"/>
+    <category term="web"/>
+    <summary type="html">&lt;p&gt;A post about &lt;em&gt;things&lt;/em&gt;&lt;/p&gt;</summary>
+    <updated>2020-05-12T09:00:00Z</updated>
+  </entry>
+</feed>"#;
+        let updates = updates_from_bytes("application/atom+xml", atom.as_bytes()).unwrap();
+        assert_eq!(
+            Metadata {
+                summary: Some("A post about things".into()),
+                author: Some("John Doe".into()),
+                categories: vec!["Rust".into(), "web".into()],
+                image_url: Some("https://example.org/post.png".into()),
+                enclosures: vec![Enclosure {
+                    url: "https://example.org/post.png".into(),
+                    mime_type: Some("image/png".into()),
+                    length: None,
+                }],
+                reading_minutes: None,
+                duration_seconds: None,
+                episode: None,
+            },
+            updates[0].metadata
+        );
+    }
+
+    #[test]
+    fn json_item_with_metadata() {
+        let json = r#"{
+  "version": "https://jsonfeed.org/version/1.1",
+  "title": "Blog",
+  "home_page_url": "https://example.org",
+  "items": [{
+    "id": "1",
+    "url": "https://example.org/1",
+    "title": "Post",
+    "content_html": "<p>Hello <i>world</i></p>",
+    "image": "https://example.org/1.png",
+    "authors": [{"name": "Jane"}],
+    "tags": ["a", "b"],
+    "attachments": [{"url": "https://example.org/1.mp3", "mime_type": "audio/mpeg", "size_in_bytes": 99, "duration_in_seconds": 3723.4}],
+    "date_published": "2020-05-12T09:00:00Z"
+  }]
+}"#;
+        let updates = updates_from_bytes("application/feed+json", json.as_bytes()).unwrap();
+        assert_eq!(
+            Metadata {
+                summary: Some("Hello world".into()),
+                author: Some("Jane".into()),
+                categories: vec!["a".into(), "b".into()],
+                image_url: Some("https://example.org/1.png".into()),
+                enclosures: vec![Enclosure {
+                    url: "https://example.org/1.mp3".into(),
+                    mime_type: Some("audio/mpeg".into()),
+                    length: Some(99),
+                }],
+                reading_minutes: None,
+                duration_seconds: Some(3723),
+                episode: None,
+            },
+            updates[0].metadata
+        );
+    }
+
+    #[test]
+    fn reject_json_that_is_not_a_feed() {
+        let json = r#"{"version": "2", "title": "Some api", "items": []}"#;
+        assert_eq!(true, ParsedFeed::parse_json(json.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn sniff_json_feeds_with_unspecific_content_types() {
+        let json = r#"
+            {
+              "title": "Served as text",
+              "version": "https://jsonfeed.org/version/1",
+              "items": [{"id": "1", "title": "Hello", "url": "https://example.org/1"}]
+            }"#;
+        for content_type in &["text/plain; charset=utf-8", "application/octet-stream", ""] {
+            let updates = updates_from_bytes(content_type, json.as_bytes()).unwrap();
+            assert_eq!("Hello", updates[0].title);
+        }
+        // the content type says it is something else
+        assert!(updates_from_bytes("text/html", json.as_bytes()).is_err());
+        // json, but not a feed
+        let api = r#"{"version": "2", "title": "Some api", "items": []}"#;
+        assert!(updates_from_bytes("text/plain", api.as_bytes()).is_err());
+        assert!(updates_from_bytes("text/plain", b"[1, 2, 3]").is_err());
+    }
+
     #[test]
     fn extract_links_in_html_head() {
         let html = r"
@@ -980,6 +2745,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn extract_json_feed_link_in_html_head() {
+        let html = r"
+          <!DOCTYPE html>
+          <html lang='en'>
+          <head>
+          <link rel='alternate' type='application/feed+json' title='JSON Feed' href='/feed.json' />
+          <link rel='https://api.w.org/' type='application/json' href='/wp-json/' />
+          </head>
+          <body>
+          </body>
+          </html>
+        ";
+        let base_url = Url::parse("https://daringfireball.net").unwrap();
+        let feeds = extract_feeds_from_html(&base_url, html).expect("Failed to parse");
+        assert_eq!(1, feeds.len());
+        assert_eq!(
+            Url::parse("https://daringfireball.net/feed.json").unwrap(),
+            feeds[0],
+        );
+    }
+
     #[test]
     fn extract_links_in_html_body_div() {
         let html = r"
@@ -1158,36 +2945,42 @@ mod tests {
             ext_id: "https://a.ch/rss.xml".into(),
             link: "https://a.ch/".into(),
             verified: false,
+            podcast: false,
         };
         let atom_hint_in_url = ChannelInfo {
             name: "blog a".into(),
             ext_id: "https://a.ch/atom.xml".into(),
             link: "https://a.ch/".into(),
             verified: false,
+            podcast: false,
         };
         let rss_hint_in_name = ChannelInfo {
             name: "blog a rss feed".into(),
             ext_id: "https://a.ch/feed.xml".into(),
             link: "https://a.ch/".into(),
             verified: false,
+            podcast: false,
         };
         let atom_hint_in_name = ChannelInfo {
             name: "blog a atom feed".into(),
             ext_id: "https://a.ch/feed.xml".into(),
             link: "https://a.ch/".into(),
             verified: false,
+            podcast: false,
         };
         let other_blog_with_no_hint = ChannelInfo {
             name: "blog b".into(),
             ext_id: "https://b.ch/feed.xml".into(),
             link: "https://b.ch/".into(),
             verified: false,
+            podcast: false,
         };
         let other_blog_with_rss_hint = ChannelInfo {
             name: "blog c rss feed".into(),
             ext_id: "https://c.ch/feed.xml".into(),
             link: "https://c.ch/".into(),
             verified: false,
+            podcast: false,
         };
 
         // SCENARIO 1: Atom replaces Rss (hint in URL)
@@ -1230,6 +3023,288 @@ mod tests {
         assert_eq!(2, feeds.len());
     }
 
+    #[test]
+    fn parse_latin1_feeds() {
+        let feed = |declaration: &str| -> Vec<u8> {
+            let mut bytes = format!(
+                r#"{}
+<rss version="2.0">
+  <channel>
+    <title>Nachrichten</title>
+    <link>https://example.org</link>
+    <description>Neues</description>
+    <item>
+      <title>"#,
+                declaration
+            )
+            .into_bytes();
+            // 'Grüezi' in iso-8859-1
+            bytes.extend_from_slice(&[b'G', b'r', 0xFC, b'e', b'z', b'i']);
+            bytes.extend_from_slice(
+                b"</title>
+      <link>https://example.org/gruezi</link>
+      <pubDate>Tue, 12 May 2020 09:00:00 GMT</pubDate>
+    </item>
+  </channel>
+</rss>",
+            );
+            bytes
+        };
+        let title = |content_type: &str, bytes: &[u8]| match parse_feed_bytes(content_type, bytes) {
+            Ok(ParsedFeed::Rss(channel)) => channel.items()[0].title().map(|t| t.to_owned()),
+            _ => None,
+        };
+
+        let latin1 = r#"<?xml version="1.0" encoding="ISO-8859-1"?>"#;
+        let utf8 = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+        let no_encoding = r#"<?xml version="1.0"?>"#;
+        let expected = Some("Grüezi".to_owned());
+        // declared in the xml only
+        assert_eq!(expected, title("application/rss+xml", &feed(latin1)));
+        // declared in the header only
+        assert_eq!(
+            expected,
+            title("text/xml; charset=iso-8859-1", &feed(no_encoding))
+        );
+        // header takes precedence over the xml declaration
+        assert_eq!(
+            expected,
+            title("application/rss+xml; charset=\"windows-1252\"", &feed(utf8))
+        );
+        // header is wrong, but the xml declaration is right
+        assert_eq!(
+            expected,
+            title("application/rss+xml; charset=utf-8", &feed(latin1))
+        );
+        // declared nowhere
+        assert_eq!(expected, title("application/rss+xml", &feed(no_encoding)));
+    }
+
+    #[test]
+    fn transcode_to_utf8() {
+        let utf8 = r#"<?xml version="1.0" encoding="utf-8"?><rss>ü</rss>"#;
+        let with_bom = [&[0xEF, 0xBB, 0xBF], utf8.as_bytes()].concat();
+        assert_eq!(utf8.as_bytes(), &*to_utf8("application/rss+xml", &with_bom));
+        assert_eq!(
+            utf8.as_bytes(),
+            &*to_utf8("application/rss+xml; charset=iso-8859-1", utf8.as_bytes())
+        );
+        // the declaration is as wrong as the header
+        let declared_latin1 = r#"<?xml version="1.0" encoding="ISO-8859-1"?><rss>ü</rss>"#;
+        assert_eq!(
+            utf8.replace("utf-8", "UTF-8").as_bytes(),
+            &*to_utf8("text/xml", declared_latin1.as_bytes())
+        );
+        let ascii = r#"<?xml version="1.0" encoding="windows-1252"?><rss>u</rss>"#;
+        assert_eq!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><rss>u</rss>"#.as_bytes(),
+            &*to_utf8("text/xml", ascii.as_bytes())
+        );
+
+        let utf16: Vec<u8> = [0xFF, 0xFE]
+            .iter()
+            .cloned()
+            .chain(
+                r#"<?xml version="1.0" encoding="UTF-16"?><rss>ü</rss>"#
+                    .encode_utf16()
+                    .flat_map(|c| c.to_le_bytes().to_vec()),
+            )
+            .collect();
+        assert_eq!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><rss>ü</rss>"#.as_bytes(),
+            &*to_utf8("text/xml", &utf16)
+        );
+
+        let latin1 = b"<?xml version='1.0' encoding='iso-8859-1' standalone='yes'?><rss>\xFC</rss>";
+        assert_eq!(
+            r#"<?xml version='1.0' encoding="UTF-8" standalone='yes'?><rss>ü</rss>"#.as_bytes(),
+            &*to_utf8("text/xml", latin1)
+        );
+    }
+
+    #[test]
+    fn decode_entities_in_titles() {
+        assert_eq!("Fish & Chips", decode_entities("Fish &amp; Chips"));
+        assert_eq!(
+            "It’s “quoted”",
+            decode_entities("It&#8217;s &ldquo;quoted&#x201D;")
+        );
+        assert_eq!("Grüezi", decode_entities("Gr&uuml;ezi"));
+        assert_eq!("Fish & Chips", decode_entities("Fish &#38; Chips"));
+        // literal text about markup
+        assert_eq!("a &lt; b", decode_entities("a &lt; b"));
+        assert_eq!("Why &lt;div&gt;?", decode_entities("Why &lt;div&gt;?"));
+        assert_eq!("&#60;p&#x3e;", decode_entities("&#60;p&#x3e;"));
+        // not entities
+        assert_eq!("AT&T; & more", decode_entities("AT&T; & more"));
+        assert_eq!("&unknown; &", decode_entities("&unknown; &"));
+        assert_eq!("&#xZZ;", decode_entities("&#xZZ;"));
+    }
+
+    #[test]
+    fn rank_channel_infos() {
+        let info = |name: &str, ext_id: &str| ChannelInfo {
+            name: name.into(),
+            ext_id: ext_id.into(),
+            link: "https://a.ch/".into(),
+            verified: false,
+            podcast: false,
+        };
+        let comments = info("Comments on blog a", "https://a.ch/comments/feed/");
+        let category = info("blog a - rust", "https://a.ch/category/rust/feed/");
+        let posts = info("blog a", "https://a.ch/feed/");
+        let json = info("blog a (json)", "https://a.ch/feed.json");
+
+        let mut feeds: Vec<ChannelInfo> = Vec::new();
+        add_channel_info(&mut feeds, comments.clone());
+        add_channel_info(&mut feeds, category.clone());
+        add_channel_info(&mut feeds, posts.clone());
+        add_channel_info(&mut feeds, json.clone());
+        assert_eq!(vec![json, posts, category, comments], feeds);
+    }
+
+    #[test]
+    fn feeds_of_platforms() {
+        let feeds = |url: &str| -> Vec<String> {
+            platform_feeds(&Url::parse(url).unwrap())
+                .iter()
+                .map(|u| u.to_string())
+                .collect()
+        };
+        assert_eq!(
+            vec!["https://github.com/rethab/digester/releases.atom"],
+            feeds("https://github.com/rethab/digester/tree/master/backend")
+        );
+        assert_eq!(
+            vec!["https://www.youtube.com/feeds/videos.xml?channel_id=UC123"],
+            feeds("https://www.youtube.com/channel/UC123/videos")
+        );
+        assert_eq!(
+            vec!["https://www.youtube.com/feeds/videos.xml?user=someone"],
+            feeds("https://youtube.com/user/someone")
+        );
+        assert_eq!(
+            vec!["https://www.youtube.com/feeds/videos.xml?playlist_id=PL42"],
+            feeds("https://www.youtube.com/playlist?list=PL42")
+        );
+        assert_eq!(
+            vec!["https://medium.com/feed/@someone"],
+            feeds("https://medium.com/@someone")
+        );
+        assert_eq!(
+            vec!["https://someone.medium.com/feed"],
+            feeds("https://someone.medium.com/some-post-123")
+        );
+        assert_eq!(
+            vec!["https://someone.substack.com/feed"],
+            feeds("https://someone.substack.com/")
+        );
+        assert_eq!(
+            vec!["https://someone.wordpress.com/feed/"],
+            feeds("https://someone.wordpress.com/2020/05/12/hello")
+        );
+        assert!(feeds("https://github.com/rethab").is_empty());
+        assert!(feeds("https://example.org/blog").is_empty());
+    }
+
+    #[test]
+    fn feeds_of_platforms_in_html() {
+        let url = Url::parse("https://www.youtube.com/@someone").unwrap();
+        let html = r#"<html><head>
+            <link rel="canonical" href="https://www.youtube.com/channel/UC123">
+            </head><body></body></html>"#;
+        assert_eq!(
+            vec![Url::parse("https://www.youtube.com/feeds/videos.xml?channel_id=UC123").unwrap()],
+            html_platform_feeds(&url, html)
+        );
+
+        let url = Url::parse("https://example.org/2020/05/hello").unwrap();
+        let html = r#"<html><head>
+            <meta name="generator" content="WordPress 5.4.1">
+            </head><body></body></html>"#;
+        assert_eq!(
+            vec![Url::parse("https://example.org/feed/").unwrap()],
+            html_platform_feeds(&url, html)
+        );
+
+        let html = r#"<html><head>
+            <link rel="stylesheet" href="https://substackcdn.com/min/main.css">
+            </head><body></body></html>"#;
+        assert_eq!(
+            vec![Url::parse("https://example.org/feed").unwrap()],
+            html_platform_feeds(&url, html)
+        );
+
+        let html = "<html><head></head><body>Hello</body></html>";
+        assert!(html_platform_feeds(&url, html).is_empty());
+    }
+
+    #[test]
+    fn well_known_feeds_next_to_page_and_at_root() {
+        let feeds: Vec<String> =
+            well_known_feeds(&Url::parse("https://example.org/blog?page=2").unwrap())
+                .iter()
+                .map(|u| u.to_string())
+                .collect();
+        assert_eq!(
+            vec![
+                "https://example.org/blog/feed",
+                "https://example.org/blog/rss.xml",
+                "https://example.org/blog/atom.xml",
+                "https://example.org/blog/index.xml",
+                "https://example.org/blog/feed.json",
+                "https://example.org/feed",
+                "https://example.org/rss.xml",
+                "https://example.org/atom.xml",
+                "https://example.org/index.xml",
+                "https://example.org/feed.json",
+            ],
+            feeds
+        );
+
+        // no duplicates if the page is at the root
+        let feeds = well_known_feeds(&Url::parse("https://example.org").unwrap());
+        assert_eq!(5, feeds.len());
+    }
+
+    #[test]
+    fn fetch_probes_well_known_paths() {
+        let _page = mockito::mock("GET", "/probing/blog")
+            .with_header("content-type", "text/html")
+            .with_body("<html><head><title>Blog</title></head><body>Hello</body></html>")
+            .create();
+        let _feed = mockito::mock("GET", "/probing/blog/atom.xml")
+            .with_header("content-type", "application/atom+xml")
+            .with_body(
+                r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>Blog</title>
+  <id>urn:blog</id>
+  <updated>2020-05-12T09:00:00Z</updated>
+  <link href="https://example.org/blog"/>
+</feed>"#,
+            )
+            .create();
+        // we stop at the first feed we find
+        let later = mockito::mock("GET", "/probing/blog/index.xml")
+            .with_header("content-type", "application/rss+xml")
+            .expect(0)
+            .create();
+        let url = Url::parse(&format!("{}/probing/blog", mockito::server_url())).unwrap();
+        let feeds = fetch_channel_info(&url, 0).expect("Failed to fetch feeds");
+        later.assert();
+        assert_eq!(
+            vec![ChannelInfo {
+                name: "Blog".into(),
+                ext_id: format!("{}/probing/blog/atom.xml", mockito::server_url()),
+                link: "https://example.org/blog".into(),
+                verified: false,
+                podcast: false,
+            }],
+            feeds
+        );
+    }
+
     #[test]
     fn parse_validation_https() {
         assert_eq!(
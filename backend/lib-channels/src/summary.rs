@@ -0,0 +1,174 @@
+use kuchiki::traits::*;
+
+/// summaries are shown below the title in the digest, so they should
+/// not be longer than two or three lines
+const MAX_SUMMARY_CHARS: usize = 280;
+
+/// Turns the (html) description of an update into a short plain text
+/// summary. Returns none if there is no text in it.
+pub fn summarize(html: &str) -> Option<String> {
+    let text = html_to_text(html);
+    if text.is_empty() {
+        None
+    } else if text.chars().count() <= MAX_SUMMARY_CHARS {
+        Some(text)
+    } else {
+        Some(truncate(&text, MAX_SUMMARY_CHARS))
+    }
+}
+
+/// Same as `summarize`, but for markdown (eg. release notes)
+pub fn summarize_markdown(markdown: &str) -> Option<String> {
+    summarize(&markdown_to_text(markdown))
+}
+
+/// The text of some html with all whitespace collapsed. Also works for plain text.
+pub fn html_to_text(html: &str) -> String {
+    // without these, the text of adjacent paragraphs would be glued together
+    let spaced = html
+        .replace("</p>", "</p> ")
+        .replace("</li>", "</li> ")
+        .replace("</div>", "</div> ")
+        .replace("<br>", " ")
+        .replace("<br/>", " ")
+        .replace("<br />", " ");
+    let document = kuchiki::parse_html().one(spaced);
+    // the text of scripts is code and not something anyone wants to read
+    if let Ok(hidden) = document.select("script, style, noscript") {
+        for node in hidden.collect::<Vec<_>>() {
+            node.as_node().detach();
+        }
+    }
+    let text = document.text_contents();
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// only strips the syntax that would get in the way of reading it as text.
+// the result is escaped, so summarize doesn't take eg. Vec<T> for a tag.
+fn markdown_to_text(markdown: &str) -> String {
+    let mut text = String::new();
+    let mut in_code_block = false;
+    for line in markdown.lines() {
+        let line = line.trim();
+        if line.starts_with("```") {
+            in_code_block = !in_code_block;
+            continue;
+        }
+        if in_code_block {
+            continue;
+        }
+        let line = line
+            .trim_start_matches(|c| c == '#' || c == '>')
+            .trim_start();
+        let line = if line.starts_with("- ") || line.starts_with("* ") || line.starts_with("+ ") {
+            &line[2..]
+        } else {
+            line
+        };
+        text.push_str(&strip_links(line));
+        text.push('\n');
+    }
+    text.replace("**", "")
+        .replace("__", "")
+        .replace('`', "")
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+}
+
+// [text](url) and ![alt](url) become text and alt
+fn strip_links(line: &str) -> String {
+    let mut result = String::new();
+    let mut rest = line;
+    while let Some(start) = rest.find('[') {
+        let link = &rest[start..];
+        match (link.find("]("), link.find(')')) {
+            (Some(text_end), Some(end)) if text_end < end => {
+                let before = rest[..start].trim_end_matches('!');
+                result.push_str(before);
+                result.push_str(&link[1..text_end]);
+                rest = &link[end + 1..];
+            }
+            _ => {
+                result.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+// cuts at the last word that fits, so we don't end up with half a word
+fn truncate(text: &str, max_chars: usize) -> String {
+    let mut truncated = String::new();
+    for word in text.split(' ') {
+        let len = truncated.chars().count() + word.chars().count() + 1;
+        if len > max_chars {
+            break;
+        }
+        if !truncated.is_empty() {
+            truncated.push(' ');
+        }
+        truncated.push_str(word);
+    }
+    if truncated.is_empty() {
+        // a single word that is longer than the summary (eg. a link)
+        truncated = text.chars().take(max_chars).collect();
+    }
+    format!("{}...", truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarize_strips_html() {
+        assert_eq!(
+            Some("Mastodon 3.2 is out! Check out the blog.joinmastodon.org".into()),
+            summarize(
+                "<p>Mastodon 3.2 is out!</p><p>Check out the <a href=\"https://blog.joinmastodon.org/\">blog.joinmastodon.org</a></p>"
+            )
+        );
+        assert_eq!(
+            Some("a b".into()),
+            summarize("<ul><li>a</li><li>b</li></ul><script>alert(1)</script><style>p {}</style>")
+        );
+        assert_eq!(
+            Some("Fish & Chips".into()),
+            summarize("  Fish &amp; Chips\n\n")
+        );
+    }
+
+    #[test]
+    fn summarize_release_notes() {
+        let notes = "## What's Changed\r\n\r\n* **Breaking**: `Vec<T>` instead of [slices](https://example.com/slices) by @jane\r\n```rust\r\nlet x = 1;\r\n```\r\n![logo](logo.png) [unclosed";
+        assert_eq!(
+            Some(
+                "What's Changed Breaking: Vec<T> instead of slices by @jane logo [unclosed".into()
+            ),
+            summarize_markdown(notes)
+        );
+        assert_eq!(None, summarize_markdown("```\ncode only\n```"));
+    }
+
+    #[test]
+    fn summarize_empty_html() {
+        assert_eq!(None, summarize(""));
+        assert_eq!(None, summarize("<p> </p><img src=\"a.png\">"));
+    }
+
+    #[test]
+    fn summarize_truncates_at_word() {
+        let long = "word ".repeat(100);
+        let summary = summarize(&long).unwrap();
+        assert!(summary.ends_with("word..."));
+        assert!(summary.chars().count() <= MAX_SUMMARY_CHARS + 3);
+
+        let no_spaces = "x".repeat(400);
+        assert_eq!(
+            format!("{}...", "x".repeat(MAX_SUMMARY_CHARS)),
+            summarize(&no_spaces).unwrap()
+        );
+    }
+}
@@ -6,6 +6,7 @@ use egg_mode::Token;
 use std::collections::HashMap;
 use tokio::runtime::Runtime;
 
+#[derive(Clone)]
 pub struct Twitter {
     token: Token,
 }
@@ -87,6 +88,8 @@ async fn tweet_search(screen_name: String, token: &Token) -> Result<Vec<Update>,
                     ext_id: Some(tweet.id.to_string()),
                     title: tweet.text.clone(),
                     url: format!("https://twitter.com/{}/status/{}", screen_name, tweet.id),
+                    external_url: None,
+                    metadata: tweet_metadata(tweet),
                     published: tweet.created_at,
                 };
                 updates.push(update);
@@ -96,6 +99,30 @@ async fn tweet_search(screen_name: String, token: &Token) -> Result<Vec<Update>,
     }
 }
 
+// the text of a tweet is already the title, so there is no summary
+fn tweet_metadata(tweet: &Tweet) -> Metadata {
+    Metadata {
+        summary: None,
+        author: tweet.user.as_ref().map(|u| u.name.clone()),
+        categories: tweet
+            .entities
+            .hashtags
+            .iter()
+            .map(|h| h.text.clone())
+            .collect(),
+        image_url: tweet
+            .entities
+            .media
+            .as_ref()
+            .and_then(|media| media.first())
+            .map(|m| m.media_url_https.clone()),
+        enclosures: Vec::new(),
+        reading_minutes: None,
+        duration_seconds: None,
+        episode: None,
+    }
+}
+
 async fn user_search(query: SanitizedName, token: &Token) -> Result<Vec<ChannelInfo>, SearchError> {
     let results = user::search(query.0.to_string(), token)
         .with_page_size(10)
@@ -116,6 +143,7 @@ async fn user_search(query: SanitizedName, token: &Token) -> Result<Vec<ChannelI
                         name: user.name,
                         link: format!("https://twitter.com/{}", user.screen_name),
                         verified: user.verified,
+                        podcast: false,
                     })
                 }
             }
@@ -0,0 +1,422 @@
+use super::channel::*;
+use super::http::{self, HttpError};
+use super::rss::SanitizedUrl;
+
+use chrono::{DateTime, Utc};
+use kuchiki::traits::TendrilSink;
+use kuchiki::{NodeRef, Selectors};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fmt::Display;
+
+const MAX_TITLE_LENGTH: usize = 120;
+
+// elements that start a new line in the normalized text
+const BLOCK_ELEMENTS: [&str; 24] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "p",
+    "pre",
+    "section",
+    "tr",
+];
+
+// elements whose text is not visible
+const IGNORED_ELEMENTS: [&str; 4] = ["script", "style", "noscript", "template"];
+
+/// What a web page looked like the last time we fetched it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// sha256 of the content (hex)
+    pub hash: String,
+    /// normalized text of the selected region, one block per line
+    pub content: String,
+}
+
+/// Remembers the snapshots of web pages between fetches
+pub trait SnapshotStore {
+    /// The snapshot of the page (identified by the channel's ext_id)
+    fn find(&self, ext_id: &str) -> Result<Option<Snapshot>, String>;
+    /// Replaces the snapshot of the page
+    fn save(&self, ext_id: &str, snapshot: &Snapshot) -> Result<(), String>;
+}
+
+/// Reports changes of web pages that don't have a feed (eg. changelogs
+/// or status pages). Since a page has no history, we need to remember
+/// what it looked like to tell whether it changed.
+pub struct WebPage<'a> {
+    snapshots: Option<Box<dyn SnapshotStore + 'a>>,
+}
+
+impl<'a> WebPage<'a> {
+    pub fn new(snapshots: Box<dyn SnapshotStore + 'a>) -> WebPage<'a> {
+        WebPage {
+            snapshots: Some(snapshots),
+        }
+    }
+
+    // searching doesn't need any snapshots, only fetching does
+    pub fn without_snapshots() -> WebPage<'a> {
+        WebPage { snapshots: None }
+    }
+}
+
+/// A web page and optionally the css selector of the region
+/// we care about. The ext_id is the url followed by the selector.
+#[derive(PartialEq, Debug)]
+pub struct PageRegion {
+    url: SanitizedUrl,
+    selector: Option<String>,
+}
+
+impl PageRegion {
+    fn unsafe_parse(name: &str) -> Self {
+        Self::parse(name).unwrap()
+    }
+
+    // eg. https://status.example.com/ .incidents li
+    fn parse(name: &str) -> Result<Self, String> {
+        let name = name.trim();
+        let (url, selector) = match name.find(char::is_whitespace) {
+            Some(idx) => (&name[..idx], Some(name[idx..].trim().to_owned())),
+            None => (name, None),
+        };
+        if let Some(selector) = &selector {
+            Selectors::compile(selector)
+                .map_err(|_| format!("Invalid css selector: {}", selector))?;
+        }
+        Ok(PageRegion {
+            url: SanitizedUrl::parse(url)?,
+            selector,
+        })
+    }
+}
+
+impl Display for PageRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.selector {
+            Some(selector) => write!(f, "{} {}", self.url, selector),
+            None => write!(f, "{}", self.url),
+        }
+    }
+}
+
+impl From<SanitizedName> for PageRegion {
+    fn from(name: SanitizedName) -> Self {
+        PageRegion::unsafe_parse(&name.0)
+    }
+}
+
+impl Into<SanitizedName> for PageRegion {
+    fn into(self) -> SanitizedName {
+        SanitizedName(self.to_string())
+    }
+}
+
+// extracts the visible text of the selected region (or the whole body)
+// with one block element per line and whitespace collapsed, so that
+// changes to the markup alone don't count as changes of the page.
+fn extract_text(html: &str, selector: Option<&str>) -> Result<String, String> {
+    let document = kuchiki::parse_html().one(html);
+    let roots: Vec<NodeRef> = document
+        .select(selector.unwrap_or("body"))
+        .map_err(|_| format!("Invalid css selector: {:?}", selector))?
+        .map(|element| element.as_node().clone())
+        .collect();
+    if roots.is_empty() {
+        return Err(format!("Nothing matches the selector {:?}", selector));
+    }
+
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for root in roots {
+        collect_lines(&root, &mut lines, &mut current);
+        flush_line(&mut lines, &mut current);
+    }
+    Ok(lines.join("\n"))
+}
+
+fn collect_lines(node: &NodeRef, lines: &mut Vec<String>, current: &mut String) {
+    for child in node.children() {
+        if let Some(text) = child.as_text() {
+            current.push_str(&text.borrow());
+        } else if let Some(element) = child.as_element() {
+            let name: &str = &element.name.local;
+            if IGNORED_ELEMENTS.contains(&name) {
+                continue;
+            }
+            let is_block = BLOCK_ELEMENTS.contains(&name);
+            if is_block {
+                flush_line(lines, current);
+            }
+            collect_lines(&child, lines, current);
+            if is_block {
+                flush_line(lines, current);
+            }
+        }
+    }
+}
+
+fn flush_line(lines: &mut Vec<String>, current: &mut String) {
+    let line = current.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    current.clear();
+}
+
+fn snapshot(content: String) -> Snapshot {
+    let hash = Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Snapshot { hash, content }
+}
+
+// a short summary of what changed, eg. "v2.3: new dashboard (+4/-1 lines)"
+fn diff_title(previous: &str, current: &str) -> String {
+    let previous_lines: HashSet<&str> = previous.lines().collect();
+    let current_lines: HashSet<&str> = current.lines().collect();
+    let added: Vec<&str> = current
+        .lines()
+        .filter(|l| !previous_lines.contains(l))
+        .collect();
+    let removed: Vec<&str> = previous
+        .lines()
+        .filter(|l| !current_lines.contains(l))
+        .collect();
+
+    let summary = match (added.first(), removed.first()) {
+        (Some(first), _) => (*first).to_owned(),
+        (None, Some(first)) => format!("Removed: {}", first),
+        (None, None) => "Content was reordered".to_owned(),
+    };
+    let counts = format!(" (+{}/-{} lines)", added.len(), removed.len());
+    let max_summary = MAX_TITLE_LENGTH - counts.len();
+    if summary.chars().count() > max_summary {
+        let truncated: String = summary.chars().take(max_summary - 3).collect();
+        format!("{}...{}", truncated, counts)
+    } else {
+        format!("{}{}", summary, counts)
+    }
+}
+
+// returns the new snapshot and an update if the page changed
+// since the previous snapshot. the first snapshot is no change.
+fn detect_change(
+    region: &PageRegion,
+    html: &str,
+    previous: Option<&Snapshot>,
+    now: DateTime<Utc>,
+) -> Result<(Snapshot, Option<Update>), String> {
+    let current = snapshot(extract_text(html, region.selector.as_deref())?);
+    let update = match previous {
+        Some(previous) if previous.hash != current.hash => Some(Update {
+            ext_id: Some(current.hash.clone()),
+            title: diff_title(&previous.content, &current.content),
+            url: region.url.to_string(),
+            external_url: None,
+            metadata: Metadata::default(),
+            published: now,
+        }),
+        _ => None,
+    };
+    Ok((current, update))
+}
+
+fn page_title(html: &str) -> Option<String> {
+    let document = kuchiki::parse_html().one(html);
+    let title = document.select_first("title").ok()?.text_contents();
+    let title = title.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+fn search_page(region: &PageRegion, html: &str) -> Result<ChannelInfo, SearchError> {
+    // make sure there is something to follow
+    extract_text(html, region.selector.as_deref()).map_err(SearchError::ChannelNotFound)?;
+    let title = page_title(html).unwrap_or_else(|| region.url.to_string_without_scheme());
+    let name = match &region.selector {
+        Some(selector) => format!("{} ({})", title, selector),
+        None => title,
+    };
+    Ok(ChannelInfo {
+        name,
+        link: region.url.to_string(),
+        ext_id: region.to_string(),
+        verified: false,
+        podcast: false,
+    })
+}
+
+impl Channel for WebPage<'_> {
+    fn sanitize(&self, name: &str) -> Result<SanitizedName, String> {
+        PageRegion::parse(name).map(|r| r.into())
+    }
+
+    fn search(&self, name: SanitizedName) -> Result<Vec<ChannelInfo>, SearchError> {
+        let region = PageRegion::from(name);
+        let html = http::get_text(&region.url.to_string()).map_err(|err| err.into())?;
+        search_page(&region, &html).map(|info| vec![info])
+    }
+
+    fn fetch_updates(&self, ext_id: &str) -> Result<Vec<Update>, String> {
+        let snapshots = self
+            .snapshots
+            .as_ref()
+            .ok_or("Cannot fetch web pages without snapshots")?;
+        let region = PageRegion::parse(ext_id)?;
+        let html = http::get_text(&region.url.to_string())
+            .map_err(|err: HttpError| format!("Failed to fetch {}: {:?}", region.url, err))?;
+
+        let previous = snapshots.find(ext_id)?;
+        let (current, update) = detect_change(&region, &html, previous.as_ref(), Utc::now())?;
+        if previous.map(|p| p.hash != current.hash).unwrap_or(true) {
+            snapshots.save(ext_id, &current)?;
+        }
+        Ok(update.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const CHANGELOG_V1: &str = r#"<html><head><title>Changelog | Example</title>
+        <style>.release { color: red }</style></head>
+        <body><nav>Home | Pricing</nav>
+        <div id="changelog">
+          <h2>v1.1</h2><ul><li>Fixed <b>login</b> bug</li></ul>
+          <h2>v1.0</h2><p>Initial   release</p>
+        </div><footer>Last built at 10:00</footer></body></html>"#;
+
+    const CHANGELOG_V2: &str = r#"<html><head><title>Changelog | Example</title></head>
+        <body><nav>Home | Pricing</nav>
+        <div id="changelog">
+          <h2>v1.2</h2><ul><li>New dashboard</li><li>Dark mode</li></ul>
+          <h2>v1.1</h2><ul><li>Fixed <b>login</b> bug</li></ul>
+          <h2>v1.0</h2><p>Initial release</p>
+        </div><footer>Last built at 11:00</footer></body></html>"#;
+
+    #[test]
+    fn parse_page_regions() {
+        let region = PageRegion::parse("example.com/changelog #changelog  h2").unwrap();
+        assert_eq!(
+            "http://example.com/changelog #changelog  h2",
+            region.to_string()
+        );
+        assert_eq!(
+            Ok("https://example.com/changelog".to_owned()),
+            PageRegion::parse("https://example.com/changelog").map(|r| r.to_string())
+        );
+        assert_eq!(
+            true,
+            PageRegion::parse("https://example.com/ div[").is_err()
+        );
+        assert_eq!(true, PageRegion::parse("https://127.0.0.1/ div").is_err());
+    }
+
+    #[test]
+    fn extract_normalized_text() {
+        assert_eq!(
+            Ok("v1.1\nFixed login bug\nv1.0\nInitial release".to_owned()),
+            extract_text(CHANGELOG_V1, Some("#changelog"))
+        );
+        assert_eq!(
+            Ok(
+                "Home | Pricing\nv1.1\nFixed login bug\nv1.0\nInitial release\nLast built at 10:00"
+                    .to_owned()
+            ),
+            extract_text(CHANGELOG_V1, None)
+        );
+        assert_eq!(true, extract_text(CHANGELOG_V1, Some("#nothing")).is_err());
+    }
+
+    #[test]
+    fn no_update_on_first_snapshot() {
+        let region = PageRegion::parse("https://example.com/changelog #changelog").unwrap();
+        let (snapshot, update) = detect_change(&region, CHANGELOG_V1, None, Utc::now()).unwrap();
+        assert_eq!(None, update);
+        assert_eq!(64, snapshot.hash.len());
+    }
+
+    #[test]
+    fn ignore_changes_outside_of_region() {
+        let region = PageRegion::parse("https://example.com/changelog #changelog").unwrap();
+        let v1 = CHANGELOG_V1.replace("10:00", "12:00");
+        let (previous, _) = detect_change(&region, CHANGELOG_V1, None, Utc::now()).unwrap();
+        let (current, update) = detect_change(&region, &v1, Some(&previous), Utc::now()).unwrap();
+        assert_eq!(None, update);
+        assert_eq!(previous, current);
+    }
+
+    #[test]
+    fn update_on_change() {
+        let now = Utc.ymd(2020, 7, 20).and_hms(8, 0, 0);
+        let region = PageRegion::parse("https://example.com/changelog #changelog").unwrap();
+        let (previous, _) = detect_change(&region, CHANGELOG_V1, None, now).unwrap();
+        let (current, update) = detect_change(&region, CHANGELOG_V2, Some(&previous), now).unwrap();
+        assert_eq!(
+            Some(Update {
+                ext_id: Some(current.hash),
+                title: "v1.2 (+3/-0 lines)".into(),
+                url: "https://example.com/changelog".into(),
+                external_url: None,
+                metadata: Metadata::default(),
+                published: now,
+            }),
+            update
+        );
+    }
+
+    #[test]
+    fn summarize_diffs() {
+        assert_eq!("b (+1/-0 lines)", diff_title("a", "a\nb"));
+        assert_eq!("Removed: b (+0/-1 lines)", diff_title("a\nb", "a"));
+        assert_eq!(
+            "Content was reordered (+0/-0 lines)",
+            diff_title("a\nb", "b\na")
+        );
+        let long = "x".repeat(200);
+        let title = diff_title("a", &long);
+        assert_eq!(MAX_TITLE_LENGTH, title.len());
+        assert_eq!(true, title.ends_with("... (+1/-1 lines)"));
+    }
+
+    #[test]
+    fn name_from_page_title() {
+        let region = PageRegion::parse("https://example.com/changelog #changelog").unwrap();
+        assert_eq!(
+            Ok(ChannelInfo {
+                name: "Changelog | Example (#changelog)".into(),
+                link: "https://example.com/changelog".into(),
+                ext_id: "https://example.com/changelog #changelog".into(),
+                verified: false,
+                podcast: false,
+            }),
+            search_page(&region, CHANGELOG_V1).map_err(|err| format!("{:?}", err))
+        );
+    }
+}
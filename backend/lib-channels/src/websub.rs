@@ -0,0 +1,326 @@
+// WebSub (formerly PubSubHubbub) lets a feed push new content to us via a hub
+// instead of us polling it. See https://www.w3.org/TR/websub/
+
+use super::http;
+use hmac::crypto_mac::InvalidKeyLength;
+use hmac::{Hmac, Mac, NewMac};
+use kuchiki::traits::*;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use reqwest::header;
+use sha1::Sha1;
+use sha2::{Sha256, Sha384, Sha512};
+
+/// how long we ask the hub to keep the subscription. hubs may choose
+/// a different value, which they tell us when verifying the intent.
+pub const LEASE_SECONDS: i64 = 10 * 24 * 60 * 60;
+
+/// The hub a feed advertises and the url under which the feed is known to the hub
+#[derive(Debug, PartialEq)]
+pub struct Hub {
+    pub url: String,
+    pub topic: String,
+}
+
+/// fetches the feed and looks for a hub, first in the http link header
+/// and then in the link elements of the feed itself
+pub fn discover(feed_url: &str) -> Result<Option<Hub>, String> {
+    let resp = http::get_response(feed_url, "*/*")
+        .map_err(|err| format!("Failed to fetch feed {}: {:?}", feed_url, err))?;
+
+    let mut links: Vec<(String, String)> = resp
+        .headers()
+        .get_all(header::LINK)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(parse_link_header)
+        .collect();
+
+    if !links.iter().any(|(rel, _)| rel == "hub") {
+        let body = resp
+            .text()
+            .map_err(|err| format!("Failed to read feed {}: {:?}", feed_url, err))?;
+        links.extend(links_in_feed(&body));
+    }
+
+    let find = |wanted: &str| {
+        links
+            .iter()
+            .find(|(rel, _)| rel == wanted)
+            .map(|(_, href)| href.clone())
+    };
+    Ok(find("hub").map(|url| Hub {
+        url,
+        topic: find("self").unwrap_or_else(|| feed_url.to_owned()),
+    }))
+}
+
+/// asks the hub to send new content of the topic to the callback. the hub then
+/// verifies our intent by calling the callback (possibly before this returns)
+pub fn subscribe(hub: &Hub, callback: &str, secret: &str) -> Result<(), String> {
+    let lease_seconds = LEASE_SECONDS.to_string();
+    http::post_form(
+        &hub.url,
+        &[
+            ("hub.mode", "subscribe"),
+            ("hub.topic", &hub.topic),
+            ("hub.callback", callback),
+            ("hub.secret", secret),
+            ("hub.lease_seconds", &lease_seconds),
+        ],
+    )
+    .map(|_| ())
+    .map_err(|err| format!("Failed to subscribe to {:?}: {:?}", hub, err))
+}
+
+/// the secret the hub uses to sign the content it pushes to us
+pub fn generate_secret() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(40)
+        .collect()
+}
+
+/// checks the X-Hub-Signature header (eg. 'sha256=abc..') of pushed content
+pub fn verify_signature(secret: &str, body: &[u8], signature: &str) -> bool {
+    let (method, hex) = match signature.find('=') {
+        Some(idx) => (&signature[..idx], &signature[idx + 1..]),
+        None => return false,
+    };
+    let expected = match decode_hex(hex) {
+        Some(expected) => expected,
+        None => return false,
+    };
+
+    let key = secret.as_bytes();
+    match method {
+        "sha1" => mac_matches(Hmac::<Sha1>::new_varkey(key), body, &expected),
+        "sha256" => mac_matches(Hmac::<Sha256>::new_varkey(key), body, &expected),
+        "sha384" => mac_matches(Hmac::<Sha384>::new_varkey(key), body, &expected),
+        "sha512" => mac_matches(Hmac::<Sha512>::new_varkey(key), body, &expected),
+        _ => false,
+    }
+}
+
+// verify compares in constant time
+fn mac_matches<M: Mac>(mac: Result<M, InvalidKeyLength>, body: &[u8], expected: &[u8]) -> bool {
+    match mac {
+        Err(_) => false,
+        Ok(mut mac) => {
+            mac.update(body);
+            mac.verify(expected).is_ok()
+        }
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+// parses eg. '<https://hub.example.com/>; rel="hub", <https://a.com/feed>; rel=self'
+// into pairs of (rel, url). a link can have multiple space separated rels.
+fn parse_link_header(value: &str) -> Vec<(String, String)> {
+    let mut links = Vec::new();
+    for link in value.split(',') {
+        let link = link.trim();
+        let end = match link.find('>') {
+            Some(end) if link.starts_with('<') => end,
+            _ => continue,
+        };
+        let url = &link[1..end];
+        for param in link[end + 1..].split(';') {
+            let mut kv = param.splitn(2, '=');
+            if kv.next().map(|k| k.trim()) == Some("rel") {
+                let rels = kv.next().unwrap_or("").trim().trim_matches('"');
+                for rel in rels.split_whitespace() {
+                    links.push((rel.to_ascii_lowercase(), url.to_owned()));
+                }
+            }
+        }
+    }
+    links
+}
+
+// atom feeds have <link rel="hub">, rss feeds <atom:link rel="hub">
+fn links_in_feed(body: &str) -> Vec<(String, String)> {
+    let document = kuchiki::parse_html().one(body);
+    let mut links = Vec::new();
+    for element in document.descendants().elements() {
+        let name = element.name.local.to_string();
+        if name != "link" && name != "atom:link" {
+            continue;
+        }
+        let attributes = element.attributes.borrow();
+        if let (Some(rel), Some(href)) = (attributes.get("rel"), attributes.get("href")) {
+            for rel in rel.split_whitespace() {
+                links.push((rel.to_ascii_lowercase(), href.to_owned()));
+            }
+        }
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::{mock, Matcher};
+
+    #[test]
+    fn discover_hub_in_link_header() {
+        let _m = mock("GET", "/websub/header.xml")
+            .with_header(
+                "link",
+                "<https://hub.example.com/>; rel=\"hub\", <https://example.com/feed>; rel=\"self\"",
+            )
+            .with_body("<rss></rss>")
+            .create();
+        let url = format!("{}/websub/header.xml", mockito::server_url());
+        assert_eq!(
+            Some(Hub {
+                url: "https://hub.example.com/".into(),
+                topic: "https://example.com/feed".into(),
+            }),
+            discover(&url).unwrap()
+        );
+    }
+
+    #[test]
+    fn discover_hub_in_rss_feed() {
+        let _m = mock("GET", "/websub/rss.xml")
+            .with_header("content-type", "application/rss+xml")
+            .with_body(
+                r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+  <channel>
+    <title>Blog</title>
+    <link>https://example.com</link>
+    <atom:link rel="hub" href="https://pubsubhubbub.appspot.com/"/>
+    <item><title>Post</title></item>
+  </channel>
+</rss>"#,
+            )
+            .create();
+        let url = format!("{}/websub/rss.xml", mockito::server_url());
+        assert_eq!(
+            Some(Hub {
+                url: "https://pubsubhubbub.appspot.com/".into(),
+                topic: url.clone(),
+            }),
+            discover(&url).unwrap()
+        );
+    }
+
+    #[test]
+    fn discover_hub_in_atom_feed() {
+        let _m = mock("GET", "/websub/atom.xml")
+            .with_body(
+                r#"<feed xmlns="http://www.w3.org/2005/Atom">
+  <link rel="alternate" href="https://example.com/"/>
+  <link rel="self" href="https://example.com/atom.xml"/>
+  <link rel="hub" href="https://hub.example.com/"/>
+</feed>"#,
+            )
+            .create();
+        let url = format!("{}/websub/atom.xml", mockito::server_url());
+        assert_eq!(
+            Some(Hub {
+                url: "https://hub.example.com/".into(),
+                topic: "https://example.com/atom.xml".into(),
+            }),
+            discover(&url).unwrap()
+        );
+    }
+
+    #[test]
+    fn discover_no_hub() {
+        let _m = mock("GET", "/websub/nohub.xml")
+            .with_body("<rss><channel><link>https://example.com</link></channel></rss>")
+            .create();
+        let url = format!("{}/websub/nohub.xml", mockito::server_url());
+        assert_eq!(None, discover(&url).unwrap());
+    }
+
+    #[test]
+    fn subscribe_posts_to_hub() {
+        let m = mock("POST", "/hub")
+            .match_body(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("hub.mode".into(), "subscribe".into()),
+                Matcher::UrlEncoded("hub.topic".into(), "https://example.com/feed".into()),
+                Matcher::UrlEncoded(
+                    "hub.callback".into(),
+                    "https://api.example.com/websub/1".into(),
+                ),
+                Matcher::UrlEncoded("hub.secret".into(), "s3cret".into()),
+                Matcher::UrlEncoded("hub.lease_seconds".into(), "864000".into()),
+            ]))
+            .with_status(202)
+            .create();
+        let hub = Hub {
+            url: format!("{}/hub", mockito::server_url()),
+            topic: "https://example.com/feed".into(),
+        };
+        subscribe(&hub, "https://api.example.com/websub/1", "s3cret").unwrap();
+        m.assert();
+    }
+
+    #[test]
+    fn subscribe_fails_if_hub_refuses() {
+        let _m = mock("POST", "/refusing-hub").with_status(400).create();
+        let hub = Hub {
+            url: format!("{}/refusing-hub", mockito::server_url()),
+            topic: "https://example.com/feed".into(),
+        };
+        assert!(subscribe(&hub, "https://api.example.com/websub/1", "s").is_err());
+    }
+
+    // test vectors from rfc 2202 and rfc 4231
+    #[test]
+    fn verify_valid_signatures() {
+        let body = b"what do ya want for nothing?";
+        assert!(verify_signature(
+            "Jefe",
+            body,
+            "sha1=effcdf6ae5eb2fa2d27416d5f184df9c259a7c79"
+        ));
+        assert!(verify_signature(
+            "Jefe",
+            body,
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        ));
+    }
+
+    #[test]
+    fn reject_invalid_signatures() {
+        let body = b"what do ya want for nothing?";
+        let valid = "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79";
+        assert!(!verify_signature("other", body, &format!("sha1={}", valid)));
+        assert!(!verify_signature(
+            "Jefe",
+            b"tampered",
+            &format!("sha1={}", valid)
+        ));
+        assert!(!verify_signature("Jefe", body, &format!("md5={}", valid)));
+        assert!(!verify_signature("Jefe", body, "sha1=xyz"));
+        assert!(!verify_signature("Jefe", body, valid));
+    }
+
+    #[test]
+    fn parse_link_headers() {
+        assert_eq!(
+            vec![
+                ("hub".to_owned(), "https://hub.example.com/".to_owned()),
+                ("self".to_owned(), "https://a.com/feed".to_owned()),
+                ("alternate".to_owned(), "https://a.com/feed".to_owned()),
+            ],
+            parse_link_header(
+                "<https://hub.example.com/>; rel=hub, <https://a.com/feed>; type=\"x\"; rel=\"self alternate\", garbage"
+            )
+        );
+    }
+}
@@ -10,7 +10,7 @@ use diesel::result;
 use diesel::result::Error;
 use diesel::sql_types::BigInt;
 use either::{Either, Left, Right};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::iter::FromIterator;
 
@@ -35,6 +35,11 @@ pub fn connection_from_str(uri: &str) -> Result<Connection, String> {
         .map(Connection)
 }
 
+/// false if the connection is gone (eg. because the database restarted)
+pub fn connection_is_alive(conn: &Connection) -> bool {
+    diesel::connection::Connection::execute(&conn.0, "SELECT 1").is_ok()
+}
+
 pub fn channels_find_by_id(conn: &PgConnection, channel_id: i32) -> Result<Channel, String> {
     use schema::channels::dsl::*;
     channels
@@ -65,6 +70,28 @@ pub fn channels_find_by_ext_id(
         })
 }
 
+pub fn channels_find_by_ext_id_opt(
+    conn: &PgConnection,
+    channel_type: ChannelType,
+    ext_id: &str,
+) -> Result<Option<Channel>, String> {
+    use schema::channels;
+    channels::table
+        .filter(
+            channels::ext_id
+                .eq(ext_id)
+                .and(channels::channel_type.eq(channel_type)),
+        )
+        .first(conn)
+        .optional()
+        .map_err(|err| {
+            format!(
+                "Failed to fetch {:?} channel with ext_id {}: {:?}",
+                channel_type, ext_id, err
+            )
+        })
+}
+
 pub fn channels_find_by_id_opt(
     conn: &PgConnection,
     channel_id: i32,
@@ -83,30 +110,68 @@ pub fn channels_find_by_id_opt(
         .map_err(|err| format!("Failed to fetch channel with id {}: {:?}", channel_id, err))
 }
 
-pub fn channels_find_by_last_fetched(
+/// Claims the channels that are due to be fetched, so other workers leave them
+/// alone for the duration of the lease. Storing what was fetched ends the claim
+/// (see channels_update_last_fetched). If we crash before that, the channels
+/// are fetched again once the lease has run out.
+pub fn channels_claim_due_for_fetch(
     conn: &Connection,
-    fetch_frequency: Duration,
+    lease: Duration,
 ) -> Result<Vec<Channel>, String> {
     use schema::channels::dsl::*;
+    use schema::websub_subscriptions;
+
+    // channels with an active websub subscription are pushed to us
+    let pushed = websub_subscriptions::table
+        .select(websub_subscriptions::channel_id)
+        .filter(websub_subscriptions::expires.gt(Utc::now()));
+
+    conn.0
+        .build_transaction()
+        .run(|| {
+            // the ones another worker is claiming right now are skipped
+            let due: Vec<i32> = channels
+                .select(id)
+                .filter(next_fetch_at.lt(Utc::now()).or(next_fetch_at.is_null()))
+                // failing channels are retried less and less often
+                .filter(retry_at.lt(Utc::now()).or(retry_at.is_null()))
+                .filter(diesel::dsl::not(id.eq_any(pushed)))
+                // newsletters are delivered to the inbox, there is nothing to fetch
+                .filter(channel_type.ne(ChannelType::Newsletter))
+                .for_update()
+                .skip_locked()
+                .load(&conn.0)?;
+            // the claim is simply that the channel isn't due until the lease is over
+            diesel::update(channels.filter(id.eq_any(due)))
+                .set(next_fetch_at.eq(Utc::now() + lease))
+                .get_results::<Channel>(&conn.0)
+        })
+        .map_err(|err| format!("Failed to claim channels due for fetch: {:?}", err))
+}
 
-    let since_last_fetched = Utc::now() - fetch_frequency;
-
-    channels
-        .filter(
-            last_fetched
-                .lt(since_last_fetched)
-                .or(last_fetched.is_null()),
-        )
-        .load::<Channel>(&conn.0)
+/// Ends the claims on channels we didn't get to fetch, so they are due right away
+pub fn channels_release_fetch_claims(
+    conn: &Connection,
+    channel_ids: Vec<i32>,
+) -> Result<(), String> {
+    use diesel::expression::dsl::now;
+    use schema::channels::dsl::*;
+    diesel::update(channels.filter(id.eq_any(channel_ids.clone())))
+        .set(next_fetch_at.eq(now))
+        .execute(&conn.0)
         .map_err(|err| {
             format!(
-                "Failed to run query in channels_find_by_last_fetched: {:?}",
-                err
+                "Failed to release claims on channels {:?}: {:?}",
+                channel_ids, err
             )
         })
+        .map(|_| ())
 }
 
-pub fn channels_find_by_last_cleaned(
+/// Claims the channels that weren't cleaned for `clean_frequency` by marking
+/// them as cleaned right away, so other workers leave them alone. If we crash
+/// while cleaning them, they are cleaned again after another `clean_frequency`.
+pub fn channels_claim_due_for_cleaning(
     conn: &Connection,
     clean_frequency: Duration,
 ) -> Result<Vec<Channel>, String> {
@@ -114,19 +179,25 @@ pub fn channels_find_by_last_cleaned(
 
     let since_last_cleaned = Utc::now() - clean_frequency;
 
-    channels
-        .filter(
-            last_cleaned
-                .lt(since_last_cleaned)
-                .or(last_cleaned.is_null()),
-        )
-        .load::<Channel>(&conn.0)
-        .map_err(|err| {
-            format!(
-                "Failed to run query in channels_find_by_last_cleaned: {:?}",
-                err
-            )
+    conn.0
+        .build_transaction()
+        .run(|| {
+            // the ones another worker is claiming right now are skipped
+            let due: Vec<i32> = channels
+                .select(id)
+                .filter(
+                    last_cleaned
+                        .lt(since_last_cleaned)
+                        .or(last_cleaned.is_null()),
+                )
+                .for_update()
+                .skip_locked()
+                .load(&conn.0)?;
+            diesel::update(channels.filter(id.eq_any(due)))
+                .set(last_cleaned.eq(Utc::now()))
+                .get_results::<Channel>(&conn.0)
         })
+        .map_err(|err| format!("Failed to claim channels due for cleaning: {:?}", err))
 }
 
 pub fn channels_find_by_list_id(conn: &PgConnection, list_id: i32) -> Result<Vec<Channel>, String> {
@@ -235,11 +306,24 @@ pub fn channels_insert_if_not_exists(
     }
 }
 
-pub fn channels_update_last_fetched(conn: &Connection, channel: &Channel) -> Result<(), String> {
+/// Also forgets about previous failures, because the channel works (again)
+pub fn channels_update_last_fetched(
+    conn: &Connection,
+    channel: &Channel,
+    next_fetch: DateTime<Utc>,
+) -> Result<(), String> {
     use diesel::expression::dsl::now;
     use schema::channels::dsl::*;
     diesel::update(channels.find(channel.id))
-        .set(last_fetched.eq(now))
+        .set((
+            last_fetched.eq(now),
+            next_fetch_at.eq(next_fetch),
+            failures.eq(0),
+            last_error_kind.eq(None::<FetchErrorKind>),
+            last_error.eq(None::<String>),
+            retry_at.eq(None::<DateTime<Utc>>),
+            dead_since.eq(None::<DateTime<Utc>>),
+        ))
         .execute(&conn.0)
         .map_err(|err| {
             format!(
@@ -250,36 +334,281 @@ pub fn channels_update_last_fetched(conn: &Connection, channel: &Channel) -> Res
         .map(|_| ())
 }
 
-pub fn channels_update_last_cleaned_by_ids(
+/// Stores that fetching the channel failed (once more)
+pub fn channels_update_failure(
     conn: &Connection,
-    channel_ids: Vec<i32>,
+    channel: &Channel,
+    failure: &ChannelFailure,
 ) -> Result<(), String> {
-    use diesel::expression::dsl::now;
     use schema::channels::dsl::*;
-    diesel::update(channels.filter(id.eq_any(channel_ids.clone())))
-        .set(last_cleaned.eq(now))
+    diesel::update(channels.find(channel.id))
+        .set((
+            failures.eq(failure.failures),
+            last_error_kind.eq(Some(failure.kind)),
+            last_error.eq(Some(&failure.error)),
+            retry_at.eq(Some(failure.retry_at)),
+            dead_since.eq(failure.dead_since),
+        ))
         .execute(&conn.0)
         .map_err(|err| {
             format!(
-                "failed to update last_cleaned field for channels {:?}: {:?}",
-                channel_ids, err
+                "failed to update failure of channel {}: {:?}",
+                channel.id, err
             )
         })
         .map(|_| ())
 }
 
-pub fn updates_insert_new(conn: &Connection, update: &NewUpdate) -> Result<(), InsertError> {
+pub fn channels_update_validators(
+    conn: &Connection,
+    channel: &Channel,
+    new_etag: Option<&str>,
+    new_last_modified: Option<&str>,
+) -> Result<(), String> {
+    use schema::channels::dsl::*;
+    diesel::update(channels.find(channel.id))
+        .set((etag.eq(new_etag), last_modified.eq(new_last_modified)))
+        .execute(&conn.0)
+        .map_err(|err| {
+            format!(
+                "failed to update validators for channel {}: {:?}",
+                channel.id, err
+            )
+        })
+        .map(|_| ())
+}
+
+/// Moves a channel to a new ext_id (eg. a feed that moved to another url). If
+/// there already is a channel with that ext_id, the channel is merged into it
+/// and deleted. Returns the channel that lives on.
+pub fn channels_move(
+    conn: &PgConnection,
+    channel: &Channel,
+    new_ext_id: &str,
+) -> Result<Channel, String> {
+    use schema::channels;
+    use schema::cleaned_updates;
+    use schema::digests;
+    use schema::lists_channels;
+    use schema::newsletter_mails;
+    use schema::subscriptions;
     use schema::updates;
+    use schema::websub_subscriptions;
+
+    conn.build_transaction()
+        .run(|| {
+            let target: Channel = match channels::table
+                .filter(
+                    channels::ext_id
+                        .eq(new_ext_id)
+                        .and(channels::channel_type.eq(channel.channel_type)),
+                )
+                .first(conn)
+                .optional()?
+            {
+                Some(target) => target,
+                None => {
+                    // the validators belong to the old location
+                    return diesel::update(channels::table.find(channel.id))
+                        .set((
+                            channels::ext_id.eq(new_ext_id),
+                            channels::etag.eq(None::<String>),
+                            channels::last_modified.eq(None::<String>),
+                        ))
+                        .get_result(conn);
+                }
+            };
+
+            // the queries below need to load these first, because subqueries with the
+            // same table are not supported in diesel (see updates_delete_old_by_channel_id)
+
+            // lists that already have the target don't need the channel anymore
+            let target_lists: Vec<i32> = lists_channels::table
+                .filter(lists_channels::channel_id.eq(target.id))
+                .select(lists_channels::list_id)
+                .load(conn)?;
+            diesel::delete(
+                lists_channels::table.filter(
+                    lists_channels::channel_id
+                        .eq(channel.id)
+                        .and(lists_channels::list_id.eq_any(target_lists)),
+                ),
+            )
+            .execute(conn)?;
+            diesel::update(lists_channels::table.filter(lists_channels::channel_id.eq(channel.id)))
+                .set(lists_channels::channel_id.eq(target.id))
+                .execute(conn)?;
+
+            // same for users that are subscribed to both
+            let target_subscribers: Vec<Option<i32>> = subscriptions::table
+                .filter(subscriptions::channel_id.eq(target.id))
+                .select(subscriptions::user_id)
+                .load(conn)?;
+            let duplicate_subscriptions: Vec<i32> =
+                subscriptions::table
+                    .filter(subscriptions::channel_id.eq(channel.id).and(
+                        subscriptions::user_id.eq_any(target_subscribers.into_iter().flatten()),
+                    ))
+                    .select(subscriptions::id)
+                    .load(conn)?;
+            diesel::delete(
+                digests::table.filter(digests::subscription_id.eq_any(&duplicate_subscriptions)),
+            )
+            .execute(conn)?;
+            diesel::delete(
+                subscriptions::table.filter(subscriptions::id.eq_any(&duplicate_subscriptions)),
+            )
+            .execute(conn)?;
+            diesel::update(subscriptions::table.filter(subscriptions::channel_id.eq(channel.id)))
+                .set(subscriptions::channel_id.eq(target.id))
+                .execute(conn)?;
+
+            // updates both have are only kept once (enclosures are deleted with them)
+            let target_guids: Vec<String> = updates::table
+                .filter(updates::channel_id.eq(target.id))
+                .select(updates::guid)
+                .load(conn)?;
+            diesel::delete(
+                updates::table.filter(
+                    updates::channel_id
+                        .eq(channel.id)
+                        .and(updates::guid.eq_any(target_guids)),
+                ),
+            )
+            .execute(conn)?;
+            diesel::update(updates::table.filter(updates::channel_id.eq(channel.id)))
+                .set(updates::channel_id.eq(target.id))
+                .execute(conn)?;
+            let target_cleaned: Vec<String> = cleaned_updates::table
+                .filter(cleaned_updates::channel_id.eq(target.id))
+                .select(cleaned_updates::guid)
+                .load(conn)?;
+            diesel::delete(
+                cleaned_updates::table.filter(
+                    cleaned_updates::channel_id
+                        .eq(channel.id)
+                        .and(cleaned_updates::guid.eq_any(target_cleaned)),
+                ),
+            )
+            .execute(conn)?;
+            diesel::update(
+                cleaned_updates::table.filter(cleaned_updates::channel_id.eq(channel.id)),
+            )
+            .set(cleaned_updates::channel_id.eq(target.id))
+            .execute(conn)?;
 
-    diesel::insert_into(updates::table)
-        .values(update)
-        .execute(&conn.0)
+            diesel::update(
+                newsletter_mails::table.filter(newsletter_mails::channel_id.eq(channel.id)),
+            )
+            .set(newsletter_mails::channel_id.eq(target.id))
+            .execute(conn)?;
+            diesel::delete(websub_subscriptions::table.find(channel.id)).execute(conn)?;
+            diesel::delete(channels::table.find(channel.id)).execute(conn)?;
+            Ok(target)
+        })
+        .map_err(|err: Error| {
+            format!(
+                "Failed to move channel {} to {}: {:?}",
+                channel.id, new_ext_id, err
+            )
+        })
+}
+
+pub fn updates_insert_new(
+    conn: &PgConnection,
+    update: &NewUpdate,
+    enclosures: &[NewEnclosure],
+) -> Result<(), InsertError> {
+    use schema::update_enclosures;
+    use schema::updates;
+
+    conn.build_transaction()
+        .run(|| {
+            let update_id: i64 = diesel::insert_into(updates::table)
+                .values(update)
+                .returning(updates::id)
+                .get_result(conn)?;
+            if !enclosures.is_empty() {
+                let values: Vec<_> = enclosures
+                    .iter()
+                    .map(|e| {
+                        (
+                            update_enclosures::update_id.eq(update_id),
+                            update_enclosures::url.eq(&e.url),
+                            update_enclosures::mime_type.eq(&e.mime_type),
+                            update_enclosures::length.eq(e.length),
+                        )
+                    })
+                    .collect();
+                diesel::insert_into(update_enclosures::table)
+                    .values(&values)
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
         .map_err(InsertError::from_diesel)
-        .map(|_| ())
+}
+
+/// the guids of the given ones that we already stored (or cleaned) for the channel
+pub fn updates_find_known_guids(
+    conn: &PgConnection,
+    channel_id: i32,
+    guids: &[String],
+) -> Result<HashSet<String>, String> {
+    use schema::cleaned_updates;
+    use schema::updates;
+    let stored = updates::table
+        .filter(updates::channel_id.eq(channel_id))
+        .filter(updates::guid.eq_any(guids))
+        .select(updates::guid);
+    let cleaned = cleaned_updates::table
+        .filter(cleaned_updates::channel_id.eq(channel_id))
+        .filter(cleaned_updates::guid.eq_any(guids))
+        .select(cleaned_updates::guid);
+    stored
+        .load::<String>(conn)
+        .and_then(|mut known| {
+            known.extend(cleaned.load::<String>(conn)?);
+            Ok(known)
+        })
+        .map(HashSet::from_iter)
+        .map_err(|err| {
+            format!(
+                "Failed to find known guids of channel {}: {:?}",
+                channel_id, err
+            )
+        })
+}
+
+pub fn updates_find_enclosures(
+    conn: &PgConnection,
+    update_ids: &[i64],
+) -> Result<HashMap<i64, Vec<UpdateEnclosure>>, String> {
+    use schema::update_enclosures;
+    update_enclosures::table
+        .filter(update_enclosures::update_id.eq_any(update_ids))
+        .order_by(update_enclosures::id)
+        .load::<UpdateEnclosure>(conn)
+        .map(|enclosures| {
+            let mut by_update: HashMap<i64, Vec<UpdateEnclosure>> = HashMap::new();
+            for enclosure in enclosures {
+                by_update
+                    .entry(enclosure.update_id)
+                    .or_insert_with(Vec::new)
+                    .push(enclosure);
+            }
+            by_update
+        })
+        .map_err(|err| {
+            format!(
+                "Failed to find enclosures of updates {:?}: {:?}",
+                update_ids, err
+            )
+        })
 }
 
 pub fn updates_find_newest_by_channel(
-    conn: &Connection,
+    conn: &PgConnection,
     channel_id: i32,
 ) -> Result<Option<Update>, String> {
     use schema::updates;
@@ -287,7 +616,7 @@ pub fn updates_find_newest_by_channel(
         .filter(updates::channel_id.eq(channel_id))
         .order_by(updates::inserted.desc())
         .limit(1)
-        .load(&conn.0)
+        .load(conn)
         .map(|us| us.into_iter().next())
         .map_err(|err| {
             format!(
@@ -297,6 +626,140 @@ pub fn updates_find_newest_by_channel(
         })
 }
 
+/// When the newest updates of the channel were published (newest first)
+pub fn updates_find_recent_published(
+    conn: &PgConnection,
+    channel_id: i32,
+    limit: i64,
+) -> Result<Vec<DateTime<Utc>>, String> {
+    use schema::updates;
+    updates::table
+        .filter(updates::channel_id.eq(channel_id))
+        .order_by(updates::published.desc())
+        .select(updates::published)
+        .limit(limit)
+        .load(conn)
+        .map_err(|err| {
+            format!(
+                "Failed to find recent updates by channel {}: {:?}",
+                channel_id, err
+            )
+        })
+}
+
+pub fn web_page_snapshots_find(
+    conn: &Connection,
+    ext_id: &str,
+) -> Result<Option<WebPageSnapshot>, String> {
+    use schema::web_page_snapshots;
+    web_page_snapshots::table
+        .find(ext_id)
+        .first(&conn.0)
+        .optional()
+        .map_err(|err| format!("Failed to find snapshot of {}: {:?}", ext_id, err))
+}
+
+pub fn web_page_snapshots_save(
+    conn: &Connection,
+    snapshot: &WebPageSnapshot,
+) -> Result<(), String> {
+    use schema::web_page_snapshots;
+    diesel::insert_into(web_page_snapshots::table)
+        .values(snapshot)
+        .on_conflict(web_page_snapshots::ext_id)
+        .do_update()
+        .set(snapshot)
+        .execute(&conn.0)
+        .map(|_| ())
+        .map_err(|err| format!("Failed to save snapshot of {}: {:?}", snapshot.ext_id, err))
+}
+
+pub fn websub_subscriptions_find(
+    conn: &PgConnection,
+    channel_id: i32,
+) -> Result<Option<WebsubSubscription>, String> {
+    use schema::websub_subscriptions;
+    websub_subscriptions::table
+        .find(channel_id)
+        .first(conn)
+        .optional()
+        .map_err(|err| {
+            format!(
+                "Failed to find websub subscription of channel {}: {:?}",
+                channel_id, err
+            )
+        })
+}
+
+// subscriptions that expire before the given time or were never
+// verified, unless we requested them recently
+pub fn websub_subscriptions_find_expiring(
+    conn: &PgConnection,
+    expires_before: DateTime<Utc>,
+    requested_before: DateTime<Utc>,
+) -> Result<Vec<WebsubSubscription>, String> {
+    use schema::websub_subscriptions::dsl::*;
+    websub_subscriptions
+        .filter(expires.lt(expires_before).or(expires.is_null()))
+        .filter(requested.lt(requested_before))
+        .load(conn)
+        .map_err(|err| format!("Failed to find expiring websub subscriptions: {:?}", err))
+}
+
+pub fn websub_subscriptions_save(
+    conn: &PgConnection,
+    subscription: &WebsubSubscription,
+) -> Result<(), String> {
+    use schema::websub_subscriptions;
+    diesel::insert_into(websub_subscriptions::table)
+        .values(subscription)
+        .on_conflict(websub_subscriptions::channel_id)
+        .do_update()
+        .set(subscription)
+        .execute(conn)
+        .map(|_| ())
+        .map_err(|err| {
+            format!(
+                "Failed to save websub subscription of channel {}: {:?}",
+                subscription.channel_id, err
+            )
+        })
+}
+
+/// Stores a received mail and its update, or neither if we already have the update
+pub fn newsletter_mails_insert(
+    conn: &Connection,
+    mail: &NewNewsletterMail,
+    update: &NewUpdate,
+) -> Result<(), InsertError> {
+    use schema::newsletter_mails;
+    use schema::updates;
+    conn.0
+        .build_transaction()
+        .run(|| {
+            diesel::insert_into(newsletter_mails::table)
+                .values(mail)
+                .execute(&conn.0)?;
+            diesel::insert_into(updates::table)
+                .values(update)
+                .execute(&conn.0)
+                .map(|_| ())
+        })
+        .map_err(InsertError::from_diesel)
+}
+
+pub fn newsletter_mails_find_by_web_id(
+    conn: &PgConnection,
+    web_id: &str,
+) -> Result<Option<NewsletterMail>, String> {
+    use schema::newsletter_mails;
+    newsletter_mails::table
+        .filter(newsletter_mails::web_id.eq(web_id))
+        .first(conn)
+        .optional()
+        .map_err(|err| format!("Failed to find mail {}: {:?}", web_id, err))
+}
+
 pub fn updates_find_new(
     conn: &Connection,
     chan_id: i32,
@@ -378,23 +841,52 @@ pub fn updates_delete_old_by_channel_id(
     // not supported in diesel: https://github.com/diesel-rs/diesel/issues/1369
 
     let delete_before = Utc::now() - retain_updates_duration;
-    let ids_to_delete = updates::table
+    let to_delete = updates::table
         .filter(
             updates::inserted
                 .lt(delete_before)
                 .and(updates::channel_id.eq(channel_id)),
         )
-        .select(updates::id)
+        .select((updates::id, updates::guid))
         .order_by(updates::inserted.desc())
         .offset(1) // need to retain one to compare against when fetching updates
-        .load::<i64>(&conn.0)
+        .load::<(i64, String)>(&conn.0)
         .map_err(|err| {
             format!(
                 "Failed to fetch updates before {:?} for channel_id {}: {:?}",
                 retain_updates_duration, channel_id, err
             )
         })?;
-    updates_delete_by_ids(conn, ids_to_delete)
+
+    // updates without a date are dated when we first see them. if one of
+    // them is still in the feed, only its guid tells us we've sent it already
+    use schema::cleaned_updates;
+    let (ids_to_delete, cleaned): (Vec<i64>, Vec<_>) = to_delete
+        .into_iter()
+        .map(|(id, guid)| {
+            let cleaned = (
+                cleaned_updates::channel_id.eq(channel_id),
+                cleaned_updates::guid.eq(guid),
+            );
+            (id, cleaned)
+        })
+        .unzip();
+    conn.0
+        .build_transaction()
+        .run(|| {
+            diesel::insert_into(cleaned_updates::table)
+                .values(&cleaned)
+                .on_conflict_do_nothing()
+                .execute(&conn.0)?;
+            diesel::delete(updates::table.filter(updates::id.eq_any(&ids_to_delete)))
+                .execute(&conn.0)
+        })
+        .map_err(|err: Error| {
+            format!(
+                "Failed to delete updates {:?} of channel {}: {:?}",
+                ids_to_delete, channel_id, err
+            )
+        })
 }
 
 pub fn updates_delete_by_ids(conn: &Connection, ids: Vec<i64>) -> Result<usize, String> {
@@ -650,31 +1142,96 @@ pub fn digests_find_users_with_due(conn: &Connection) -> Result<Vec<User>, Strin
         .inner_join(subscriptions::table.on(digests::subscription_id.eq(subscriptions::id)))
         .inner_join(users::table.on(subscriptions::user_id.eq(users::id.nullable())))
         .filter(digests::due.lt(now).and(digests::sent.is_null()))
+        // they are being sent by another worker
+        .filter(
+            digests::claimed_until
+                .lt(now)
+                .or(digests::claimed_until.is_null()),
+        )
         .distinct()
         .select(users::all_columns)
         .load::<User>(&conn.0)
         .map_err(|err| format!("failed to retrieve users with due digests: {:?}", err))
 }
 
-pub fn digests_find_due_for_user(
+/// Claims the due digests of the user, so no other worker sends them for the
+/// duration of the lease. Sending them ends the claim (see digests_set_sent),
+/// as does failing to do so (see digests_release). If we crash in between,
+/// they are sent once the lease has run out.
+pub fn digests_claim_due_for_user(
     conn: &Connection,
     user: &User,
+    lease: Duration,
 ) -> Result<Vec<(Digest, Subscription)>, String> {
-    use diesel::expression::dsl::now;
     use schema::digests;
     use schema::subscriptions;
+
+    conn.0
+        .build_transaction()
+        .run(|| {
+            let subscription_ids: Vec<i32> = subscriptions::table
+                .select(subscriptions::id)
+                .filter(subscriptions::user_id.eq(user.id))
+                .load(&conn.0)?;
+            // the ones another worker is claiming right now are skipped
+            let due: Vec<i64> = digests::table
+                .select(digests::id)
+                .filter(digests::subscription_id.eq_any(subscription_ids))
+                .filter(digests::due.lt(Utc::now()).and(digests::sent.is_null()))
+                .filter(
+                    digests::claimed_until
+                        .lt(Utc::now())
+                        .or(digests::claimed_until.is_null()),
+                )
+                .for_update()
+                .skip_locked()
+                .load(&conn.0)?;
+            diesel::update(digests::table.filter(digests::id.eq_any(&due)))
+                .set(digests::claimed_until.eq(Utc::now() + lease))
+                .execute(&conn.0)?;
+            digests::table
+                .inner_join(subscriptions::table.on(digests::subscription_id.eq(subscriptions::id)))
+                .filter(digests::id.eq_any(due))
+                .select((digests::all_columns, subscriptions::all_columns))
+                .load::<(Digest, Subscription)>(&conn.0)
+        })
+        .map_err(|err| format!("failed to claim due digests for user: {:?}", err))
+}
+
+/// When the next digest with the channel (directly or via a list) is due
+pub fn digests_find_next_due_by_channel(
+    conn: &Connection,
+    chan_id: i32,
+) -> Result<Option<DateTime<Utc>>, String> {
+    use schema::digests;
+    use schema::lists_channels;
+    use schema::subscriptions;
+
+    // loaded first, because diesel can't tell that the subquery is independent
+    let lists: Vec<Option<i32>> = lists_channels::table
+        .filter(lists_channels::channel_id.eq(chan_id))
+        .select(lists_channels::list_id.nullable())
+        .load(&conn.0)
+        .map_err(|err| format!("failed to find lists of channel {}: {:?}", chan_id, err))?;
+
     digests::table
         .inner_join(subscriptions::table.on(digests::subscription_id.eq(subscriptions::id)))
+        .filter(digests::sent.is_null())
         .filter(
-            digests::due
-                .lt(now)
-                .and(digests::sent.is_null())
-                .and(subscriptions::user_id.eq(user.id)),
+            subscriptions::channel_id
+                .eq(chan_id)
+                .or(subscriptions::list_id.eq_any(lists)),
         )
-        .select((digests::all_columns, subscriptions::all_columns))
-        .load::<(Digest, Subscription)>(&conn.0)
-        .map_err(|err| format!("failed to retrieve due digests for user: {:?}", err))
+        .select(diesel::dsl::min(digests::due))
+        .first::<Option<DateTime<Utc>>>(&conn.0)
+        .map_err(|err| {
+            format!(
+                "failed to find next due digest of channel {}: {:?}",
+                chan_id, err
+            )
+        })
 }
+
 pub fn digests_find_previous(conn: &Connection, digest: &Digest) -> Result<Option<Digest>, String> {
     use schema::digests::dsl::*;
     digests
@@ -690,16 +1247,32 @@ pub fn digests_find_previous(conn: &Connection, digest: &Digest) -> Result<Optio
         .map_err(|err| format!("Failed to find previous digest: {:?}", err))
 }
 
-pub fn digests_set_sent(conn: &Connection, digest: &Digest) -> Result<(), String> {
+/// Marks the claimed digest as sent. Returns false if it isn't ours anymore,
+/// because our claim ran out and another worker claimed it in the meantime.
+pub fn digests_set_sent(conn: &Connection, digest: &Digest) -> Result<bool, String> {
     use diesel::expression::dsl::now;
     use schema::digests::dsl::*;
-    diesel::update(digests.find(digest.id))
+    // only if the claim is still the one we got with the digest
+    let ours = claimed_until.eq(digest.claimed_until).and(sent.is_null());
+    diesel::update(digests.find(digest.id).filter(ours))
         .set(sent.eq(now))
         .execute(&conn.0)
-        .map(|_| ())
+        .map(|n| n > 0)
         .map_err(|err| format!("Failed to update 'sent' for digest {:?}: {:?}", digest, err))
 }
 
+/// Gives up the claim on a digest that couldn't be sent, so the next run tries
+/// again. If another worker claimed it in the meantime, the claim is left to it.
+pub fn digests_release(conn: &Connection, digest: &Digest) -> Result<(), String> {
+    use schema::digests::dsl::*;
+    let ours = claimed_until.eq(digest.claimed_until).and(sent.is_null());
+    diesel::update(digests.find(digest.id).filter(ours))
+        .set(claimed_until.eq(None::<DateTime<Utc>>))
+        .execute(&conn.0)
+        .map(|_| ())
+        .map_err(|err| format!("Failed to release digest {:?}: {:?}", digest, err))
+}
+
 pub fn digests_remove_unsent_for_subscription(
     conn: &PgConnection,
     sub: &Subscription,
@@ -46,7 +46,25 @@ pub struct Channel {
     pub verified: bool,
     pub last_fetched: Option<DateTime<Utc>>,
     pub last_cleaned: Option<DateTime<Utc>>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
     pub inserted: DateTime<Utc>,
+    // fetch the linked pages of new updates to extract the articles
+    pub extract_articles: bool,
+    // the updates are episodes with audio files
+    pub podcast: bool,
+    // fetches that failed in a row
+    pub failures: i32,
+    pub last_error_kind: Option<FetchErrorKind>,
+    pub last_error: Option<String>,
+    // we don't fetch the channel before this
+    pub retry_at: Option<DateTime<Utc>>,
+    // set if it failed so often that we consider it gone
+    pub dead_since: Option<DateTime<Utc>>,
+    // when the channel is due to be fetched again (none means now)
+    pub next_fetch_at: Option<DateTime<Utc>>,
+    // the user who created the address of a newsletter
+    pub owner_id: Option<UserId>,
 }
 
 impl Channel {
@@ -56,6 +74,26 @@ impl Channel {
             _ => false,
         }
     }
+
+    /// The address of a newsletter (ie. its ext_id and link) is the secret that
+    /// lets anyone send mails to it, so only the owner may see or subscribe to it
+    pub fn is_hidden_from(&self, user_id: Option<UserId>) -> bool {
+        match (self.channel_type, self.owner_id) {
+            (ChannelType::Newsletter, Some(owner_id)) => Some(owner_id) != user_id,
+            (ChannelType::Newsletter, None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The state of a channel after a failed fetch
+#[derive(Debug, PartialEq)]
+pub struct ChannelFailure {
+    pub failures: i32,
+    pub kind: FetchErrorKind,
+    pub error: String,
+    pub retry_at: DateTime<Utc>,
+    pub dead_since: Option<DateTime<Utc>>,
 }
 
 #[derive(Insertable, Debug)]
@@ -66,6 +104,8 @@ pub struct NewChannel {
     pub name: String,
     pub link: String,
     pub verified: bool,
+    pub podcast: bool,
+    pub owner_id: Option<UserId>,
 }
 
 #[derive(Debug, Insertable)]
@@ -75,7 +115,16 @@ pub struct NewUpdate {
     pub ext_id: Option<String>,
     pub title: String,
     pub url: String,
+    pub external_url: Option<String>,
     pub published: DateTime<Utc>,
+    pub summary: Option<String>,
+    pub author: Option<String>,
+    pub categories: Vec<String>,
+    pub image_url: Option<String>,
+    pub guid: String,
+    pub reading_minutes: Option<i32>,
+    pub duration_seconds: Option<i32>,
+    pub episode: Option<i32>,
 }
 
 #[derive(Debug, Queryable)]
@@ -85,16 +134,114 @@ pub struct Update {
     pub ext_id: Option<String>,
     pub title: String,
     pub url: String,
+    pub external_url: Option<String>,
     pub published: DateTime<Utc>,
     pub inserted: DateTime<Utc>,
+    pub summary: Option<String>,
+    pub author: Option<String>,
+    pub categories: Vec<String>,
+    pub image_url: Option<String>,
+    pub guid: String,
+    pub reading_minutes: Option<i32>,
+    pub duration_seconds: Option<i32>,
+    pub episode: Option<i32>,
+}
+
+#[derive(Debug, Clone, Queryable)]
+pub struct UpdateEnclosure {
+    pub id: i64,
+    pub update_id: i64,
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub length: Option<i64>,
+}
+
+// enclosures of an update that is yet to be inserted
+#[derive(Debug, Clone)]
+pub struct NewEnclosure {
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub length: Option<i64>,
+}
+
+#[derive(Debug, Queryable, Insertable, AsChangeset)]
+#[table_name = "web_page_snapshots"]
+pub struct WebPageSnapshot {
+    pub ext_id: String,
+    pub hash: String,
+    pub content: String,
+    pub updated: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Queryable, Insertable, AsChangeset)]
+#[table_name = "websub_subscriptions"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct WebsubSubscription {
+    pub channel_id: i32,
+    pub hub: String,
+    pub topic: String,
+    pub secret: String,
+    // none until the hub verified our intent
+    pub expires: Option<DateTime<Utc>>,
+    pub requested: DateTime<Utc>,
+    // the hub may only answer a request once
+    pub answered: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Queryable)]
+pub struct NewsletterMail {
+    pub id: i64,
+    pub web_id: String,
+    pub channel_id: i32,
+    pub sender: String,
+    pub subject: String,
+    pub html: Option<String>,
+    pub text: Option<String>,
+    pub received: DateTime<Utc>,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "newsletter_mails"]
+pub struct NewNewsletterMail {
+    pub web_id: String,
+    pub channel_id: i32,
+    pub sender: String,
+    pub subject: String,
+    pub html: Option<String>,
+    pub text: Option<String>,
+    pub received: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, FromSqlRow, AsExpression, Serialize, Deserialize)]
 #[sql_type = "Text"]
 pub enum ChannelType {
     GithubRelease,
+    GithubTag,
     RssFeed,
     Twitter,
+    Mastodon,
+    CratesIo,
+    Npm,
+    PyPi,
+    ForgeRelease,
+    OciImage,
+    LinkAggregator,
+    Reddit,
+    WebPage,
+    Newsletter,
+}
+
+/// Why fetching a channel failed
+#[derive(Debug, Clone, Copy, PartialEq, FromSqlRow, AsExpression, Serialize, Deserialize)]
+#[sql_type = "Text"]
+pub enum FetchErrorKind {
+    NotFound,
+    Timeout,
+    // eg. the robots.txt disallows it
+    Refused,
+    // eg. not a feed (anymore)
+    Invalid,
+    Unknown,
 }
 
 #[derive(Debug, Clone, PartialEq, FromSqlRow, AsExpression, Serialize, Deserialize)]
@@ -198,6 +345,7 @@ pub struct Digest {
     pub subscription_id: i32,
     pub due: DateTime<Utc>,
     pub sent: Option<DateTime<Utc>>,
+    pub claimed_until: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Queryable, AsChangeset, Identifiable)]
@@ -297,12 +445,51 @@ impl FromSql<Text, Pg> for Frequency {
     }
 }
 
+impl ToSql<Text, Pg> for FetchErrorKind {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            FetchErrorKind::NotFound => out.write_all(b"not_found")?,
+            FetchErrorKind::Timeout => out.write_all(b"timeout")?,
+            FetchErrorKind::Refused => out.write_all(b"refused")?,
+            FetchErrorKind::Invalid => out.write_all(b"invalid")?,
+            FetchErrorKind::Unknown => out.write_all(b"unknown")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for FetchErrorKind {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"not_found" => Ok(FetchErrorKind::NotFound),
+            b"timeout" => Ok(FetchErrorKind::Timeout),
+            b"refused" => Ok(FetchErrorKind::Refused),
+            b"invalid" => Ok(FetchErrorKind::Invalid),
+            b"unknown" => Ok(FetchErrorKind::Unknown),
+            unrecognized => {
+                Err(format!("Unrecognized fetch error kind: {:?}", unrecognized).into())
+            }
+        }
+    }
+}
+
 impl ToSql<Text, Pg> for ChannelType {
     fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
         match *self {
             ChannelType::GithubRelease => out.write_all(b"github_release")?,
+            ChannelType::GithubTag => out.write_all(b"github_tag")?,
             ChannelType::RssFeed => out.write_all(b"rss_feed")?,
             ChannelType::Twitter => out.write_all(b"twitter")?,
+            ChannelType::Mastodon => out.write_all(b"mastodon")?,
+            ChannelType::CratesIo => out.write_all(b"crates_io")?,
+            ChannelType::Npm => out.write_all(b"npm")?,
+            ChannelType::PyPi => out.write_all(b"pypi")?,
+            ChannelType::ForgeRelease => out.write_all(b"forge_release")?,
+            ChannelType::OciImage => out.write_all(b"oci_image")?,
+            ChannelType::LinkAggregator => out.write_all(b"link_aggregator")?,
+            ChannelType::Reddit => out.write_all(b"reddit")?,
+            ChannelType::WebPage => out.write_all(b"web_page")?,
+            ChannelType::Newsletter => out.write_all(b"newsletter")?,
         }
         Ok(IsNull::No)
     }
@@ -312,8 +499,19 @@ impl FromSql<Text, Pg> for ChannelType {
     fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
         match not_none!(bytes) {
             b"github_release" => Ok(ChannelType::GithubRelease),
+            b"github_tag" => Ok(ChannelType::GithubTag),
             b"rss_feed" => Ok(ChannelType::RssFeed),
             b"twitter" => Ok(ChannelType::Twitter),
+            b"mastodon" => Ok(ChannelType::Mastodon),
+            b"crates_io" => Ok(ChannelType::CratesIo),
+            b"npm" => Ok(ChannelType::Npm),
+            b"pypi" => Ok(ChannelType::PyPi),
+            b"forge_release" => Ok(ChannelType::ForgeRelease),
+            b"oci_image" => Ok(ChannelType::OciImage),
+            b"link_aggregator" => Ok(ChannelType::LinkAggregator),
+            b"reddit" => Ok(ChannelType::Reddit),
+            b"web_page" => Ok(ChannelType::WebPage),
+            b"newsletter" => Ok(ChannelType::Newsletter),
             unrecognized => {
                 Err(format!("Unrecognized channel type enum variant: {:?}", unrecognized).into())
             }
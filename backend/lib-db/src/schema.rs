@@ -26,7 +26,18 @@ table! {
         verified -> Bool,
         last_fetched -> Nullable<Timestamptz>,
         last_cleaned -> Nullable<Timestamptz>,
+        etag -> Nullable<Text>,
+        last_modified -> Nullable<Text>,
         inserted -> Timestamptz,
+        extract_articles -> Bool,
+        podcast -> Bool,
+        failures -> Integer,
+        last_error_kind -> Nullable<Text>,
+        last_error -> Nullable<Text>,
+        retry_at -> Nullable<Timestamptz>,
+        dead_since -> Nullable<Timestamptz>,
+        next_fetch_at -> Nullable<Timestamptz>,
+        owner_id -> Nullable<Integer>,
     }
 }
 
@@ -37,8 +48,69 @@ table! {
         ext_id -> Nullable<Text>,
         title -> Text,
         url -> Text,
+        external_url -> Nullable<Text>,
         published -> Timestamptz,
         inserted -> Timestamptz,
+        summary -> Nullable<Text>,
+        author -> Nullable<Text>,
+        categories -> Array<Text>,
+        image_url -> Nullable<Text>,
+        guid -> Text,
+        reading_minutes -> Nullable<Integer>,
+        duration_seconds -> Nullable<Integer>,
+        episode -> Nullable<Integer>,
+    }
+}
+
+table! {
+    cleaned_updates(channel_id, guid) {
+        channel_id -> Integer,
+        guid -> Text,
+        cleaned -> Timestamptz,
+    }
+}
+
+table! {
+    update_enclosures(id) {
+        id -> BigInt,
+        update_id -> BigInt,
+        url -> Text,
+        mime_type -> Nullable<Text>,
+        length -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    web_page_snapshots(ext_id) {
+        ext_id -> Text,
+        hash -> Text,
+        content -> Text,
+        updated -> Timestamptz,
+    }
+}
+
+table! {
+    websub_subscriptions(channel_id) {
+        channel_id -> Integer,
+        hub -> Text,
+        topic -> Text,
+        secret -> Text,
+        expires -> Nullable<Timestamptz>,
+        requested -> Timestamptz,
+        answered -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    newsletter_mails(id) {
+        id -> BigInt,
+        web_id -> Text,
+        channel_id -> Integer,
+        sender -> Text,
+        subject -> Text,
+        html -> Nullable<Text>,
+        text -> Nullable<Text>,
+        received -> Timestamptz,
     }
 }
 
@@ -78,6 +150,7 @@ table! {
       subscription_id -> Integer,
       due -> Timestamptz,
       sent -> Nullable<Timestamptz>,
+      claimed_until -> Nullable<Timestamptz>,
     }
 }
 
@@ -107,3 +180,4 @@ allow_tables_to_appear_in_same_query!(digests, users);
 allow_tables_to_appear_in_same_query!(updates, channels);
 allow_tables_to_appear_in_same_query!(users, identities);
 allow_tables_to_appear_in_same_query!(channels, lists_channels);
+allow_tables_to_appear_in_same_query!(channels, websub_subscriptions);
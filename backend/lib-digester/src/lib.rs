@@ -5,9 +5,13 @@ use lib_db as db;
 use lib_db::{Digest, Frequency, InsertDigest, Subscription, User};
 use lib_messaging as messaging;
 use messaging::sendgrid::*;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub use messaging::sendgrid::SendgridCredentials;
 
+// if we crash while sending a digest, other workers send it after that long
+const DIGEST_CLAIM_MINUTES: i64 = 15;
+
 pub struct App<'a> {
     db_conn: &'a db::Connection,
     sendgrid: SendgridCredentials,
@@ -40,7 +44,10 @@ impl App<'_> {
         }
     }
 
-    pub fn run(&self) -> Result<(), String> {
+    /// Creates the next digests and sends the due ones. Once `stop` is set,
+    /// the digest that is being sent is finished and the rest is left for the
+    /// next run. `progress` is called after every user.
+    pub fn run(&self, stop: &AtomicBool, progress: &dyn Fn()) -> Result<(), String> {
         let subscriptions = db::subscriptions_find_without_due_digest(&self.db_conn)?;
         println!(
             "{} subscriptions need a digest (unsent)",
@@ -55,15 +62,36 @@ impl App<'_> {
         println!("Found {} users with due digests", users.len());
 
         for user in users {
-            let d_and_s = db::digests_find_due_for_user(&self.db_conn, &user)?;
+            if stop.load(Ordering::SeqCst) {
+                println!("Stopping before sending the remaining digests");
+                break;
+            }
+            let claim = Duration::minutes(DIGEST_CLAIM_MINUTES);
+            let d_and_s = db::digests_claim_due_for_user(&self.db_conn, &user, claim)?;
+            if d_and_s.is_empty() {
+                println!("Digests of user {} are sent by another worker", user.id);
+                continue;
+            }
             match self.send_digest(&user, &d_and_s) {
                 Ok(_) => {
                     for (digest, _) in d_and_s {
-                        db::digests_set_sent(&self.db_conn, &digest)?;
+                        if !db::digests_set_sent(&self.db_conn, &digest)? {
+                            // sending took longer than our claim, so the user might get it twice
+                            eprintln!(
+                                "Digest {} was claimed by another worker while we sent it",
+                                digest.id
+                            );
+                        }
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Failed to send digest: {:?}", err);
+                    for (digest, _) in d_and_s {
+                        db::digests_release(&self.db_conn, &digest)?;
                     }
                 }
-                Err(err) => eprintln!("Failed to send digest: {:?}", err),
             }
+            progress();
         }
 
         Ok(())
@@ -167,13 +195,7 @@ impl App<'_> {
             let updates = db::updates_find_new(&self.db_conn, *channel_id, updates_since)?;
             if !updates.is_empty() {
                 let channel = db::channels_find_by_id(&self.db_conn.0, *channel_id)?;
-                let sendgrid_updates = updates
-                    .into_iter()
-                    .map(|u| SendgridUpdate {
-                        title: u.title,
-                        url: u.url,
-                    })
-                    .collect();
+                let sendgrid_updates = self.sendgrid_updates(updates)?;
                 sendgrid_subscriptions
                     .push(SendgridSubscription::new(&channel.name, sendgrid_updates));
             }
@@ -203,6 +225,35 @@ impl App<'_> {
         }
     }
 
+    fn sendgrid_updates(&self, updates: Vec<db::Update>) -> Result<Vec<SendgridUpdate>, String> {
+        let update_ids: Vec<i64> = updates.iter().map(|u| u.id).collect();
+        let mut enclosures = db::updates_find_enclosures(&self.db_conn.0, &update_ids)?;
+        Ok(updates
+            .into_iter()
+            .map(|u| {
+                // so podcast episodes can be played right from the digest
+                let audio_url = enclosures
+                    .remove(&u.id)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .find(is_audio)
+                    .map(|e| e.url);
+                SendgridUpdate {
+                    title: u.title,
+                    url: u.url,
+                    external_url: u.external_url,
+                    summary: u.summary,
+                    author: u.author,
+                    image_url: u.image_url,
+                    reading_minutes: u.reading_minutes,
+                    duration: u.duration_seconds.map(format_duration),
+                    episode: u.episode,
+                    audio_url,
+                }
+            })
+            .collect())
+    }
+
     fn updates_since(
         &self,
         digest: &Digest,
@@ -254,13 +305,7 @@ impl App<'_> {
         for channel in channels {
             let updates = db::updates_find_new(&self.db_conn, channel.id, updates_since)?;
             if !updates.is_empty() {
-                let sendgrid_updates = updates
-                    .into_iter()
-                    .map(|u| SendgridUpdate {
-                        title: u.title,
-                        url: u.url,
-                    })
-                    .collect();
+                let sendgrid_updates = self.sendgrid_updates(updates)?;
                 sendgrid_subscriptions
                     .push(SendgridSubscription::new(&channel.name, sendgrid_updates));
             }
@@ -290,6 +335,22 @@ impl App<'_> {
     }
 }
 
+// audio or video, as opposed to eg. the cover image of an episode
+fn is_audio(enclosure: &db::UpdateEnclosure) -> bool {
+    let mime_type = enclosure.mime_type.as_deref().unwrap_or("");
+    mime_type.starts_with("audio/") || mime_type.starts_with("video/")
+}
+
+// eg. '1h 5min' or '45min'
+fn format_duration(seconds: i32) -> String {
+    let minutes = (seconds + 30) / 60;
+    if minutes >= 60 {
+        format!("{}h {}min", minutes / 60, minutes % 60)
+    } else {
+        format!("{}min", minutes.max(1))
+    }
+}
+
 fn next_due_date_for_subscription(subscription: &Subscription, now: DateTime<Tz>) -> DateTime<Tz> {
     match subscription.frequency {
         Frequency::Daily => {
@@ -348,6 +409,14 @@ mod tests {
     use chrono_tz::Europe::Zurich;
     use lib_db::Day;
 
+    #[test]
+    fn format_episode_durations() {
+        assert_eq!("1min", format_duration(10));
+        assert_eq!("45min", format_duration(45 * 60 + 10));
+        assert_eq!("1h 0min", format_duration(3599));
+        assert_eq!("1h 2min", format_duration(3723));
+    }
+
     #[test]
     fn digester_due_daily_tomorrow() {
         let subscription = mk_daily(9, 0);
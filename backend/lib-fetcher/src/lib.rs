@@ -1,58 +1,107 @@
 use lib_channels as channels;
 use lib_db as db;
 
+use channels::article;
+use channels::forge_release::ForgeRelease;
 use channels::github_release::GithubRelease;
+use channels::github_tag::GithubTag;
+use channels::link_aggregator::LinkAggregator;
+use channels::mastodon::Mastodon;
+use channels::newsletter::Newsletter;
+use channels::oci_image::OciImage;
+use channels::package_release::{CratesIo, Npm, PyPi};
+use channels::reddit::Reddit;
 use channels::rss::Rss;
 use channels::twitter::Twitter;
-use channels::{Channel, Update};
+use channels::web_page::{Snapshot, SnapshotStore, WebPage};
+use channels::{CacheValidators, Channel, ConditionalUpdates, Update};
 use chrono::{DateTime, Duration, Utc};
-use std::collections::HashMap;
+use diesel::pg::PgConnection;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+mod pool;
+mod schedule;
+pub mod websub;
+
+// failing channels are retried at least once a week
+const MAX_RETRY_DELAY_DAYS: i64 = 7;
+// if we crash while fetching channels, other workers fetch them after that long
+const FETCH_CLAIM_MINUTES: i64 = 60;
 
 pub struct App<'a> {
     channel_github_release: GithubRelease,
+    channel_github_tag: GithubTag,
+    channel_forge_release: ForgeRelease,
     channel_rss_feed: Rss,
     channel_twitter: Twitter,
+    channel_mastodon: Mastodon,
+    channel_crates_io: CratesIo,
+    channel_npm: Npm,
+    channel_pypi: PyPi,
+    channel_oci_image: OciImage,
+    channel_link_aggregator: LinkAggregator,
+    channel_reddit: Reddit,
+    channel_web_page: WebPage<'a>,
+    channel_newsletter: Newsletter,
     db: &'a db::Connection,
+    websub_callback_url: Option<String>,
+    settings: Settings,
+}
+
+pub struct Settings {
+    /// channels that failed that many times in a row are considered dead
+    pub dead_after_failures: i32,
+    /// how many channels are fetched at the same time
+    pub concurrency: usize,
 }
 
 impl App<'_> {
-    pub fn new(db_conn: &db::Connection, github: GithubRelease, twitter: Twitter) -> App {
+    pub fn new(
+        db_conn: &db::Connection,
+        github: GithubRelease,
+        github_tag: GithubTag,
+        forge_release: ForgeRelease,
+        twitter: Twitter,
+        websub_callback_url: Option<String>,
+        settings: Settings,
+    ) -> App {
         App {
             channel_github_release: github,
+            channel_github_tag: github_tag,
+            channel_forge_release: forge_release,
             channel_rss_feed: Rss {},
             channel_twitter: twitter,
+            channel_mastodon: Mastodon {},
+            channel_crates_io: CratesIo {},
+            channel_npm: Npm {},
+            channel_pypi: PyPi {},
+            channel_oci_image: OciImage {},
+            channel_link_aggregator: LinkAggregator {},
+            channel_reddit: Reddit {},
+            channel_web_page: WebPage::new(Box::new(DbSnapshots(db_conn))),
+            channel_newsletter: Newsletter {},
             db: db_conn,
+            websub_callback_url,
+            settings,
         }
     }
 
-    pub fn run(&self) -> Result<(), String> {
+    /// Cleans and fetches the due channels once. Once `stop` is set, the
+    /// channel at hand is finished and the rest is left for the next run.
+    /// `progress` is called after every channel.
+    pub fn run(&self, stop: &AtomicBool, progress: &dyn Fn()) -> Result<(), String> {
         let mut err = String::new();
 
-        let start = Utc::now();
-        if let Err(cleaner_err) = self.run_cleaner() {
-            err.push_str(&format!("Cleaner failed: {}", cleaner_err))
-        } else {
-            let duration = Utc::now() - start;
-            println!(
-                "Cleaner done. Took {}m {}s",
-                duration.num_minutes(),
-                duration.num_seconds()
-            );
+        if let Err(cleaner_err) = self.clean(progress) {
+            err.push_str(&cleaner_err)
         }
 
-        let start = Utc::now();
-        if let Err(fetcher_err) = self.run_fetcher() {
+        if let Err(fetcher_err) = self.fetch(stop, progress) {
             if !err.is_empty() {
                 err.push_str(", ")
             }
-            err.push_str(&format!("Fetcher failed: {}", fetcher_err))
-        } else {
-            let duration = Utc::now() - start;
-            println!(
-                "Fetcher done. Took {}m {}s",
-                duration.num_minutes(),
-                duration.num_seconds()
-            );
+            err.push_str(&fetcher_err)
         }
 
         if err.is_empty() {
@@ -62,10 +111,40 @@ impl App<'_> {
         }
     }
 
-    fn run_fetcher(&self) -> Result<(), String> {
-        let fetch_frequency = Duration::hours(6);
+    pub fn clean(&self, progress: &dyn Fn()) -> Result<(), String> {
+        let start = Utc::now();
+        self.run_cleaner(progress)
+            .map_err(|err| format!("Cleaner failed: {}", err))?;
+        let duration = Utc::now() - start;
+        println!(
+            "Cleaner done. Took {}m {}s",
+            duration.num_minutes(),
+            duration.num_seconds()
+        );
+        Ok(())
+    }
+
+    pub fn fetch(&self, stop: &AtomicBool, progress: &dyn Fn()) -> Result<(), String> {
+        let start = Utc::now();
+        self.run_fetcher(stop, progress)
+            .map_err(|err| format!("Fetcher failed: {}", err))?;
+        let duration = Utc::now() - start;
+        println!(
+            "Fetcher done. Took {}m {}s",
+            duration.num_minutes(),
+            duration.num_seconds()
+        );
+        Ok(())
+    }
+
+    fn run_fetcher(&self, stop: &AtomicBool, progress: &dyn Fn()) -> Result<(), String> {
+        if let Some(callback_url) = &self.websub_callback_url {
+            if let Err(err) = websub::renew_expiring(&self.db.0, callback_url) {
+                eprintln!("Failed to renew websub subscriptions: {}", err)
+            }
+        }
 
-        let channels = self.find_fetch_due_channels(fetch_frequency)?;
+        let channels = self.claim_fetch_due_channels()?;
 
         if channels.is_empty() {
             println!("Found no channels to update");
@@ -73,90 +152,268 @@ impl App<'_> {
             println!("Found {} channels to update", channels.len());
         }
 
-        for channel in channels {
+        // the ones we don't get to because we're asked to stop
+        let mut unfetched: HashSet<i32> = channels.iter().map(|c| c.id).collect();
+
+        // the shared ones are fetched on other threads, while we fetch the
+        // rest. all results are stored here, because we only have one connection
+        let shared = self.shared_channels();
+        let (concurrent, serial): (Vec<db::Channel>, Vec<db::Channel>) = channels
+            .into_iter()
+            .partition(|channel| shared.get(&channel.channel_type).is_some());
+        let fetching = pool::fetch_concurrently(concurrent, &shared, self.settings.concurrency);
+
+        for channel in serial {
+            if stop.load(Ordering::SeqCst) {
+                break;
+            }
             let res = self.fetch_articles(&channel);
             self.update_last_sync(&channel, res)?;
+            unfetched.remove(&channel.id);
+            progress();
+        }
+        // what the threads already fetched is stored even if we're asked to stop,
+        // they only don't start on the remaining channels
+        if stop.load(Ordering::SeqCst) {
+            fetching.cancel();
+        }
+        for (channel, updates) in fetching.results.iter() {
+            if stop.load(Ordering::SeqCst) {
+                fetching.cancel();
+            }
+            let res = updates
+                .map_err(FetchError::Channel)
+                .and_then(|updates| self.store_fetched(&channel, updates));
+            self.update_last_sync(&channel, res)?;
+            unfetched.remove(&channel.id);
+            progress();
+        }
+
+        if !unfetched.is_empty() {
+            // rather than waiting for our claim to run out, the next run fetches them
+            println!("Stopping with {} channels left to fetch", unfetched.len());
+            db::channels_release_fetch_claims(&self.db, unfetched.into_iter().collect())?;
         }
 
         Ok(())
     }
 
-    fn find_fetch_due_channels(
-        &self,
-        fetch_frequency: Duration,
-    ) -> Result<Vec<db::Channel>, String> {
-        db::channels_find_by_last_fetched(&self.db, fetch_frequency)
+    fn shared_channels(&self) -> pool::SharedChannels {
+        pool::SharedChannels {
+            forge_release: self.channel_forge_release.clone(),
+            twitter: self.channel_twitter.clone(),
+        }
     }
 
-    fn fetch_articles(&self, channel: &db::Channel) -> Result<(), String> {
+    fn claim_fetch_due_channels(&self) -> Result<Vec<db::Channel>, String> {
+        db::channels_claim_due_for_fetch(&self.db, Duration::minutes(FETCH_CLAIM_MINUTES))
+    }
+
+    // returns how long the channel says it won't change, if it says so
+    fn fetch_articles(&self, channel: &db::Channel) -> Result<Option<Duration>, FetchError> {
+        let updates = self
+            .get_channel(channel)
+            .fetch_updates_if_modified(&channel.ext_id, &cache_validators(channel))
+            .map_err(FetchError::Channel)?;
+        self.store_fetched(channel, updates)
+    }
+
+    fn store_fetched(
+        &self,
+        channel: &db::Channel,
+        updates: ConditionalUpdates,
+    ) -> Result<Option<Duration>, FetchError> {
         let c = self.get_channel(channel);
-        let last_known_update = db::updates_find_newest_by_channel(self.db, channel.id)?;
-        let all_updates = c.fetch_updates(&channel.ext_id)?;
-        let n_all_updates = all_updates.len();
-        let updates = filter_new_updates(all_updates, last_known_update);
+        let validators = cache_validators(channel);
+        let (all_updates, new_validators, moved_to) = match updates {
+            ConditionalUpdates::NotModified => {
+                println!(
+                    "{:?} channel {} ({}) was not modified",
+                    channel.channel_type, channel.ext_id, channel.name
+                );
+                return Ok(None);
+            }
+            ConditionalUpdates::Modified(updates, new_validators) => {
+                (updates, new_validators, None)
+            }
+            ConditionalUpdates::Moved(location, updates, new_validators) => {
+                (updates, new_validators, Some(location))
+            }
+        };
+        let max_age = new_validators
+            .max_age
+            .and_then(|max_age| Duration::from_std(max_age).ok());
+
+        if let Some(location) = moved_to {
+            if let Some(moved) = self.move_channel(channel, c, &location)? {
+                // the validators are from the old location, which
+                // might not be the one we fetched the updates from
+                store_updates(&self.db.0, &moved, all_updates)?;
+                return Ok(max_age);
+            }
+        }
 
-        println!(
-            "Found {} new updates (total {}) in {:?} channel {} ({})",
-            updates.len(),
-            n_all_updates,
-            channel.channel_type,
-            channel.ext_id,
-            channel.name
-        );
+        store_updates(&self.db.0, channel, all_updates)?;
+
+        // only store the validators once the updates are stored, because
+        // we would otherwise never see them if the insert failed
+        if new_validators.etag != validators.etag
+            || new_validators.last_modified != validators.last_modified
+        {
+            db::channels_update_validators(
+                self.db,
+                channel,
+                new_validators.etag.as_deref(),
+                new_validators.last_modified.as_deref(),
+            )?;
+        }
 
-        for update in updates {
-            let new_update = db::NewUpdate {
-                channel_id: channel.id,
-                ext_id: update.ext_id,
-                title: update.title,
-                url: update.url,
-                published: update.published,
-            };
-            match db::updates_insert_new(&self.db, &new_update) {
-                Ok(_) => {}
-                Err(db::InsertError::Unknown(err)) => {
-                    return Err(format!("Error during updates insert: {:?}", err))
-                }
-                Err(db::InsertError::Duplicate) => {
-                    println!("Ignoring duplicate update: {}", new_update.title)
-                }
+        Ok(max_age)
+    }
+
+    // moves the channel to where it says it lives now, but only if there actually
+    // is something. returns the channel under its new location (which may be
+    // another one it was merged into) or none if it stays where it is.
+    fn move_channel(
+        &self,
+        channel: &db::Channel,
+        c: &dyn Channel,
+        location: &str,
+    ) -> Result<Option<db::Channel>, String> {
+        let location = match sanitize_location(c, location) {
+            Ok(location) => location,
+            Err(err) => {
+                eprintln!(
+                    "Not moving channel {} to {}, because it is invalid: {}",
+                    channel.id, location, err
+                );
+                return Ok(None);
+            }
+        };
+        let location = location.as_str();
+        match c.fetch_updates_if_modified(location, &CacheValidators::default()) {
+            Ok(ConditionalUpdates::Modified(_, _)) => {}
+            Ok(_) => {
+                eprintln!(
+                    "Not moving channel {} to {}, because it moved again",
+                    channel.id, location
+                );
+                return Ok(None);
+            }
+            Err(err) => {
+                eprintln!(
+                    "Not moving channel {} to {}, because it failed: {}",
+                    channel.id, location, err
+                );
+                return Ok(None);
             }
         }
 
-        Ok(())
+        let moved = db::channels_move(&self.db.0, channel, location)?;
+        if moved.id == channel.id {
+            println!(
+                "Moved {:?} channel {} from {} to {}",
+                channel.channel_type, channel.id, channel.ext_id, location
+            );
+        } else {
+            println!(
+                "Merged {:?} channel {} ({}) into channel {} ({})",
+                channel.channel_type, channel.id, channel.ext_id, moved.id, location
+            );
+        }
+        Ok(Some(moved))
     }
 
     fn get_channel(&self, channel: &db::Channel) -> &dyn Channel {
         match channel.channel_type {
             db::ChannelType::GithubRelease => &self.channel_github_release,
+            db::ChannelType::GithubTag => &self.channel_github_tag,
             db::ChannelType::RssFeed => &self.channel_rss_feed,
             db::ChannelType::Twitter => &self.channel_twitter,
+            db::ChannelType::Mastodon => &self.channel_mastodon,
+            db::ChannelType::CratesIo => &self.channel_crates_io,
+            db::ChannelType::Npm => &self.channel_npm,
+            db::ChannelType::PyPi => &self.channel_pypi,
+            db::ChannelType::ForgeRelease => &self.channel_forge_release,
+            db::ChannelType::OciImage => &self.channel_oci_image,
+            db::ChannelType::LinkAggregator => &self.channel_link_aggregator,
+            db::ChannelType::Reddit => &self.channel_reddit,
+            db::ChannelType::WebPage => &self.channel_web_page,
+            db::ChannelType::Newsletter => &self.channel_newsletter,
         }
     }
 
     fn update_last_sync(
         &self,
         channel: &db::Channel,
-        sync_result: Result<(), String>,
+        sync_result: Result<Option<Duration>, FetchError>,
     ) -> Result<(), String> {
         match sync_result {
-            Err(err) => {
+            Err(FetchError::Internal(err)) => {
+                // not the channel's fault, so we simply try again once our claim runs out
                 eprintln!(
-                    "update_last_sync for {} (name='{}', exit_id='{}'): failures are not handled yet: {:?}",
+                    "update_last_sync for {} (name='{}', exit_id='{}'): failed to store: {:?}",
                     channel.id, channel.name, channel.ext_id, err
                 );
                 Ok(())
             }
-            Ok(()) => {
-                db::channels_update_last_fetched(&self.db, channel)?;
+            Err(FetchError::Channel(err)) => {
+                let failure = next_failure(
+                    channel.failures,
+                    channel.dead_since,
+                    &err,
+                    Duration::hours(schedule::DEFAULT_INTERVAL_HOURS),
+                    self.settings.dead_after_failures,
+                    Utc::now(),
+                );
+                eprintln!(
+                    "update_last_sync for {} (name='{}', exit_id='{}'): failed {} times in a row, retrying at {}: {:?}",
+                    channel.id, channel.name, channel.ext_id, failure.failures, failure.retry_at, failure.error
+                );
+                if channel.dead_since.is_none() && failure.dead_since.is_some() {
+                    println!(
+                        "Considering {:?} channel {} ({}) dead after {} failures",
+                        channel.channel_type, channel.id, channel.ext_id, failure.failures
+                    );
+                }
+                db::channels_update_failure(&self.db, channel, &failure)
+            }
+            Ok(max_age) => {
+                if channel.dead_since.is_some() {
+                    println!(
+                        "{:?} channel {} ({}) works again",
+                        channel.channel_type, channel.id, channel.ext_id
+                    );
+                }
+                let next_fetch = self.next_fetch(channel, max_age)?;
+                db::channels_update_last_fetched(&self.db, channel, next_fetch)?;
                 Ok(())
             }
         }
     }
 
-    fn run_cleaner(&self) -> Result<(), String> {
+    fn next_fetch(
+        &self,
+        channel: &db::Channel,
+        max_age: Option<Duration>,
+    ) -> Result<DateTime<Utc>, String> {
+        let recent_published = db::updates_find_recent_published(
+            &self.db.0,
+            channel.id,
+            schedule::POSTING_RATE_SAMPLE,
+        )?;
+        let next_digest = db::digests_find_next_due_by_channel(&self.db, channel.id)?;
+        Ok(schedule::next_fetch(
+            Utc::now(),
+            &recent_published,
+            max_age,
+            next_digest,
+        ))
+    }
+
+    fn run_cleaner(&self, progress: &dyn Fn()) -> Result<(), String> {
         let clean_frequency = Duration::hours(24);
-        let channels = self.find_clean_due_channels(clean_frequency)?;
+        let channels = self.claim_clean_due_channels(clean_frequency)?;
 
         if channels.is_empty() {
             println!("Nothing to clean");
@@ -171,25 +428,20 @@ impl App<'_> {
         let retain_updates_duration = Duration::weeks(2);
         for channel in channels.iter() {
             self.delete_old_updates(channel, retain_updates_duration)?;
+            progress();
         }
 
-        let twitter_channels = channels
-            .clone()
-            .into_iter()
-            .filter(|c| c.is_twitter())
-            .collect();
+        let twitter_channels = channels.into_iter().filter(|c| c.is_twitter()).collect();
         self.delete_deleted_tweets(twitter_channels)?;
 
-        self.update_last_cleaned(channels);
-
         Ok(())
     }
 
-    fn find_clean_due_channels(
+    fn claim_clean_due_channels(
         &self,
         clean_frequency: Duration,
     ) -> Result<Vec<db::Channel>, String> {
-        db::channels_find_by_last_cleaned(&self.db, clean_frequency)
+        db::channels_claim_due_for_cleaning(&self.db, clean_frequency)
     }
 
     fn delete_old_updates(
@@ -283,14 +535,190 @@ impl App<'_> {
             )),
         }
     }
+}
+
+// channels are stored the way the channel sanitizes what the user searched for,
+// so the location must look the same to find the channel if we already have it.
+// if that drops something the feed needs (eg. its query), it isn't moved,
+// because the sanitized location is fetched before moving there.
+fn sanitize_location(c: &dyn Channel, location: &str) -> Result<String, String> {
+    c.sanitize(location).map(|sanitized| sanitized.0)
+}
+
+fn cache_validators(channel: &db::Channel) -> CacheValidators {
+    CacheValidators {
+        etag: channel.etag.clone(),
+        last_modified: channel.last_modified.clone(),
+        max_age: None,
+    }
+}
+
+// a fetch either fails because of the channel (eg. the feed is gone) or because of
+// us (eg. the database is down), in which case it doesn't count as failure of the channel
+enum FetchError {
+    Channel(channels::FetchError),
+    Internal(String),
+}
+
+impl From<String> for FetchError {
+    fn from(err: String) -> Self {
+        FetchError::Internal(err)
+    }
+}
+
+// the state of a channel that failed once more. we wait twice as long after every
+// failure (starting with the regular fetch frequency) and consider the channel dead
+// after too many of them. dead channels are still retried in case they come back.
+fn next_failure(
+    failures: i32,
+    dead_since: Option<DateTime<Utc>>,
+    error: &channels::FetchError,
+    fetch_frequency: Duration,
+    dead_after_failures: i32,
+    now: DateTime<Utc>,
+) -> db::ChannelFailure {
+    let failures = failures + 1;
+    let max_delay = Duration::days(MAX_RETRY_DELAY_DAYS);
+    // the cap on the exponent only prevents an overflow
+    let delay = (fetch_frequency * 2i32.pow((failures - 1).min(16) as u32)).min(max_delay);
+    let dead_since = match dead_since {
+        Some(since) => Some(since),
+        None if failures >= dead_after_failures => Some(now),
+        None => None,
+    };
+    db::ChannelFailure {
+        failures,
+        kind: error_kind(error),
+        error: error.to_string(),
+        retry_at: now + delay,
+        dead_since,
+    }
+}
+
+// a status the server is likely to answer with again next time (eg. 404) says more
+// about the channel than one that is over soon (eg. 503 during maintenance)
+fn error_kind(error: &channels::FetchError) -> db::FetchErrorKind {
+    use channels::FetchError as E;
+    use db::FetchErrorKind::*;
+    match error {
+        E::Status(404, _) | E::Status(410, _) | E::NotFound(_) => NotFound,
+        E::Status(401, _) | E::Status(403, _) | E::Status(429, _) | E::Refused(_) => Refused,
+        E::Timeout(_) => Timeout,
+        E::Invalid(_) => Invalid,
+        E::Status(_, _) | E::Other(_) => Unknown,
+    }
+}
+
+/// stores those of the updates that are new to us. this is shared by the
+/// fetcher and the updates that are pushed to us by websub hubs
+pub fn store_updates(
+    conn: &PgConnection,
+    channel: &db::Channel,
+    all_updates: Vec<Update>,
+) -> Result<(), String> {
+    let last_known_update = db::updates_find_newest_by_channel(conn, channel.id)?;
+    let guids: Vec<String> = all_updates.iter().map(|u| u.guid()).collect();
+    let known_guids = db::updates_find_known_guids(conn, channel.id, &guids)?;
+    let n_all_updates = all_updates.len();
+    let mut updates = filter_new_updates(all_updates, last_known_update, &known_guids);
+    if channel.extract_articles {
+        extract_articles(channel, &mut updates);
+    }
+
+    println!(
+        "Found {} new updates (total {}) in {:?} channel {} ({})",
+        updates.len(),
+        n_all_updates,
+        channel.channel_type,
+        channel.ext_id,
+        channel.name
+    );
+
+    for update in updates {
+        let guid = update.guid();
+        let metadata = update.metadata;
+        let new_update = db::NewUpdate {
+            channel_id: channel.id,
+            ext_id: update.ext_id,
+            title: update.title,
+            url: update.url,
+            external_url: update.external_url,
+            published: update.published,
+            summary: metadata.summary,
+            author: metadata.author,
+            categories: metadata.categories,
+            image_url: metadata.image_url,
+            guid,
+            reading_minutes: metadata.reading_minutes,
+            duration_seconds: metadata.duration_seconds,
+            episode: metadata.episode,
+        };
+        let enclosures: Vec<db::NewEnclosure> = metadata
+            .enclosures
+            .into_iter()
+            .map(|e| db::NewEnclosure {
+                url: e.url,
+                mime_type: e.mime_type,
+                length: e.length,
+            })
+            .collect();
+        match db::updates_insert_new(conn, &new_update, &enclosures) {
+            Ok(_) => {}
+            Err(db::InsertError::Unknown(err)) => {
+                return Err(format!("Error during updates insert: {:?}", err))
+            }
+            Err(db::InsertError::Duplicate) => {
+                println!("Ignoring duplicate update: {}", new_update.title)
+            }
+        }
+    }
+    Ok(())
+}
 
-    fn update_last_cleaned(&self, channels: Vec<db::Channel>) {
-        let channel_ids: Vec<i32> = channels.into_iter().map(|c| c.id).collect();
-        if let Err(err) = db::channels_update_last_cleaned_by_ids(&self.db, channel_ids.clone()) {
-            eprintln!(
-                "Failed to update last_cleaned for channels {:?}: {:?}",
-                channel_ids, err
+// keeps the snapshots of web pages in the database
+struct DbSnapshots<'a>(&'a db::Connection);
+
+impl SnapshotStore for DbSnapshots<'_> {
+    fn find(&self, ext_id: &str) -> Result<Option<Snapshot>, String> {
+        db::web_page_snapshots_find(self.0, ext_id).map(|snapshot| {
+            snapshot.map(|s| Snapshot {
+                hash: s.hash,
+                content: s.content,
+            })
+        })
+    }
+
+    fn save(&self, ext_id: &str, snapshot: &Snapshot) -> Result<(), String> {
+        db::web_page_snapshots_save(
+            self.0,
+            &db::WebPageSnapshot {
+                ext_id: ext_id.to_owned(),
+                hash: snapshot.hash.clone(),
+                content: snapshot.content.clone(),
+                updated: Utc::now(),
+            },
+        )
+    }
+}
+
+// fetching the article of each update takes a while, so a channel with lots
+// of new updates must not hold up the others. the rest keep their summaries.
+fn extract_articles(channel: &db::Channel, updates: &mut [Update]) {
+    let deadline = Utc::now() + Duration::seconds(30);
+    for update in updates.iter_mut() {
+        if Utc::now() > deadline {
+            println!(
+                "Stopped extracting articles of channel {}, because it took too long",
+                channel.id
             );
+            return;
+        }
+        // the url may only point to a discussion of the article
+        let url = update.external_url.as_ref().unwrap_or(&update.url);
+        match article::fetch(url) {
+            Ok(Some(article)) => article::enrich(&mut update.metadata, article),
+            Ok(None) => println!("Found no article in {}", url),
+            Err(err) => eprintln!("Failed to extract article: {}", err),
         }
     }
 }
@@ -303,6 +731,10 @@ impl App<'_> {
 // 3am, we would ignore the update, because the published date is before the last_fetched.
 //
 // Strategy:
+//  - updates whose guid we already stored are never new, even if their title or date changed.
+//    updates without a date get the date when they were first seen, so this is the only thing
+//    that keeps them from being new on every fetch. the cleaner keeps the guids of the updates
+//    it deletes for the same reason
 //  - if we fetch a channel for the first time (last_known == None), we look at the published date
 //    and take everything one week back (to be on the safe side)
 //  - otherise, take everything with a published date newer than the inserted date of the last_known
@@ -311,7 +743,11 @@ impl App<'_> {
 //  - Blog X publishes entry A at 9am sets date to 00:00am. We fetch it at 9:15am. At 10am they
 //    publish entry B and again set the date to 00:00am. We'd miss the second entry
 //  - Blog Y publishes entry A at 1.1.1970
-fn filter_new_updates(updates: Vec<Update>, last_known: Option<db::Update>) -> Vec<Update> {
+fn filter_new_updates(
+    updates: Vec<Update>,
+    last_known: Option<db::Update>,
+    known_guids: &HashSet<String>,
+) -> Vec<Update> {
     // do we also need to change the digester code? does that currently work on published or inserted?
     let one_week_ago: &DateTime<Utc> = &(Utc::now() - Duration::weeks(1));
     let filter_fn = |u: &Update| match last_known.as_ref() {
@@ -319,12 +755,37 @@ fn filter_new_updates(updates: Vec<Update>, last_known: Option<db::Update>) -> V
         None => u.published > *one_week_ago,
     };
 
-    updates.into_iter().filter(filter_fn).collect()
+    // feeds sometimes list the same item twice
+    let mut seen = HashSet::new();
+    updates
+        .into_iter()
+        .filter(|u| {
+            let guid = u.guid();
+            !known_guids.contains(&guid) && seen.insert(guid)
+        })
+        .filter(filter_fn)
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use channels::Metadata;
+
+    #[test]
+    fn sanitize_location_like_searched_channels() {
+        let rss = Rss {};
+        let searched = rss.sanitize("example.org/feed.xml").unwrap().0;
+        assert_eq!(
+            Ok(searched),
+            sanitize_location(&rss, "HTTP://Example.ORG/feed.xml#latest")
+        );
+        assert_eq!(
+            Ok("http://example.org/".to_owned()),
+            sanitize_location(&rss, "http://example.org")
+        );
+        assert!(sanitize_location(&rss, "ftp://example.org/feed.xml").is_err());
+    }
 
     #[test]
     fn ignore_updates_older_than_a_week_in_first_fetch() {
@@ -332,7 +793,7 @@ mod tests {
         let old_update = mk_update(Utc::now() - Duration::days(9));
         assert_eq!(
             vec![new_update.clone()],
-            filter_new_updates(vec![new_update, old_update], None)
+            filter_new_updates(vec![new_update, old_update], None, &HashSet::new())
         );
     }
 
@@ -341,7 +802,7 @@ mod tests {
         let new_update = mk_update(Utc::now() + Duration::days(1));
         assert_eq!(
             vec![new_update.clone()],
-            filter_new_updates(vec![new_update], None)
+            filter_new_updates(vec![new_update], None, &HashSet::new())
         );
     }
 
@@ -351,7 +812,7 @@ mod tests {
         let last_known_update = Some(mk_db_update(Utc::now() - Duration::hours(6)));
         assert_eq!(
             vec![update.clone()],
-            filter_new_updates(vec![update], last_known_update)
+            filter_new_updates(vec![update], last_known_update, &HashSet::new())
         );
     }
 
@@ -365,7 +826,8 @@ mod tests {
             vec![new_update.clone(), new_update_2.clone()],
             filter_new_updates(
                 vec![old_update, new_update, new_update_2],
-                last_known_update
+                last_known_update,
+                &HashSet::new()
             )
         );
     }
@@ -377,7 +839,11 @@ mod tests {
         let last_known_update = Some(mk_db_update(Utc::now() - Duration::hours(4)));
         assert_eq!(
             vec![new_update.clone()],
-            filter_new_updates(vec![old_update, new_update], last_known_update)
+            filter_new_updates(
+                vec![old_update, new_update],
+                last_known_update,
+                &HashSet::new()
+            )
         );
     }
 
@@ -388,14 +854,95 @@ mod tests {
         let last_known_update = Some(mk_db_update(timestamp));
         assert_eq!(
             vec![] as Vec<Update>,
-            filter_new_updates(vec![old_update], last_known_update)
+            filter_new_updates(vec![old_update], last_known_update, &HashSet::new())
         );
     }
 
+    #[test]
+    fn ignore_known_updates_even_if_their_date_changed() {
+        let mut edited = mk_update(Utc::now());
+        edited.ext_id = Some("guid-1".into());
+        let new_update = mk_update(Utc::now() - Duration::minutes(1));
+        let last_known_update = Some(mk_db_update(Utc::now() - Duration::hours(1)));
+        let known_guids: HashSet<String> = vec!["guid-1".to_owned()].into_iter().collect();
+        assert_eq!(
+            vec![new_update.clone()],
+            filter_new_updates(vec![edited, new_update], last_known_update, &known_guids)
+        );
+    }
+
+    #[test]
+    fn ignore_duplicates_within_the_same_fetch() {
+        let update = mk_update(Utc::now());
+        assert_eq!(
+            vec![update.clone()],
+            filter_new_updates(vec![update.clone(), update], None, &HashSet::new())
+        );
+    }
+
+    #[test]
+    fn back_off_exponentially_from_failing_channels() {
+        let now = Utc::now();
+        let frequency = Duration::hours(6);
+        let retry_in = |failures: i32| {
+            next_failure(failures, None, &error(), frequency, 100, now).retry_at - now
+        };
+        assert_eq!(Duration::hours(6), retry_in(0));
+        assert_eq!(Duration::hours(12), retry_in(1));
+        assert_eq!(Duration::hours(24), retry_in(2));
+        assert_eq!(Duration::days(7), retry_in(5));
+        assert_eq!(Duration::days(7), retry_in(50));
+    }
+
+    #[test]
+    fn consider_channels_dead_after_too_many_failures() {
+        let now = Utc::now();
+        let frequency = Duration::hours(6);
+        let failure = next_failure(8, None, &error(), frequency, 10, now);
+        assert_eq!(9, failure.failures);
+        assert_eq!(None, failure.dead_since);
+
+        let failure = next_failure(9, None, &error(), frequency, 10, now);
+        assert_eq!(10, failure.failures);
+        assert_eq!(Some(now), failure.dead_since);
+
+        let since = now - Duration::days(30);
+        let failure = next_failure(20, Some(since), &error(), frequency, 10, now);
+        assert_eq!(Some(since), failure.dead_since);
+    }
+
+    #[test]
+    fn classify_fetch_errors() {
+        use channels::FetchError as E;
+        use db::FetchErrorKind::*;
+        let msg = || "Failed to fetch url 'https://x.io/feed'".to_owned();
+        assert_eq!(NotFound, error_kind(&E::Status(404, msg())));
+        assert_eq!(NotFound, error_kind(&E::Status(410, msg())));
+        assert_eq!(NotFound, error_kind(&E::NotFound(msg())));
+        assert_eq!(Refused, error_kind(&E::Status(403, msg())));
+        assert_eq!(Refused, error_kind(&E::Status(429, msg())));
+        assert_eq!(Refused, error_kind(&E::Refused(msg())));
+        assert_eq!(Timeout, error_kind(&E::Timeout(msg())));
+        assert_eq!(Invalid, error_kind(&E::Invalid(msg())));
+        // the server is only down for now
+        assert_eq!(Unknown, error_kind(&E::Status(500, msg())));
+        assert_eq!(Unknown, error_kind(&E::Status(503, msg())));
+        // it doesn't matter what the message says
+        assert_eq!(Unknown, error_kind(&E::Other("code 404 not found".into())));
+    }
+
+    fn error() -> channels::FetchError {
+        channels::FetchError::Other("error".into())
+    }
+
     fn mk_update(published: DateTime<Utc>) -> Update {
         Update {
-            title: "title".into(),
+            ext_id: None,
+            // titles must differ, because the fingerprint is made of url and title
+            title: format!("published at {}", published),
             url: "url".into(),
+            external_url: None,
+            metadata: Metadata::default(),
             published,
         }
     }
@@ -404,10 +951,20 @@ mod tests {
         db::Update {
             id: 1,
             channel_id: 2,
+            ext_id: None,
             title: "title".into(),
             url: "url".into(),
+            external_url: None,
             published: Utc::now(),
             inserted,
+            summary: None,
+            author: None,
+            categories: Vec::new(),
+            image_url: None,
+            guid: "guid".into(),
+            reading_minutes: None,
+            duration_seconds: None,
+            episode: None,
         }
     }
 }
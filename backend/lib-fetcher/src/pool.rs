@@ -0,0 +1,207 @@
+use super::cache_validators;
+use lib_channels as channels;
+use lib_db as db;
+
+use channels::forge_release::ForgeRelease;
+use channels::link_aggregator::LinkAggregator;
+use channels::mastodon::Mastodon;
+use channels::newsletter::Newsletter;
+use channels::oci_image::OciImage;
+use channels::package_release::{CratesIo, Npm, PyPi};
+use channels::reddit::Reddit;
+use channels::rss::Rss;
+use channels::twitter::Twitter;
+use channels::{Channel, ConditionalUpdates, FetchError};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use url::Url;
+
+pub type Fetched = Result<ConditionalUpdates, FetchError>;
+
+/// The channels that can be fetched on other threads, because they share
+/// nothing but their configuration. The others are fetched by the fetcher
+/// itself:
+///  - the github client keeps its event loop in an `Rc`, so it can't leave the
+///    thread that created it. a client per thread wouldn't get us far either,
+///    because they'd all share the rate limit of the same api token
+///  - web pages are compared to their last snapshot, which is loaded from and
+///    stored in the database during the fetch. the fetcher has the only
+///    connection and it can't be shared between threads
+#[derive(Clone)]
+pub struct SharedChannels {
+    pub forge_release: ForgeRelease,
+    pub twitter: Twitter,
+}
+
+impl SharedChannels {
+    pub fn get(&self, channel_type: &db::ChannelType) -> Option<&dyn Channel> {
+        match channel_type {
+            db::ChannelType::RssFeed => Some(&Rss {}),
+            db::ChannelType::Twitter => Some(&self.twitter),
+            db::ChannelType::Mastodon => Some(&Mastodon {}),
+            db::ChannelType::CratesIo => Some(&CratesIo {}),
+            db::ChannelType::Npm => Some(&Npm {}),
+            db::ChannelType::PyPi => Some(&PyPi {}),
+            db::ChannelType::ForgeRelease => Some(&self.forge_release),
+            db::ChannelType::OciImage => Some(&OciImage {}),
+            db::ChannelType::LinkAggregator => Some(&LinkAggregator {}),
+            db::ChannelType::Reddit => Some(&Reddit {}),
+            db::ChannelType::Newsletter => Some(&Newsletter {}),
+            // see above why these can't be fetched on other threads
+            db::ChannelType::GithubRelease
+            | db::ChannelType::GithubTag
+            | db::ChannelType::WebPage => None,
+        }
+    }
+
+    fn fetch(&self, channel: &db::Channel) -> Fetched {
+        match self.get(&channel.channel_type) {
+            Some(c) => c.fetch_updates_if_modified(&channel.ext_id, &cache_validators(channel)),
+            None => Err(FetchError::Other(format!(
+                "{:?} channels cannot be fetched concurrently",
+                channel.channel_type
+            ))),
+        }
+    }
+}
+
+/// The channels being fetched on other threads
+pub struct Fetching {
+    /// ends once all threads are done
+    pub results: Receiver<(db::Channel, Fetched)>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Fetching {
+    /// The threads don't start fetching any more channels, but the results
+    /// of those they're already fetching still arrive.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Fetches the channels on up to `concurrency` threads. The results arrive as
+/// soon as they are fetched, so the caller can store them (on its own database
+/// connection) while the others are still being fetched. How many requests go
+/// to the same host at a time is up to the politeness of lib-channels.
+pub fn fetch_concurrently(
+    channels: Vec<db::Channel>,
+    shared: &SharedChannels,
+    concurrency: usize,
+) -> Fetching {
+    let threads = concurrency.max(1).min(channels.len());
+    let queue = Arc::new(Mutex::new(VecDeque::from(interleave_hosts(channels))));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+    for _ in 0..threads {
+        let queue = queue.clone();
+        let cancelled = cancelled.clone();
+        let sender = sender.clone();
+        let shared = shared.clone();
+        thread::spawn(move || loop {
+            if cancelled.load(Ordering::SeqCst) {
+                break;
+            }
+            let next = match queue.lock() {
+                Ok(mut queue) => queue.pop_front(),
+                Err(_) => None,
+            };
+            let channel = match next {
+                Some(channel) => channel,
+                None => break,
+            };
+            let fetched = shared.fetch(&channel);
+            if sender.send((channel, fetched)).is_err() {
+                // the fetcher gave up, so nobody would store the rest
+                break;
+            }
+        });
+    }
+    Fetching {
+        results: receiver,
+        cancelled,
+    }
+}
+
+// orders the channels such that consecutive ones are on different hosts,
+// because the threads would otherwise all wait for the same busy host.
+fn interleave_hosts(channels: Vec<db::Channel>) -> Vec<db::Channel> {
+    let n_channels = channels.len();
+    let mut hosts: HashMap<String, usize> = HashMap::new();
+    let mut by_host: Vec<VecDeque<db::Channel>> = Vec::new();
+    for channel in channels {
+        let next_index = by_host.len();
+        let index = *hosts.entry(host(&channel)).or_insert(next_index);
+        if index == next_index {
+            by_host.push(VecDeque::new());
+        }
+        by_host[index].push_back(channel);
+    }
+
+    let mut interleaved = Vec::with_capacity(n_channels);
+    while interleaved.len() < n_channels {
+        for channels in by_host.iter_mut() {
+            if let Some(channel) = channels.pop_front() {
+                interleaved.push(channel);
+            }
+        }
+    }
+    interleaved
+}
+
+// feeds and web pages are identified by their url, all others
+// are fetched from the api of their type (eg. crates.io)
+fn host(channel: &db::Channel) -> String {
+    Url::parse(&channel.ext_id)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_owned()))
+        .unwrap_or_else(|| format!("{:?}", channel.channel_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn interleave_channels_of_the_same_host() {
+        let channels = vec![
+            mk_channel(1, db::ChannelType::RssFeed, "https://a.com/feed"),
+            mk_channel(2, db::ChannelType::RssFeed, "https://a.com/other/feed"),
+            mk_channel(3, db::ChannelType::RssFeed, "https://a.com/comments/feed"),
+            mk_channel(4, db::ChannelType::CratesIo, "serde"),
+            mk_channel(5, db::ChannelType::CratesIo, "tokio"),
+            mk_channel(6, db::ChannelType::RssFeed, "https://b.com/feed"),
+        ];
+        let ids: Vec<i32> = interleave_hosts(channels).iter().map(|c| c.id).collect();
+        assert_eq!(vec![1, 4, 6, 2, 5, 3], ids);
+    }
+
+    fn mk_channel(id: i32, channel_type: db::ChannelType, ext_id: &str) -> db::Channel {
+        db::Channel {
+            id,
+            ext_id: ext_id.into(),
+            channel_type,
+            name: ext_id.into(),
+            link: ext_id.into(),
+            verified: false,
+            last_fetched: None,
+            last_cleaned: None,
+            etag: None,
+            last_modified: None,
+            inserted: Utc::now(),
+            extract_articles: false,
+            podcast: false,
+            failures: 0,
+            last_error_kind: None,
+            last_error: None,
+            retry_at: None,
+            dead_since: None,
+            next_fetch_at: None,
+            owner_id: None,
+        }
+    }
+}
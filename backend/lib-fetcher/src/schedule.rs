@@ -0,0 +1,153 @@
+use chrono::{DateTime, Duration, Utc};
+
+/// How often we fetch channels we know nothing about
+pub const DEFAULT_INTERVAL_HOURS: i64 = 6;
+/// How many of the newest updates tell us how often a channel posts
+pub const POSTING_RATE_SAMPLE: i64 = 10;
+
+// no matter how busy a channel is or what it says about itself..
+const MIN_INTERVAL_MINUTES: i64 = 60;
+// ..and no matter how quiet it is
+const MAX_INTERVAL_DAYS: i64 = 7;
+// how long before a digest is sent we want to have fetched its channels
+const DIGEST_MARGIN_MINUTES: i64 = 30;
+
+/// When to fetch the channel again, given when its newest updates were
+/// published, how long it says it won't change (eg. the ttl of a feed)
+/// and when the next digest with the channel is due.
+///
+/// We fetch about twice per posting interval. A channel that hasn't
+/// posted for a while is treated as if it posted that rarely, so dormant
+/// channels are fetched less and less often. The hint of the channel
+/// is respected, unless a digest is due before that.
+pub fn next_fetch(
+    now: DateTime<Utc>,
+    recent_published: &[DateTime<Utc>],
+    hint: Option<Duration>,
+    next_digest: Option<DateTime<Utc>>,
+) -> DateTime<Utc> {
+    let min_interval = Duration::minutes(MIN_INTERVAL_MINUTES);
+    let max_interval = Duration::days(MAX_INTERVAL_DAYS);
+
+    let interval = match posting_interval(now, recent_published) {
+        Some(posting_interval) => posting_interval / 2,
+        None => Duration::hours(DEFAULT_INTERVAL_HOURS),
+    };
+    let interval = match hint {
+        Some(hint) => interval.max(hint),
+        None => interval,
+    };
+    let next = now + interval.max(min_interval).min(max_interval);
+
+    match next_digest.map(|due| due - Duration::minutes(DIGEST_MARGIN_MINUTES)) {
+        Some(before_digest) if before_digest > now + min_interval && before_digest < next => {
+            before_digest
+        }
+        _ => next,
+    }
+}
+
+// the average time between the updates, or since the newest one if that is longer
+fn posting_interval(now: DateTime<Utc>, recent_published: &[DateTime<Utc>]) -> Option<Duration> {
+    let newest = recent_published.iter().max()?;
+    let oldest = recent_published.iter().min()?;
+    let since_newest = now - *newest;
+    if recent_published.len() < 2 {
+        return Some(since_newest).filter(|since| *since > Duration::hours(DEFAULT_INTERVAL_HOURS));
+    }
+    let average = (*newest - *oldest) / (recent_published.len() as i32 - 1);
+    Some(average.max(since_newest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fetch_busy_channels_often() {
+        let now = Utc::now();
+        let hourly: Vec<DateTime<Utc>> = (0..10).map(|h| now - Duration::hours(h)).collect();
+        assert_eq!(
+            now + Duration::hours(1),
+            next_fetch(now, &hourly, None, None)
+        );
+
+        let every_4_hours: Vec<DateTime<Utc>> =
+            (0..10).map(|h| now - Duration::hours(h * 4)).collect();
+        assert_eq!(
+            now + Duration::hours(2),
+            next_fetch(now, &every_4_hours, None, None)
+        );
+    }
+
+    #[test]
+    fn fetch_dormant_channels_rarely() {
+        let now = Utc::now();
+        let years_ago: Vec<DateTime<Utc>> =
+            (0..10).map(|d| now - Duration::days(700 + d)).collect();
+        assert_eq!(
+            now + Duration::days(7),
+            next_fetch(now, &years_ago, None, None)
+        );
+
+        let once = vec![now - Duration::days(4)];
+        assert_eq!(now + Duration::days(2), next_fetch(now, &once, None, None));
+    }
+
+    #[test]
+    fn fetch_unknown_channels_on_default_interval() {
+        let now = Utc::now();
+        assert_eq!(now + Duration::hours(6), next_fetch(now, &[], None, None));
+        let just_now = vec![now - Duration::minutes(5)];
+        assert_eq!(
+            now + Duration::hours(6),
+            next_fetch(now, &just_now, None, None)
+        );
+    }
+
+    #[test]
+    fn respect_hints_of_channels() {
+        let now = Utc::now();
+        let hourly: Vec<DateTime<Utc>> = (0..10).map(|h| now - Duration::hours(h)).collect();
+        assert_eq!(
+            now + Duration::hours(12),
+            next_fetch(now, &hourly, Some(Duration::hours(12)), None)
+        );
+        assert_eq!(
+            now + Duration::hours(1),
+            next_fetch(now, &hourly, Some(Duration::minutes(5)), None)
+        );
+        assert_eq!(
+            now + Duration::days(7),
+            next_fetch(now, &hourly, Some(Duration::days(365)), None)
+        );
+    }
+
+    #[test]
+    fn fetch_before_digests_are_due() {
+        let now = Utc::now();
+        let digest = now + Duration::hours(3);
+        assert_eq!(
+            digest - Duration::minutes(30),
+            next_fetch(now, &[], None, Some(digest))
+        );
+        assert_eq!(
+            digest - Duration::minutes(30),
+            next_fetch(now, &[], Some(Duration::days(1)), Some(digest))
+        );
+
+        // we just fetched it
+        let soon = now + Duration::minutes(45);
+        assert_eq!(
+            now + Duration::hours(6),
+            next_fetch(now, &[], None, Some(soon))
+        );
+
+        // we fetch it before anyway
+        let later = now + Duration::hours(10);
+        assert_eq!(
+            now + Duration::hours(6),
+            next_fetch(now, &[], None, Some(later))
+        );
+    }
+}
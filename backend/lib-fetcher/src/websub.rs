@@ -0,0 +1,243 @@
+use super::store_updates;
+use chrono::{DateTime, Duration, Utc};
+use diesel::pg::PgConnection;
+use lib_channels::rss;
+use lib_channels::websub::{self, Hub};
+use lib_db as db;
+
+// hubs may choose a different lease than we asked for, but not one that
+// makes us skip polling the channel for longer than this
+const MAX_LEASE_SECONDS: i64 = 3 * websub::LEASE_SECONDS;
+
+/// Subscribes to the hub of a feed, if it advertises one. The hub then calls
+/// `{callback_url}/{channel_id}` to verify the subscription and push content.
+/// Returns whether the feed has a hub.
+pub fn subscribe(
+    conn: &PgConnection,
+    channel: &db::Channel,
+    callback_url: &str,
+) -> Result<bool, String> {
+    if channel.channel_type != db::ChannelType::RssFeed {
+        return Ok(false);
+    }
+
+    let hub = match websub::discover(&channel.ext_id)? {
+        Some(hub) => hub,
+        None => return Ok(false),
+    };
+
+    // keep the secret, because the hub might still push with the old one
+    let existing = db::websub_subscriptions_find(conn, channel.id)?;
+    let subscription = db::WebsubSubscription {
+        channel_id: channel.id,
+        hub: hub.url.clone(),
+        topic: hub.topic.clone(),
+        secret: existing
+            .as_ref()
+            .map(|s| s.secret.clone())
+            .unwrap_or_else(websub::generate_secret),
+        expires: existing.as_ref().and_then(|s| s.expires),
+        requested: Utc::now(),
+        answered: existing.and_then(|s| s.answered),
+    };
+    // stored before asking the hub, because it may verify before it responds
+    db::websub_subscriptions_save(conn, &subscription)?;
+
+    websub::subscribe(
+        &hub,
+        &callback(callback_url, channel.id),
+        &subscription.secret,
+    )?;
+    println!(
+        "Requested websub subscription for channel {} at {}",
+        channel.id, hub.url
+    );
+    Ok(true)
+}
+
+/// Asks the hubs again for subscriptions that are about to expire or that
+/// were never verified
+pub fn renew_expiring(conn: &PgConnection, callback_url: &str) -> Result<(), String> {
+    let now = Utc::now();
+    let expiring = db::websub_subscriptions_find_expiring(
+        conn,
+        now + Duration::days(1),
+        now - Duration::days(1),
+    )?;
+    for subscription in expiring {
+        let hub = Hub {
+            url: subscription.hub.clone(),
+            topic: subscription.topic.clone(),
+        };
+        // the hub gets another chance tomorrow, if it fails now
+        db::websub_subscriptions_save(
+            conn,
+            &db::WebsubSubscription {
+                requested: now,
+                ..subscription.clone()
+            },
+        )?;
+        if let Err(err) = websub::subscribe(
+            &hub,
+            &callback(callback_url, subscription.channel_id),
+            &subscription.secret,
+        ) {
+            eprintln!(
+                "Failed to renew websub subscription of channel {}: {}",
+                subscription.channel_id, err
+            )
+        }
+    }
+    Ok(())
+}
+
+/// The hub verifies that we actually asked for the subscription. Returns
+/// false if we are not waiting for the hub to answer a request for that topic.
+pub fn verify_intent(
+    conn: &PgConnection,
+    channel_id: i32,
+    topic: &str,
+    lease_seconds: Option<i64>,
+) -> Result<bool, String> {
+    let now = Utc::now();
+    let subscription = match db::websub_subscriptions_find(conn, channel_id)? {
+        Some(subscription) if is_pending(&subscription, topic, now) => subscription,
+        _ => return Ok(false),
+    };
+    let lease_seconds = lease_seconds
+        .unwrap_or(websub::LEASE_SECONDS)
+        .max(1)
+        .min(MAX_LEASE_SECONDS);
+    let expires = lease_expires(now, lease_seconds)?;
+    db::websub_subscriptions_save(
+        conn,
+        &db::WebsubSubscription {
+            expires: Some(expires),
+            answered: Some(now),
+            ..subscription
+        },
+    )?;
+    println!(
+        "Websub subscription of channel {} verified for {}s",
+        channel_id, lease_seconds
+    );
+    Ok(true)
+}
+
+/// The hub denied the subscription, so we poll the feed until it is renewed.
+/// Returns false if we are not waiting for the hub to answer a request for that topic.
+pub fn denied(conn: &PgConnection, channel_id: i32, topic: &str) -> Result<bool, String> {
+    let now = Utc::now();
+    let subscription = match db::websub_subscriptions_find(conn, channel_id)? {
+        Some(subscription) if is_pending(&subscription, topic, now) => subscription,
+        _ => return Ok(false),
+    };
+    db::websub_subscriptions_save(
+        conn,
+        &db::WebsubSubscription {
+            expires: None,
+            answered: Some(now),
+            ..subscription
+        },
+    )?;
+    Ok(true)
+}
+
+// the hub answers shortly after we asked. anything else doesn't come from the
+// hub, but from someone who wants us to stop polling the channel
+fn is_pending(subscription: &db::WebsubSubscription, topic: &str, now: DateTime<Utc>) -> bool {
+    subscription.topic == topic
+        && subscription.requested > now - Duration::days(1)
+        && subscription
+            .answered
+            .map_or(true, |answered| answered < subscription.requested)
+}
+
+/// Content pushed by the hub. Content with an invalid signature is ignored,
+/// but the hub must not learn about that, so this is still a success.
+/// Returns false if we have no subscription for the channel (anymore).
+pub fn receive(
+    conn: &PgConnection,
+    channel_id: i32,
+    content_type: &str,
+    body: &[u8],
+    signature: Option<&str>,
+) -> Result<bool, String> {
+    let subscription = match db::websub_subscriptions_find(conn, channel_id)? {
+        Some(subscription) => subscription,
+        None => return Ok(false),
+    };
+    let valid = signature
+        .map(|s| websub::verify_signature(&subscription.secret, body, s))
+        .unwrap_or(false);
+    if !valid {
+        eprintln!(
+            "Ignoring pushed content with invalid signature for channel {}",
+            channel_id
+        );
+        return Ok(true);
+    }
+
+    let channel = db::channels_find_by_id(conn, channel_id)?;
+    let updates = rss::updates_from_bytes(content_type, body)?;
+    store_updates(conn, &channel, updates)?;
+    Ok(true)
+}
+
+fn lease_expires(now: DateTime<Utc>, lease_seconds: i64) -> Result<DateTime<Utc>, String> {
+    now.checked_add_signed(Duration::seconds(lease_seconds))
+        .ok_or_else(|| format!("Lease of {}s is out of range", lease_seconds))
+}
+
+fn callback(callback_url: &str, channel_id: i32) -> String {
+    format!("{}/{}", callback_url.trim_end_matches('/'), channel_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subscription(requested: DateTime<Utc>) -> db::WebsubSubscription {
+        db::WebsubSubscription {
+            channel_id: 1,
+            hub: "https://hub.example.org".into(),
+            topic: "https://example.org/feed".into(),
+            secret: "secret".into(),
+            expires: None,
+            requested,
+            answered: None,
+        }
+    }
+
+    #[test]
+    fn accept_answers_only_to_pending_requests() {
+        let now = Utc::now();
+        let topic = "https://example.org/feed";
+        let requested = subscription(now - Duration::minutes(1));
+        assert!(is_pending(&requested, topic, now));
+        assert!(!is_pending(&requested, "https://example.org/other", now));
+
+        let answered = db::WebsubSubscription {
+            answered: Some(now),
+            ..subscription(now - Duration::minutes(1))
+        };
+        assert!(!is_pending(&answered, topic, now));
+
+        // we asked again after the hub answered the last time
+        let renewed = db::WebsubSubscription {
+            answered: Some(now - Duration::days(9)),
+            ..subscription(now - Duration::minutes(1))
+        };
+        assert!(is_pending(&renewed, topic, now));
+
+        let forgotten = subscription(now - Duration::days(2));
+        assert!(!is_pending(&forgotten, topic, now));
+    }
+
+    #[test]
+    fn lease_out_of_range() {
+        let now = Utc::now();
+        assert_eq!(Ok(now + Duration::seconds(60)), lease_expires(now, 60));
+        assert!(lease_expires(chrono::MAX_DATETIME, 60).is_err());
+    }
+}
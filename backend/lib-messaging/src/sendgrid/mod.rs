@@ -158,4 +158,13 @@ impl SendgridSubscription {
 pub struct SendgridUpdate {
     pub title: String,
     pub url: String,
+    pub external_url: Option<String>,
+    pub summary: Option<String>,
+    pub author: Option<String>,
+    pub image_url: Option<String>,
+    pub reading_minutes: Option<i32>,
+    // of a podcast episode
+    pub duration: Option<String>,
+    pub episode: Option<i32>,
+    pub audio_url: Option<String>,
 }
@@ -0,0 +1,237 @@
+use chrono::Utc;
+use rand::Rng;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// how often we touch the health file while waiting for the next job
+const HEARTBEAT_SECONDS: u64 = 10;
+// we never sleep longer than this, so we notice quickly when we should stop
+const MAX_SLEEP_SECONDS: u64 = 1;
+
+/// Something the daemon runs over and over again. The job gets a function to
+/// call after every step (eg. every channel it fetched), so a long job isn't
+/// mistaken for one that is stuck.
+pub struct Job<'a> {
+    name: &'static str,
+    interval: Duration,
+    run: Box<RunJob<'a>>,
+}
+
+// gets the function to call after every step
+type RunJob<'a> = dyn Fn(&dyn Fn()) -> Result<(), String> + 'a;
+
+impl<'a> Job<'a> {
+    pub fn new<F>(name: &'static str, interval: Duration, run: F) -> Job<'a>
+    where
+        F: Fn(&dyn Fn()) -> Result<(), String> + 'a,
+    {
+        Job {
+            name,
+            interval,
+            run: Box::new(run),
+        }
+    }
+}
+
+/// The health file of the daemon, whose loops run on their own threads. It is
+/// only touched once every loop showed a sign of life since the last time, so
+/// its age tells how long the loop that is stuck the longest has been stuck.
+pub struct Health {
+    file: Option<PathBuf>,
+    // when each loop was last alive and the oldest of those when we touched the file
+    beats: Mutex<(HashMap<&'static str, Instant>, Option<Instant>)>,
+}
+
+impl Health {
+    pub fn new(file: Option<PathBuf>) -> Health {
+        Health {
+            file,
+            beats: Mutex::new((HashMap::new(), None)),
+        }
+    }
+
+    fn beat(&self, name: &'static str) -> Result<(), String> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        // another loop panicked, which doesn't make the beats wrong
+        let mut beats = self.beats.lock().unwrap_or_else(|err| err.into_inner());
+        let (last_beats, touched) = &mut *beats;
+        last_beats.insert(name, Instant::now());
+        let oldest = last_beats.values().min().cloned();
+        if oldest > *touched {
+            *touched = oldest;
+            heartbeat(file)?;
+        }
+        Ok(())
+    }
+
+    pub fn remove(&self) {
+        if let Some(file) = &self.file {
+            // we're not healthy anymore, but the file might be gone already
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+/// Runs the jobs of the loop `name`, each one again `interval` plus up to
+/// `jitter` after it last finished, until `stop` is set. The jitter keeps
+/// several workers that were started at the same time from running their jobs
+/// in lockstep.
+///
+/// The loop beats the health after every step of a job and every few seconds
+/// while waiting. A failing job doesn't stop the loop, it is simply run again
+/// next time. Unless the connection it used is gone (`alive` says so), in
+/// which case this returns, so the caller can start over with a new one.
+pub fn run(
+    name: &'static str,
+    jobs: &[Job],
+    jitter: Duration,
+    health: &Health,
+    alive: &dyn Fn() -> bool,
+    stop: &AtomicBool,
+) -> Result<(), String> {
+    let mut rng = rand::thread_rng();
+    let step = || {
+        if let Err(err) = health.beat(name) {
+            eprintln!("Loop {} failed to beat: {}", name, err);
+        }
+    };
+
+    // the first runs are spread as well
+    let start = Instant::now();
+    let mut next_runs: Vec<Instant> = jobs
+        .iter()
+        .map(|_| start + jittered(Duration::from_secs(0), jitter, rng.gen()))
+        .collect();
+
+    let mut last_heartbeat: Option<Instant> = None;
+    while !stop.load(Ordering::SeqCst) {
+        let heartbeat_due = last_heartbeat.map_or(true, |at| {
+            at.elapsed() >= Duration::from_secs(HEARTBEAT_SECONDS)
+        });
+        if heartbeat_due {
+            health.beat(name)?;
+            last_heartbeat = Some(Instant::now());
+        }
+
+        let now = Instant::now();
+        match next_due(&next_runs, now) {
+            Some(index) => {
+                let job = &jobs[index];
+                if let Err(err) = (job.run)(&step) {
+                    eprintln!("Job {} failed: {}", job.name, err);
+                    if !alive() {
+                        eprintln!("Loop {} lost its database connection", name);
+                        return Ok(());
+                    }
+                }
+                next_runs[index] = Instant::now() + jittered(job.interval, jitter, rng.gen());
+                last_heartbeat = None;
+            }
+            None => {
+                let until_next = next_runs
+                    .iter()
+                    .min()
+                    .map(|next| next.saturating_duration_since(now))
+                    .unwrap_or_default();
+                thread::sleep(until_next.min(Duration::from_secs(MAX_SLEEP_SECONDS)));
+            }
+        }
+    }
+
+    println!("Stopped loop {}", name);
+    Ok(())
+}
+
+// the job that has been due the longest, if any
+fn next_due(next_runs: &[Instant], now: Instant) -> Option<usize> {
+    next_runs
+        .iter()
+        .enumerate()
+        .filter(|(_, next_run)| **next_run <= now)
+        .min_by_key(|(_, next_run)| **next_run)
+        .map(|(index, _)| index)
+}
+
+// `random` is between 0 and 1
+fn jittered(interval: Duration, jitter: Duration, random: f64) -> Duration {
+    interval + jitter.mul_f64(random)
+}
+
+fn heartbeat(health_file: &Path) -> Result<(), String> {
+    fs::write(health_file, Utc::now().to_rfc3339()).map_err(|err| {
+        format!(
+            "Failed to write health file {}: {:?}",
+            health_file.display(),
+            err
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn run_the_job_that_has_been_due_the_longest() {
+        let now = Instant::now();
+        let next_runs = vec![
+            now - Duration::from_secs(10),
+            now - Duration::from_secs(60),
+            now + Duration::from_secs(60),
+        ];
+        assert_eq!(Some(1), next_due(&next_runs, now));
+        assert_eq!(Some(1), next_due(&next_runs, now - Duration::from_secs(30)));
+        assert_eq!(Some(0), next_due(&next_runs[..1], now));
+    }
+
+    #[test]
+    fn run_nothing_before_it_is_due() {
+        let now = Instant::now();
+        let next_runs = vec![now + Duration::from_secs(1), now + Duration::from_secs(60)];
+        assert_eq!(None, next_due(&next_runs, now));
+        assert_eq!(None, next_due(&[], now));
+    }
+
+    #[test]
+    fn touch_the_health_file_once_all_loops_beat() {
+        let file = env::temp_dir().join(format!("digester-health-{}", std::process::id()));
+        let health = Health::new(Some(file.clone()));
+        let beat = |name: &'static str| {
+            thread::sleep(Duration::from_millis(2));
+            health.beat(name).unwrap();
+        };
+
+        beat("channels");
+        assert!(file.exists());
+        fs::remove_file(&file).unwrap();
+
+        // the channels loop didn't beat since the file was touched
+        beat("digests");
+        beat("digests");
+        assert!(!file.exists());
+
+        beat("channels");
+        assert!(file.exists());
+        health.remove();
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn jitter_delays_jobs_by_up_to_the_jitter() {
+        let interval = Duration::from_secs(300);
+        let jitter = Duration::from_secs(30);
+        assert_eq!(interval, jittered(interval, jitter, 0.0));
+        assert_eq!(Duration::from_secs(315), jittered(interval, jitter, 0.5));
+        assert!(jittered(interval, jitter, 0.999) < interval + jitter);
+        assert_eq!(interval, jittered(interval, Duration::from_secs(0), 0.7));
+    }
+}
@@ -1,17 +1,37 @@
+use channels::forge_release::ForgeRelease;
 use channels::github_release::GithubRelease;
+use channels::github_tag::GithubTag;
+use channels::politeness::{self, Politeness};
 use channels::twitter::Twitter;
 use lib_channels as channels;
 use lib_db as db;
 use lib_digester as digester;
 use lib_fetcher as fetcher;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 use structopt::StructOpt;
 
-#[derive(Debug, StructOpt)]
+mod daemon;
+mod shutdown;
+
+use daemon::{Health, Job};
+
+// how long we wait before we try again if we can't connect to the database
+const RECONNECT_SECONDS: u64 = 10;
+
+#[derive(Debug, Clone, StructOpt)]
 struct Opt {
     #[structopt(long)]
     github_api_token: String,
+    /// api token for a gitlab or gitea host (eg. gitlab.com=secret). can be repeated
+    #[structopt(long = "forge-token")]
+    forge_tokens: Vec<ForgeToken>,
     #[structopt(long)]
     twitter_api_key: String,
     #[structopt(long)]
@@ -24,11 +44,64 @@ struct Opt {
     database_uri: String,
     #[structopt(long)]
     sendgrid_api_key: String,
+    /// base url of the websub callback in the api (eg. https://api.digester.app/websub)
+    #[structopt(long)]
+    websub_callback_url: Option<String>,
     #[structopt(long = "app-env", default_value = "prod")]
     app_env: AppEnv,
+    /// sent in the user agent, so site owners can find out who we are
+    #[structopt(long, default_value = politeness::DEFAULT_CONTACT_URL)]
+    contact_url: String,
+    /// minimum number of seconds between two requests to the same host
+    #[structopt(long, default_value = "2")]
+    host_delay_seconds: u64,
+    /// how many requests may be in flight to the same host at a time
+    #[structopt(long, default_value = "2")]
+    host_concurrency: usize,
+    /// channels that failed that many times in a row are considered dead
+    #[structopt(long, default_value = "10")]
+    dead_after_failures: i32,
+    /// how many channels are fetched at the same time
+    #[structopt(long, default_value = "8")]
+    fetch_concurrency: usize,
+    /// without a mode, the worker fetches and sends the digests once and exits
+    #[structopt(subcommand)]
+    mode: Option<Mode>,
+}
+
+#[derive(Debug, Clone, StructOpt)]
+enum Mode {
+    /// Keeps running and schedules the jobs itself
+    Daemon(DaemonOpt),
+}
+
+#[derive(Debug, Clone, StructOpt)]
+struct DaemonOpt {
+    /// seconds between two looks for channels that are due to be fetched
+    #[structopt(long, default_value = "300")]
+    fetch_interval_seconds: u64,
+    /// seconds between two looks for channels that are due to be cleaned
+    #[structopt(long, default_value = "3600")]
+    clean_interval_seconds: u64,
+    /// seconds between two looks for digests that are due to be sent
+    #[structopt(long, default_value = "60")]
+    digest_interval_seconds: u64,
+    /// jobs run up to that many seconds later than their interval says
+    #[structopt(long, default_value = "30")]
+    jitter_seconds: u64,
+    /// touched every few seconds while idle and while jobs make progress,
+    /// so a liveness probe can check its age
+    #[structopt(long, parse(from_os_str))]
+    health_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+struct ForgeToken {
+    host: String,
+    token: String,
 }
 
-#[derive(StructOpt, Debug)]
+#[derive(StructOpt, Debug, Clone)]
 enum AppEnv {
     Dev,
     Stg,
@@ -37,20 +110,136 @@ enum AppEnv {
 
 fn main() -> Result<(), String> {
     let opt = Opt::from_args();
+    politeness::configure(Politeness {
+        user_agent: politeness::user_agent_with_contact(&opt.contact_url),
+        host_delay: Duration::from_secs(opt.host_delay_seconds),
+        host_concurrency: opt.host_concurrency,
+        respect_robots: true,
+    });
+    let stop = shutdown::on_terminate()?;
+    println!("Running worker in {:?} mode", opt.app_env);
+    match &opt.mode {
+        None => run_once(&opt, stop),
+        Some(Mode::Daemon(daemon_opt)) => run_daemon(&opt, daemon_opt, stop),
+    }
+}
+
+fn run_once(opt: &Opt, stop: &AtomicBool) -> Result<(), String> {
     let db_conn = db::connection_from_str(&opt.database_uri)?;
+    fetcher(opt, &db_conn)?.run(stop, &|| {})?;
+    digester(opt, &db_conn).run(stop, &|| {})
+}
+
+// the digests are sent in a loop on their own thread, so they don't wait for a
+// long fetch. the channels are fetched and cleaned on this thread, because the
+// github clients can't leave the thread that created them.
+fn run_daemon(opt: &Opt, daemon_opt: &DaemonOpt, stop: &'static AtomicBool) -> Result<(), String> {
+    let jitter = Duration::from_secs(daemon_opt.jitter_seconds);
+    let health = Arc::new(Health::new(daemon_opt.health_file.clone()));
+
+    let digests = {
+        let (opt, daemon_opt, health) = (opt.clone(), daemon_opt.clone(), health.clone());
+        thread::spawn(move || {
+            with_connection(&opt, stop, |db_conn| {
+                let digester = digester(&opt, db_conn);
+                let jobs = vec![Job::new(
+                    "digest",
+                    Duration::from_secs(daemon_opt.digest_interval_seconds),
+                    |progress| digester.run(stop, progress),
+                )];
+                let alive = || db::connection_is_alive(db_conn);
+                daemon::run("digests", &jobs, jitter, &health, &alive, stop)
+            })
+        })
+    };
+
+    let channels = with_connection(opt, stop, |db_conn| {
+        let fetcher = fetcher(opt, db_conn)?;
+        let jobs = vec![
+            Job::new(
+                "fetch",
+                Duration::from_secs(daemon_opt.fetch_interval_seconds),
+                |progress| fetcher.fetch(stop, progress),
+            ),
+            Job::new(
+                "clean",
+                Duration::from_secs(daemon_opt.clean_interval_seconds),
+                |progress| fetcher.clean(progress),
+            ),
+        ];
+        let alive = || db::connection_is_alive(db_conn);
+        daemon::run("channels", &jobs, jitter, &health, &alive, stop)
+    });
+    // the digests shouldn't keep running if the channels failed
+    stop.store(true, Ordering::SeqCst);
+    let digests = digests
+        .join()
+        .unwrap_or_else(|_| Err("Digests loop panicked".into()));
+
+    println!("Stopped worker");
+    health.remove();
+    channels.and(digests)
+}
+
+// the connection and everything built on it lives as long as the loop. if the
+// loop returns before we're asked to stop, the connection is gone and we start
+// over with a new one. that's also where we end up if we can't connect at all.
+fn with_connection<F>(opt: &Opt, stop: &AtomicBool, run_loop: F) -> Result<(), String>
+where
+    F: Fn(&db::Connection) -> Result<(), String>,
+{
+    while !stop.load(Ordering::SeqCst) {
+        match db::connection_from_str(&opt.database_uri) {
+            Ok(db_conn) => run_loop(&db_conn)?,
+            Err(err) => {
+                eprintln!("{}, trying again in {}s", err, RECONNECT_SECONDS);
+                for _ in 0..RECONNECT_SECONDS {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn fetcher<'a>(opt: &Opt, db_conn: &'a db::Connection) -> Result<fetcher::App<'a>, String> {
     let github = GithubRelease::new(&opt.github_api_token)?;
+    let github_tag = GithubTag::new(&opt.github_api_token)?;
+    let forge_tokens: HashMap<String, String> = opt
+        .forge_tokens
+        .iter()
+        .cloned()
+        .map(|ft| (ft.host, ft.token))
+        .collect();
+    let forge_release = ForgeRelease::new(forge_tokens);
     let twitter = Twitter::new(
         &opt.twitter_api_key,
         &opt.twitter_api_secret_key,
         &opt.twitter_access_token,
         &opt.twitter_access_token_secret,
     )?;
+    Ok(fetcher::App::new(
+        db_conn,
+        github,
+        github_tag,
+        forge_release,
+        twitter,
+        opt.websub_callback_url.clone(),
+        fetcher::Settings {
+            dead_after_failures: opt.dead_after_failures,
+            concurrency: opt.fetch_concurrency,
+        },
+    ))
+}
+
+fn digester<'a>(opt: &Opt, db_conn: &'a db::Connection) -> digester::App<'a> {
     let sendgrid = digester::SendgridCredentials {
-        api_key: opt.sendgrid_api_key,
+        api_key: opt.sendgrid_api_key.clone(),
     };
-    println!("Running worker in {:?} mode", opt.app_env);
-    fetcher::App::new(&db_conn, github, twitter).run()?;
-    digester::App::new(&db_conn, sendgrid, opt.app_env.into()).run()
+    digester::App::new(db_conn, sendgrid, opt.app_env.clone().into())
 }
 
 impl FromStr for AppEnv {
@@ -65,6 +254,20 @@ impl FromStr for AppEnv {
     }
 }
 
+impl FromStr for ForgeToken {
+    type Err = String;
+    fn from_str(param: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = param.splitn(2, '=').collect();
+        match parts.as_slice() {
+            [host, token] if !host.is_empty() && !token.is_empty() => Ok(ForgeToken {
+                host: (*host).to_owned(),
+                token: (*token).to_owned(),
+            }),
+            _ => Err("Forge token must have format host=token".into()),
+        }
+    }
+}
+
 impl Into<digester::Env> for AppEnv {
     fn into(self) -> digester::Env {
         match self {
@@ -0,0 +1,24 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_stop(_signal: libc::c_int) {
+    // the second signal means whoever sent it doesn't want to wait
+    if STOP.swap(true, Ordering::SeqCst) {
+        unsafe { libc::_exit(1) }
+    }
+}
+
+/// Makes SIGTERM and SIGINT ask the worker to stop rather than killing it, so
+/// it can finish what it is doing (eg. sending a digest). The returned flag is
+/// set once one of them arrives.
+pub fn on_terminate() -> Result<&'static AtomicBool, String> {
+    for signal in &[libc::SIGTERM, libc::SIGINT] {
+        // the handler only touches an atomic, which is safe in a signal handler
+        let handler = request_stop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        if unsafe { libc::signal(*signal, handler) } == libc::SIG_ERR {
+            return Err(format!("Failed to install handler for signal {}", signal));
+        }
+    }
+    Ok(&STOP)
+}
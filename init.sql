@@ -30,20 +30,88 @@ CREATE TABLE channels (
   link VARCHAR NOT NULL, -- link of the website eg. blog.acolyer.com or github.com/kubernetes/kubernetes
   verified BOOL NOT NULL, -- if true, this twitter account is verified
   last_fetched TIMESTAMP WITH TIME ZONE NULL, -- last successful fetch
-  last_cleaned TIMESTAMP WITH TIME ZONE NULL, -- last time we cleaned old updates, deleted inexistent tweets etc
+  last_cleaned TIMESTAMP WITH TIME ZONE NULL, -- last time we (started to) clean old updates, delete inexistent tweets etc
+  etag VARCHAR NULL, -- etag of the last response, sent as If-None-Match on the next fetch
+  last_modified VARCHAR NULL, -- last-modified of the last response, sent as If-Modified-Since on the next fetch
   inserted TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  extract_articles BOOL NOT NULL DEFAULT FALSE, -- fetch the linked pages of new updates (for feeds that only have titles)
+  podcast BOOL NOT NULL DEFAULT FALSE, -- if true, the updates are episodes with audio files
+  failures INT NOT NULL DEFAULT 0, -- fetches that failed in a row since the last successful one
+  last_error_kind VARCHAR NULL, -- eg. not_found, timeout
+  last_error VARCHAR NULL, -- message of the last failed fetch
+  retry_at TIMESTAMP WITH TIME ZONE NULL, -- we back off from failing channels until then
+  dead_since TIMESTAMP WITH TIME ZONE NULL, -- set once it failed too often, subscribers are told about it
+  next_fetch_at TIMESTAMP WITH TIME ZONE NULL, -- from how often it changes and when its subscribers get digests. null means now. pushed forward while a worker fetches it
+  owner_id INT NULL REFERENCES users(id), -- only for newsletters: the user who created the address, nobody else may see or subscribe to it
   UNIQUE(channel_type, ext_id) -- cannot have channel twice
 );
 
 CREATE TABLE updates (
   id BIGSERIAL PRIMARY KEY,
   channel_id INT REFERENCES channels(id),
-  ext_id VARCHAR NULL, -- identifies update in external system (currently only used for tweet id)
+  ext_id VARCHAR NULL, -- identifies update in external system (eg. tweet id or guid of an rss item)
   title VARCHAR NOT NULL,
   url VARCHAR NULL, -- direct link to update
+  external_url VARCHAR NULL, -- what the update links to if url is a discussion (eg. reddit post)
   published TIMESTAMP WITH TIME ZONE NULL, -- when the update was published
   inserted TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
-  UNIQUE(channel_id, title, published) -- title could be duplicate, but not for the same published date
+  summary VARCHAR NULL, -- short plain text, shown below the title in digests
+  author VARCHAR NULL,
+  categories VARCHAR[] NOT NULL DEFAULT '{}',
+  image_url VARCHAR NULL,
+  guid VARCHAR NOT NULL, -- identifies the update within the channel (ext_id or a fingerprint of url and title)
+  reading_minutes INT NULL, -- how long it takes to read the linked article
+  duration_seconds INT NULL, -- how long the episode of a podcast is
+  episode INT NULL, -- number of the episode of a podcast
+  UNIQUE(channel_id, guid)
+);
+
+-- guids of the updates the cleaner deleted, so updates without a date that
+-- are still in the feed are not new again once they're gone from updates
+CREATE TABLE cleaned_updates (
+  channel_id INT NOT NULL REFERENCES channels(id),
+  guid VARCHAR NOT NULL,
+  cleaned TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY(channel_id, guid)
+);
+
+-- media files attached to an update (eg. the audio of a podcast episode)
+CREATE TABLE update_enclosures (
+  id BIGSERIAL PRIMARY KEY,
+  update_id BIGINT NOT NULL REFERENCES updates(id) ON DELETE CASCADE,
+  url VARCHAR NOT NULL,
+  mime_type VARCHAR NULL,
+  length BIGINT NULL -- in bytes
+);
+
+-- snapshot of the page the last time we fetched a web_page channel
+CREATE TABLE web_page_snapshots (
+  ext_id VARCHAR PRIMARY KEY, -- ext_id of the channel (url and selector)
+  hash VARCHAR NOT NULL, -- sha256 of the content
+  content TEXT NOT NULL, -- normalized text of the selected region
+  updated TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+-- mails received by the inbox for newsletter channels
+CREATE TABLE newsletter_mails (
+  id BIGSERIAL PRIMARY KEY,
+  web_id VARCHAR NOT NULL UNIQUE, -- random id in the url of the web view, so mails cannot be enumerated
+  channel_id INT NOT NULL REFERENCES channels(id),
+  sender VARCHAR NOT NULL,
+  subject VARCHAR NOT NULL,
+  html TEXT NULL,
+  text TEXT NULL,
+  received TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE TABLE websub_subscriptions (
+  channel_id INT PRIMARY KEY REFERENCES channels(id),
+  hub VARCHAR NOT NULL,
+  topic VARCHAR NOT NULL,
+  secret VARCHAR NOT NULL,
+  expires TIMESTAMP WITH TIME ZONE NULL, -- null until the hub verified the subscription
+  requested TIMESTAMP WITH TIME ZONE NOT NULL,
+  answered TIMESTAMP WITH TIME ZONE NULL -- when the hub last verified or denied a request of ours
 );
 
 -- LISTS
@@ -89,7 +157,8 @@ CREATE TABLE digests (
   subscription_id INT REFERENCES subscriptions(id),
   due TIMESTAMP WITH TIME ZONE NOT NULL, -- when the digest shoud be sent
   sent TIMESTAMP WITH TIME ZONE, -- null if not sent yet, otherwise set to the send time
-  inserted TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+  inserted TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  claimed_until TIMESTAMP WITH TIME ZONE -- a worker is sending the digest until then (or crashed doing so)
 );
 
 -- per subscription, we can only have one unsent digest
@@ -0,0 +1 @@
+ALTER TABLE updates ADD COLUMN external_url VARCHAR NULL;
@@ -0,0 +1,6 @@
+CREATE TABLE web_page_snapshots (
+  ext_id VARCHAR PRIMARY KEY,
+  hash VARCHAR NOT NULL,
+  content TEXT NOT NULL,
+  updated TIMESTAMP WITH TIME ZONE NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE newsletter_mails (
+  id BIGSERIAL PRIMARY KEY,
+  web_id VARCHAR NOT NULL UNIQUE,
+  channel_id INT NOT NULL REFERENCES channels(id),
+  sender VARCHAR NOT NULL,
+  subject VARCHAR NOT NULL,
+  html TEXT NULL,
+  text TEXT NULL,
+  received TIMESTAMP WITH TIME ZONE NOT NULL
+);
@@ -0,0 +1,3 @@
+ALTER TABLE channels
+  ADD COLUMN etag VARCHAR NULL,
+  ADD COLUMN last_modified VARCHAR NULL;
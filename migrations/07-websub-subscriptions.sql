@@ -0,0 +1,8 @@
+CREATE TABLE websub_subscriptions (
+  channel_id INT PRIMARY KEY REFERENCES channels(id),
+  hub VARCHAR NOT NULL,
+  topic VARCHAR NOT NULL,
+  secret VARCHAR NOT NULL,
+  expires TIMESTAMP WITH TIME ZONE NULL, -- null until the hub verified the subscription
+  requested TIMESTAMP WITH TIME ZONE NOT NULL
+);
@@ -0,0 +1,13 @@
+ALTER TABLE updates
+  ADD COLUMN summary VARCHAR NULL,
+  ADD COLUMN author VARCHAR NULL,
+  ADD COLUMN categories VARCHAR[] NOT NULL DEFAULT '{}',
+  ADD COLUMN image_url VARCHAR NULL;
+
+CREATE TABLE update_enclosures (
+  id BIGSERIAL PRIMARY KEY,
+  update_id BIGINT NOT NULL REFERENCES updates(id) ON DELETE CASCADE,
+  url VARCHAR NOT NULL,
+  mime_type VARCHAR NULL,
+  length BIGINT NULL
+);
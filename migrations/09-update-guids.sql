@@ -0,0 +1,9 @@
+ALTER TABLE updates ADD COLUMN guid VARCHAR NULL;
+
+-- existing updates get a guid that never matches, because we can't compute
+-- the fingerprints here. updates we fetch again are still filtered by date.
+UPDATE updates SET guid = 'legacy:' || id;
+
+ALTER TABLE updates ALTER COLUMN guid SET NOT NULL;
+ALTER TABLE updates DROP CONSTRAINT updates_channel_id_title_published_key;
+ALTER TABLE updates ADD CONSTRAINT updates_channel_id_guid_key UNIQUE (channel_id, guid);
@@ -0,0 +1,4 @@
+-- opt-in, because it means an additional request per update
+ALTER TABLE channels ADD COLUMN extract_articles BOOL NOT NULL DEFAULT FALSE;
+
+ALTER TABLE updates ADD COLUMN reading_minutes INT NULL;
@@ -0,0 +1,13 @@
+ALTER TABLE channels ADD COLUMN podcast BOOL NOT NULL DEFAULT FALSE;
+
+-- feeds we already know as podcasts, because their episodes have audio files
+UPDATE channels SET podcast = TRUE
+WHERE channel_type = 'rss_feed' AND id IN (
+  SELECT u.channel_id FROM updates u
+  JOIN update_enclosures e ON e.update_id = u.id
+  WHERE e.mime_type LIKE 'audio/%'
+);
+
+ALTER TABLE updates
+  ADD COLUMN duration_seconds INT NULL,
+  ADD COLUMN episode INT NULL;
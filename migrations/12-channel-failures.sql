@@ -0,0 +1,6 @@
+ALTER TABLE channels
+  ADD COLUMN failures INT NOT NULL DEFAULT 0,
+  ADD COLUMN last_error_kind VARCHAR NULL,
+  ADD COLUMN last_error VARCHAR NULL,
+  ADD COLUMN retry_at TIMESTAMP WITH TIME ZONE NULL,
+  ADD COLUMN dead_since TIMESTAMP WITH TIME ZONE NULL;
@@ -0,0 +1,4 @@
+ALTER TABLE channels ADD COLUMN next_fetch_at TIMESTAMP WITH TIME ZONE NULL;
+
+-- keep fetching on the old schedule until we know better
+UPDATE channels SET next_fetch_at = last_fetched + INTERVAL '6 hours' WHERE last_fetched IS NOT NULL;
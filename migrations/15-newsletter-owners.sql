@@ -0,0 +1,6 @@
+ALTER TABLE channels ADD COLUMN owner_id INT NULL REFERENCES users(id);
+
+-- whoever subscribed first created the address
+UPDATE channels SET owner_id = (
+  SELECT user_id FROM subscriptions WHERE subscriptions.channel_id = channels.id ORDER BY subscriptions.id LIMIT 1
+) WHERE channel_type = 'newsletter';
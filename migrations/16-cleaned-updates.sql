@@ -0,0 +1,6 @@
+CREATE TABLE cleaned_updates (
+  channel_id INT NOT NULL REFERENCES channels(id),
+  guid VARCHAR NOT NULL,
+  cleaned TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY(channel_id, guid)
+);
@@ -0,0 +1 @@
+ALTER TABLE websub_subscriptions ADD COLUMN answered TIMESTAMP WITH TIME ZONE NULL;
@@ -25,7 +25,17 @@ function run_worker(){
     --twitter-api-secret-key "$TWITTER_API_SECRET_KEY" \
     --twitter-access-token "$TWITTER_ACCESS_TOKEN" \
     --twitter-access-token-secret "$TWITTER_ACCESS_TOKEN_SECRET" \
-    --app-env dev
+    --app-env dev \
+    "$@"
+  popd
+}
+
+function run_inbox(){
+  pushd backend/inbox
+  cargo run -- \
+    --listen 127.0.0.1:2525 \
+    --database-uri "$POSTGRES_CONNECTION" \
+    --web-view-url https://localhost:8000/newsletters
   popd
 }
 
@@ -158,7 +168,7 @@ function run_sanity_check() {
   popd
 
   # update .bashrc
-  sed -i "s/^DIGESTER_RUN_WORDLIST=.*/DIGESTER_RUN_WORDLIST=\"worker worker-loop api fe db kill-db build-db psql psql-stg psql-prod redis install-redli logs-db sanity pull-stg-cfg api-stg prod-backup\"/g" ~/.bashrc
+  sed -i "s/^DIGESTER_RUN_WORDLIST=.*/DIGESTER_RUN_WORDLIST=\"worker worker-loop worker-daemon inbox api fe db kill-db build-db psql psql-stg psql-prod redis install-redli logs-db sanity pull-stg-cfg api-stg prod-backup\"/g" ~/.bashrc
   echo "You might have to reload your .bashrc"
 
   # check this script
@@ -169,6 +179,8 @@ function run_sanity_check() {
 case $CMD in
   worker)        run_worker ;;
   worker-loop)   loop_worker ;;
+  worker-daemon) run_worker daemon ;;
+  inbox)         run_inbox ;;
   api)           run_api ;;
   api-stg)       run_heroku_stg ;;
   fe)            run_fe ;;